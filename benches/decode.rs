@@ -0,0 +1,86 @@
+#[macro_use]
+extern crate criterion;
+extern crate alice;
+
+use std::env;
+use std::fs::File;
+use std::io::{Cursor, Write};
+use std::path::PathBuf;
+use criterion::Criterion;
+use alice::data::{BinaryReader, BinaryWriter, TextReader, TextWriter};
+use alice::model::{Model, Path, Point, FillRule, Paint, NO_WEIGHTS};
+
+fn large_model() -> Model {
+    let paths = (0..200).map(|i| {
+        let points = (0..1000).map(|j| {
+            let a = j as f64 * std::f64::consts::PI / 500.0;
+            let r = 100.0 + i as f64;
+            Point {
+                location: (r * a.cos(), r * a.sin()),
+                curve_bias: (j % 3) as f64 * 0.5,
+                weights: NO_WEIGHTS
+            }
+        }).collect();
+
+        Path {
+            name: None,
+            paint: Paint::solid((i as f64 / 200.0, 0.5, 0.25)),
+            points: points,
+            contours: Vec::new(),
+            fill_rule: FillRule::NonZero,
+            filled: true,
+            stroke: None
+        }
+    }).collect();
+
+    Model { paths: paths, .. Default::default() }
+}
+
+fn write_temp(name: &str, data: &[u8]) -> PathBuf {
+    let path = env::temp_dir().join(name);
+    File::create(&path).unwrap().write_all(data).unwrap();
+    path
+}
+
+fn decode_binary(c: &mut Criterion) {
+    let model = large_model();
+    let mut writer = BinaryWriter::new(Vec::new());
+    model.write(&mut writer).unwrap();
+    let data = writer.into_inner();
+    let path = write_temp("alice-bench.model", &data);
+
+    c.bench_function("binary memory", move |b| b.iter(|| {
+        let mut reader = BinaryReader::new(Cursor::new(&data[..]));
+        Model::read(&mut reader).unwrap()
+    }));
+
+    c.bench_function("binary file", move |b| b.iter(|| {
+        let mut reader = BinaryReader::new(File::open(&path).unwrap());
+        Model::read(&mut reader).unwrap()
+    }));
+}
+
+fn decode_text(c: &mut Criterion) {
+    let model = large_model();
+    let mut writer = TextWriter::new(Vec::new());
+    model.write(&mut writer).unwrap();
+    let data = writer.into_inner();
+    let path = write_temp("alice-bench.model.txt", &data);
+
+    c.bench_function("text memory", move |b| b.iter(|| {
+        let mut reader = TextReader::new(Cursor::new(&data[..]));
+        Model::read(&mut reader).unwrap()
+    }));
+
+    c.bench_function("text file", move |b| b.iter(|| {
+        let mut reader = TextReader::new(File::open(&path).unwrap());
+        Model::read(&mut reader).unwrap()
+    }));
+}
+
+criterion_group! {
+    name = benches;
+    config = Criterion::default().sample_size(20);
+    targets = decode_binary, decode_text
+}
+criterion_main!(benches);
@@ -0,0 +1,59 @@
+extern crate alice;
+
+use std::fs::File;
+use std::io::{self, Read, Write, BufReader, BufWriter};
+use std::process::exit;
+use alice::data::{Reader, Writer, copy};
+use alice::data::{BinaryReader, TextReader, JsonReader, CborReader};
+use alice::data::{BinaryWriter, TextWriter, JsonWriter, CborWriter};
+
+fn usage() -> ! {
+    let _ = writeln!(io::stderr(),
+        "Usage: alice-convert <from> <to> [input [output]]\n\
+         Formats: binary, text, json, cbor");
+    exit(1);
+}
+
+fn reader<'a, R: Read + 'a>(format: &str, input: R) -> Box<Reader + 'a> {
+    match format {
+        "binary" => Box::new(BinaryReader::new(input)),
+        "text" => Box::new(TextReader::new(input)),
+        "json" => Box::new(JsonReader::new(input)),
+        "cbor" => Box::new(CborReader::new(input)),
+        _ => usage()
+    }
+}
+
+fn writer<'a, W: Write + 'a>(format: &str, output: W) -> Box<Writer + 'a> {
+    match format {
+        "binary" => Box::new(BinaryWriter::new(output)),
+        "text" => Box::new(TextWriter::new(output)),
+        "json" => Box::new(JsonWriter::new(output)),
+        "cbor" => Box::new(CborWriter::new(output)),
+        _ => usage()
+    }
+}
+
+fn main() {
+    let args = std::env::args().collect::<Vec<_>>();
+    if args.len() < 3 || args.len() > 5 {
+        usage();
+    }
+
+    let input: Box<Read> = match args.get(3) {
+        Some(path) if path != "-" => Box::new(File::open(path).unwrap()),
+        _ => Box::new(io::stdin())
+    };
+    let output: Box<Write> = match args.get(4) {
+        Some(path) if path != "-" => Box::new(File::create(path).unwrap()),
+        _ => Box::new(io::stdout())
+    };
+
+    let mut reader = reader(&args[1], BufReader::new(input));
+    let mut writer = writer(&args[2], BufWriter::new(output));
+
+    if let Err(e) = copy(&mut *reader, &mut *writer) {
+        let _ = writeln!(io::stderr(), "alice-convert: {}", e);
+        exit(1);
+    }
+}
@@ -0,0 +1,47 @@
+extern crate alice;
+
+use std::fs::File;
+use std::io::{self, Read, Write, BufReader, BufWriter};
+use std::process::exit;
+use alice::data::BinaryReader;
+use alice::model::Model;
+use alice::model::svg::write_svg;
+use alice::model::pdf::write_pdf;
+
+fn usage() -> ! {
+    let _ = writeln!(io::stderr(),
+        "Usage: alice-export <svg|pdf> [input.model [output]]");
+    exit(1);
+}
+
+fn main() {
+    let args = std::env::args().collect::<Vec<_>>();
+    if args.len() < 2 || args.len() > 4 || (args[1] != "svg" && args[1] != "pdf") {
+        usage();
+    }
+
+    let input: Box<Read> = match args.get(2) {
+        Some(path) if path != "-" => Box::new(File::open(path).unwrap()),
+        _ => Box::new(io::stdin())
+    };
+    let output: Box<Write> = match args.get(3) {
+        Some(path) if path != "-" => Box::new(File::create(path).unwrap()),
+        _ => Box::new(io::stdout())
+    };
+
+    let mut reader = BinaryReader::new(BufReader::new(input));
+    let mut output = BufWriter::new(output);
+
+    let result = Model::read(&mut reader).and_then(|model| {
+        if args[1] == "svg" {
+            write_svg(&model, &mut output)
+        } else {
+            write_pdf(&model, &mut output)
+        }
+    });
+
+    if let Err(e) = result.and_then(|_| output.flush()) {
+        let _ = writeln!(io::stderr(), "alice-export: {}", e);
+        exit(1);
+    }
+}
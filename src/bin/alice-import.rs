@@ -0,0 +1,49 @@
+extern crate alice;
+
+use std::fs::File;
+use std::io::{self, Read, Write, BufReader, BufWriter};
+use std::process::exit;
+use alice::data::BinaryWriter;
+use alice::model::svg::read_svg;
+
+fn usage() -> ! {
+    let _ = writeln!(io::stderr(),
+        "Usage: alice-import [-t tolerance] [input.svg [output.model]]");
+    exit(1);
+}
+
+fn main() {
+    let mut args = std::env::args().skip(1).collect::<Vec<_>>();
+    let mut tolerance = 0.1;
+
+    if args.len() >= 2 && args[0] == "-t" {
+        tolerance = match args[1].parse::<f64>() {
+            Ok(t) if t > 0.0 => t,
+            _ => usage()
+        };
+        args.drain(..2);
+    }
+
+    if args.len() > 2 {
+        usage();
+    }
+
+    let mut input: Box<Read> = match args.get(0) {
+        Some(path) if path != "-" => Box::new(BufReader::new(File::open(path).unwrap())),
+        _ => Box::new(io::stdin())
+    };
+    let output: Box<Write> = match args.get(1) {
+        Some(path) if path != "-" => Box::new(File::create(path).unwrap()),
+        _ => Box::new(io::stdout())
+    };
+
+    let result = read_svg(&mut *input, tolerance).and_then(|model| {
+        let mut writer = BinaryWriter::new(BufWriter::new(output));
+        model.write(&mut writer)
+    });
+
+    if let Err(e) = result {
+        let _ = writeln!(io::stderr(), "alice-import: {}", e);
+        exit(1);
+    }
+}
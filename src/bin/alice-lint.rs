@@ -0,0 +1,144 @@
+extern crate alice;
+
+use std::fs::{self, File};
+use std::io::{self, Write, BufReader, BufWriter};
+use std::process::exit;
+use alice::data::{BinaryReader, BinaryWriter};
+use alice::model::{Model, Issue, Problem};
+
+fn usage() -> ! {
+    let _ = writeln!(io::stderr(),
+        "Usage: alice-lint [-r] [input.model [output.model]]\n\
+         -r repairs the model and writes it out, listing what's left");
+    exit(1);
+}
+
+fn describe(model: &Model, issue: &Issue) -> String {
+    let path = match issue.symbol {
+        Some(symbol) => &model.symbols[symbol].paths[issue.path],
+        None => &model.paths[issue.path]
+    };
+
+    let mut location = match issue.symbol {
+        Some(symbol) => format!("symbol {} ({}), path {}", symbol, model.symbols[symbol].name, issue.path),
+        None => format!("path {}", issue.path)
+    };
+    if let Some(ref name) = path.name {
+        location.push_str(&format!(" ({})", name));
+    }
+    if issue.outline > 0 {
+        location.push_str(&format!(", contour {}", issue.outline - 1));
+    }
+    if let Some(point) = issue.point {
+        location.push_str(&format!(", point {}", point));
+    }
+
+    let problem = match issue.problem {
+        Problem::TooFewPoints => "too few points",
+        Problem::NonFinite => "coordinate not a finite number",
+        Problem::CurveBias => "curve bias outside [0, 1]",
+        Problem::ZeroLength => "zero length segment",
+        Problem::Orientation => "winds the wrong way",
+        Problem::SelfIntersection => "crosses itself",
+        Problem::FineDashes => "dashes too short to draw"
+    };
+
+    format!("{}: {}", location, problem)
+}
+
+fn with_path(path: &str, e: io::Error) -> io::Error {
+    io::Error::new(e.kind(), format!("{}: {}", path, e))
+}
+
+// Writes the model to a file, or to stdout for "-". A model written over its
+// input goes through a temporary file, so the input is only replaced once the
+// whole model has been written.
+fn write_model(model: &Model, path: &str, input: Option<&str>) -> io::Result<()> {
+    if path == "-" {
+        let stdout = io::stdout();
+        let mut writer = BinaryWriter::new(BufWriter::new(stdout.lock()));
+        try!(model.write(&mut writer));
+        return writer.into_inner().flush();
+    }
+
+    let same = match input {
+        Some(input) => match (fs::canonicalize(input), fs::canonicalize(path)) {
+            (Ok(a), Ok(b)) => a == b,
+            _ => false
+        },
+        None => false
+    };
+    let target = if same { format!("{}.tmp", path) } else { path.to_string() };
+
+    let file = try!(File::create(&target).map_err(|e| with_path(&target, e)));
+    let mut writer = BinaryWriter::new(BufWriter::new(file));
+    try!(model.write(&mut writer));
+    try!(writer.into_inner().flush());
+
+    if same {
+        try!(fs::rename(&target, path).map_err(|e| with_path(path, e)));
+    }
+    Ok(())
+}
+
+fn run(input: Option<&str>, output: Option<&str>, repair: bool) -> io::Result<usize> {
+    let mut model = match input {
+        Some(path) => {
+            let file = try!(File::open(path).map_err(|e| with_path(path, e)));
+            try!(Model::read(&mut BinaryReader::new(BufReader::new(file))))
+        },
+        None => {
+            let stdin = io::stdin();
+            let model = try!(Model::read(&mut BinaryReader::new(stdin.lock())));
+            model
+        }
+    };
+
+    // The output is only opened once the input has been read, as they may
+    // be the same file
+    if repair {
+        model.repair();
+        try!(write_model(&model, output.unwrap_or("-"), input));
+    }
+
+    // Issues go alongside any error if the model is written to stdout
+    let stdout = io::stdout();
+    let stderr = io::stderr();
+    let mut report: Box<Write> = if repair { Box::new(stderr.lock()) } else { Box::new(stdout.lock()) };
+
+    let issues = model.validate();
+    for issue in &issues {
+        try!(writeln!(report, "{}", describe(&model, issue)));
+    }
+
+    Ok(issues.len())
+}
+
+fn main() {
+    let mut args = std::env::args().skip(1).collect::<Vec<_>>();
+
+    let repair = args.len() > 0 && args[0] == "-r";
+    if repair {
+        args.remove(0);
+    }
+
+    if args.len() > 2 || (!repair && args.len() > 1) {
+        usage();
+    }
+
+    let input = match args.get(0) {
+        Some(path) if path != "-" => Some(&path[..]),
+        _ => None
+    };
+    let output = args.get(1).map(|s| &s[..]);
+
+    match run(input, output, repair) {
+        Ok(0) => (),
+        Ok(_) => exit(1),
+        Err(ref e) if e.kind() == io::ErrorKind::BrokenPipe => (),
+        Err(e) => {
+            let _ = writeln!(io::stderr(), "alice-lint: {}", e);
+            exit(2);
+        }
+    }
+}
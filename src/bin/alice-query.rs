@@ -0,0 +1,103 @@
+extern crate alice;
+
+use std::fs::File;
+use std::io::{self, Read, Write, BufRead, BufReader};
+use std::process::exit;
+use alice::data::{Reader, Query, TokenReader, copy};
+use alice::data::{BinaryReader, TextReader, JsonReader, CborReader, TextWriter};
+
+fn usage() -> ! {
+    let _ = writeln!(io::stderr(),
+        "Usage: alice-query [-f format] <query> [input]\n\
+         Formats: binary, text, json, cbor (detected if not given)");
+    exit(1);
+}
+
+fn detect<R: BufRead>(input: &mut R) -> io::Result<&'static str> {
+    let buffer = try!(input.fill_buf());
+    let mut start = buffer.iter().cloned().filter(|&b| !(b as char).is_whitespace());
+
+    Ok(match buffer.first() {
+        Some(&0xfe) | Some(&0xee) => "binary",
+        Some(&0x9f) => "cbor",
+        // JSON groups hold groups and objects where text vectors hold
+        // numbers, and JSON objects start with a key where text arrays can't
+        _ => match (start.next(), start.next()) {
+            (Some(b'['), Some(b'[')) | (Some(b'['), Some(b'{')) | (Some(b'['), Some(b']')) |
+            (Some(b'{'), Some(b'"')) => "json",
+            _ => "text"
+        }
+    })
+}
+
+fn reader<'a, R: Read + 'a>(format: &str, input: R) -> Box<Reader + 'a> {
+    match format {
+        "binary" => Box::new(BinaryReader::new(input)),
+        "text" => Box::new(TextReader::new(input)),
+        "json" => Box::new(JsonReader::new(input)),
+        "cbor" => Box::new(CborReader::new(input)),
+        _ => usage()
+    }
+}
+
+fn run(format: Option<&str>, query: &str, input: Box<Read>) -> io::Result<usize> {
+    let query = try!(Query::parse(query));
+
+    let mut input = BufReader::new(input);
+    let format = match format {
+        Some(format) => format,
+        None => try!(detect(&mut input))
+    };
+    let mut reader = reader(format, input);
+
+    let stdout = io::stdout();
+    let mut output = stdout.lock();
+
+    query.run(&mut *reader, |tokens| {
+        {
+            let mut writer = TextWriter::new(&mut output);
+            try!(copy(&mut TokenReader::new(tokens), &mut writer));
+        }
+        writeln!(output, "")
+    })
+}
+
+fn main() {
+    let mut args = std::env::args().skip(1).collect::<Vec<_>>();
+
+    let format = if args.len() > 0 && args[0] == "-f" {
+        if args.len() < 2 {
+            usage();
+        }
+        let format = args[1].clone();
+        args.drain(..2);
+        Some(format)
+    } else {
+        None
+    };
+
+    if args.len() < 1 || args.len() > 2 {
+        usage();
+    }
+
+    let input: Box<Read> = match args.get(1) {
+        Some(path) if path != "-" => match File::open(path) {
+            Ok(file) => Box::new(file),
+            Err(e) => {
+                let _ = writeln!(io::stderr(), "alice-query: {}: {}", path, e);
+                exit(1);
+            }
+        },
+        _ => Box::new(io::stdin())
+    };
+
+    match run(format.as_ref().map(|f| &f[..]), &args[0], input) {
+        Ok(0) => exit(1),
+        Ok(_) => (),
+        Err(ref e) if e.kind() == io::ErrorKind::BrokenPipe => (),
+        Err(e) => {
+            let _ = writeln!(io::stderr(), "alice-query: {}", e);
+            exit(2);
+        }
+    }
+}
@@ -1,22 +1,22 @@
 #[macro_use]
 extern crate glium;
 extern crate alice;
-extern crate rand;
 
 use std::fs::File;
 use std::io::Cursor;
+use std::time::Instant;
 use glium::{DisplayBuild, Surface};
 use glium::glutin::{Event, ElementState, VirtualKeyCode, MouseScrollDelta, MouseButton};
 use alice::model::rendering::{ModelRenderer, prepare_model};
-use alice::model::{Model, Path, Point};
-use alice::data::{Vec2, Vec3};
-use rand::{thread_rng, Rng};
+use alice::model::Model;
+use alice::model::softbody::SoftBody;
 
 fn main() {
     let display = glium::glutin::WindowBuilder::new()
         .with_title(format!("Alice"))
         .with_dimensions(1024, 768)
         .with_vsync()
+        .with_stencil_buffer(8)
         .build_glium()
         .unwrap();
     let window = display.get_window().unwrap();
@@ -34,7 +34,10 @@ fn main() {
         Model::read(&mut reader).unwrap()
     };
 
-    let mut wobble = WobbleModel::new(&model, 0.5, 0.95);
+    let mut wobble = SoftBody::new(&model);
+    wobble.edge_stiffness = 600.0;
+    wobble.area_stiffness = 600.0;
+    let mut last_frame = Instant::now();
 
     let mut x = 512.0;
     let mut y = 384.0;
@@ -48,7 +51,9 @@ fn main() {
         let (w, h) = window.get_inner_size_points().unwrap();
         renderer.set_size(w as f32, h as f32);
 
-        wobble.tick();
+        let elapsed = last_frame.elapsed();
+        last_frame = Instant::now();
+        wobble.advance(elapsed.as_secs() as f64 + elapsed.subsec_nanos() as f64 * 1e-9);
         let model = prepare_model(&display, &wobble.model());
         renderer.draw(&mut target, x, y, scale, &model);
 
@@ -58,7 +63,7 @@ fn main() {
             match ev {
                 Event::Closed => return,
                 Event::KeyboardInput(ElementState::Pressed, _, Some(VirtualKeyCode::Escape)) => return,
-                Event::ReceivedCharacter(' ') => wobble.shuffle(10.0 / scale as f64),
+                Event::ReceivedCharacter(' ') => wobble.shuffle(600.0 / scale as f64),
                 Event::ReceivedCharacter('+') => scale *= 1.1,
                 Event::ReceivedCharacter('=') => scale *= 1.1,
                 Event::ReceivedCharacter('-') => scale /= 1.1,
@@ -77,122 +82,10 @@ fn main() {
                     let (mx, my) = (mouse_pos.0 as f64 / f, h as f64 - mouse_pos.1 as f64 / f);
                     let (mx, my) = (mx - x as f64, my - y as f64);
                     let (mx, my) = (mx / scale as f64, my / scale as f64);
-                    wobble.push((mx, my), 0.3, 100.0 / scale as f64)
+                    wobble.push((mx, my), 18.0, 100.0 / scale as f64)
                 },
                 _ => ()
             }
         }
     }
 }
-
-struct WobbleModel {
-    spring: f64,
-    damping: f64,
-    paths: Vec<WobblePath>,
-}
-
-struct WobblePath {
-    colour: Vec3,
-    points: Vec<WobblePoint>
-}
-
-struct WobblePoint {
-    location: Vec2,
-    curve_bias: f64,
-    offset: Vec2,
-    velocity: Vec2
-}
-
-fn gauss(c: f64, x: f64) -> f64 {
-    (-x * x / (2.0 * c * c)).exp()
-}
-
-impl WobbleModel {
-    pub fn new(model: &Model, spring: f64, damping: f64) -> WobbleModel {
-        WobbleModel {
-            spring: spring,
-            damping: damping,
-            paths: model.paths
-                .iter()
-                .map(|path| WobblePath {
-                    colour: path.colour,
-                    points: path.points
-                        .iter()
-                        .map(|point| WobblePoint {
-                            location: point.location,
-                            curve_bias: point.curve_bias,
-                            offset: (0.0, 0.0),
-                            velocity: (0.0, 0.0)
-                        })
-                        .collect()
-                })
-                .collect()
-        }
-    }
-
-    pub fn model(&self) -> Model {
-        Model {
-            paths: self.paths
-                .iter()
-                .map(|path| Path {
-                    colour: path.colour,
-                    points: path.points
-                        .iter()
-                        .map(|point| {
-                            let x = point.location.0 + point.offset.0;
-                            let y = point.location.1 + point.offset.1;
-                            Point {
-                                location: (x, y),
-                                curve_bias: point.curve_bias
-                            }
-                        })
-                        .collect()
-                })
-                .collect()
-        }
-    }
-
-    pub fn shuffle(&mut self, v: f64) {
-        let mut rng = rand::thread_rng();
-        for path in self.paths.iter_mut() {
-            for point in path.points.iter_mut() {
-                point.velocity.0 += rng.gen_range(-v, v);
-                point.velocity.1 += rng.gen_range(-v, v);
-            }
-        }
-    }
-
-    pub fn push(&mut self, (x, y): Vec2, s: f64, w: f64) {
-        for path in self.paths.iter_mut() {
-            for point in path.points.iter_mut() {
-                let (px, py) = point.location;
-                let (dx, dy) = (px - x, py - y);
-                let d = (dx * dx + dy * dy).sqrt();
-                let d = s * gauss(w, d);
-
-                point.velocity.0 += d * dx;
-                point.velocity.1 += d * dy;
-            }
-        }
-    }
-
-    pub fn tick(&mut self) {
-        for path in self.paths.iter_mut() {
-            for point in path.points.iter_mut() {
-                let (mut ox, mut oy) = point.offset;
-                let (mut vx, mut vy) = point.velocity;
-
-                vx += -self.spring * ox;
-                vy += -self.spring * oy;
-                vx *= self.damping;
-                vy *= self.damping;
-
-                ox += vx;
-                oy += vy;
-
-                point.offset = (ox, oy);
-                point.velocity = (vx, vy);
-            }
-        }
-    }
-}
@@ -1,9 +1,9 @@
-use std::io::{self, Read};
-use byteorder::{LittleEndian, BigEndian, ReadBytesExt};
+use std::io::{self, Read, BufRead, BufReader};
+use byteorder::{ByteOrder, LittleEndian, BigEndian, ReadBytesExt};
 use super::{Value, Tag, Vec2, Vec3, Vec4, Box2, Token, Reader};
 
 pub struct BinaryReader<R> {
-    input: R
+    input: BufReader<R>
 }
 
 fn invalid_token<T>() -> io::Result<T> {
@@ -12,10 +12,12 @@ fn invalid_token<T>() -> io::Result<T> {
 
 impl<R: Read> BinaryReader<R> {
     pub fn new(input: R) -> BinaryReader<R> {
-        BinaryReader { input: input }
+        BinaryReader { input: BufReader::new(input) }
     }
 
-    pub fn into_inner(self) -> R {
+    // The input comes back still buffered, as bytes past the last token may
+    // have been read from it already
+    pub fn into_inner(self) -> BufReader<R> {
         self.input
     }
 
@@ -37,19 +39,19 @@ impl<R: Read> BinaryReader<R> {
 
     fn read_array(&mut self, t:u8) -> io::Result<Value> {
         match t {
-            0x80 => self.read_array_values(BinaryReader::read_bool)
+            0x80 => self.read_bool_array()
                 .map(Value::BoolArray),
             0x81 => self.read_array_values(BinaryReader::read_int)
                 .map(Value::IntArray),
-            0x82 => self.read_array_values(BinaryReader::read_double)
+            0x82 => self.read_double_array(1, |d| d[0])
                 .map(Value::DoubleArray),
-            0x83 => self.read_array_values(BinaryReader::read_vec2)
+            0x83 => self.read_double_array(2, |d| (d[0], d[1]))
                 .map(Value::Vec2Array),
-            0x84 => self.read_array_values(BinaryReader::read_vec3)
+            0x84 => self.read_double_array(3, |d| (d[0], d[1], d[2]))
                 .map(Value::Vec3Array),
-            0x85 => self.read_array_values(BinaryReader::read_vec4)
+            0x85 => self.read_double_array(4, |d| (d[0], d[1], d[2], d[3]))
                 .map(Value::Vec4Array),
-            0x86 => self.read_array_values(BinaryReader::read_box2)
+            0x86 => self.read_double_array(4, |d| ((d[0], d[1]), (d[2], d[3])))
                 .map(Value::Box2Array),
             _ => invalid_token(),
         }
@@ -67,12 +69,20 @@ impl<R: Read> BinaryReader<R> {
     }
 
     fn read_uint(&mut self) -> io::Result<u64> {
+        let decoded = decode_uint(try!(self.input.fill_buf()));
+        if let Some((result, length)) = decoded {
+            self.input.consume(length);
+            return Ok(result);
+        }
+
+        // The varint straddles the end of the buffer, fall back to reading
+        // it a byte at a time
         let mut buffer = [0; 1];
         let mut length = 0;
         let mut result = 0u64;
 
         loop {
-            if length > 10 {
+            if length >= 10 {
                 return invalid_token();
             }
 
@@ -159,6 +169,39 @@ impl<R: Read> BinaryReader<R> {
         Ok(buffer.into_boxed_slice())
     }
 
+    fn read_bool_array(&mut self) -> io::Result<Box<[bool]>> {
+        let length = try!(self.read_uint()) as usize;
+        let mut buffer = vec![0; length];
+        try!(self.input.read_exact(&mut buffer[..]));
+
+        Ok(buffer.iter()
+            .map(|&b| b != 0)
+            .collect::<Vec<_>>()
+            .into_boxed_slice())
+    }
+
+    fn read_double_array<F, T>(&mut self, width: usize, f: F) -> io::Result<Box<[T]>>
+        where F: Fn(&[f64]) -> T {
+
+        let length = try!(self.read_uint()) as usize;
+        let size = match length.checked_mul(width * 8) {
+            Some(size) => size,
+            None => return invalid_token()
+        };
+
+        let mut buffer = vec![0; size];
+        try!(self.input.read_exact(&mut buffer[..]));
+
+        let doubles = buffer.chunks(8)
+            .map(LittleEndian::read_f64)
+            .collect::<Vec<_>>();
+
+        Ok(doubles.chunks(width)
+            .map(f)
+            .collect::<Vec<_>>()
+            .into_boxed_slice())
+    }
+
     fn read_array_values<F, T>(&mut self, f: F) -> io::Result<Box<[T]>>
         where F: Fn(&mut Self) -> io::Result<T> {
 
@@ -173,30 +216,174 @@ impl<R: Read> BinaryReader<R> {
     }
 }
 
-impl<R: Read> Reader for BinaryReader<R> {
-    fn read_next(&mut self) -> io::Result<Token> {
-        let mut buffer = [0; 1];
-        let read = try!(self.input.read(&mut buffer));
+fn decode_uint(buffer: &[u8]) -> Option<(u64, usize)> {
+    let mut result = 0u64;
 
-        if read == 0 {
-            return Ok(Token::EndOfFile);
+    for (i, &b) in buffer.iter().take(10).enumerate() {
+        result |= (u64::from(b) & 0x7f) << (i * 7);
+
+        if b & 0x80 == 0 {
+            return Some((result, i + 1));
         }
+    }
+
+    None
+}
 
-        match buffer[0] {
+impl<R: Read> Reader for BinaryReader<R> {
+    fn read_next(&mut self) -> io::Result<Token> {
+        let t = match try!(self.input.fill_buf()).first() {
+            Some(&t) => t,
+            None => return Ok(Token::EndOfFile)
+        };
+        self.input.consume(1);
+
+        match t {
             0xfe => Ok(Token::Start),
             0xef => Ok(Token::End),
 
             0x00 ... 0x08 | 0xee =>
-                self.read_value(buffer[0]).map(Token::Value),
+                self.read_value(t).map(Token::Value),
 
             0x80 ... 0x86 =>
-                self.read_array(buffer[0]).map(Token::Value),
+                self.read_array(t).map(Token::Value),
 
             _ => invalid_token(),
         }
     }
 }
 
+// How much of the token at the front of a decoder's buffer is known
+enum Size {
+    Known(usize),
+    // At least this many bytes are needed to find out more
+    AtLeast(usize)
+}
+
+pub struct BinaryDecoder {
+    buffer: Vec<u8>,
+    // The bytes needed before the next token is worth looking at again, and
+    // how far through an int array it got, with how many ints were left
+    needed: usize,
+    ints: Option<(usize, u64)>
+}
+
+// A varint at the offset, with the offset after it, or None if it goes past
+// the end of the buffer
+fn scan_uint(buffer: &[u8], at: usize) -> io::Result<Option<(u64, usize)>> {
+    match decode_uint(&buffer[at..]) {
+        Some((value, length)) => Ok(Some((value, at + length))),
+        None if buffer.len() - at >= 10 => invalid_token(),
+        None => Ok(None)
+    }
+}
+
+// Finds the length of the token at the start of the buffer without reading
+// its values, carrying on through an int array from where the last call
+// stopped
+fn token_size(buffer: &[u8], ints: &mut Option<(usize, u64)>) -> io::Result<Size> {
+    let fixed = |size: u64| {
+        if size > buffer.len() as u64 {
+            Size::AtLeast(size as usize)
+        } else {
+            Size::Known(size as usize)
+        }
+    };
+    let more = Size::AtLeast(buffer.len() + 1);
+
+    let width = match buffer[0] {
+        0xfe | 0xef => return Ok(Size::Known(1)),
+        0x00 => return Ok(fixed(2)),
+        0x02 => return Ok(fixed(9)),
+        0x03 => return Ok(fixed(17)),
+        0x04 => return Ok(fixed(25)),
+        0x05 | 0x06 => return Ok(fixed(33)),
+        0xee => return Ok(fixed(5)),
+        0x01 => return Ok(match try!(scan_uint(buffer, 1)) {
+            Some((_, end)) => Size::Known(end),
+            None => more
+        }),
+        0x81 => 0,
+        0x07 | 0x08 | 0x80 => 1,
+        0x82 => 8,
+        0x83 => 16,
+        0x84 => 24,
+        0x85 | 0x86 => 32,
+        _ => return invalid_token()
+    };
+
+    if width > 0 {
+        return Ok(match try!(scan_uint(buffer, 1)) {
+            Some((length, end)) => match length.checked_mul(width).and_then(|n| n.checked_add(end as u64)) {
+                Some(size) => fixed(size),
+                None => return invalid_token()
+            },
+            None => more
+        });
+    }
+
+    let (mut at, mut left) = match *ints {
+        Some(progress) => progress,
+        None => match try!(scan_uint(buffer, 1)) {
+            Some((length, end)) => (end, length),
+            None => return Ok(more)
+        }
+    };
+
+    while left > 0 {
+        match try!(scan_uint(buffer, at)) {
+            Some((_, end)) => {
+                at = end;
+                left -= 1;
+            },
+            None => {
+                *ints = Some((at, left));
+                return Ok(Size::AtLeast(buffer.len() + 1));
+            }
+        }
+    }
+
+    *ints = None;
+    Ok(Size::Known(at))
+}
+
+impl BinaryDecoder {
+    pub fn new() -> BinaryDecoder {
+        BinaryDecoder {
+            buffer: Vec::new(),
+            needed: 1,
+            ints: None
+        }
+    }
+
+    // Tokens are only read once they're complete, so bytes are scanned once
+    // however they're split, and any error from the reader is a real one
+    pub fn feed(&mut self, data: &[u8]) -> io::Result<Vec<Token>> {
+        self.buffer.extend_from_slice(data);
+
+        let mut tokens = Vec::new();
+        let mut start = 0;
+
+        while self.buffer.len() - start >= self.needed {
+            match try!(token_size(&self.buffer[start..], &mut self.ints)) {
+                Size::AtLeast(needed) => {
+                    self.needed = needed;
+                    break;
+                },
+                Size::Known(length) => {
+                    let mut reader = BinaryReader::new(&self.buffer[start..start + length]);
+                    tokens.push(try!(reader.read_next()));
+                    start += length;
+                    self.needed = 1;
+                }
+            }
+        }
+
+        self.buffer.drain(..start);
+        Ok(tokens)
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -648,4 +835,112 @@ mod tests {
         assert_eq!(reader.expect_blob().unwrap(), vec![0x48, 0x65, 0x6c, 0x6c, 0x6f].into_boxed_slice());
         assert!(reader.expect_blob().is_err());
     }
+
+    #[test]
+    fn decode_chunks() {
+        let data = include_bytes!("../bin/cat.model");
+
+        let mut expected = Vec::new();
+        let mut reader = setup(data.to_vec());
+        loop {
+            match reader.read_next().unwrap() {
+                Token::EndOfFile => break,
+                token => expected.push(token)
+            }
+        }
+
+        for &size in [1, 2, 3, 7, 64, data.len()].iter() {
+            let mut decoder = BinaryDecoder::new();
+            let mut tokens = Vec::new();
+            for chunk in data.chunks(size) {
+                tokens.extend(decoder.feed(chunk).unwrap());
+            }
+
+            assert_eq!(tokens, expected);
+        }
+    }
+
+    #[test]
+    fn into_inner() {
+        let mut reader = setup(vec![0xfe, 0xef, 1, 2, 3]);
+        assert!(is_token(reader.read_next(), Token::Start));
+        assert!(is_token(reader.read_next(), Token::End));
+
+        // The rest of the input has been buffered, but isn't lost
+        let mut rest = Vec::new();
+        reader.into_inner().read_to_end(&mut rest).unwrap();
+        assert_eq!(rest, vec![1, 2, 3]);
+    }
+
+    #[test]
+    fn decode_partial() {
+        let mut decoder = BinaryDecoder::new();
+
+        assert_eq!(decoder.feed(&[0xfe, 0xee, 0x53, 0x48]).unwrap(), vec![Token::Start]);
+        assert_eq!(decoder.feed(&[0x41]).unwrap(), vec![]);
+        assert_eq!(decoder.feed(&[0x50, 0x07, 0x05, 0x48]).unwrap(), vec![
+            Token::Value(Value::Tag(tag!(S H A P)))
+        ]);
+        assert_eq!(decoder.feed(&[0x65, 0x6c, 0x6c, 0x6f, 0xef]).unwrap(), vec![
+            Token::Value(Value::String("Hello".to_string().into_boxed_str())),
+            Token::End
+        ]);
+        assert!(decoder.feed(&[0x42]).is_err());
+
+        // An int array is scanned once however it's split, and a bad value
+        // inside one is an error rather than more bytes to wait for
+        let mut decoder = BinaryDecoder::new();
+        assert_eq!(decoder.feed(&[0x81, 0x03, 0x02]).unwrap(), vec![]);
+        assert_eq!(decoder.feed(&[0x04]).unwrap(), vec![]);
+        assert_eq!(decoder.feed(&[0x06]).unwrap(), vec![
+            Token::Value(Value::IntArray(vec![1, 2, 3].into_boxed_slice()))
+        ]);
+        assert!(decoder.feed(&[0x81, 0x01, 0xff, 0xff, 0xff, 0xff, 0xff, 0x01]).is_err());
+        assert!(BinaryDecoder::new().feed(&[0x07, 0x02, 0xff, 0xfe]).is_err());
+    }
+
+    struct Trickle<'a> {
+        data: &'a [u8]
+    }
+
+    impl<'a> io::Read for Trickle<'a> {
+        fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+            if self.data.len() == 0 || buf.len() == 0 {
+                return Ok(0);
+            }
+
+            buf[0] = self.data[0];
+            self.data = &self.data[1..];
+            Ok(1)
+        }
+    }
+
+    #[test]
+    fn read_trickle() {
+        let data = include_bytes!("../bin/cat.model");
+
+        let mut reader = setup(data.to_vec());
+        let mut trickle = BinaryReader::new(Trickle { data: data });
+        loop {
+            let token = reader.read_next().unwrap();
+            assert_eq!(trickle.read_next().unwrap(), token);
+
+            if token == Token::EndOfFile {
+                break;
+            }
+        }
+    }
+
+    #[test]
+    fn read_long_uint() {
+        let mut reader = setup(vec![
+            0x01, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0x01
+        ]);
+        assert!(reader.read_next().is_err());
+
+        let mut reader = BinaryReader::new(Trickle { data: &[
+            0x01, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0x01
+        ] });
+        assert!(reader.read_next().is_err());
+    }
 }
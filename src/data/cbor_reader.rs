@@ -0,0 +1,458 @@
+use std::io::{self, Read};
+use byteorder::{LittleEndian, BigEndian, ReadBytesExt};
+use super::{Value, Token, Reader};
+
+pub const TAG_MULTI_DIM: u64 = 40;
+pub const TAG_UINT8: u64 = 64;
+pub const TAG_SINT32_LE: u64 = 78;
+pub const TAG_FLOAT64_LE: u64 = 86;
+pub const TAG_ALICE_TAG: u64 = 0x616c6963;
+
+pub struct CborReader<R> {
+    input: R
+}
+
+fn invalid_token<T>() -> io::Result<T> {
+    Err(io::Error::new(io::ErrorKind::InvalidData, "Invalid token"))
+}
+
+// A sign bit, five exponent bits biased by 15 and ten fraction bits, as in
+// RFC 7049's appendix D
+fn half_to_f64(half: u16) -> f64 {
+    let exponent = ((half >> 10) & 0x1f) as i32;
+    let fraction = (half & 0x3ff) as f64;
+
+    let value = match exponent {
+        0 => fraction * 2f64.powi(-24),
+        31 if fraction == 0.0 => ::std::f64::INFINITY,
+        31 => ::std::f64::NAN,
+        _ => (1.0 + fraction / 1024.0) * 2f64.powi(exponent - 15)
+    };
+
+    if half & 0x8000 != 0 { -value } else { value }
+}
+
+impl<R: Read> CborReader<R> {
+    pub fn new(input: R) -> CborReader<R> {
+        CborReader { input: input }
+    }
+
+    pub fn into_inner(self) -> R {
+        self.input
+    }
+
+    fn read_head(&mut self) -> io::Result<(u8, u64)> {
+        let initial = try!(self.input.read_u8());
+        self.read_argument(initial)
+    }
+
+    fn read_argument(&mut self, initial: u8) -> io::Result<(u8, u64)> {
+        let major = initial >> 5;
+        let value = match initial & 0x1f {
+            n @ 0 ... 23 => n as u64,
+            24 => try!(self.input.read_u8()) as u64,
+            25 => try!(self.input.read_u16::<BigEndian>()) as u64,
+            26 => try!(self.input.read_u32::<BigEndian>()) as u64,
+            27 => try!(self.input.read_u64::<BigEndian>()),
+            _ => return invalid_token()
+        };
+
+        Ok((major, value))
+    }
+
+    fn read_bytes(&mut self, major: u8) -> io::Result<Vec<u8>> {
+        match try!(self.read_head()) {
+            (m, length) if m == major => self.read_length(length),
+            _ => invalid_token()
+        }
+    }
+
+    // Only allocates as much as actually arrives, whatever length the input
+    // claims
+    fn read_length(&mut self, length: u64) -> io::Result<Vec<u8>> {
+        let mut bytes = Vec::new();
+        try!((&mut self.input).take(length).read_to_end(&mut bytes));
+        if (bytes.len() as u64) < length {
+            return Err(io::Error::new(io::ErrorKind::InvalidData, "Unexpected end of input"));
+        }
+
+        Ok(bytes)
+    }
+
+    fn read_uint(&mut self) -> io::Result<u64> {
+        match try!(self.read_head()) {
+            (0, value) => Ok(value),
+            _ => invalid_token()
+        }
+    }
+
+    fn read_doubles(&mut self) -> io::Result<Vec<f64>> {
+        if try!(self.read_head()) != (6, TAG_FLOAT64_LE) {
+            return invalid_token();
+        }
+
+        self.read_float64_array()
+    }
+
+    fn read_float64_array(&mut self) -> io::Result<Vec<f64>> {
+        let bytes = try!(self.read_bytes(2));
+        if bytes.len() % 8 != 0 {
+            return invalid_token();
+        }
+
+        let mut input = &bytes[..];
+        let mut result = Vec::with_capacity(bytes.len() / 8);
+        while input.len() > 0 {
+            result.push(try!(input.read_f64::<LittleEndian>()));
+        }
+
+        Ok(result)
+    }
+
+    fn read_tagged(&mut self, tag: u64) -> io::Result<Value> {
+        match tag {
+            TAG_ALICE_TAG => {
+                let value = try!(self.read_uint());
+                if value > 0xffffffff {
+                    return invalid_token();
+                }
+                Ok(Value::Tag(value as u32))
+            },
+            TAG_UINT8 => {
+                let bytes = try!(self.read_bytes(2));
+                Ok(Value::BoolArray(bytes.iter()
+                    .map(|&b| b != 0)
+                    .collect::<Vec<_>>()
+                    .into_boxed_slice()))
+            },
+            TAG_SINT32_LE => {
+                let bytes = try!(self.read_bytes(2));
+                if bytes.len() % 4 != 0 {
+                    return invalid_token();
+                }
+
+                let mut input = &bytes[..];
+                let mut result = Vec::with_capacity(bytes.len() / 4);
+                while input.len() > 0 {
+                    result.push(try!(input.read_i32::<LittleEndian>()));
+                }
+                Ok(Value::IntArray(result.into_boxed_slice()))
+            },
+            TAG_FLOAT64_LE => self.read_float64_array()
+                .map(|v| Value::DoubleArray(v.into_boxed_slice())),
+            TAG_MULTI_DIM => self.read_multi_dim(),
+            _ => invalid_token()
+        }
+    }
+
+    fn read_multi_dim(&mut self) -> io::Result<Value> {
+        if try!(self.read_head()) != (4, 2) {
+            return invalid_token();
+        }
+
+        let (count, width) = match try!(self.read_head()) {
+            (4, 2) => {
+                let count = try!(self.read_uint()) as usize;
+                (count, try!(self.read_uint()) as usize)
+            },
+            (4, 3) => {
+                let count = try!(self.read_uint()) as usize;
+                if try!(self.read_uint()) != 2 || try!(self.read_uint()) != 2 {
+                    return invalid_token();
+                }
+                (count, 0)
+            },
+            _ => return invalid_token()
+        };
+
+        let v = try!(self.read_doubles());
+        let fits = |n: usize| count.checked_mul(n) == Some(v.len());
+
+        match width {
+            2 if fits(2) => Ok(Value::Vec2Array(v.chunks(2)
+                .map(|v| (v[0], v[1]))
+                .collect::<Vec<_>>()
+                .into_boxed_slice())),
+            3 if fits(3) => Ok(Value::Vec3Array(v.chunks(3)
+                .map(|v| (v[0], v[1], v[2]))
+                .collect::<Vec<_>>()
+                .into_boxed_slice())),
+            4 if fits(4) => Ok(Value::Vec4Array(v.chunks(4)
+                .map(|v| (v[0], v[1], v[2], v[3]))
+                .collect::<Vec<_>>()
+                .into_boxed_slice())),
+            0 if fits(4) => Ok(Value::Box2Array(v.chunks(4)
+                .map(|v| ((v[0], v[1]), (v[2], v[3])))
+                .collect::<Vec<_>>()
+                .into_boxed_slice())),
+            _ => invalid_token()
+        }
+    }
+
+    fn read_double(&mut self) -> io::Result<f64> {
+        let initial = try!(self.input.read_u8());
+        self.read_float(initial)
+    }
+
+    // Half and single precision floats are widened, as other encoders may
+    // write values in the shortest form that holds them
+    fn read_float(&mut self, initial: u8) -> io::Result<f64> {
+        match initial {
+            0xf9 => self.input.read_u16::<BigEndian>().map(half_to_f64),
+            0xfa => self.input.read_f32::<BigEndian>().map(|v| v as f64),
+            0xfb => self.input.read_f64::<BigEndian>(),
+            _ => invalid_token()
+        }
+    }
+
+    fn read_vec2(&mut self) -> io::Result<(f64, f64)> {
+        let x = try!(self.read_double());
+        let y = try!(self.read_double());
+        Ok((x, y))
+    }
+
+    fn read_vec(&mut self, length: u64) -> io::Result<Value> {
+        let initial = try!(self.input.read_u8());
+
+        if length == 2 && initial == 0x82 {
+            let min = try!(self.read_vec2());
+            if try!(self.input.read_u8()) != 0x82 {
+                return invalid_token();
+            }
+            let max = try!(self.read_vec2());
+            return Ok(Value::Box2((min, max)));
+        }
+
+        let x = try!(self.read_float(initial));
+        match length {
+            2 => {
+                let y = try!(self.read_double());
+                Ok(Value::Vec2((x, y)))
+            },
+            3 => {
+                let y = try!(self.read_double());
+                let z = try!(self.read_double());
+                Ok(Value::Vec3((x, y, z)))
+            },
+            _ => {
+                let y = try!(self.read_double());
+                let z = try!(self.read_double());
+                let w = try!(self.read_double());
+                Ok(Value::Vec4((x, y, z, w)))
+            }
+        }
+    }
+}
+
+impl<R: Read> Reader for CborReader<R> {
+    fn read_next(&mut self) -> io::Result<Token> {
+        let mut buffer = [0; 1];
+        let read = try!(self.input.read(&mut buffer));
+
+        if read == 0 {
+            return Ok(Token::EndOfFile);
+        }
+
+        match buffer[0] {
+            0x9f => Ok(Token::Start),
+            0xff => Ok(Token::End),
+            0xf4 => Ok(Token::Value(Value::Bool(false))),
+            0xf5 => Ok(Token::Value(Value::Bool(true))),
+            0xf9 ... 0xfb => self.read_float(buffer[0])
+                .map(|v| Token::Value(Value::Double(v))),
+
+            0x00 ... 0x1b => {
+                let (_, value) = try!(self.read_argument(buffer[0]));
+                if value > i32::max_value() as u64 {
+                    return invalid_token();
+                }
+                Ok(Token::Value(Value::Int(value as i32)))
+            },
+            0x20 ... 0x3b => {
+                let (_, value) = try!(self.read_argument(buffer[0]));
+                if value > i32::max_value() as u64 {
+                    return invalid_token();
+                }
+                Ok(Token::Value(Value::Int(!(value as i32))))
+            },
+            0x40 ... 0x5b => {
+                let (_, length) = try!(self.read_argument(buffer[0]));
+                let bytes = try!(self.read_length(length));
+                Ok(Token::Value(Value::Blob(bytes.into_boxed_slice())))
+            },
+            0x60 ... 0x7b => {
+                let (_, length) = try!(self.read_argument(buffer[0]));
+                let bytes = try!(self.read_length(length));
+                String::from_utf8(bytes)
+                    .map(|s| Token::Value(Value::String(s.into_boxed_str())))
+                    .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))
+            },
+            0x82 ... 0x84 => {
+                let (_, length) = try!(self.read_argument(buffer[0]));
+                self.read_vec(length).map(Token::Value)
+            },
+            0xc0 ... 0xdb => {
+                let (_, tag) = try!(self.read_argument(buffer[0]));
+                self.read_tagged(tag).map(Token::Value)
+            },
+
+            _ => invalid_token(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use super::super::{Value, Token, Reader, BinaryReader, BinaryWriter, CborWriter, copy};
+    use std::io::{self, Cursor};
+
+    fn setup(data: Vec<u8>) -> CborReader<Cursor<Vec<u8>>> {
+        CborReader::new(Cursor::new(data))
+    }
+
+    fn is_token(result: io::Result<Token>, expected: Token) -> bool {
+        if let Ok(result) = result {
+            result == expected
+        } else {
+            false
+        }
+    }
+
+    fn is_value(result: io::Result<Token>, expected: Value) -> bool {
+        is_token(result, Token::Value(expected))
+    }
+
+    #[test]
+    fn read_simple() {
+        let mut reader = setup(vec![
+            0x9f, 0xff
+        ]);
+
+        assert!(is_token(reader.read_next(), Token::Start));
+        assert!(is_token(reader.read_next(), Token::End));
+        assert!(is_token(reader.read_next(), Token::EndOfFile));
+    }
+
+    #[test]
+    fn read_simple_values() {
+        let mut reader = setup(vec![
+            0xf4,
+            0xf5,
+
+            0x00,
+            0x18, 0x18,
+            0x19, 0x03, 0xe8,
+            0x20,
+            0x3a, 0x00, 0x04, 0xbb, 0x79,
+
+            0xfb, 0x40, 0xf0, 0x6a, 0xd6, 0x00, 0x00, 0x00, 0x00,
+
+            0x82,
+                0xfb, 0x40, 0xf0, 0x6a, 0xd6, 0x00, 0x00, 0x00, 0x00,
+                0xfb, 0x3f, 0xf0, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
+            0x82,
+                0x82,
+                    0xfb, 0x3f, 0xf0, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
+                    0xfb, 0x40, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
+                0x82,
+                    0xfb, 0x40, 0x08, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
+                    0xfb, 0x40, 0x10, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
+
+            0xda, 0x61, 0x6c, 0x69, 0x63, 0x1a, 0x53, 0x48, 0x41, 0x50,
+        ]);
+
+        assert!(is_value(reader.read_next(), Value::Bool(false)));
+        assert!(is_value(reader.read_next(), Value::Bool(true)));
+        assert!(is_value(reader.read_next(), Value::Int(0)));
+        assert!(is_value(reader.read_next(), Value::Int(24)));
+        assert!(is_value(reader.read_next(), Value::Int(1000)));
+        assert!(is_value(reader.read_next(), Value::Int(-1)));
+        assert!(is_value(reader.read_next(), Value::Int(-310138)));
+        assert!(is_value(reader.read_next(), Value::Double(67245.375)));
+        assert!(is_value(reader.read_next(), Value::Vec2((67245.375, 1.0))));
+        assert!(is_value(reader.read_next(), Value::Box2(((1.0, 2.0), (3.0, 4.0)))));
+        assert!(is_value(reader.read_next(), Value::Tag(tag!(S H A P))));
+        assert!(is_token(reader.read_next(), Token::EndOfFile));
+    }
+
+    #[test]
+    fn read_arrays() {
+        let mut reader = setup(vec![
+            0xd8, 0x40, 0x42, 0x01, 0x00,
+            0xd8, 0x4e, 0x48, 0x01, 0x00, 0x00, 0x00, 0xfe, 0xff, 0xff, 0xff,
+            0xd8, 0x56, 0x40,
+            0xd8, 0x28, 0x82, 0x82, 0x01, 0x02, 0xd8, 0x56, 0x50,
+                0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0xf0, 0x3f,
+                0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x40,
+            0xd8, 0x28, 0x82, 0x83, 0x00, 0x02, 0x02, 0xd8, 0x56, 0x40,
+        ]);
+
+        assert!(is_value(reader.read_next(), Value::BoolArray(vec![true, false].into_boxed_slice())));
+        assert!(is_value(reader.read_next(), Value::IntArray(vec![1, -2].into_boxed_slice())));
+        assert!(is_value(reader.read_next(), Value::DoubleArray(vec![].into_boxed_slice())));
+        assert!(is_value(reader.read_next(), Value::Vec2Array(vec![(1.0, 2.0)].into_boxed_slice())));
+        assert!(is_value(reader.read_next(), Value::Box2Array(vec![].into_boxed_slice())));
+        assert!(is_token(reader.read_next(), Token::EndOfFile));
+    }
+
+    #[test]
+    fn read_short_floats() {
+        let mut reader = setup(vec![
+            0xf9, 0x3c, 0x00,
+            0xf9, 0xc0, 0x00,
+            0xf9, 0x00, 0x01,
+            0xf9, 0x7c, 0x00,
+            0xf9, 0x7e, 0x00,
+            0xfa, 0x3f, 0xc0, 0x00, 0x00,
+            0x82,
+                0xf9, 0x3c, 0x00,
+                0xfa, 0x40, 0x00, 0x00, 0x00,
+        ]);
+
+        assert!(is_value(reader.read_next(), Value::Double(1.0)));
+        assert!(is_value(reader.read_next(), Value::Double(-2.0)));
+        assert!(is_value(reader.read_next(), Value::Double(2f64.powi(-24))));
+        assert!(is_value(reader.read_next(), Value::Double(::std::f64::INFINITY)));
+        match reader.read_next() {
+            Ok(Token::Value(Value::Double(v))) => assert!(v.is_nan()),
+            _ => panic!("expected NaN")
+        }
+        assert!(is_value(reader.read_next(), Value::Double(1.5)));
+        assert!(is_value(reader.read_next(), Value::Vec2((1.0, 2.0))));
+        assert!(is_token(reader.read_next(), Token::EndOfFile));
+    }
+
+    #[test]
+    fn read_invalid() {
+        assert!(setup(vec![0x1b, 0x00, 0x00, 0x00, 0x01, 0x00, 0x00, 0x00, 0x00]).read_next().is_err());
+        assert!(setup(vec![0x80]).read_next().is_err());
+        assert!(setup(vec![0x82, 0x01, 0x02]).read_next().is_err());
+        assert!(setup(vec![0xd8, 0x56, 0x43, 0x00, 0x00, 0x00]).read_next().is_err());
+        assert!(setup(vec![0xd8, 0x28, 0x82, 0x82, 0x01, 0x05, 0xd8, 0x56, 0x40]).read_next().is_err());
+
+        // Lengths far beyond the input aren't allocated up front
+        assert!(setup(vec![0x5b, 0x00, 0x00, 0x7f, 0xff, 0xff, 0xff, 0xff, 0xff]).read_next().is_err());
+        assert!(setup(vec![0x7b, 0x00, 0x00, 0x7f, 0xff, 0xff, 0xff, 0xff, 0xff]).read_next().is_err());
+        assert!(setup(vec![0xd8, 0x56, 0x5b, 0x00, 0x00, 0x7f, 0xff, 0xff, 0xff, 0xff, 0xff]).read_next().is_err());
+
+        // As are counts that overflow when multiplied out
+        assert!(setup(vec![0xd8, 0x28, 0x82, 0x82, 0x1b, 0x40, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x04,
+                           0xd8, 0x56, 0x40]).read_next().is_err());
+    }
+
+    #[test]
+    fn round_trip() {
+        let orig = include_bytes!("../bin/cat.model");
+
+        let mut writer = CborWriter::new(Cursor::new(Vec::new()));
+        copy(&mut BinaryReader::new(Cursor::new(&orig[..])), &mut writer).unwrap();
+        let encoded = writer.into_inner().into_inner();
+
+        let mut writer = BinaryWriter::new(Cursor::new(Vec::new()));
+        copy(&mut CborReader::new(Cursor::new(encoded)), &mut writer).unwrap();
+        let copy = writer.into_inner().into_inner();
+
+        assert_eq!(&copy[..], &orig[..]);
+    }
+}
@@ -0,0 +1,286 @@
+use std::io::{self, Write};
+use byteorder::{LittleEndian, BigEndian, WriteBytesExt};
+use super::{Value, Vec2, Vec3, Vec4, Box2, Writer};
+use super::cbor_reader::{TAG_ALICE_TAG, TAG_MULTI_DIM, TAG_UINT8, TAG_SINT32_LE, TAG_FLOAT64_LE};
+
+pub struct CborWriter<W> {
+    output: W
+}
+
+impl<W: Write> CborWriter<W> {
+    pub fn new(output: W) -> CborWriter<W> {
+        CborWriter { output: output }
+    }
+
+    pub fn into_inner(self) -> W {
+        self.output
+    }
+
+    fn write_head(&mut self, major: u8, value: u64) -> io::Result<()> {
+        let major = major << 5;
+
+        if value < 24 {
+            self.output.write_all(&[major | value as u8])
+        } else if value <= 0xff {
+            self.output.write_all(&[major | 24, value as u8])
+        } else if value <= 0xffff {
+            try!(self.output.write_all(&[major | 25]));
+            self.output.write_u16::<BigEndian>(value as u16)
+        } else if value <= 0xffffffff {
+            try!(self.output.write_all(&[major | 26]));
+            self.output.write_u32::<BigEndian>(value as u32)
+        } else {
+            try!(self.output.write_all(&[major | 27]));
+            self.output.write_u64::<BigEndian>(value)
+        }
+    }
+
+    fn write_bool(&mut self, value: bool) -> io::Result<()> {
+        self.output.write_all(&[
+            if value { 0xf5 } else { 0xf4 }
+        ])
+    }
+
+    fn write_int(&mut self, value: i32) -> io::Result<()> {
+        if value < 0 {
+            self.write_head(1, !(value as i64) as u64)
+        } else {
+            self.write_head(0, value as u64)
+        }
+    }
+
+    fn write_double(&mut self, value: f64) -> io::Result<()> {
+        try!(self.output.write_all(&[0xfb]));
+        self.output.write_f64::<BigEndian>(value)
+    }
+
+    fn write_bytes(&mut self, major: u8, value: &[u8]) -> io::Result<()> {
+        try!(self.write_head(major, value.len() as u64));
+        self.output.write_all(value)
+    }
+
+    fn write_doubles(&mut self, dims: &[usize], values: &[f64]) -> io::Result<()> {
+        let mut buffer = Vec::with_capacity(values.len() * 8);
+        for &v in values {
+            try!(buffer.write_f64::<LittleEndian>(v));
+        }
+
+        if dims.len() > 0 {
+            try!(self.write_head(6, TAG_MULTI_DIM));
+            try!(self.write_head(4, 2));
+            try!(self.write_head(4, dims.len() as u64));
+            for &d in dims {
+                try!(self.write_head(0, d as u64));
+            }
+        }
+
+        try!(self.write_head(6, TAG_FLOAT64_LE));
+        self.write_bytes(2, &buffer)
+    }
+
+    fn write_vec2(&mut self, (x, y): Vec2) -> io::Result<()> {
+        try!(self.write_head(4, 2));
+        try!(self.write_double(x));
+        self.write_double(y)
+    }
+
+    fn write_vec3(&mut self, (x, y, z): Vec3) -> io::Result<()> {
+        try!(self.write_head(4, 3));
+        try!(self.write_double(x));
+        try!(self.write_double(y));
+        self.write_double(z)
+    }
+
+    fn write_vec4(&mut self, (x, y, z, w): Vec4) -> io::Result<()> {
+        try!(self.write_head(4, 4));
+        try!(self.write_double(x));
+        try!(self.write_double(y));
+        try!(self.write_double(z));
+        self.write_double(w)
+    }
+
+    fn write_box2(&mut self, (min, max): Box2) -> io::Result<()> {
+        try!(self.write_head(4, 2));
+        try!(self.write_vec2(min));
+        self.write_vec2(max)
+    }
+
+    fn write_bool_array(&mut self, values: &Box<[bool]>) -> io::Result<()> {
+        let buffer = values.iter()
+            .map(|&v| if v { 1 } else { 0 })
+            .collect::<Vec<u8>>();
+
+        try!(self.write_head(6, TAG_UINT8));
+        self.write_bytes(2, &buffer)
+    }
+
+    fn write_int_array(&mut self, values: &Box<[i32]>) -> io::Result<()> {
+        let mut buffer = Vec::with_capacity(values.len() * 4);
+        for &v in values.iter() {
+            try!(buffer.write_i32::<LittleEndian>(v));
+        }
+
+        try!(self.write_head(6, TAG_SINT32_LE));
+        self.write_bytes(2, &buffer)
+    }
+
+    fn write_double_array(&mut self, values: &Box<[f64]>) -> io::Result<()> {
+        self.write_doubles(&[], values)
+    }
+
+    fn write_vec2_array(&mut self, values: &Box<[Vec2]>) -> io::Result<()> {
+        let flat = values.iter()
+            .flat_map(|&(x, y)| vec![x, y])
+            .collect::<Vec<_>>();
+        self.write_doubles(&[values.len(), 2], &flat)
+    }
+
+    fn write_vec3_array(&mut self, values: &Box<[Vec3]>) -> io::Result<()> {
+        let flat = values.iter()
+            .flat_map(|&(x, y, z)| vec![x, y, z])
+            .collect::<Vec<_>>();
+        self.write_doubles(&[values.len(), 3], &flat)
+    }
+
+    fn write_vec4_array(&mut self, values: &Box<[Vec4]>) -> io::Result<()> {
+        let flat = values.iter()
+            .flat_map(|&(x, y, z, w)| vec![x, y, z, w])
+            .collect::<Vec<_>>();
+        self.write_doubles(&[values.len(), 4], &flat)
+    }
+
+    fn write_box2_array(&mut self, values: &Box<[Box2]>) -> io::Result<()> {
+        let flat = values.iter()
+            .flat_map(|&((x, y), (z, w))| vec![x, y, z, w])
+            .collect::<Vec<_>>();
+        self.write_doubles(&[values.len(), 2, 2], &flat)
+    }
+}
+
+impl<W: Write> Writer for CborWriter<W> {
+    fn write_start(&mut self) -> io::Result<()> {
+        self.output.write_all(&[0x9f])
+    }
+
+    fn write_end(&mut self) -> io::Result<()> {
+        self.output.write_all(&[0xff])
+    }
+
+    fn write_value(&mut self, value: &Value) -> io::Result<()> {
+        match value {
+            &Value::Bool(value) => self.write_bool(value),
+            &Value::Int(value) => self.write_int(value),
+            &Value::Double(value) => self.write_double(value),
+            &Value::Vec2(value) => self.write_vec2(value),
+            &Value::Vec3(value) => self.write_vec3(value),
+            &Value::Vec4(value) => self.write_vec4(value),
+            &Value::Box2(value) => self.write_box2(value),
+            &Value::String(ref value) => self.write_bytes(3, value.as_bytes()),
+            &Value::Blob(ref value) => self.write_bytes(2, value),
+            &Value::Tag(value) => {
+                try!(self.write_head(6, TAG_ALICE_TAG));
+                self.write_head(0, value as u64)
+            },
+
+            &Value::BoolArray(ref values) => self.write_bool_array(values),
+            &Value::IntArray(ref values) => self.write_int_array(values),
+            &Value::DoubleArray(ref values) => self.write_double_array(values),
+            &Value::Vec2Array(ref values) => self.write_vec2_array(values),
+            &Value::Vec3Array(ref values) => self.write_vec3_array(values),
+            &Value::Vec4Array(ref values) => self.write_vec4_array(values),
+            &Value::Box2Array(ref values) => self.write_box2_array(values),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use super::super::{Value, Writer};
+    use std::io::Cursor;
+
+    fn setup() -> CborWriter<Cursor<Vec<u8>>> {
+        CborWriter::new(Cursor::new(Vec::new()))
+    }
+
+    #[test]
+    fn write_simple() {
+        let mut writer = setup();
+
+        writer.write_start().unwrap();
+        writer.write_end().unwrap();
+
+        assert_eq!(writer.output.into_inner(), vec![
+            0x9f, 0xff
+        ]);
+    }
+
+    #[test]
+    fn write_simple_values() {
+        let mut writer = setup();
+
+        writer.write_value(&Value::Bool(false)).unwrap();
+        writer.write_value(&Value::Bool(true)).unwrap();
+        writer.write_value(&Value::Int(0)).unwrap();
+        writer.write_value(&Value::Int(23)).unwrap();
+        writer.write_value(&Value::Int(24)).unwrap();
+        writer.write_value(&Value::Int(1000)).unwrap();
+        writer.write_value(&Value::Int(-1)).unwrap();
+        writer.write_value(&Value::Int(-310138)).unwrap();
+        writer.write_value(&Value::Double(67245.375)).unwrap();
+        writer.write_value(&Value::Vec2((67245.375, 1.0))).unwrap();
+        writer.write_value(&Value::Tag(tag!(S H A P))).unwrap();
+
+        assert_eq!(writer.output.into_inner(), vec![
+            0xf4,
+            0xf5,
+
+            0x00,
+            0x17,
+            0x18, 0x18,
+            0x19, 0x03, 0xe8,
+            0x20,
+            0x3a, 0x00, 0x04, 0xbb, 0x79,
+
+            0xfb, 0x40, 0xf0, 0x6a, 0xd6, 0x00, 0x00, 0x00, 0x00,
+
+            0x82,
+                0xfb, 0x40, 0xf0, 0x6a, 0xd6, 0x00, 0x00, 0x00, 0x00,
+                0xfb, 0x3f, 0xf0, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
+
+            0xda, 0x61, 0x6c, 0x69, 0x63, 0x1a, 0x53, 0x48, 0x41, 0x50,
+        ]);
+    }
+
+    #[test]
+    fn write_string_and_blob() {
+        let mut writer = setup();
+
+        writer.write_value(&Value::String("Hello".to_string().into_boxed_str())).unwrap();
+        writer.write_value(&Value::Blob(vec![0x48, 0x65].into_boxed_slice())).unwrap();
+
+        assert_eq!(writer.output.into_inner(), vec![
+            0x65, 0x48, 0x65, 0x6c, 0x6c, 0x6f,
+            0x42, 0x48, 0x65,
+        ]);
+    }
+
+    #[test]
+    fn write_arrays() {
+        let mut writer = setup();
+
+        writer.write_value(&Value::BoolArray(vec![true, false].into_boxed_slice())).unwrap();
+        writer.write_value(&Value::IntArray(vec![1, -2].into_boxed_slice())).unwrap();
+        writer.write_value(&Value::DoubleArray(vec![].into_boxed_slice())).unwrap();
+        writer.write_value(&Value::Vec2Array(vec![].into_boxed_slice())).unwrap();
+        writer.write_value(&Value::Box2Array(vec![].into_boxed_slice())).unwrap();
+
+        assert_eq!(writer.output.into_inner(), vec![
+            0xd8, 0x40, 0x42, 0x01, 0x00,
+            0xd8, 0x4e, 0x48, 0x01, 0x00, 0x00, 0x00, 0xfe, 0xff, 0xff, 0xff,
+            0xd8, 0x56, 0x40,
+            0xd8, 0x28, 0x82, 0x82, 0x00, 0x02, 0xd8, 0x56, 0x40,
+            0xd8, 0x28, 0x82, 0x83, 0x00, 0x02, 0x02, 0xd8, 0x56, 0x40,
+        ]);
+    }
+}
@@ -0,0 +1,603 @@
+use std::io::{self, Read};
+use std::char;
+use super::{Value, Tag, Vec2, Vec3, Vec4, Box2, Token, Reader};
+
+#[derive(Debug)]
+enum Json {
+    Null,
+    Bool(bool),
+    Number(String),
+    String(String),
+    Array(Vec<Json>),
+    Object(Vec<(String, Json)>),
+}
+
+fn from_hex(b: u8) -> Option<u8> {
+    match b {
+        b'0' ... b'9' => Some(b - b'0'),
+        b'a' ... b'f' => Some(b - b'a' + 10),
+        b'A' ... b'F' => Some(b - b'A' + 10),
+        _ => None
+    }
+}
+
+pub struct JsonReader<R> {
+    input: R,
+    line: i32,
+    pos: i32,
+    last: Option<u8>,
+    depth: i32,
+    first: bool,
+}
+
+impl<R: Read> JsonReader<R> {
+    pub fn new(input: R) -> JsonReader<R> {
+        JsonReader {
+            input: input,
+            line: 1,
+            pos: 0,
+            last: None,
+            depth: 0,
+            first: true,
+        }
+    }
+
+    pub fn into_inner(self) -> R {
+        self.input
+    }
+
+    fn invalid_token<T>(&self) -> io::Result<T> {
+        let msg = format!("Invalid token at {}:{}", self.line, self.pos);
+        Err(io::Error::new(io::ErrorKind::InvalidData, msg))
+    }
+
+    fn next_byte(&mut self) -> io::Result<Option<u8>> {
+        if self.last == None {
+            let mut buffer = [0; 1];
+            let n = try!(self.input.read(&mut buffer));
+
+            self.last = if n == 0 {
+                None
+            } else {
+                if buffer[0] == b'\n' {
+                    self.line += 1;
+                    self.pos = 0;
+                } else {
+                    self.pos += 1;
+                }
+                Some(buffer[0])
+            }
+        }
+
+        Ok(self.last)
+    }
+
+    fn consume(&mut self) {
+        self.last = None
+    }
+
+    fn skip_whitespace(&mut self) -> io::Result<Option<u8>> {
+        while let Some(b) = try!(self.next_byte()) {
+            match b {
+                b' ' | b'\t' | b'\n' | b'\r' => self.consume(),
+                _ => return Ok(Some(b))
+            }
+        }
+
+        Ok(None)
+    }
+
+    fn expect_byte(&mut self, expected: u8) -> io::Result<()> {
+        if try!(self.skip_whitespace()) == Some(expected) {
+            self.consume();
+            Ok(())
+        } else {
+            self.invalid_token()
+        }
+    }
+
+    fn parse_json(&mut self) -> io::Result<Json> {
+        match try!(self.skip_whitespace()) {
+            Some(b'{') => {
+                self.consume();
+                self.parse_object()
+            },
+            Some(b'[') => {
+                self.consume();
+                self.parse_array()
+            },
+            Some(b'"') => {
+                self.consume();
+                self.parse_string().map(Json::String)
+            },
+            Some(b't') => self.parse_literal(b"true", Json::Bool(true)),
+            Some(b'f') => self.parse_literal(b"false", Json::Bool(false)),
+            Some(b'n') => self.parse_literal(b"null", Json::Null),
+            Some(b'-') | Some(b'0' ... b'9') => self.parse_number(),
+            _ => self.invalid_token()
+        }
+    }
+
+    fn parse_literal(&mut self, expected: &[u8], result: Json) -> io::Result<Json> {
+        for &b in expected {
+            if try!(self.next_byte()) != Some(b) {
+                return self.invalid_token();
+            }
+            self.consume();
+        }
+
+        Ok(result)
+    }
+
+    fn parse_number(&mut self) -> io::Result<Json> {
+        let mut buffer = String::new();
+
+        while let Some(b) = try!(self.next_byte()) {
+            match b {
+                b'0' ... b'9' | b'-' | b'+' | b'.' | b'e' | b'E' => {
+                    buffer.push(b as char);
+                    self.consume();
+                },
+                _ => break
+            }
+        }
+
+        Ok(Json::Number(buffer))
+    }
+
+    fn parse_string(&mut self) -> io::Result<String> {
+        let mut buffer = Vec::new();
+
+        loop {
+            let b = match try!(self.next_byte()) {
+                Some(b) => b,
+                None => return self.invalid_token()
+            };
+            self.consume();
+
+            match b {
+                b'"' => break,
+                b'\\' => {
+                    let c = try!(self.parse_escape());
+                    let mut utf8 = String::new();
+                    utf8.push(c);
+                    buffer.extend(utf8.bytes());
+                },
+                _ => buffer.push(b)
+            }
+        }
+
+        match String::from_utf8(buffer) {
+            Ok(s) => Ok(s),
+            Err(_) => self.invalid_token()
+        }
+    }
+
+    fn parse_escape(&mut self) -> io::Result<char> {
+        let b = match try!(self.next_byte()) {
+            Some(b) => b,
+            None => return self.invalid_token()
+        };
+        self.consume();
+
+        match b {
+            b'"' => Ok('"'),
+            b'\\' => Ok('\\'),
+            b'/' => Ok('/'),
+            b'b' => Ok('\u{8}'),
+            b'f' => Ok('\u{c}'),
+            b'n' => Ok('\n'),
+            b'r' => Ok('\r'),
+            b't' => Ok('\t'),
+            b'u' => {
+                let high = try!(self.parse_hex4());
+                if high < 0xd800 || high > 0xdbff {
+                    return match char::from_u32(high) {
+                        Some(c) => Ok(c),
+                        None => self.invalid_token()
+                    };
+                }
+
+                try!(self.parse_literal(b"\\u", Json::Null));
+                let low = try!(self.parse_hex4());
+                if low < 0xdc00 || low > 0xdfff {
+                    return self.invalid_token();
+                }
+
+                match char::from_u32(0x10000 + ((high - 0xd800) << 10) + (low - 0xdc00)) {
+                    Some(c) => Ok(c),
+                    None => self.invalid_token()
+                }
+            },
+            _ => self.invalid_token()
+        }
+    }
+
+    fn parse_hex4(&mut self) -> io::Result<u32> {
+        let mut result = 0;
+        for _ in 0..4 {
+            match try!(self.next_byte()).and_then(from_hex) {
+                Some(v) => result = result << 4 | v as u32,
+                None => return self.invalid_token()
+            }
+            self.consume();
+        }
+
+        Ok(result)
+    }
+
+    fn parse_array(&mut self) -> io::Result<Json> {
+        let mut values = Vec::new();
+
+        if try!(self.skip_whitespace()) == Some(b']') {
+            self.consume();
+            return Ok(Json::Array(values));
+        }
+
+        loop {
+            values.push(try!(self.parse_json()));
+
+            match try!(self.skip_whitespace()) {
+                Some(b',') => self.consume(),
+                Some(b']') => {
+                    self.consume();
+                    return Ok(Json::Array(values));
+                },
+                _ => return self.invalid_token()
+            }
+        }
+    }
+
+    fn parse_object(&mut self) -> io::Result<Json> {
+        let mut fields = Vec::new();
+
+        if try!(self.skip_whitespace()) == Some(b'}') {
+            self.consume();
+            return Ok(Json::Object(fields));
+        }
+
+        loop {
+            try!(self.expect_byte(b'"'));
+            let key = try!(self.parse_string());
+            try!(self.expect_byte(b':'));
+            let value = try!(self.parse_json());
+            fields.push((key, value));
+
+            match try!(self.skip_whitespace()) {
+                Some(b',') => self.consume(),
+                Some(b'}') => {
+                    self.consume();
+                    return Ok(Json::Object(fields));
+                },
+                _ => return self.invalid_token()
+            }
+        }
+    }
+
+    fn read_value(&mut self) -> io::Result<Value> {
+        let mut fields = match try!(self.parse_object()) {
+            Json::Object(fields) => fields,
+            _ => return self.invalid_token()
+        };
+
+        if fields.len() != 1 {
+            return self.invalid_token();
+        }
+
+        let (key, json) = fields.pop().unwrap();
+        let value = match &key[..] {
+            "tag" => to_tag(&json).map(Value::Tag),
+            "bool" => to_bool(&json).map(Value::Bool),
+            "int" => to_int(&json).map(Value::Int),
+            "double" => to_double(&json).map(Value::Double),
+            "vec2" => to_vec2(&json).map(Value::Vec2),
+            "vec3" => to_vec3(&json).map(Value::Vec3),
+            "vec4" => to_vec4(&json).map(Value::Vec4),
+            "box2" => to_box2(&json).map(Value::Box2),
+            "string" => to_string(&json).map(Value::String),
+            "blob" => to_blob(&json).map(Value::Blob),
+
+            "bool_array" => to_array(&json, to_bool).map(Value::BoolArray),
+            "int_array" => to_array(&json, to_int).map(Value::IntArray),
+            "double_array" => to_array(&json, to_double).map(Value::DoubleArray),
+            "vec2_array" => to_array(&json, to_vec2).map(Value::Vec2Array),
+            "vec3_array" => to_array(&json, to_vec3).map(Value::Vec3Array),
+            "vec4_array" => to_array(&json, to_vec4).map(Value::Vec4Array),
+            "box2_array" => to_array(&json, to_box2).map(Value::Box2Array),
+            _ => None
+        };
+
+        match value {
+            Some(value) => Ok(value),
+            None => self.invalid_token()
+        }
+    }
+}
+
+fn to_tag(json: &Json) -> Option<Tag> {
+    if let &Json::String(ref s) = json {
+        let mut tag = 0;
+        let mut count = 0;
+        for c in s.chars() {
+            if c as u32 > 0xff || count == 4 {
+                return None;
+            }
+            tag = tag << 8 | c as u32;
+            count += 1;
+        }
+
+        if count == 4 { Some(tag) } else { None }
+    } else {
+        None
+    }
+}
+
+fn to_bool(json: &Json) -> Option<bool> {
+    match json {
+        &Json::Bool(v) => Some(v),
+        _ => None
+    }
+}
+
+fn to_int(json: &Json) -> Option<i32> {
+    match json {
+        &Json::Number(ref v) => v.parse().ok(),
+        _ => None
+    }
+}
+
+fn to_double(json: &Json) -> Option<f64> {
+    match json {
+        &Json::Number(ref v) => v.parse().ok(),
+        &Json::String(ref v) => match &v[..] {
+            "NaN" => Some(::std::f64::NAN),
+            "Infinity" => Some(::std::f64::INFINITY),
+            "-Infinity" => Some(::std::f64::NEG_INFINITY),
+            _ => None
+        },
+        _ => None
+    }
+}
+
+fn to_doubles(json: &Json, n: usize) -> Option<Vec<f64>> {
+    match json {
+        &Json::Array(ref values) if values.len() == n =>
+            values.iter().map(to_double).collect(),
+        _ => None
+    }
+}
+
+fn to_vec2(json: &Json) -> Option<Vec2> {
+    to_doubles(json, 2).map(|v| (v[0], v[1]))
+}
+
+fn to_vec3(json: &Json) -> Option<Vec3> {
+    to_doubles(json, 3).map(|v| (v[0], v[1], v[2]))
+}
+
+fn to_vec4(json: &Json) -> Option<Vec4> {
+    to_doubles(json, 4).map(|v| (v[0], v[1], v[2], v[3]))
+}
+
+fn to_box2(json: &Json) -> Option<Box2> {
+    match json {
+        &Json::Array(ref values) if values.len() == 2 =>
+            match (to_vec2(&values[0]), to_vec2(&values[1])) {
+                (Some(min), Some(max)) => Some((min, max)),
+                _ => None
+            },
+        _ => None
+    }
+}
+
+fn to_string(json: &Json) -> Option<Box<str>> {
+    match json {
+        &Json::String(ref v) => Some(v.clone().into_boxed_str()),
+        _ => None
+    }
+}
+
+fn to_blob(json: &Json) -> Option<Box<[u8]>> {
+    if let &Json::String(ref v) = json {
+        let bytes = v.as_bytes();
+        if bytes.len() % 2 != 0 {
+            return None;
+        }
+
+        bytes.chunks(2)
+            .map(|pair| match (from_hex(pair[0]), from_hex(pair[1])) {
+                (Some(top), Some(bottom)) => Some(top << 4 | bottom),
+                _ => None
+            })
+            .collect::<Option<Vec<_>>>()
+            .map(|v| v.into_boxed_slice())
+    } else {
+        None
+    }
+}
+
+fn to_array<T, F>(json: &Json, f: F) -> Option<Box<[T]>>
+    where F: Fn(&Json) -> Option<T> {
+
+    match json {
+        &Json::Array(ref values) =>
+            values.iter()
+                .map(f)
+                .collect::<Option<Vec<_>>>()
+                .map(|v| v.into_boxed_slice()),
+        _ => None
+    }
+}
+
+impl<R: Read> Reader for JsonReader<R> {
+    fn read_next(&mut self) -> io::Result<Token> {
+        let mut b = try!(self.skip_whitespace());
+
+        if self.depth > 0 {
+            if b == Some(b']') {
+                self.consume();
+                self.depth -= 1;
+                self.first = false;
+                return Ok(Token::End);
+            }
+
+            if !self.first {
+                if b != Some(b',') {
+                    return self.invalid_token();
+                }
+                self.consume();
+                b = try!(self.skip_whitespace());
+            }
+        }
+
+        match b {
+            None if self.depth == 0 =>
+                Ok(Token::EndOfFile),
+            Some(b'[') => {
+                self.consume();
+                self.depth += 1;
+                self.first = true;
+                Ok(Token::Start)
+            },
+            Some(b'{') => {
+                self.consume();
+                let value = try!(self.read_value());
+                self.first = false;
+                Ok(Token::Value(value))
+            },
+            _ => self.invalid_token()
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use super::super::{Value, Token, Reader, BinaryReader, BinaryWriter, JsonWriter, copy};
+    use std::io::{self, Cursor};
+
+    fn setup(data: &[u8]) -> JsonReader<Cursor<&[u8]>> {
+        JsonReader::new(Cursor::new(data))
+    }
+
+    fn is_token(result: io::Result<Token>, expected: Token) -> bool {
+        if let Ok(result) = result {
+            result == expected
+        } else {
+            false
+        }
+    }
+
+    fn is_value(result: io::Result<Token>, expected: Value) -> bool {
+        is_token(result, Token::Value(expected))
+    }
+
+    #[test]
+    fn read_simple() {
+        let mut reader = setup(b"[] [[], [ ]]");
+
+        assert!(is_token(reader.read_next(), Token::Start));
+        assert!(is_token(reader.read_next(), Token::End));
+        assert!(is_token(reader.read_next(), Token::Start));
+        assert!(is_token(reader.read_next(), Token::Start));
+        assert!(is_token(reader.read_next(), Token::End));
+        assert!(is_token(reader.read_next(), Token::Start));
+        assert!(is_token(reader.read_next(), Token::End));
+        assert!(is_token(reader.read_next(), Token::End));
+        assert!(is_token(reader.read_next(), Token::EndOfFile));
+    }
+
+    #[test]
+    fn read_simple_values() {
+        let mut reader = setup(br#"
+            [{"bool": false}, {"int": -310138}, {"double": 0.0},
+             {"double": -1.25e123}, {"double": "-Infinity"}, {"double": 1},
+             {"vec2": [67245.375, 3464.85]},
+             {"vec3": [67245.375, 3464.85, -8769.4565]},
+             {"vec4": [67245.375, 3464.85, -8769.4565, -1882.52]},
+             {"box2": [[67245.375, 3464.85], [-8769.4565, -1882.52]]},
+             {"tag": "SHAP"}]"#);
+
+        assert!(is_token(reader.read_next(), Token::Start));
+        assert!(is_value(reader.read_next(), Value::Bool(false)));
+        assert!(is_value(reader.read_next(), Value::Int(-310138)));
+        assert!(is_value(reader.read_next(), Value::Double(0.0)));
+        assert!(is_value(reader.read_next(), Value::Double(-1.25e123)));
+        assert!(is_value(reader.read_next(), Value::Double(::std::f64::NEG_INFINITY)));
+        assert!(is_value(reader.read_next(), Value::Double(1.0)));
+        assert!(is_value(reader.read_next(), Value::Vec2((67245.375, 3464.85))));
+        assert!(is_value(reader.read_next(), Value::Vec3((67245.375, 3464.85, -8769.4565))));
+        assert!(is_value(reader.read_next(), Value::Vec4((67245.375, 3464.85, -8769.4565, -1882.52))));
+        assert!(is_value(reader.read_next(), Value::Box2(((67245.375, 3464.85), (-8769.4565, -1882.52)))));
+        assert!(is_value(reader.read_next(), Value::Tag(tag!(S H A P))));
+        assert!(is_token(reader.read_next(), Token::End));
+        assert!(is_token(reader.read_next(), Token::EndOfFile));
+    }
+
+    #[test]
+    fn read_string_and_blob() {
+        let mut reader = setup(r#"
+            {"string": "Héllø"}
+            {"string": "one \"two\"\n\ud83d\ude00\u00e9"}
+            {"blob": ""}
+            {"blob": "48656C6c6f"}"#.as_bytes());
+
+        assert!(is_value(reader.read_next(), Value::String("Héllø".to_string().into_boxed_str())));
+        assert!(is_value(reader.read_next(), Value::String("one \"two\"\n\u{1f600}é".to_string().into_boxed_str())));
+        assert!(is_value(reader.read_next(), Value::Blob(vec![].into_boxed_slice())));
+        assert!(is_value(reader.read_next(), Value::Blob(vec![0x48, 0x65, 0x6c, 0x6c, 0x6f].into_boxed_slice())));
+        assert!(is_token(reader.read_next(), Token::EndOfFile));
+    }
+
+    #[test]
+    fn read_arrays() {
+        let mut reader = setup(br#"
+            {"bool_array": [true, false, true]}
+            {"int_array": []}
+            {"double_array": [67245.375, 3464.85]}
+            {"vec2_array": [[67245.375, 3464.85], [-8769.4565, -1882.52]]}
+            {"box2_array": [[[1.0, 2.0], [3.0, 4.0]]]}"#);
+
+        assert!(is_value(reader.read_next(), Value::BoolArray(vec![
+            true, false, true
+        ].into_boxed_slice())));
+        assert!(is_value(reader.read_next(), Value::IntArray(vec![].into_boxed_slice())));
+        assert!(is_value(reader.read_next(), Value::DoubleArray(vec![
+            67245.375, 3464.85
+        ].into_boxed_slice())));
+        assert!(is_value(reader.read_next(), Value::Vec2Array(vec![
+            (67245.375, 3464.85),
+            (-8769.4565, -1882.52)
+        ].into_boxed_slice())));
+        assert!(is_value(reader.read_next(), Value::Box2Array(vec![
+            ((1.0, 2.0), (3.0, 4.0))
+        ].into_boxed_slice())));
+        assert!(is_token(reader.read_next(), Token::EndOfFile));
+    }
+
+    #[test]
+    fn read_invalid() {
+        assert!(setup(b"[{\"int\": 1} {\"int\": 2}]").skip_to_end().is_err());
+        assert!(setup(b"{\"int\": 1.5}").read_next().is_err());
+        assert!(setup(b"{\"int\": 1, \"bool\": true}").read_next().is_err());
+        assert!(setup(b"{\"vec2\": [1.0]}").read_next().is_err());
+        assert!(setup(b"{\"tag\": \"SHAPE\"}").read_next().is_err());
+        assert!(setup(b"]").read_next().is_err());
+        assert!(setup(b"[").skip_to_end().is_err());
+    }
+
+    #[test]
+    fn round_trip() {
+        let orig = include_bytes!("../bin/cat.model");
+
+        let mut writer = JsonWriter::new(Cursor::new(Vec::new()));
+        copy(&mut BinaryReader::new(Cursor::new(&orig[..])), &mut writer).unwrap();
+        let encoded = writer.into_inner().into_inner();
+
+        let mut writer = BinaryWriter::new(Cursor::new(Vec::new()));
+        copy(&mut JsonReader::new(Cursor::new(encoded)), &mut writer).unwrap();
+        let copy = writer.into_inner().into_inner();
+
+        assert_eq!(&copy[..], &orig[..]);
+    }
+}
@@ -0,0 +1,311 @@
+use std::io::{self, Write};
+use super::{Value, Tag, Vec2, Vec3, Vec4, Box2, Writer};
+
+pub struct JsonWriter<W> {
+    output: W,
+    first: bool,
+    depth: i32,
+}
+
+impl<W: Write> JsonWriter<W> {
+    pub fn new(output: W) -> JsonWriter<W> {
+        JsonWriter {
+            output: output,
+            first: true,
+            depth: 0,
+        }
+    }
+
+    pub fn into_inner(self) -> W {
+        self.output
+    }
+
+    fn write_separator(&mut self) -> io::Result<()> {
+        if self.first {
+            self.first = false;
+            Ok(())
+        } else {
+            self.output.write_all(b",")
+        }
+    }
+
+    fn write_single_value(&mut self, value: &Value) -> io::Result<()> {
+        match value {
+            &Value::Bool(v) => self.write_field("bool", v, JsonWriter::write_bool),
+            &Value::Int(v) => self.write_field("int", v, JsonWriter::write_int),
+            &Value::Double(v) => self.write_field("double", v, JsonWriter::write_double),
+            &Value::Vec2(v) => self.write_field("vec2", v, JsonWriter::write_vec2),
+            &Value::Vec3(v) => self.write_field("vec3", v, JsonWriter::write_vec3),
+            &Value::Vec4(v) => self.write_field("vec4", v, JsonWriter::write_vec4),
+            &Value::Box2(v) => self.write_field("box2", v, JsonWriter::write_box2),
+            &Value::String(ref v) => self.write_field("string", v, JsonWriter::write_string),
+            &Value::Blob(ref v) => self.write_field("blob", v, JsonWriter::write_blob),
+            &Value::Tag(v) => self.write_field("tag", v, JsonWriter::write_tag),
+
+            &Value::BoolArray(ref v) => self.write_field("bool_array", v, |w, v| w.write_array(v, JsonWriter::write_bool)),
+            &Value::IntArray(ref v) => self.write_field("int_array", v, |w, v| w.write_array(v, JsonWriter::write_int)),
+            &Value::DoubleArray(ref v) => self.write_field("double_array", v, |w, v| w.write_array(v, JsonWriter::write_double)),
+            &Value::Vec2Array(ref v) => self.write_field("vec2_array", v, |w, v| w.write_array(v, JsonWriter::write_vec2)),
+            &Value::Vec3Array(ref v) => self.write_field("vec3_array", v, |w, v| w.write_array(v, JsonWriter::write_vec3)),
+            &Value::Vec4Array(ref v) => self.write_field("vec4_array", v, |w, v| w.write_array(v, JsonWriter::write_vec4)),
+            &Value::Box2Array(ref v) => self.write_field("box2_array", v, |w, v| w.write_array(v, JsonWriter::write_box2)),
+        }
+    }
+
+    fn write_field<T, F>(&mut self, name: &str, value: T, f: F) -> io::Result<()>
+        where F: Fn(&mut Self, T) -> io::Result<()> {
+
+        try!(write!(self.output, "{{\"{}\":", name));
+        try!(f(self, value));
+        self.output.write_all(b"}")
+    }
+
+    fn write_tag(&mut self, value: Tag) -> io::Result<()> {
+        let bytes = [
+            ((0xff000000 & value) >> 24) as u8,
+            ((0x00ff0000 & value) >> 16) as u8,
+            ((0x0000ff00 & value) >> 8) as u8,
+            ((0x000000ff & value) >> 0) as u8,
+        ];
+
+        try!(self.output.write_all(b"\""));
+        for &b in bytes.iter() {
+            try!(self.write_char(b as char));
+        }
+        self.output.write_all(b"\"")
+    }
+
+    fn write_bool(&mut self, value: bool) -> io::Result<()> {
+        self.output.write_all(
+            if value { b"true" } else { b"false" })
+    }
+
+    fn write_int(&mut self, value: i32) -> io::Result<()> {
+        write!(self.output, "{}", value)
+    }
+
+    fn write_double(&mut self, value: f64) -> io::Result<()> {
+        if value.is_nan() {
+            self.output.write_all(b"\"NaN\"")
+        } else if value.is_infinite() {
+            self.output.write_all(
+                if value > 0.0 { b"\"Infinity\"" } else { b"\"-Infinity\"" })
+        } else {
+            write!(self.output, "{:?}", value)
+        }
+    }
+
+    fn write_vec2(&mut self, (x, y): Vec2) -> io::Result<()> {
+        try!(self.output.write_all(b"["));
+        try!(self.write_double(x));
+        try!(self.output.write_all(b","));
+        try!(self.write_double(y));
+        self.output.write_all(b"]")
+    }
+
+    fn write_vec3(&mut self, (x, y, z): Vec3) -> io::Result<()> {
+        try!(self.output.write_all(b"["));
+        try!(self.write_double(x));
+        try!(self.output.write_all(b","));
+        try!(self.write_double(y));
+        try!(self.output.write_all(b","));
+        try!(self.write_double(z));
+        self.output.write_all(b"]")
+    }
+
+    fn write_vec4(&mut self, (x, y, z, w): Vec4) -> io::Result<()> {
+        try!(self.output.write_all(b"["));
+        try!(self.write_double(x));
+        try!(self.output.write_all(b","));
+        try!(self.write_double(y));
+        try!(self.output.write_all(b","));
+        try!(self.write_double(z));
+        try!(self.output.write_all(b","));
+        try!(self.write_double(w));
+        self.output.write_all(b"]")
+    }
+
+    fn write_box2(&mut self, (min, max): Box2) -> io::Result<()> {
+        try!(self.output.write_all(b"["));
+        try!(self.write_vec2(min));
+        try!(self.output.write_all(b","));
+        try!(self.write_vec2(max));
+        self.output.write_all(b"]")
+    }
+
+    fn write_string(&mut self, value: &Box<str>) -> io::Result<()> {
+        try!(self.output.write_all(b"\""));
+        for c in value.chars() {
+            try!(self.write_char(c));
+        }
+        self.output.write_all(b"\"")
+    }
+
+    fn write_char(&mut self, c: char) -> io::Result<()> {
+        match c {
+            '"' => self.output.write_all(b"\\\""),
+            '\\' => self.output.write_all(b"\\\\"),
+            '\n' => self.output.write_all(b"\\n"),
+            '\r' => self.output.write_all(b"\\r"),
+            '\t' => self.output.write_all(b"\\t"),
+            '\u{0}' ... '\u{1f}' | '\u{7f}' =>
+                write!(self.output, "\\u{:04x}", c as u32),
+            _ => write!(self.output, "{}", c)
+        }
+    }
+
+    fn write_blob(&mut self, value: &Box<[u8]>) -> io::Result<()> {
+        try!(self.output.write_all(b"\""));
+        for b in value.iter() {
+            try!(write!(self.output, "{:02x}", b));
+        }
+        self.output.write_all(b"\"")
+    }
+
+    fn write_array<T, F>(&mut self, values: &Box<[T]>, f: F) -> io::Result<()>
+        where F: Fn(&mut Self, T) -> io::Result<()>, T: Copy {
+
+        try!(self.output.write_all(b"["));
+        for (i, &v) in values.iter().enumerate() {
+            if i > 0 {
+                try!(self.output.write_all(b","));
+            }
+            try!(f(self, v));
+        }
+        self.output.write_all(b"]")
+    }
+}
+
+impl<W: Write> Writer for JsonWriter<W> {
+    fn write_start(&mut self) -> io::Result<()> {
+        try!(self.write_separator());
+        try!(self.output.write_all(b"["));
+
+        self.first = true;
+        self.depth += 1;
+
+        Ok(())
+    }
+
+    fn write_end(&mut self) -> io::Result<()> {
+        self.first = false;
+        self.depth -= 1;
+        try!(self.output.write_all(b"]"));
+
+        if self.depth == 0 {
+            try!(self.output.write_all(b"\n"));
+            self.first = true;
+        }
+
+        Ok(())
+    }
+
+    fn write_value(&mut self, value: &Value) -> io::Result<()> {
+        try!(self.write_separator());
+        try!(self.write_single_value(value));
+
+        if self.depth == 0 {
+            try!(self.output.write_all(b"\n"));
+            self.first = true;
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use super::super::{Value, Writer};
+    use std::io::Cursor;
+
+    fn setup() -> JsonWriter<Cursor<Vec<u8>>> {
+        JsonWriter::new(Cursor::new(Vec::new()))
+    }
+
+    fn result(writer: JsonWriter<Cursor<Vec<u8>>>) -> String {
+        String::from_utf8(writer.output.into_inner()).unwrap()
+    }
+
+    #[test]
+    fn write_simple() {
+        let mut writer = setup();
+
+        writer.write_start().unwrap();
+        writer.write_end().unwrap();
+        writer.write_start().unwrap();
+        writer.write_start().unwrap();
+        writer.write_end().unwrap();
+        writer.write_start().unwrap();
+        writer.write_end().unwrap();
+        writer.write_end().unwrap();
+
+        assert_eq!(result(writer), "[]\n[[],[]]\n");
+    }
+
+    #[test]
+    fn write_simple_values() {
+        let mut writer = setup();
+
+        writer.write_start().unwrap();
+        writer.write_value(&Value::Bool(false)).unwrap();
+        writer.write_value(&Value::Int(-310138)).unwrap();
+        writer.write_value(&Value::Double(0.0)).unwrap();
+        writer.write_value(&Value::Double(-1.25e123)).unwrap();
+        writer.write_value(&Value::Double(::std::f64::INFINITY)).unwrap();
+        writer.write_value(&Value::Vec2((67245.375, 3464.85))).unwrap();
+        writer.write_value(&Value::Vec3((0.0, 1.0, 2.0))).unwrap();
+        writer.write_value(&Value::Vec4((0.0, 1.0, 2.0, 3.0))).unwrap();
+        writer.write_value(&Value::Box2(((0.0, 1.0), (2.0, 3.0)))).unwrap();
+        writer.write_value(&Value::Tag(tag!(S H A P))).unwrap();
+        writer.write_end().unwrap();
+
+        assert_eq!(result(writer), "[\
+            {\"bool\":false},\
+            {\"int\":-310138},\
+            {\"double\":0.0},\
+            {\"double\":-1.25e123},\
+            {\"double\":\"Infinity\"},\
+            {\"vec2\":[67245.375,3464.85]},\
+            {\"vec3\":[0.0,1.0,2.0]},\
+            {\"vec4\":[0.0,1.0,2.0,3.0]},\
+            {\"box2\":[[0.0,1.0],[2.0,3.0]]},\
+            {\"tag\":\"SHAP\"}\
+            ]\n");
+    }
+
+    #[test]
+    fn write_string_and_blob() {
+        let mut writer = setup();
+
+        writer.write_value(&Value::String("Héllø".to_string().into_boxed_str())).unwrap();
+        writer.write_value(&Value::String("a\"b\\c\nd\u{1}".to_string().into_boxed_str())).unwrap();
+        writer.write_value(&Value::Blob(vec![0x48, 0x65, 0x0c].into_boxed_slice())).unwrap();
+
+        assert_eq!(result(writer), "\
+            {\"string\":\"Héllø\"}\n\
+            {\"string\":\"a\\\"b\\\\c\\nd\\u0001\"}\n\
+            {\"blob\":\"48650c\"}\n");
+    }
+
+    #[test]
+    fn write_arrays() {
+        let mut writer = setup();
+
+        writer.write_value(&Value::IntArray(vec![].into_boxed_slice())).unwrap();
+        writer.write_value(&Value::BoolArray(vec![true, false].into_boxed_slice())).unwrap();
+        writer.write_value(&Value::Vec2Array(vec![
+            (1.0, 2.0),
+            (3.0, 4.0)
+        ].into_boxed_slice())).unwrap();
+        writer.write_value(&Value::Box2Array(vec![
+            ((1.0, 2.0), (3.0, 4.0))
+        ].into_boxed_slice())).unwrap();
+
+        assert_eq!(result(writer), "\
+            {\"int_array\":[]}\n\
+            {\"bool_array\":[true,false]}\n\
+            {\"vec2_array\":[[1.0,2.0],[3.0,4.0]]}\n\
+            {\"box2_array\":[[[1.0,2.0],[3.0,4.0]]]}\n");
+    }
+}
@@ -31,14 +31,25 @@ pub mod macros;
 pub mod reader;
 mod binary_reader;
 mod text_reader;
+mod json_reader;
+mod cbor_reader;
 
 pub mod writer;
 mod binary_writer;
 mod text_writer;
+mod json_writer;
+mod cbor_writer;
 
-pub use self::reader::{Token, Reader};
-pub use self::binary_reader::BinaryReader;
-pub use self::text_reader::TextReader;
+mod query;
+
+pub use self::reader::{Token, Reader, TokenReader, copy};
+pub use self::binary_reader::{BinaryReader, BinaryDecoder};
+pub use self::text_reader::{TextReader, TextDecoder};
+pub use self::json_reader::JsonReader;
+pub use self::cbor_reader::CborReader;
 pub use self::writer::Writer;
 pub use self::binary_writer::BinaryWriter;
 pub use self::text_writer::TextWriter;
+pub use self::json_writer::JsonWriter;
+pub use self::cbor_writer::CborWriter;
+pub use self::query::Query;
@@ -0,0 +1,455 @@
+use std::io;
+use super::{Value, Tag, Token, Reader};
+
+// Queries select groups from a stream by following a path of group tags,
+// e.g. `SHAP/PTHS/*/COLR`. Each segment is either a four character tag, `*`
+// for any group or `**` for any number of nested groups, and may be followed
+// by an index such as `PTHS[0]` to pick a single matching group. An optional
+// type filter such as `:vec3` or `:string[1]` selects the values of that type
+// within the matched groups rather than the groups themselves.
+
+#[derive(PartialEq, Debug)]
+enum Name {
+    Tag(Tag),
+    Any,
+    Descendants
+}
+
+#[derive(PartialEq, Debug)]
+struct Segment {
+    name: Name,
+    index: Option<usize>
+}
+
+#[derive(PartialEq, Debug)]
+struct Filter {
+    type_name: &'static str,
+    index: Option<usize>
+}
+
+#[derive(PartialEq, Debug)]
+pub struct Query {
+    segments: Vec<Segment>,
+    filter: Option<Filter>
+}
+
+const TYPE_NAMES: &'static [&'static str] = &[
+    "tag", "bool", "int", "double", "vec2", "vec3", "vec4", "box2",
+    "string", "blob", "bool_array", "int_array", "double_array",
+    "vec2_array", "vec3_array", "vec4_array", "box2_array"
+];
+
+fn type_name(value: &Value) -> &'static str {
+    match value {
+        &Value::Tag(_) => "tag",
+        &Value::Bool(_) => "bool",
+        &Value::Int(_) => "int",
+        &Value::Double(_) => "double",
+        &Value::Vec2(_) => "vec2",
+        &Value::Vec3(_) => "vec3",
+        &Value::Vec4(_) => "vec4",
+        &Value::Box2(_) => "box2",
+        &Value::String(_) => "string",
+        &Value::Blob(_) => "blob",
+        &Value::BoolArray(_) => "bool_array",
+        &Value::IntArray(_) => "int_array",
+        &Value::DoubleArray(_) => "double_array",
+        &Value::Vec2Array(_) => "vec2_array",
+        &Value::Vec3Array(_) => "vec3_array",
+        &Value::Vec4Array(_) => "vec4_array",
+        &Value::Box2Array(_) => "box2_array",
+    }
+}
+
+fn invalid_query<T>(query: &str) -> io::Result<T> {
+    let msg = format!("Invalid query \"{}\"", query);
+    Err(io::Error::new(io::ErrorKind::InvalidInput, msg))
+}
+
+fn unexpected<T>(msg: &str) -> io::Result<T> {
+    Err(io::Error::new(io::ErrorKind::InvalidData, msg))
+}
+
+fn split_index(s: &str) -> Option<(&str, Option<usize>)> {
+    if let Some(start) = s.find('[') {
+        if !s.ends_with(']') {
+            return None;
+        }
+
+        s[start + 1..s.len() - 1].parse().ok()
+            .map(|index| (&s[..start], Some(index)))
+    } else {
+        Some((s, None))
+    }
+}
+
+fn parse_segment(s: &str) -> Option<Segment> {
+    let (name, index) = match split_index(s) {
+        Some(v) => v,
+        None => return None
+    };
+
+    let name = match name {
+        "*" => Name::Any,
+        "**" if index.is_none() => Name::Descendants,
+        _ if name.len() == 4 => {
+            let mut tag = 0;
+            for b in name.bytes() {
+                match b {
+                    b'A' ... b'Z' | b'0' ... b'9' | b'_' =>
+                        tag = tag << 8 | b as u32,
+                    _ => return None
+                }
+            }
+            Name::Tag(tag)
+        },
+        _ => return None
+    };
+
+    Some(Segment {
+        name: name,
+        index: index
+    })
+}
+
+fn parse_filter(s: &str) -> Option<Filter> {
+    let (name, index) = match split_index(s) {
+        Some(v) => v,
+        None => return None
+    };
+
+    TYPE_NAMES.iter()
+        .find(|&&n| n == name)
+        .map(|&n| Filter {
+            type_name: n,
+            index: index
+        })
+}
+
+impl Segment {
+    fn matches(&self, tag: Option<Tag>) -> bool {
+        match self.name {
+            Name::Tag(t) => tag == Some(t),
+            Name::Any | Name::Descendants => true
+        }
+    }
+}
+
+impl Query {
+    pub fn parse(query: &str) -> io::Result<Query> {
+        let (path, filter) = match query.find(':') {
+            Some(i) => (&query[..i], Some(&query[i + 1..])),
+            None => (query, None)
+        };
+
+        let mut segments = Vec::new();
+        for s in path.split('/') {
+            match parse_segment(s) {
+                Some(segment) => segments.push(segment),
+                None => return invalid_query(query)
+            }
+        }
+
+        let filter = match filter.map(parse_filter) {
+            Some(Some(filter)) => Some(filter),
+            Some(None) => return invalid_query(query),
+            None => None
+        };
+
+        Ok(Query {
+            segments: segments,
+            filter: filter
+        })
+    }
+
+    // Calls f with the tokens of each match in stream order, and returns the
+    // number of matches. Groups that cannot match are skipped with
+    // skip_to_end, and matched groups are not searched for further matches.
+    pub fn run<F>(&self, reader: &mut Reader, mut f: F) -> io::Result<usize>
+        where F: FnMut(Vec<Token>) -> io::Result<()> {
+
+        let states = self.closure(vec![0]);
+        let mut count = 0;
+        try!(self.search(reader, None, &states, true, &mut f, &mut count));
+
+        Ok(count)
+    }
+
+    fn closure(&self, mut states: Vec<usize>) -> Vec<usize> {
+        let mut i = 0;
+        while i < states.len() {
+            let s = states[i];
+            if s < self.segments.len() &&
+                self.segments[s].name == Name::Descendants &&
+                !states.contains(&(s + 1)) {
+                    states.push(s + 1);
+                }
+            i += 1;
+        }
+
+        states
+    }
+
+    fn search<F>(&self, reader: &mut Reader, first: Option<Token>,
+                 states: &[usize], top: bool, f: &mut F, count: &mut usize)
+                 -> io::Result<()>
+        where F: FnMut(Vec<Token>) -> io::Result<()> {
+
+        let matched = states.contains(&self.segments.len());
+        let mut counters = vec![0; states.len()];
+        let mut values = 0;
+        let mut next = first;
+
+        loop {
+            let token = match next.take() {
+                Some(token) => token,
+                None => try!(reader.read_next())
+            };
+
+            match token {
+                Token::End if top =>
+                    return unexpected("Unexpected end of group"),
+                Token::End =>
+                    return Ok(()),
+                Token::EndOfFile if top =>
+                    return Ok(()),
+                Token::EndOfFile =>
+                    return unexpected("Unexpected end of file"),
+
+                Token::Value(value) => {
+                    if let (true, Some(filter)) = (matched, self.filter.as_ref()) {
+                        if type_name(&value) == filter.type_name {
+                            if filter.index.map_or(true, |i| i == values) {
+                                try!(f(vec![Token::Value(value)]));
+                                *count += 1;
+                            }
+                            values += 1;
+                        }
+                    }
+                },
+
+                Token::Start => {
+                    let first = try!(reader.read_next());
+                    let tag = match first {
+                        Token::Value(Value::Tag(tag)) => Some(tag),
+                        _ => None
+                    };
+
+                    let mut child = Vec::new();
+                    for (i, &s) in states.iter().enumerate() {
+                        if s == self.segments.len() {
+                            continue;
+                        }
+
+                        let segment = &self.segments[s];
+                        if segment.name == Name::Descendants {
+                            child.push(s);
+                        } else if segment.matches(tag) {
+                            if segment.index.map_or(true, |n| n == counters[i]) {
+                                child.push(s + 1);
+                            }
+                            counters[i] += 1;
+                        }
+                    }
+
+                    let child = self.closure(child);
+                    let first = if tag.is_some() { None } else { Some(first) };
+
+                    if child.contains(&self.segments.len()) && self.filter.is_none() {
+                        let mut tokens = vec![Token::Start];
+                        if let Some(tag) = tag {
+                            tokens.push(Token::Value(Value::Tag(tag)));
+                        }
+                        try!(read_group(reader, first, &mut tokens));
+
+                        try!(f(tokens));
+                        *count += 1;
+                    } else if child.is_empty() {
+                        match first {
+                            None | Some(Token::Value(_)) =>
+                                try!(reader.skip_to_end()),
+                            Some(Token::Start) => {
+                                try!(reader.skip_to_end());
+                                try!(reader.skip_to_end());
+                            },
+                            Some(Token::End) => (),
+                            Some(Token::EndOfFile) =>
+                                return unexpected("Unexpected end of file")
+                        }
+                    } else {
+                        try!(self.search(reader, first, &child, false, f, count));
+                    }
+                }
+            }
+        }
+    }
+}
+
+fn read_group(reader: &mut Reader, first: Option<Token>, tokens: &mut Vec<Token>) -> io::Result<()> {
+    let mut levels = 0;
+    let mut next = first;
+
+    loop {
+        let token = match next.take() {
+            Some(token) => token,
+            None => try!(reader.read_next())
+        };
+
+        match token {
+            Token::Start => levels += 1,
+            Token::End if levels == 0 => {
+                tokens.push(token);
+                return Ok(());
+            },
+            Token::End => levels -= 1,
+            Token::EndOfFile => return unexpected("Unexpected end of file"),
+            Token::Value(_) => ()
+        }
+
+        tokens.push(token);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use super::super::{Value, Token, Reader, BinaryReader, TextReader};
+    use std::io::Cursor;
+
+    fn run(query: &str, data: &[u8]) -> Vec<Vec<Token>> {
+        let query = Query::parse(query).unwrap();
+        let mut reader = TextReader::new(Cursor::new(data));
+        let mut matches = Vec::new();
+
+        let count = query.run(&mut reader, |tokens| {
+            matches.push(tokens);
+            Ok(())
+        }).unwrap();
+
+        assert_eq!(count, matches.len());
+        matches
+    }
+
+    fn values(query: &str, data: &[u8]) -> Vec<Value> {
+        run(query, data).into_iter()
+            .map(|mut tokens| {
+                assert_eq!(tokens.len(), 1);
+                match tokens.pop() {
+                    Some(Token::Value(v)) => v,
+                    _ => panic!()
+                }
+            })
+            .collect()
+    }
+
+    #[test]
+    fn parse() {
+        assert_eq!(Query::parse("SHAP/PTHS[1]/*/**:vec3[2]").unwrap(), Query {
+            segments: vec![
+                Segment { name: Name::Tag(tag!(S H A P)), index: None },
+                Segment { name: Name::Tag(tag!(P T H S)), index: Some(1) },
+                Segment { name: Name::Any, index: None },
+                Segment { name: Name::Descendants, index: None },
+            ],
+            filter: Some(Filter { type_name: "vec3", index: Some(2) })
+        });
+
+        assert!(Query::parse("").is_err());
+        assert!(Query::parse("SHAP/").is_err());
+        assert!(Query::parse("SHA").is_err());
+        assert!(Query::parse("shap").is_err());
+        assert!(Query::parse("SHAP[").is_err());
+        assert!(Query::parse("SHAP[-1]").is_err());
+        assert!(Query::parse("**[0]").is_err());
+        assert!(Query::parse("SHAP:float").is_err());
+        assert!(Query::parse("SHAP:int:int").is_err());
+    }
+
+    #[test]
+    fn run_groups() {
+        let data = b"(ABCD 1 (EFGH 2) (EFGH 3 (IJKL 4))) (IJKL 5) (6 (EFGH 7))";
+
+        assert_eq!(run("ABCD/EFGH", data), vec![
+            vec![Token::Start, Token::Value(Value::Tag(tag!(E F G H))),
+                 Token::Value(Value::Int(2)), Token::End],
+            vec![Token::Start, Token::Value(Value::Tag(tag!(E F G H))),
+                 Token::Value(Value::Int(3)),
+                 Token::Start, Token::Value(Value::Tag(tag!(I J K L))),
+                 Token::Value(Value::Int(4)), Token::End,
+                 Token::End],
+        ]);
+        assert_eq!(run("EFGH", data).len(), 0);
+        assert_eq!(run("*/EFGH", data).len(), 3);
+        assert_eq!(run("**/EFGH", data).len(), 3);
+        assert_eq!(run("**/IJKL", data).len(), 2);
+        assert_eq!(run("*", data).len(), 3);
+    }
+
+    #[test]
+    fn run_values() {
+        let data = b"(ABCD 1 (EFGH 2 \"a\") (EFGH 3 \"b\" (IJKL 4 \"c\"))) (6 (EFGH 7 8 [1.0 2.0]))";
+
+        assert_eq!(values("ABCD:int", data), vec![Value::Int(1)]);
+        assert_eq!(values("ABCD/EFGH:int", data), vec![Value::Int(2), Value::Int(3)]);
+        assert_eq!(values("ABCD/EFGH[1]:int", data), vec![Value::Int(3)]);
+        assert_eq!(values("*/EFGH:int[1]", data), vec![Value::Int(8)]);
+        assert_eq!(values("*/EFGH:vec2", data), vec![Value::Vec2((1.0, 2.0))]);
+        assert_eq!(values("**:string", data), vec![
+            Value::String("a".to_string().into_boxed_str()),
+            Value::String("b".to_string().into_boxed_str()),
+            Value::String("c".to_string().into_boxed_str()),
+        ]);
+        assert_eq!(values("*:tag", data), vec![]);
+    }
+
+    #[test]
+    fn run_errors() {
+        let query = Query::parse("**:int").unwrap();
+
+        let mut reader = TextReader::new(Cursor::new(&b"(ABCD (1 2"[..]));
+        assert!(query.run(&mut reader, |_| Ok(())).is_err());
+
+        let mut reader = TextReader::new(Cursor::new(&b"(ABCD 1))"[..]));
+        assert!(query.run(&mut reader, |_| Ok(())).is_err());
+    }
+
+    #[test]
+    fn run_widget() {
+        let data = include_bytes!("../bin/scene.widget");
+
+        assert_eq!(values("**/TEXT:string", data), vec![
+            Value::String("Hello World!".to_string().into_boxed_str())
+        ]);
+        assert_eq!(values("WDGT/DOWN:int", data), vec![Value::Int(1000)]);
+        assert_eq!(values("WDGT/*/WDGT/DOWN:int", data), vec![
+            Value::Int(2000), Value::Int(2001)
+        ]);
+        assert_eq!(values("**/GRUP/*/WDGT[1]/DOWN:int", data), vec![Value::Int(3001)]);
+    }
+
+    #[test]
+    fn run_model() {
+        let data = include_bytes!("../bin/cat.model");
+
+        let mut reader = BinaryReader::new(Cursor::new(&data[..]));
+        let colours = values_from(&mut reader, "SHAP/PTHS/*/COLR:vec3");
+
+        let mut reader = BinaryReader::new(Cursor::new(&data[..]));
+        let paths = values_from(&mut reader, "SHAP/PTHS:int");
+
+        assert!(colours.len() > 0);
+        assert_eq!(paths, vec![Value::Int(colours.len() as i32)]);
+    }
+
+    fn values_from(reader: &mut Reader, query: &str) -> Vec<Value> {
+        let mut values = Vec::new();
+        Query::parse(query).unwrap().run(reader, |mut tokens| {
+            if let Some(Token::Value(v)) = tokens.pop() {
+                values.push(v);
+            }
+            Ok(())
+        }).unwrap();
+
+        values
+    }
+}
@@ -1,5 +1,6 @@
 use std::io::{self};
-use super::{Value, Tag, Vec2, Vec3, Vec4, Box2};
+use std::collections::VecDeque;
+use super::{Value, Tag, Vec2, Vec3, Vec4, Box2, Writer};
 
 #[derive(PartialEq, Debug)]
 pub enum Token {
@@ -202,3 +203,49 @@ pub trait Reader {
         expect_or_end!(self, Blob)
     }
 }
+
+pub struct TokenReader {
+    tokens: VecDeque<Token>
+}
+
+impl TokenReader {
+    pub fn new(tokens: Vec<Token>) -> TokenReader {
+        TokenReader {
+            tokens: tokens.into_iter().collect()
+        }
+    }
+
+    pub fn push(&mut self, token: Token) {
+        self.tokens.push_back(token);
+    }
+
+    pub fn len(&self) -> usize {
+        self.tokens.len()
+    }
+}
+
+impl Reader for TokenReader {
+    fn read_next(&mut self) -> io::Result<Token> {
+        Ok(self.tokens.pop_front().unwrap_or(Token::EndOfFile))
+    }
+}
+
+pub fn copy(reader: &mut Reader, writer: &mut Writer) -> io::Result<()> {
+    let mut levels = 0;
+    loop {
+        match try!(reader.read_next()) {
+            Token::Start => {
+                levels += 1;
+                try!(writer.write_start());
+            },
+            Token::End if levels > 0 => {
+                levels -= 1;
+                try!(writer.write_end());
+            },
+            Token::End => return unexpected("Unexpected end of group"),
+            Token::Value(ref value) => try!(writer.write_value(value)),
+            Token::EndOfFile if levels == 0 => return Ok(()),
+            Token::EndOfFile => return unexpected("Unexpected end of file")
+        }
+    }
+}
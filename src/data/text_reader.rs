@@ -1,4 +1,4 @@
-use std::io::{self, Read};
+use std::io::{self, Read, BufRead, BufReader, Cursor};
 use super::*;
 
 fn from_hex(b: u8) -> u8 {
@@ -11,11 +11,18 @@ fn from_hex(b: u8) -> u8 {
     }
 }
 
+#[derive(Debug)]
+enum ArrayType {
+    Bool, Int, Double,
+    Vec2, Vec3, Vec4, Box2
+}
+
 #[derive(Debug)]
 enum SubToken {
     Start, End,
     VecStart, VecEnd,
     ArrayStart, ArrayEnd,
+    TypedArrayStart(ArrayType),
     Tag(u32),
     Bool(bool),
     Int(i32),
@@ -26,7 +33,7 @@ enum SubToken {
 }
 
 struct SubReader<R> {
-    input: R,
+    input: BufReader<R>,
     line: i32,
     pos: i32,
     last: Option<u8>
@@ -35,7 +42,7 @@ struct SubReader<R> {
 impl<R: Read> SubReader<R> {
     fn new(input: R) -> SubReader<R> {
         SubReader {
-            input: input,
+            input: BufReader::new(input),
             line: 1,
             pos: 0,
             last: None
@@ -49,19 +56,19 @@ impl<R: Read> SubReader<R> {
 
     fn next_byte(&mut self) -> io::Result<Option<u8>> {
         if self.last == None {
-            let mut buffer = [0; 1];
-            let n = try!(self.input.read(&mut buffer));
+            // The byte stays in the buffer until it's consumed
+            let next = try!(self.input.fill_buf()).first().cloned();
 
-            self.last = if n == 0 {
-                None
-            } else {
-                if buffer[0] == b'\n' {
+            self.last = if let Some(b) = next {
+                if b == b'\n' {
                     self.line += 1;
                     self.pos = 0;
                 } else {
                     self.pos += 1;
                 }
-                Some(buffer[0])
+                Some(b)
+            } else {
+                None
             }
         }
 
@@ -69,7 +76,9 @@ impl<R: Read> SubReader<R> {
     }
 
     fn consume(&mut self) {
-        self.last = None
+        if self.last.take().is_some() {
+            self.input.consume(1);
+        }
     }
 
     fn read_next(&mut self) -> io::Result<SubToken> {
@@ -113,6 +122,7 @@ impl<R: Read> SubReader<R> {
 
                 b't' => return self.read_bool(b"true", true),
                 b'f' => return self.read_bool(b"false", false),
+                b'a' ... b'z' => return self.read_word(),
                 b';' => try!(self.read_comment()),
                 _ =>
                     return self.invalid_token()
@@ -165,6 +175,45 @@ impl<R: Read> SubReader<R> {
         self.invalid_token()
     }
 
+    // The type before a typed array, or a double that isn't a number
+    fn read_word(&mut self) -> io::Result<SubToken> {
+        let mut buffer = Vec::new();
+
+        while let Some(b) = try!(self.next_byte()) {
+            match b {
+                b' ' | b'\t' | b'\n' | b'\r' |
+                b')' | b']' | b'}' =>
+                    return match &buffer[..] {
+                        b"nan" => Ok(SubToken::Double(::std::f64::NAN)),
+                        b"inf" => Ok(SubToken::Double(::std::f64::INFINITY)),
+                        _ => self.invalid_token()
+                    },
+
+                b'a' ... b'z' | b'0' ... b'9' if buffer.len() < 6 => {
+                    buffer.push(b);
+                    self.consume();
+                },
+                b'{' => {
+                    self.consume();
+                    let t = match String::from_utf8(buffer).unwrap().as_ref() {
+                        "bool" => ArrayType::Bool,
+                        "int" => ArrayType::Int,
+                        "double" => ArrayType::Double,
+                        "vec2" => ArrayType::Vec2,
+                        "vec3" => ArrayType::Vec3,
+                        "vec4" => ArrayType::Vec4,
+                        "box2" => ArrayType::Box2,
+                        _ => return self.invalid_token()
+                    };
+                    return Ok(SubToken::TypedArrayStart(t));
+                },
+                _ => return self.invalid_token()
+            }
+        }
+
+        self.invalid_token()
+    }
+
     fn read_number(&mut self) -> io::Result<SubToken> {
         let mut buffer = Vec::new();
 
@@ -190,7 +239,12 @@ impl<R: Read> SubReader<R> {
                 b'x' if buffer.len() == 1 && buffer.last() == Some(&b'0') => {
                     self.consume();
                     return self.read_blob();
-                }
+                },
+                b'i' if buffer.len() == 1 && buffer.last() == Some(&b'-') =>
+                    return match try!(self.read_word()) {
+                        SubToken::Double(v) if v.is_infinite() => Ok(SubToken::Double(-v)),
+                        _ => self.invalid_token()
+                    },
                 _ => return self.invalid_token()
             }
         }
@@ -311,7 +365,9 @@ impl<R: Read> TextReader<R> {
         }
     }
 
-    pub fn into_inner(self) -> R {
+    // The input comes back still buffered, as bytes past the last token may
+    // have been read from it already
+    pub fn into_inner(self) -> BufReader<R> {
         self.sub.input
     }
 
@@ -365,22 +421,34 @@ impl<R: Read> TextReader<R> {
 
     fn read_array(&mut self) -> io::Result<Token> {
         match try!(self.sub.read_next()) {
-            SubToken::Bool(v) => return self.read_bool_array(v),
-            SubToken::Int(v) => return self.read_int_array(v),
-            SubToken::Double(v) => return self.read_double_array(v),
+            SubToken::Bool(v) => return self.read_bool_array(vec![v]),
+            SubToken::Int(v) => return self.read_int_array(vec![v]),
+            SubToken::Double(v) => return self.read_double_array(vec![v]),
             SubToken::VecStart => match try!(self.read_vec()) {
-                Token::Value(Value::Vec2(v)) => return self.read_vec2_array(v),
-                Token::Value(Value::Vec3(v)) => return self.read_vec3_array(v),
-                Token::Value(Value::Vec4(v)) => return self.read_vec4_array(v),
-                Token::Value(Value::Box2(v)) => return self.read_box2_array(v),
+                Token::Value(Value::Vec2(v)) => return self.read_vec2_array(vec![v]),
+                Token::Value(Value::Vec3(v)) => return self.read_vec3_array(vec![v]),
+                Token::Value(Value::Vec4(v)) => return self.read_vec4_array(vec![v]),
+                Token::Value(Value::Box2(v)) => return self.read_box2_array(vec![v]),
                 _ => self.invalid_token()
             },
             _ => self.invalid_token()
         }
     }
 
-    fn read_bool_array(&mut self, first: bool) -> io::Result<Token> {
-        let mut values = vec![first];
+    fn read_typed_array(&mut self, t: ArrayType) -> io::Result<Token> {
+        match t {
+            ArrayType::Bool => self.read_bool_array(Vec::new()),
+            ArrayType::Int => self.read_int_array(Vec::new()),
+            ArrayType::Double => self.read_double_array(Vec::new()),
+            ArrayType::Vec2 => self.read_vec2_array(Vec::new()),
+            ArrayType::Vec3 => self.read_vec3_array(Vec::new()),
+            ArrayType::Vec4 => self.read_vec4_array(Vec::new()),
+            ArrayType::Box2 => self.read_box2_array(Vec::new()),
+        }
+    }
+
+    fn read_bool_array(&mut self, values: Vec<bool>) -> io::Result<Token> {
+        let mut values = values;
         loop {
             match try!(self.sub.read_next()) {
                 SubToken::Bool(v) => values.push(v),
@@ -391,8 +459,8 @@ impl<R: Read> TextReader<R> {
         }
     }
 
-    fn read_int_array(&mut self, first: i32) -> io::Result<Token> {
-        let mut values = vec![first];
+    fn read_int_array(&mut self, values: Vec<i32>) -> io::Result<Token> {
+        let mut values = values;
         loop {
             match try!(self.sub.read_next()) {
                 SubToken::Int(v) => values.push(v),
@@ -403,8 +471,8 @@ impl<R: Read> TextReader<R> {
         }
     }
 
-    fn read_double_array(&mut self, first: f64) -> io::Result<Token> {
-        let mut values = vec![first];
+    fn read_double_array(&mut self, values: Vec<f64>) -> io::Result<Token> {
+        let mut values = values;
         loop {
             match try!(self.sub.read_next()) {
                 SubToken::Double(v) => values.push(v),
@@ -415,8 +483,8 @@ impl<R: Read> TextReader<R> {
         }
     }
 
-    fn read_vec2_array(&mut self, first: Vec2) -> io::Result<Token> {
-        let mut values = vec![first];
+    fn read_vec2_array(&mut self, values: Vec<Vec2>) -> io::Result<Token> {
+        let mut values = values;
         loop {
             match try!(self.sub.read_next()) {
                 SubToken::VecStart =>
@@ -432,8 +500,8 @@ impl<R: Read> TextReader<R> {
         }
     }
 
-    fn read_vec3_array(&mut self, first: Vec3) -> io::Result<Token> {
-        let mut values = vec![first];
+    fn read_vec3_array(&mut self, values: Vec<Vec3>) -> io::Result<Token> {
+        let mut values = values;
         loop {
             match try!(self.sub.read_next()) {
                 SubToken::VecStart =>
@@ -449,8 +517,8 @@ impl<R: Read> TextReader<R> {
         }
     }
 
-    fn read_vec4_array(&mut self, first: Vec4) -> io::Result<Token> {
-        let mut values = vec![first];
+    fn read_vec4_array(&mut self, values: Vec<Vec4>) -> io::Result<Token> {
+        let mut values = values;
         loop {
             match try!(self.sub.read_next()) {
                 SubToken::VecStart =>
@@ -466,8 +534,8 @@ impl<R: Read> TextReader<R> {
         }
     }
 
-    fn read_box2_array(&mut self, first: Box2) -> io::Result<Token> {
-        let mut values = vec![first];
+    fn read_box2_array(&mut self, values: Vec<Box2>) -> io::Result<Token> {
+        let mut values = values;
         loop {
             match try!(self.sub.read_next()) {
                 SubToken::VecStart =>
@@ -498,12 +566,146 @@ impl<R: Read> Reader for TextReader<R> {
             SubToken::Blob(v) => return Ok(Token::Value(Value::Blob(v.into_boxed_slice()))),
             SubToken::VecStart => return self.read_vec(),
             SubToken::ArrayStart => return self.read_array(),
+            SubToken::TypedArrayStart(t) => return self.read_typed_array(t),
             SubToken::VecEnd | SubToken::ArrayEnd =>
                 return self.invalid_token()
         }
     }
 }
 
+// What the decoder is in the middle of while looking for the end of a token
+#[derive(Copy, Clone, PartialEq)]
+enum Scan {
+    Space, Comment, Atom, String,
+    // A quote in a string, which ends it unless another follows
+    Quote
+}
+
+// Finds where the next token ends without parsing it, carrying on from where
+// it stopped when it runs out of bytes
+struct Scanner {
+    scanned: usize,
+    depth: usize,
+    scan: Scan
+}
+
+impl Scanner {
+    fn new(start: usize) -> Scanner {
+        Scanner {
+            scanned: start,
+            depth: 0,
+            scan: Scan::Space
+        }
+    }
+
+    // The end of the next token, including the byte after it so the reader
+    // can see where it stops. Invalid bytes end a token early, so that the
+    // reader reports them.
+    fn scan(&mut self, buffer: &[u8]) -> Option<usize> {
+        let atom = |b| match b {
+            b'A' ... b'Z' | b'a' ... b'z' | b'0' ... b'9' | b'_' | b'.' | b'-' => true,
+            _ => false
+        };
+
+        while self.scanned < buffer.len() {
+            let b = buffer[self.scanned];
+            self.scanned += 1;
+
+            match self.scan {
+                Scan::Comment => if b == b'\n' {
+                    self.scan = Scan::Space;
+                },
+                Scan::String => if b == b'"' {
+                    self.scan = Scan::Quote;
+                },
+                Scan::Quote if b == b'"' =>
+                    self.scan = Scan::String,
+                Scan::Atom if atom(b) =>
+                    (),
+                ended => {
+                    self.scan = Scan::Space;
+
+                    match b {
+                        // A word before a brace starts a typed array
+                        b'{' if ended != Scan::Quote =>
+                            self.depth += 1,
+                        _ if ended != Scan::Space && self.depth == 0 =>
+                            return Some(self.scanned),
+
+                        b' ' | b'\t' | b'\n' | b'\r' => (),
+                        b';' => self.scan = Scan::Comment,
+                        b'"' => self.scan = Scan::String,
+                        b'[' => self.depth += 1,
+                        b']' | b'}' if self.depth > 1 => self.depth -= 1,
+                        _ if atom(b) => self.scan = Scan::Atom,
+                        _ => return Some(self.scanned)
+                    }
+                }
+            }
+        }
+
+        None
+    }
+}
+
+pub struct TextDecoder {
+    buffer: Vec<u8>,
+    line: i32,
+    pos: i32,
+    scanner: Scanner
+}
+
+impl TextDecoder {
+    pub fn new() -> TextDecoder {
+        TextDecoder {
+            buffer: Vec::new(),
+            line: 1,
+            pos: 0,
+            scanner: Scanner::new(0)
+        }
+    }
+
+    // Only complete tokens are parsed, so bytes are scanned once however
+    // they're split, and any error from the reader is a real one
+    pub fn feed(&mut self, data: &[u8]) -> io::Result<Vec<Token>> {
+        self.buffer.extend_from_slice(data);
+
+        let mut tokens = Vec::new();
+        let mut start = 0;
+
+        while let Some(end) = self.scanner.scan(&self.buffer) {
+            let consumed = {
+                let mut reader = TextReader::new(Cursor::new(&self.buffer[start..end]));
+                reader.sub.line = self.line;
+                reader.sub.pos = self.pos;
+
+                match try!(reader.read_next()) {
+                    Token::EndOfFile => break,
+                    token => tokens.push(token)
+                }
+
+                reader.sub.input.get_ref().position() as usize - reader.sub.input.buffer().len()
+            };
+
+            for &b in &self.buffer[start..start + consumed] {
+                if b == b'\n' {
+                    self.line += 1;
+                    self.pos = 0;
+                } else {
+                    self.pos += 1;
+                }
+            }
+
+            start += consumed;
+            self.scanner = Scanner::new(start);
+        }
+
+        self.buffer.drain(..start);
+        self.scanner.scanned -= start;
+        Ok(tokens)
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -689,4 +891,113 @@ mod tests {
         assert!(is_token(reader.read_next(), Token::End));
         assert!(is_token(reader.read_next(), Token::EndOfFile));
     }
+
+    #[test]
+    fn read_typed_arrays() {
+        let mut reader = setup(br"
+            bool{} int{ } double{
+            }
+            vec2{} vec3{} vec4{} box2{}
+            int{6 128}
+            vec2{[67245.375 3464.85]}
+            box2{[[67245.375 3464.85] [-8769.4565 -1882.52]]}");
+
+        assert!(is_value(reader.read_next(), Value::BoolArray(vec![].into_boxed_slice())));
+        assert!(is_value(reader.read_next(), Value::IntArray(vec![].into_boxed_slice())));
+        assert!(is_value(reader.read_next(), Value::DoubleArray(vec![].into_boxed_slice())));
+        assert!(is_value(reader.read_next(), Value::Vec2Array(vec![].into_boxed_slice())));
+        assert!(is_value(reader.read_next(), Value::Vec3Array(vec![].into_boxed_slice())));
+        assert!(is_value(reader.read_next(), Value::Vec4Array(vec![].into_boxed_slice())));
+        assert!(is_value(reader.read_next(), Value::Box2Array(vec![].into_boxed_slice())));
+        assert!(is_value(reader.read_next(), Value::IntArray(vec![
+            6, 128
+        ].into_boxed_slice())));
+        assert!(is_value(reader.read_next(), Value::Vec2Array(vec![
+            (67245.375, 3464.85)
+        ].into_boxed_slice())));
+        assert!(is_value(reader.read_next(), Value::Box2Array(vec![
+            ((67245.375, 3464.85), (-8769.4565, -1882.52))
+        ].into_boxed_slice())));
+        assert!(is_token(reader.read_next(), Token::EndOfFile));
+    }
+
+    #[test]
+    fn read_typed_arrays_fail() {
+        assert!(setup(b"{}").read_next().is_err());
+        assert!(setup(b"int {}").read_next().is_err());
+        assert!(setup(b"uint{}").read_next().is_err());
+        assert!(setup(b"int{1.0}").read_next().is_err());
+        assert!(setup(b"vec2{[1.0 2.0 3.0]}").read_next().is_err());
+        assert!(setup(b"box2{[1.0 2.0]}").read_next().is_err());
+    }
+
+    #[test]
+    fn into_inner() {
+        let mut reader = setup(b"(1 2) 3");
+        assert!(is_token(reader.read_next(), Token::Start));
+        assert!(is_value(reader.read_next(), Value::Int(1)));
+
+        // The space after the 1 was looked at, but not used up
+        let mut rest = String::new();
+        reader.into_inner().read_to_string(&mut rest).unwrap();
+        assert_eq!(rest, " 2) 3");
+    }
+
+    #[test]
+    fn decode_chunks() {
+        let data = include_bytes!("../bin/scene.widget");
+
+        let mut expected = Vec::new();
+        let mut reader = setup(data);
+        loop {
+            match reader.read_next().unwrap() {
+                Token::EndOfFile => break,
+                token => expected.push(token)
+            }
+        }
+
+        for &size in [1, 2, 3, 7, 64, data.len()].iter() {
+            let mut decoder = TextDecoder::new();
+            let mut tokens = Vec::new();
+            for chunk in data.chunks(size) {
+                tokens.extend(decoder.feed(chunk).unwrap());
+            }
+
+            assert_eq!(tokens, expected);
+        }
+    }
+
+    #[test]
+    fn decode_partial() {
+        let mut decoder = TextDecoder::new();
+
+        assert_eq!(decoder.feed(b"(SH").unwrap(), vec![Token::Start]);
+        assert_eq!(decoder.feed(b"AP 12").unwrap(), vec![
+            Token::Value(Value::Tag(tag!(S H A P)))
+        ]);
+        assert_eq!(decoder.feed(b"3 ; a comm").unwrap(), vec![
+            Token::Value(Value::Int(123))
+        ]);
+        assert_eq!(decoder.feed(b"ent (with parens)\n [1.0").unwrap(), vec![]);
+        assert_eq!(decoder.feed(b" 2.0])").unwrap(), vec![
+            Token::Value(Value::Vec2((1.0, 2.0))),
+            Token::End
+        ]);
+
+        let error = decoder.feed(b"\n  ?").unwrap_err();
+        assert_eq!(format!("{}", error), "Invalid token at 3:3");
+
+        // Errors inside a token are reported once it's been scanned, not
+        // taken for the end of the data
+        let mut decoder = TextDecoder::new();
+        assert_eq!(decoder.feed(b"int{1 2 3").unwrap(), vec![]);
+        assert!(decoder.feed(b" 2.0}").is_err());
+
+        let mut decoder = TextDecoder::new();
+        assert_eq!(decoder.feed(b"(\"a \"\"b").unwrap(), vec![Token::Start]);
+        assert_eq!(decoder.feed(b"\"\"\" ").unwrap(), vec![
+            Token::Value(Value::String("a \"b\"".to_string().into_boxed_str()))
+        ]);
+        assert!(decoder.feed(b"[1.0 (").is_err());
+    }
 }
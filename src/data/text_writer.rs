@@ -40,13 +40,13 @@ impl<W: Write> TextWriter<W> {
             &Value::Blob(ref v) => self.write_blob(v),
             &Value::Tag(v) => self.write_tag(v),
 
-            &Value::BoolArray(ref v) => self.write_array(v, TextWriter::write_bool),
-            &Value::IntArray(ref v) => self.write_array(v, TextWriter::write_int),
-            &Value::DoubleArray(ref v) => self.write_array(v, TextWriter::write_double),
-            &Value::Vec2Array(ref v) => self.write_array(v, TextWriter::write_vec2),
-            &Value::Vec3Array(ref v) => self.write_array(v, TextWriter::write_vec3),
-            &Value::Vec4Array(ref v) => self.write_array(v, TextWriter::write_vec4),
-            &Value::Box2Array(ref v) => self.write_array(v, TextWriter::write_box2),
+            &Value::BoolArray(ref v) => self.write_array("bool", v, TextWriter::write_bool),
+            &Value::IntArray(ref v) => self.write_array("int", v, TextWriter::write_int),
+            &Value::DoubleArray(ref v) => self.write_array("double", v, TextWriter::write_double),
+            &Value::Vec2Array(ref v) => self.write_array("vec2", v, TextWriter::write_vec2),
+            &Value::Vec3Array(ref v) => self.write_array("vec3", v, TextWriter::write_vec3),
+            &Value::Vec4Array(ref v) => self.write_array("vec4", v, TextWriter::write_vec4),
+            &Value::Box2Array(ref v) => self.write_array("box2", v, TextWriter::write_box2),
         }
     }
 
@@ -69,7 +69,11 @@ impl<W: Write> TextWriter<W> {
     }
 
     fn write_double(&mut self, value: f64) -> io::Result<()> {
-        if value.fract() == 0.0 {
+        if value.is_nan() {
+            self.output.write_all(b"nan")
+        } else if value.is_infinite() {
+            self.output.write_all(if value > 0.0 { b"inf" } else { b"-inf" })
+        } else if value.fract() == 0.0 {
            write!(self.output, "{:.1}", value)
         } else {
             write!(self.output, "{}", value)
@@ -134,9 +138,13 @@ impl<W: Write> TextWriter<W> {
         Ok(())
     }
 
-    fn write_array<T, F>(&mut self, values: &Box<[T]>, f: F) -> io::Result<()>
+    fn write_array<T, F>(&mut self, type_name: &str, values: &Box<[T]>, f: F) -> io::Result<()>
         where F: Fn(&mut Self, T) -> io::Result<()>, T: Copy {
 
+        if values.len() == 0 {
+            return write!(self.output, "{}{{}}", type_name);
+        }
+
         let indent = self.indent;
         try!(self.output.write_all(b"{\n"));
         for &v in values.iter() {
@@ -185,7 +193,7 @@ impl<W: Write> Writer for TextWriter<W> {
 #[cfg(test)]
 mod tests {
     use super::*;
-    use super::super::{Value, Writer};
+    use super::super::{Value, Token, Writer, Reader, TextReader};
     use std::io::Cursor;
 
     fn setup() -> TextWriter<Cursor<Vec<u8>>> {
@@ -356,35 +364,28 @@ mod tests {
         ].into_boxed_slice())).unwrap();
 
         assert_eq!(result(writer), "\
-{
-} {
+bool{} {
   true
   false
   true
-} {
-} {
+} int{} {
   6
   128
   1000
-} {
-} {
+} double{} {
   67245.375
   3464.85
   -8769.4565
-} {
-} {
+} vec2{} {
   [67245.375 3464.85]
   [-8769.4565 -1882.52]
-} {
-} {
+} vec3{} {
   [67245.375 3464.85 -8769.4565]
   [-1882.52 67245.375 3464.85]
-} {
-} {
+} vec4{} {
   [67245.375 3464.85 -8769.4565 -1882.52]
   [-1882.52 -8769.4565 3464.85 67245.375]
-} {
-} {
+} box2{} {
   [[67245.375 3464.85] [-8769.4565 -1882.52]]
   [[-1882.52 -8769.4565] [3464.85 67245.375]]
 }\
@@ -420,4 +421,73 @@ r#"(DICT "one" 1 "two"
       [5.0 6.0]
     }) "three" false)"#);
     }
+
+    #[test]
+    fn round_trip_arrays() {
+        let values = vec![
+            Value::BoolArray(vec![].into_boxed_slice()),
+            Value::IntArray(vec![].into_boxed_slice()),
+            Value::DoubleArray(vec![].into_boxed_slice()),
+            Value::Vec2Array(vec![].into_boxed_slice()),
+            Value::Vec3Array(vec![].into_boxed_slice()),
+            Value::Vec4Array(vec![].into_boxed_slice()),
+            Value::Box2Array(vec![].into_boxed_slice()),
+            Value::Box2Array(vec![((0.0, 1.0), (2.0, 3.0))].into_boxed_slice()),
+        ];
+
+        let mut writer = setup();
+        writer.write_start().unwrap();
+        for value in values.iter() {
+            writer.write_value(value).unwrap();
+        }
+        writer.write_end().unwrap();
+
+        let text = writer.output.into_inner();
+        let mut reader = TextReader::new(Cursor::new(text));
+
+        assert_eq!(reader.read_next().unwrap(), Token::Start);
+        for value in values.into_iter() {
+            assert_eq!(reader.read_next().unwrap(), Token::Value(value));
+        }
+        assert_eq!(reader.read_next().unwrap(), Token::End);
+        assert_eq!(reader.read_next().unwrap(), Token::EndOfFile);
+    }
+
+    #[test]
+    fn round_trip_non_finite() {
+        let (inf, nan) = (::std::f64::INFINITY, ::std::f64::NAN);
+        let values = vec![
+            Value::Double(inf),
+            Value::Double(-inf),
+            Value::Vec2((-inf, 1.5)),
+            Value::DoubleArray(vec![inf, -inf, 0.0].into_boxed_slice()),
+        ];
+
+        let mut writer = setup();
+        writer.write_start().unwrap();
+        for value in values.iter() {
+            writer.write_value(value).unwrap();
+        }
+        writer.write_value(&Value::Double(nan)).unwrap();
+        writer.write_value(&Value::Vec3((0.0, nan, 1.0))).unwrap();
+        writer.write_end().unwrap();
+
+        let text = writer.output.into_inner();
+        let mut reader = TextReader::new(Cursor::new(text));
+
+        assert_eq!(reader.read_next().unwrap(), Token::Start);
+        for value in values.into_iter() {
+            assert_eq!(reader.read_next().unwrap(), Token::Value(value));
+        }
+        match reader.read_next().unwrap() {
+            Token::Value(Value::Double(v)) => assert!(v.is_nan()),
+            token => panic!("{:?}", token)
+        }
+        match reader.read_next().unwrap() {
+            Token::Value(Value::Vec3((x, y, z))) => assert!(x == 0.0 && y.is_nan() && z == 1.0),
+            token => panic!("{:?}", token)
+        }
+        assert_eq!(reader.read_next().unwrap(), Token::End);
+        assert_eq!(reader.read_next().unwrap(), Token::EndOfFile);
+    }
 }
@@ -0,0 +1,431 @@
+use std::io::{self, Write};
+use data::{Vec3, Vec4};
+
+// An RGBA image with straight alpha, stored from the top row down
+pub struct Image {
+    pub width: usize,
+    pub height: usize,
+    pub pixels: Vec<Vec4>
+}
+
+impl Image {
+    pub fn new(width: usize, height: usize, background: Vec4) -> Image {
+        Image {
+            width: width,
+            height: height,
+            pixels: vec![background; width * height]
+        }
+    }
+
+    // Pixels are addressed from the bottom left, like the GL viewport
+    pub fn pixel(&self, x: usize, y: usize) -> Vec4 {
+        self.pixels[(self.height - 1 - y) * self.width + x]
+    }
+
+    // Draws a colour over a pixel with the renderers' alpha blending
+    pub fn blend(&mut self, x: usize, y: usize, colour: Vec3, alpha: f64) {
+        let index = (self.height - 1 - y) * self.width + x;
+        let (r, g, b, a) = self.pixels[index];
+
+        let out = alpha + a * (1.0 - alpha);
+        if out <= 0.0 {
+            return;
+        }
+
+        let mix = |c: f64, d: f64| (c * alpha + d * a * (1.0 - alpha)) / out;
+        self.pixels[index] = (mix(colour.0, r), mix(colour.1, g), mix(colour.2, b), out);
+    }
+
+    pub fn to_rgba8(&self) -> Vec<u8> {
+        let channel = |c: f64| (c.max(0.0).min(1.0) * 255.0).round() as u8;
+
+        let mut data = Vec::with_capacity(self.pixels.len() * 4);
+        for &(r, g, b, a) in &self.pixels {
+            data.extend_from_slice(&[channel(r), channel(g), channel(b), channel(a)]);
+        }
+
+        data
+    }
+
+    pub fn write_png(&self, output: &mut Write) -> io::Result<()> {
+        let mut header = Vec::with_capacity(13);
+        header.extend_from_slice(&u32_be(self.width as u32));
+        header.extend_from_slice(&u32_be(self.height as u32));
+        // 8 bits per channel, RGBA, no interlacing
+        header.extend_from_slice(&[8, 6, 0, 0, 0]);
+
+        // Each row starts with its filter type, always none here
+        let rgba = self.to_rgba8();
+        let mut raw = Vec::with_capacity(rgba.len() + self.height);
+        if self.width > 0 {
+            for row in rgba.chunks(self.width * 4) {
+                raw.push(0);
+                raw.extend_from_slice(row);
+            }
+        } else {
+            raw.resize(self.height, 0);
+        }
+
+        let mut zlib = vec![0x78, 0x9c];
+        zlib.extend_from_slice(&deflate(&raw));
+        zlib.extend_from_slice(&u32_be(adler32(&raw)));
+
+        try!(output.write_all(b"\x89PNG\r\n\x1a\n"));
+        try!(write_chunk(output, b"IHDR", &header));
+        try!(write_chunk(output, b"IDAT", &zlib));
+        try!(write_chunk(output, b"IEND", &[]));
+        Ok(())
+    }
+}
+
+fn u32_be(n: u32) -> [u8; 4] {
+    [(n >> 24) as u8, (n >> 16) as u8, (n >> 8) as u8, n as u8]
+}
+
+fn write_chunk(output: &mut Write, kind: &[u8; 4], data: &[u8]) -> io::Result<()> {
+    let mut crc = Crc::new();
+    crc.update(kind);
+    crc.update(data);
+
+    try!(output.write_all(&u32_be(data.len() as u32)));
+    try!(output.write_all(kind));
+    try!(output.write_all(data));
+    try!(output.write_all(&u32_be(crc.value())));
+    Ok(())
+}
+
+struct Crc {
+    table: [u32; 256],
+    value: u32
+}
+
+impl Crc {
+    fn new() -> Crc {
+        let mut table = [0; 256];
+        for n in 0..256 {
+            let mut c = n as u32;
+            for _ in 0..8 {
+                c = if c & 1 != 0 { 0xedb88320 ^ (c >> 1) } else { c >> 1 };
+            }
+            table[n] = c;
+        }
+
+        Crc {
+            table: table,
+            value: 0xffffffff
+        }
+    }
+
+    fn update(&mut self, data: &[u8]) {
+        for &b in data {
+            self.value = self.table[((self.value ^ b as u32) & 0xff) as usize] ^ (self.value >> 8);
+        }
+    }
+
+    fn value(&self) -> u32 {
+        self.value ^ 0xffffffff
+    }
+}
+
+fn adler32(data: &[u8]) -> u32 {
+    let (mut a, mut b) = (1u32, 0u32);
+    for chunk in data.chunks(5552) {
+        for &byte in chunk {
+            a += byte as u32;
+            b += a;
+        }
+        a %= 65521;
+        b %= 65521;
+    }
+
+    (b << 16) | a
+}
+
+struct BitWriter {
+    output: Vec<u8>,
+    bits: u32,
+    count: u32
+}
+
+impl BitWriter {
+    fn write(&mut self, value: u32, count: u32) {
+        self.bits |= value << self.count;
+        self.count += count;
+
+        while self.count >= 8 {
+            self.output.push(self.bits as u8);
+            self.bits >>= 8;
+            self.count -= 8;
+        }
+    }
+
+    // Huffman codes are packed starting from their most significant bit
+    fn write_code(&mut self, code: u32, count: u32) {
+        let mut reversed = 0;
+        for i in 0..count {
+            reversed |= ((code >> i) & 1) << (count - 1 - i);
+        }
+        self.write(reversed, count);
+    }
+
+    fn finish(mut self) -> Vec<u8> {
+        if self.count > 0 {
+            self.output.push(self.bits as u8);
+        }
+        self.output
+    }
+}
+
+const LENGTH_BASES: [u32; 29] = [
+    3, 4, 5, 6, 7, 8, 9, 10, 11, 13, 15, 17, 19, 23, 27, 31,
+    35, 43, 51, 59, 67, 83, 99, 115, 131, 163, 195, 227, 258];
+const LENGTH_EXTRA: [u32; 29] = [
+    0, 0, 0, 0, 0, 0, 0, 0, 1, 1, 1, 1, 2, 2, 2, 2,
+    3, 3, 3, 3, 4, 4, 4, 4, 5, 5, 5, 5, 0];
+const DISTANCE_BASES: [u32; 30] = [
+    1, 2, 3, 4, 5, 7, 9, 13, 17, 25, 33, 49, 65, 97, 129, 193,
+    257, 385, 513, 769, 1025, 1537, 2049, 3073, 4097, 6145, 8193, 12289, 16385, 24577];
+const DISTANCE_EXTRA: [u32; 30] = [
+    0, 0, 0, 0, 1, 1, 2, 2, 3, 3, 4, 4, 5, 5, 6, 6,
+    7, 7, 8, 8, 9, 9, 10, 10, 11, 11, 12, 12, 13, 13];
+
+const WINDOW: usize = 32768;
+const MAX_MATCH: usize = 258;
+const MAX_CHAIN: usize = 64;
+
+fn write_literal(writer: &mut BitWriter, symbol: u32) {
+    // The fixed Huffman code from the deflate spec
+    match symbol {
+        0...143 => writer.write_code(0x30 + symbol, 8),
+        144...255 => writer.write_code(0x190 + symbol - 144, 9),
+        256...279 => writer.write_code(symbol - 256, 7),
+        _ => writer.write_code(0xc0 + symbol - 280, 8)
+    }
+}
+
+fn write_match(writer: &mut BitWriter, length: usize, distance: usize) {
+    let (length, distance) = (length as u32, distance as u32);
+
+    let l = LENGTH_BASES.iter().rposition(|&base| base <= length).unwrap();
+    write_literal(writer, 257 + l as u32);
+    writer.write(length - LENGTH_BASES[l], LENGTH_EXTRA[l]);
+
+    let d = DISTANCE_BASES.iter().rposition(|&base| base <= distance).unwrap();
+    writer.write_code(d as u32, 5);
+    writer.write(distance - DISTANCE_BASES[d], DISTANCE_EXTRA[d]);
+}
+
+// Compresses data as a single deflate block with the fixed Huffman codes,
+// finding repeats with hash chains. Flat areas of an image compress well
+// enough without dynamic codes.
+fn deflate(data: &[u8]) -> Vec<u8> {
+    let mut writer = BitWriter {
+        output: Vec::new(),
+        bits: 0,
+        count: 0
+    };
+    writer.write(1, 1);
+    writer.write(1, 2);
+
+    let hash = |i: usize| {
+        ((data[i] as usize) << 10 ^ (data[i + 1] as usize) << 5 ^ data[i + 2] as usize) & 0x7fff
+    };
+    let mut head = vec![usize::max_value(); 0x8000];
+    let mut previous = vec![usize::max_value(); WINDOW];
+
+    let mut i = 0;
+    while i < data.len() {
+        let mut best = (0, 0);
+
+        if i + 3 <= data.len() {
+            let h = hash(i);
+            let mut candidate = head[h];
+            let mut chain = 0;
+
+            while candidate != usize::max_value() && i - candidate <= WINDOW && chain < MAX_CHAIN {
+                let limit = MAX_MATCH.min(data.len() - i);
+                let mut length = 0;
+                while length < limit && data[candidate + length] == data[i + length] {
+                    length += 1;
+                }
+
+                if length > best.0 {
+                    best = (length, i - candidate);
+                    if length == limit {
+                        break;
+                    }
+                }
+
+                let next = previous[candidate % WINDOW];
+                if next == usize::max_value() || next >= candidate {
+                    break;
+                }
+                candidate = next;
+                chain += 1;
+            }
+        }
+
+        let step = if best.0 >= 3 {
+            write_match(&mut writer, best.0, best.1);
+            best.0
+        } else {
+            write_literal(&mut writer, data[i] as u32);
+            1
+        };
+
+        for j in i..i + step {
+            if j + 3 <= data.len() {
+                let h = hash(j);
+                previous[j % WINDOW] = head[h];
+                head[h] = j;
+            }
+        }
+        i += step;
+    }
+
+    write_literal(&mut writer, 256);
+    writer.finish()
+}
+
+// Compares an image with a PNG under tests/golden, or replaces the PNG when
+// ALICE_UPDATE_GOLDEN is set. Our encoder is deterministic, so comparing
+// the encoded files compares the pixels.
+#[cfg(test)]
+pub fn check_golden(image: &Image, name: &str) {
+    use std::env;
+    use std::fs::File;
+    use std::io::Read;
+
+    let mut actual = Vec::new();
+    image.write_png(&mut actual).unwrap();
+
+    let path = format!("{}/tests/golden/{}.png", env!("CARGO_MANIFEST_DIR"), name);
+    if env::var_os("ALICE_UPDATE_GOLDEN").is_some() {
+        File::create(&path).unwrap().write_all(&actual).unwrap();
+        return;
+    }
+
+    let mut expected = Vec::new();
+    File::open(&path).unwrap().read_to_end(&mut expected).unwrap();
+
+    if actual != expected {
+        let out = env::temp_dir().join(format!("{}.png", name));
+        File::create(&out).unwrap().write_all(&actual).unwrap();
+        panic!("{} differs from {}, see {}", name, path, out.display());
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Just enough of inflate to read back the fixed Huffman block
+    fn inflate(data: &[u8]) -> Vec<u8> {
+        let mut pos = 0;
+        let mut bit = |n: u32| {
+            let mut value = 0;
+            for i in 0..n {
+                value |= ((data[pos / 8] >> (pos % 8)) as u32 & 1) << i;
+                pos += 1;
+            }
+            value
+        };
+
+        assert_eq!(bit(3), 0b011);
+
+        let mut output: Vec<u8> = Vec::new();
+        loop {
+            let mut code = 0;
+            for _ in 0..7 {
+                code = code << 1 | bit(1);
+            }
+            let symbol = if code < 0x18 {
+                code + 256
+            } else {
+                code = code << 1 | bit(1);
+                if code < 0xc0 {
+                    code - 0x30
+                } else if code < 0xc8 {
+                    code - 0xc0 + 280
+                } else {
+                    (code << 1 | bit(1)) - 0x190 + 144
+                }
+            };
+
+            if symbol < 256 {
+                output.push(symbol as u8);
+            } else if symbol == 256 {
+                return output;
+            } else {
+                let l = (symbol - 257) as usize;
+                let length = LENGTH_BASES[l] + bit(LENGTH_EXTRA[l]);
+                let mut d = 0;
+                for _ in 0..5 {
+                    d = d << 1 | bit(1);
+                }
+                let distance = DISTANCE_BASES[d as usize] + bit(DISTANCE_EXTRA[d as usize]);
+
+                for _ in 0..length {
+                    let b = output[output.len() - distance as usize];
+                    output.push(b);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn compress() {
+        let mut data = b"abcabcabcabcabcabc hello hello hello".to_vec();
+        for i in 0..70000u64 {
+            data.push((i * i / 7 % 251) as u8);
+        }
+        data.extend(vec![7; 1000]);
+
+        for input in &[&b""[..], &b"a"[..], &b"aaaa"[..], &data[..]] {
+            assert_eq!(&inflate(&deflate(input))[..], *input);
+        }
+
+        assert!(deflate(&vec![9; 100000]).len() < 1000);
+        assert_eq!(adler32(b"Wikipedia"), 0x11e60398);
+    }
+
+    #[test]
+    fn png() {
+        let mut image = Image::new(3, 2, (1.0, 0.0, 0.0, 1.0));
+        image.blend(0, 0, (0.0, 0.0, 1.0), 0.5);
+
+        // Bottom left is the start of the last row
+        assert_eq!(image.pixel(0, 0), (0.5, 0.0, 0.5, 1.0));
+        assert_eq!(&image.to_rgba8()[12..16], &[128, 0, 128, 255]);
+
+        let mut png = Vec::new();
+        image.write_png(&mut png).unwrap();
+        assert_eq!(&png[..8], b"\x89PNG\r\n\x1a\n");
+        assert_eq!(&png[8..16], b"\0\0\0\x0dIHDR");
+        assert_eq!(&png[16..29], &[0, 0, 0, 3, 0, 0, 0, 2, 8, 6, 0, 0, 0]);
+        // The CRC of this header, as computed by zlib
+        assert_eq!(&png[29..33], &[0x9d, 0x74, 0x66, 0x1a]);
+
+        let length = (png[33] as usize) << 24 | (png[34] as usize) << 16 |
+                     (png[35] as usize) << 8 | png[36] as usize;
+        assert_eq!(&png[37..41], b"IDAT");
+        let zlib = &png[41..41 + length];
+        assert_eq!(((zlib[0] as u32) << 8 | zlib[1] as u32) % 31, 0);
+
+        let mut raw = vec![0, 255, 0, 0, 255, 255, 0, 0, 255, 255, 0, 0, 255];
+        raw.extend_from_slice(&[0, 128, 0, 128, 255, 255, 0, 0, 255, 255, 0, 0, 255]);
+        assert_eq!(inflate(&zlib[2..length - 4]), raw);
+
+        assert_eq!(&png[png.len() - 12..], b"\0\0\0\0IEND\xae\x42\x60\x82");
+    }
+
+    #[test]
+    fn transparency() {
+        let mut image = Image::new(1, 1, (0.0, 0.0, 0.0, 0.0));
+        image.blend(0, 0, (1.0, 0.5, 0.0), 0.5);
+        assert_eq!(image.pixel(0, 0), (1.0, 0.5, 0.0, 0.5));
+
+        image.blend(0, 0, (0.0, 0.5, 1.0), 0.5);
+        assert_eq!(image.pixel(0, 0), (1.0 / 3.0, 0.5, 2.0 / 3.0, 0.75));
+    }
+}
@@ -1,8 +1,10 @@
 #[macro_use]
 extern crate glium;
 extern crate byteorder;
+extern crate rand;
 
 #[macro_use]
 pub mod data;
+pub mod image;
 pub mod model;
 pub mod widget;
@@ -0,0 +1,563 @@
+use std::io;
+use data::{Value, Tag, Vec2, Vec3, Vec4, Reader, Writer};
+use super::{Model, Path, Point, Stroke, Paint, Stop, Instance, Affine2, SHAPE};
+
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub enum Easing {
+    Linear,
+    EaseIn,
+    EaseOut,
+    EaseInOut,
+    // Holds the first keyframe until the next one is reached
+    Step,
+    // As in CSS's cubic-bezier(x1, y1, x2, y2)
+    CubicBezier(f64, f64, f64, f64)
+}
+
+// A model to show at a time, eased towards the next keyframe's
+pub struct Keyframe {
+    pub time: f64,
+    pub easing: Easing,
+    pub model: Model
+}
+
+// Keyframes in order of time, whose models all have the same structure so
+// that each point can move to the matching point of the next
+pub struct Animation {
+    pub keyframes: Vec<Keyframe>,
+    pub looping: bool
+}
+
+const ANIMATION: Tag = tag!(A N I M);
+const LOOP: Tag = tag!(L O O P);
+const KEYFRAMES: Tag = tag!(K E Y S);
+const TIME: Tag = tag!(T I M E);
+const EASING: Tag = tag!(E A S E);
+
+const LINEAR: Tag = tag!(L I N R);
+const EASE_IN: Tag = tag!(I N _ _);
+const EASE_OUT: Tag = tag!(O U T _);
+const EASE_IN_OUT: Tag = tag!(I N O U);
+const STEP: Tag = tag!(S T E P);
+const CUBIC_BEZIER: Tag = tag!(C B E Z);
+
+impl Easing {
+    // Maps the time between two keyframes, from 0 to 1, to the amount of
+    // the way from one to the other
+    pub fn apply(self, t: f64) -> f64 {
+        let t = t.max(0.0).min(1.0);
+
+        match self {
+            Easing::Linear => t,
+            Easing::EaseIn => t * t * t,
+            Easing::EaseOut => 1.0 - (1.0 - t) * (1.0 - t) * (1.0 - t),
+            Easing::EaseInOut => t * t * (3.0 - 2.0 * t),
+            Easing::Step => if t < 1.0 { 0.0 } else { 1.0 },
+            Easing::CubicBezier(_, _, _, _) if t == 0.0 || t == 1.0 => t,
+            Easing::CubicBezier(x1, y1, x2, y2) => {
+                let bezier = |a: f64, b: f64, s: f64| {
+                    3.0 * (1.0 - s) * (1.0 - s) * s * a + 3.0 * (1.0 - s) * s * s * b + s * s * s
+                };
+
+                // x is increasing in s when x1 and x2 are in 0..1, so the s
+                // giving t can be found by bisection
+                let (mut low, mut high) = (0.0, 1.0);
+                for _ in 0..50 {
+                    let mid = (low + high) / 2.0;
+                    if bezier(x1, x2, mid) < t {
+                        low = mid;
+                    } else {
+                        high = mid;
+                    }
+                }
+
+                bezier(y1, y2, (low + high) / 2.0)
+            }
+        }
+    }
+}
+
+fn mix(a: f64, b: f64, t: f64) -> f64 {
+    a + (b - a) * t
+}
+
+fn mix2(a: Vec2, b: Vec2, t: f64) -> Vec2 {
+    (mix(a.0, b.0, t), mix(a.1, b.1, t))
+}
+
+fn mix3(a: Vec3, b: Vec3, t: f64) -> Vec3 {
+    (mix(a.0, b.0, t), mix(a.1, b.1, t), mix(a.2, b.2, t))
+}
+
+fn mix4(a: Vec4, b: Vec4, t: f64) -> Vec4 {
+    (mix(a.0, b.0, t), mix(a.1, b.1, t), mix(a.2, b.2, t), mix(a.3, b.3, t))
+}
+
+fn mix_points(a: &[Point], b: &[Point], t: f64) -> Vec<Point> {
+    a.iter()
+        .zip(b)
+        .map(|(p, q)| Point {
+            location: mix2(p.location, q.location, t),
+            curve_bias: mix(p.curve_bias, q.curve_bias, t),
+            weights: if t < 0.5 { p.weights } else { q.weights }
+        })
+        .collect()
+}
+
+impl Model {
+    // Whether each path of the models has the same number of outlines and
+    // points, and each instance the same symbol, so that they can be
+    // interpolated
+    pub fn is_compatible(&self, other: &Model) -> bool {
+        self.paths.len() == other.paths.len() &&
+            self.paths.iter().zip(&other.paths).all(|(a, b)| {
+                a.points.len() == b.points.len() &&
+                    a.contours.len() == b.contours.len() &&
+                    a.contours.iter().zip(&b.contours).all(|(c, d)| c.len() == d.len())
+            }) &&
+            self.instances.len() == other.instances.len() &&
+            self.instances.iter().zip(&other.instances).all(|(a, b)| a.symbol == b.symbol)
+    }
+
+    // The model t of the way from a to b, matching paths, points and
+    // instances by index. Locations, curve biases, colours, stroke widths and
+    // instance transforms are blended, while settings that can't be blended
+    // switch over halfway. Symbols are taken from the nearer model.
+    pub fn lerp(a: &Model, b: &Model, t: f64) -> Option<Model> {
+        if !a.is_compatible(b) {
+            return None;
+        }
+
+        let nearest = if t < 0.5 { a } else { b };
+
+        Some(Model {
+            paths: a.paths.iter().zip(&b.paths).map(|(p, q)| lerp_path(p, q, t)).collect(),
+            skeleton: nearest.skeleton.clone(),
+            metadata: nearest.metadata.clone(),
+            layers: nearest.layers.clone(),
+            symbols: nearest.symbols.clone(),
+            instances: a.instances.iter().zip(&b.instances).map(|(i, j)| lerp_instance(i, j, t)).collect(),
+            clips: nearest.clips.clone()
+        })
+    }
+}
+
+fn lerp_instance(a: &Instance, b: &Instance, t: f64) -> Instance {
+    let mut transform = Affine2::identity();
+    for (m, (&p, &q)) in transform.matrix.iter_mut().zip(a.transform.matrix.iter().zip(&b.transform.matrix)) {
+        *m = mix(p, q, t);
+    }
+
+    Instance {
+        symbol: a.symbol,
+        transform: transform,
+        paint: match (&a.paint, &b.paint) {
+            (&Some(ref p), &Some(ref q)) => Some(lerp_paint(p, q, t)),
+            _ => if t < 0.5 { a.paint.clone() } else { b.paint.clone() }
+        }
+    }
+}
+
+fn lerp_path(a: &Path, b: &Path, t: f64) -> Path {
+    let nearest = if t < 0.5 { a } else { b };
+
+    Path {
+        name: nearest.name.clone(),
+        paint: lerp_paint(&a.paint, &b.paint, t),
+        points: mix_points(&a.points, &b.points, t),
+        contours: a.contours.iter().zip(&b.contours).map(|(c, d)| mix_points(c, d, t)).collect(),
+        fill_rule: nearest.fill_rule,
+        filled: nearest.filled,
+        stroke: match (&a.stroke, &b.stroke) {
+            (&Some(ref s), &Some(ref u)) => {
+                let nearest = if t < 0.5 { s } else { u };
+                Some(Stroke {
+                    colour: mix3(s.colour, u.colour, t),
+                    width: mix(s.width, u.width, t),
+                    join: nearest.join,
+                    cap: nearest.cap,
+                    dashes: if s.dashes.len() == u.dashes.len() {
+                        s.dashes.iter().zip(&u.dashes).map(|(&d, &e)| mix(d, e, t)).collect()
+                    } else {
+                        nearest.dashes.clone()
+                    },
+                    closed: nearest.closed
+                })
+            },
+            _ => nearest.stroke.clone()
+        }
+    }
+}
+
+// Paints of the same kind with as many stops are blended, and others switch
+// over halfway
+fn lerp_paint(a: &Paint, b: &Paint, t: f64) -> Paint {
+    let mix_stops = |s: &[Stop], u: &[Stop]| -> Vec<Stop> {
+        s.iter()
+            .zip(u)
+            .map(|(p, q)| Stop {
+                offset: mix(p.offset, q.offset, t),
+                colour: mix4(p.colour, q.colour, t)
+            })
+            .collect()
+    };
+
+    match (a, b) {
+        (&Paint::Solid(c), &Paint::Solid(d)) => Paint::Solid(mix4(c, d, t)),
+        (&Paint::LinearGradient { start: s, end: e, stops: ref p },
+         &Paint::LinearGradient { start: u, end: f, stops: ref q }) if p.len() == q.len() => {
+            Paint::LinearGradient {
+                start: mix2(s, u, t),
+                end: mix2(e, f, t),
+                stops: mix_stops(p, q)
+            }
+        },
+        (&Paint::RadialGradient { centre: c, radius: r, stops: ref p },
+         &Paint::RadialGradient { centre: d, radius: s, stops: ref q }) if p.len() == q.len() => {
+            Paint::RadialGradient {
+                centre: mix2(c, d, t),
+                radius: mix(r, s, t),
+                stops: mix_stops(p, q)
+            }
+        },
+        _ => if t < 0.5 { a.clone() } else { b.clone() }
+    }
+}
+
+impl Animation {
+    pub fn duration(&self) -> f64 {
+        match (self.keyframes.first(), self.keyframes.last()) {
+            (Some(first), Some(last)) => last.time - first.time,
+            _ => 0.0
+        }
+    }
+
+    // The model shown at a time. Before the first keyframe and after the
+    // last the nearest one is held, unless the animation loops, when the
+    // last keyframe jumps back to the first. Gives None for a time that isn't
+    // a finite number.
+    pub fn model_at(&self, time: f64) -> Option<Model> {
+        let (first, last) = match (self.keyframes.first(), self.keyframes.last()) {
+            (Some(first), Some(last)) if time.is_finite() => (first, last),
+            _ => return None
+        };
+
+        let duration = self.duration();
+        let time = if self.looping && duration > 0.0 {
+            first.time + (time - first.time) - ((time - first.time) / duration).floor() * duration
+        } else {
+            time
+        };
+
+        if time <= first.time {
+            return Some(first.model.clone());
+        }
+        if time >= last.time {
+            return Some(last.model.clone());
+        }
+
+        // Keyframe times that aren't numbers can leave no keyframe after it
+        let next = match self.keyframes.iter().position(|k| k.time > time) {
+            Some(next) if next > 0 => next,
+            _ => return None
+        };
+        let (from, to) = (&self.keyframes[next - 1], &self.keyframes[next]);
+        let t = (time - from.time) / (to.time - from.time);
+
+        Model::lerp(&from.model, &to.model, from.easing.apply(t))
+    }
+
+    pub fn write(&self, writer: &mut Writer) -> io::Result<()> {
+        try!(writer.write_start());
+        try!(writer.write_value(&Value::Tag(ANIMATION)));
+
+        try!(writer.write_start());
+        try!(writer.write_value(&Value::Tag(LOOP)));
+        try!(writer.write_value(&Value::Bool(self.looping)));
+        try!(writer.write_end());
+
+        try!(writer.write_start());
+        try!(writer.write_value(&Value::Tag(KEYFRAMES)));
+        try!(writer.write_value(&Value::Int(self.keyframes.len() as i32)));
+
+        for keyframe in &self.keyframes {
+            try!(keyframe.write(writer));
+        }
+
+        try!(writer.write_end());
+
+        writer.write_end()
+    }
+
+    pub fn read(reader: &mut Reader) -> io::Result<Animation> {
+        try!(reader.expect_start());
+        Animation::read_started(reader)
+    }
+
+    pub fn read_started(reader: &mut Reader) -> io::Result<Animation> {
+        if try!(reader.expect_tag()) != ANIMATION {
+            return Err(io::Error::new(io::ErrorKind::InvalidData, "Unexpected data"));
+        }
+
+        let mut animation = Animation {
+            keyframes: Vec::new(),
+            looping: false
+        };
+
+        while try!(reader.expect_start_or_end()) {
+            match try!(reader.expect_tag()) {
+                LOOP => {
+                    animation.looping = try!(reader.expect_bool());
+                    try!(reader.skip_to_end());
+                },
+                KEYFRAMES => {
+                    let count = try!(reader.expect_int());
+                    for _ in 0..count {
+                        animation.keyframes.push(try!(Keyframe::read(reader)));
+                    }
+                    try!(reader.skip_to_end());
+                },
+                _ => try!(reader.skip_to_end())
+            }
+        }
+
+        // Keyframes that can't be played in order or blended are rejected
+        // up front rather than when they are reached
+        let valid = animation.keyframes.windows(2).all(|w| {
+            w[0].time <= w[1].time && w[0].model.is_compatible(&w[1].model)
+        });
+        if !valid {
+            return Err(io::Error::new(io::ErrorKind::InvalidData, "Mismatched keyframes"));
+        }
+
+        Ok(animation)
+    }
+}
+
+impl Keyframe {
+    fn write(&self, writer: &mut Writer) -> io::Result<()> {
+        try!(writer.write_start());
+
+        try!(writer.write_start());
+        try!(writer.write_value(&Value::Tag(TIME)));
+        try!(writer.write_value(&Value::Double(self.time)));
+        try!(writer.write_end());
+
+        try!(writer.write_start());
+        try!(writer.write_value(&Value::Tag(EASING)));
+        match self.easing {
+            Easing::Linear => try!(writer.write_value(&Value::Tag(LINEAR))),
+            Easing::EaseIn => try!(writer.write_value(&Value::Tag(EASE_IN))),
+            Easing::EaseOut => try!(writer.write_value(&Value::Tag(EASE_OUT))),
+            Easing::EaseInOut => try!(writer.write_value(&Value::Tag(EASE_IN_OUT))),
+            Easing::Step => try!(writer.write_value(&Value::Tag(STEP))),
+            Easing::CubicBezier(x1, y1, x2, y2) => {
+                try!(writer.write_value(&Value::Tag(CUBIC_BEZIER)));
+                try!(writer.write_value(&Value::Vec4((x1, y1, x2, y2))));
+            }
+        }
+        try!(writer.write_end());
+
+        try!(self.model.write(writer));
+
+        writer.write_end()
+    }
+
+    fn read(reader: &mut Reader) -> io::Result<Keyframe> {
+        let mut time = 0.0;
+        let mut easing = Easing::Linear;
+        let mut model = None;
+
+        try!(reader.expect_start());
+
+        while try!(reader.expect_start_or_end()) {
+            match try!(reader.expect_tag()) {
+                TIME => {
+                    time = try!(reader.expect_double());
+                    try!(reader.skip_to_end());
+                },
+                EASING => {
+                    easing = try!(read_easing(reader));
+                    try!(reader.skip_to_end());
+                },
+                SHAPE => model = Some(try!(Model::read_tagged(reader))),
+                _ => try!(reader.skip_to_end())
+            }
+        }
+
+        match model {
+            Some(model) => Ok(Keyframe {
+                time: time,
+                easing: easing,
+                model: model
+            }),
+            None => Err(io::Error::new(io::ErrorKind::InvalidData, "Unexpected data"))
+        }
+    }
+}
+
+fn read_easing(reader: &mut Reader) -> io::Result<Easing> {
+    Ok(match try!(reader.expect_tag()) {
+        LINEAR => Easing::Linear,
+        EASE_IN => Easing::EaseIn,
+        EASE_OUT => Easing::EaseOut,
+        EASE_IN_OUT => Easing::EaseInOut,
+        STEP => Easing::Step,
+        CUBIC_BEZIER => {
+            let (x1, y1, x2, y2): Vec4 = try!(reader.expect_vec4());
+            Easing::CubicBezier(x1, y1, x2, y2)
+        },
+        _ => return Err(io::Error::new(io::ErrorKind::InvalidData, "Unknown easing"))
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Cursor;
+    use data::{BinaryReader, BinaryWriter, TextReader, TextWriter};
+    use model::{Model, Stroke, LineJoin, LineCap, path};
+
+    fn triangle(size: f64, colour: Vec3) -> Model {
+        let mut triangle = path(&[(0.0, 0.0, 0.0), (size, 0.0, 0.0), (0.0, size, 0.0)]);
+        triangle.paint = Paint::solid(colour);
+        triangle.stroke = Some(Stroke {
+            colour: colour,
+            width: size,
+            join: LineJoin::Miter,
+            cap: LineCap::Butt,
+            dashes: Vec::new(),
+            closed: true
+        });
+        Model { paths: vec![triangle], .. Default::default() }
+    }
+
+    fn keyframe(time: f64, easing: Easing, model: Model) -> Keyframe {
+        Keyframe {
+            time: time,
+            easing: easing,
+            model: model
+        }
+    }
+
+    #[test]
+    fn lerp() {
+        let (a, mut b) = (triangle(1.0, (1.0, 0.0, 0.0)), triangle(3.0, (0.0, 0.0, 1.0)));
+        b.paths[0].points[0].curve_bias = 0.5;
+        b.paths[0].stroke.as_mut().unwrap().join = LineJoin::Round;
+
+        let mid = Model::lerp(&a, &b, 0.25).unwrap();
+        let path = &mid.paths[0];
+        assert_eq!(path.points[1].location, (1.5, 0.0));
+        assert_eq!(path.points[0].curve_bias, 0.125);
+        assert_eq!(path.paint, Paint::Solid((0.75, 0.0, 0.25, 1.0)));
+
+        let stroke = path.stroke.as_ref().unwrap();
+        assert_eq!(stroke.width, 1.5);
+        assert_eq!(stroke.join, LineJoin::Miter);
+        assert_eq!(Model::lerp(&a, &b, 0.5).unwrap().paths[0].stroke.as_ref().unwrap().join, LineJoin::Round);
+
+        b.paths[0].points.pop();
+        assert!(!a.is_compatible(&b));
+        assert!(Model::lerp(&a, &b, 0.5).is_none());
+    }
+
+    #[test]
+    fn easing() {
+        for &easing in &[Easing::Linear, Easing::EaseIn, Easing::EaseOut, Easing::EaseInOut,
+                         Easing::CubicBezier(0.25, 0.1, 0.25, 1.0)] {
+            assert_eq!(easing.apply(0.0), 0.0);
+            assert!((easing.apply(1.0) - 1.0).abs() < 1e-9);
+            assert!(easing.apply(0.3) < easing.apply(0.6));
+        }
+
+        assert_eq!(Easing::EaseIn.apply(0.5), 0.125);
+        assert_eq!(Easing::EaseOut.apply(0.5), 0.875);
+        assert_eq!(Easing::EaseInOut.apply(0.5), 0.5);
+        assert_eq!(Easing::Step.apply(0.99), 0.0);
+        assert_eq!(Easing::Linear.apply(2.0), 1.0);
+
+        // A straight bézier is linear
+        assert!((Easing::CubicBezier(0.25, 0.25, 0.75, 0.75).apply(0.3) - 0.3).abs() < 1e-9);
+    }
+
+    #[test]
+    fn timeline() {
+        let mut animation = Animation {
+            keyframes: vec![
+                keyframe(1.0, Easing::Linear, triangle(1.0, (0.0, 0.0, 0.0))),
+                keyframe(2.0, Easing::Step, triangle(2.0, (0.0, 0.0, 0.0))),
+                keyframe(3.0, Easing::Linear, triangle(4.0, (0.0, 0.0, 0.0)))
+            ],
+            looping: false
+        };
+        let size = |animation: &Animation, time| animation.model_at(time).unwrap().paths[0].points[1].location.0;
+
+        assert_eq!(animation.duration(), 2.0);
+        assert_eq!(size(&animation, 0.0), 1.0);
+        assert_eq!(size(&animation, 1.5), 1.5);
+        assert_eq!(size(&animation, 2.5), 2.0);
+        assert_eq!(size(&animation, 3.0), 4.0);
+        assert_eq!(size(&animation, 10.0), 4.0);
+
+        animation.looping = true;
+        assert_eq!(size(&animation, 3.5), 1.5);
+        assert_eq!(size(&animation, -0.5), 1.5);
+
+        assert!(Animation { keyframes: Vec::new(), looping: true }.model_at(1.0).is_none());
+        assert!(animation.model_at(::std::f64::NAN).is_none());
+        assert!(animation.model_at(::std::f64::INFINITY).is_none());
+
+        animation.looping = false;
+        animation.keyframes[2].time = ::std::f64::NAN;
+        assert!(animation.model_at(2.5).is_none());
+    }
+
+    #[test]
+    fn write_and_read() {
+        let animation = Animation {
+            keyframes: vec![
+                keyframe(0.0, Easing::CubicBezier(0.1, 0.2, 0.3, 0.4), triangle(1.0, (1.0, 0.0, 0.0))),
+                keyframe(0.5, Easing::EaseOut, triangle(2.0, (0.0, 1.0, 0.0)))
+            ],
+            looping: true
+        };
+
+        let mut writer = BinaryWriter::new(Vec::new());
+        animation.write(&mut writer).unwrap();
+        let data = writer.into_inner();
+
+        let read = Animation::read(&mut BinaryReader::new(Cursor::new(&data[..]))).unwrap();
+        assert!(read.looping);
+        assert_eq!(read.keyframes.len(), 2);
+        assert_eq!(read.keyframes[0].easing, Easing::CubicBezier(0.1, 0.2, 0.3, 0.4));
+        assert_eq!(read.keyframes[1].time, 0.5);
+        assert_eq!(read.keyframes[1].easing, Easing::EaseOut);
+        assert_eq!(read.keyframes[1].model.paths[0].paint, Paint::solid((0.0, 1.0, 0.0)));
+
+        let mut output = Vec::new();
+        Animation {
+            keyframes: vec![keyframe(0.0, Easing::Step, triangle(1.0, (1.0, 1.0, 1.0)))],
+            looping: false
+        }.write(&mut TextWriter::new(&mut output)).unwrap();
+        let text = String::from_utf8(output).unwrap();
+        assert!(text.starts_with("(ANIM"));
+        assert!(text.contains("(EASE STEP)"));
+        assert!(text.contains("(SHAP"));
+
+        // Keyframes out of order, or that can't be blended
+        let mut mismatched = triangle(1.0, (0.0, 0.0, 0.0));
+        mismatched.paths[0].points.pop();
+        for &(time, ref model) in &[(-1.0, triangle(1.0, (0.0, 0.0, 0.0))), (1.0, mismatched)] {
+            let mut output = Vec::new();
+            Animation {
+                keyframes: vec![keyframe(0.0, Easing::Linear, triangle(1.0, (0.0, 0.0, 0.0))),
+                                keyframe(time, Easing::Linear, model.clone())],
+                looping: false
+            }.write(&mut TextWriter::new(&mut output)).unwrap();
+
+            let mut reader = TextReader::new(Cursor::new(&output[..]));
+            assert_eq!(Animation::read(&mut reader).err().unwrap().kind(), io::ErrorKind::InvalidData);
+        }
+    }
+}
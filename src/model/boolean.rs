@@ -0,0 +1,499 @@
+use std::collections::{HashMap, HashSet};
+use data::Vec2;
+use super::{Model, Path, Point, FillRule, Layer, Clip};
+use super::simplify::{flatten_outline, fit_curves};
+
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub enum BooleanOp {
+    Union,
+    Intersection,
+    Difference,
+    Xor
+}
+
+impl BooleanOp {
+    fn apply(self, a: bool, b: bool) -> bool {
+        match self {
+            BooleanOp::Union => a || b,
+            BooleanOp::Intersection => a && b,
+            BooleanOp::Difference => a && !b,
+            BooleanOp::Xor => a != b
+        }
+    }
+}
+
+impl Path {
+    pub fn union(&self, other: &Path, tolerance: f64) -> Path {
+        self.boolean(other, BooleanOp::Union, tolerance)
+    }
+
+    pub fn intersection(&self, other: &Path, tolerance: f64) -> Path {
+        self.boolean(other, BooleanOp::Intersection, tolerance)
+    }
+
+    pub fn difference(&self, other: &Path, tolerance: f64) -> Path {
+        self.boolean(other, BooleanOp::Difference, tolerance)
+    }
+
+    pub fn xor(&self, other: &Path, tolerance: f64) -> Path {
+        self.boolean(other, BooleanOp::Xor, tolerance)
+    }
+
+    // Combines the areas of two paths under their fill rules, keeping this
+    // one's colour and stroke. Curves are flattened to within tolerance and
+    // refitted to within tolerance of that. Holes come out as contours.
+    pub fn boolean(&self, other: &Path, op: BooleanOp, tolerance: f64) -> Path {
+        let outlines = combine(&[self, other], tolerance, |inside| op.apply(inside[0], inside[1]));
+        result(self, outlines)
+    }
+}
+
+impl Model {
+    // Replaces each run of filled, unstroked paths of the same opaque paint,
+    // layers and clips with their union, which is drawn the same but without
+    // seams. Translucent paths aren't merged, as they're drawn darker where
+    // they overlap. Clip paths are left as they are.
+    pub fn merge(&self, tolerance: f64) -> Model {
+        let mut paths: Vec<Path> = Vec::with_capacity(self.paths.len());
+        // The index of the path each original ended up in
+        let mut merged = Vec::with_capacity(self.paths.len());
+        let mut start = 0;
+
+        while start < self.paths.len() {
+            let first = &self.paths[start];
+            let mergeable = |i: usize| {
+                let path = &self.paths[i];
+                path.filled && path.stroke.is_none() && path.paint == first.paint && path.paint.is_opaque() &&
+                    self.layers.iter().all(|layer| layer.paths.contains(&i) == layer.paths.contains(&start)) &&
+                    !self.is_clip_path(i) && self.clip_paths(i) == self.clip_paths(start)
+            };
+
+            let mut end = start + 1;
+            while end < self.paths.len() && mergeable(start) && mergeable(end) {
+                end += 1;
+            }
+
+            if end - start == 1 {
+                paths.push(first.clone());
+            } else {
+                let run = self.paths[start..end].iter().collect::<Vec<_>>();
+                paths.push(result(first, combine(&run, tolerance, |inside| inside.iter().any(|&i| i))));
+            }
+
+            for _ in start..end {
+                merged.push(paths.len() - 1);
+            }
+
+            start = end;
+        }
+
+        let remap = |indices: &[usize]| -> Vec<usize> {
+            let mut indices = indices.iter().filter_map(|&i| merged.get(i).cloned()).collect::<Vec<_>>();
+            indices.sort();
+            indices.dedup();
+            indices
+        };
+
+        let layers = self.layers
+            .iter()
+            .map(|layer| Layer { paths: remap(&layer.paths), .. layer.clone() })
+            .collect();
+
+        let clips = self.clips
+            .iter()
+            .map(|clip| Clip { path: merged[clip.path], paths: remap(&clip.paths) })
+            .collect();
+
+        Model {
+            paths: paths,
+            layers: layers,
+            clips: clips,
+            .. self.clone()
+        }
+    }
+}
+
+fn result(path: &Path, mut outlines: Vec<Vec<Point>>) -> Path {
+    let points = if outlines.len() > 0 { outlines.remove(0) } else { Vec::new() };
+
+    Path {
+        name: path.name.clone(),
+        paint: path.paint.clone(),
+        points: points,
+        contours: outlines,
+        fill_rule: FillRule::NonZero,
+        filled: path.filled,
+        stroke: path.stroke.clone()
+    }
+}
+
+struct Edge {
+    x0: f64, y0: f64,
+    x1: f64, y1: f64,
+    winding: i32,
+    source: usize
+}
+
+impl Edge {
+    fn x_at(&self, y: f64) -> f64 {
+        if y <= self.y0 {
+            self.x0
+        } else if y >= self.y1 {
+            self.x1
+        } else {
+            self.x0 + (y - self.y0) * (self.x1 - self.x0) / (self.y1 - self.y0)
+        }
+    }
+
+    fn crossing(&self, other: &Edge) -> Option<f64> {
+        let (dx1, dy1) = (self.x1 - self.x0, self.y1 - self.y0);
+        let (dx2, dy2) = (other.x1 - other.x0, other.y1 - other.y0);
+        let denom = dx1 * dy2 - dy1 * dx2;
+        if denom == 0.0 {
+            return None;
+        }
+
+        let (ex, ey) = (other.x0 - self.x0, other.y0 - self.y0);
+        let t = (ex * dy2 - ey * dx2) / denom;
+        let u = (ex * dy1 - ey * dx1) / denom;
+
+        if t > 0.0 && t < 1.0 && u > 0.0 && u < 1.0 {
+            Some(self.y0 + t * dy1)
+        } else {
+            None
+        }
+    }
+}
+
+fn key(p: Vec2) -> (u64, u64) {
+    // Adding zero turns -0 into 0
+    ((p.0 + 0.0).to_bits(), (p.1 + 0.0).to_bits())
+}
+
+// Finds the outlines of the area where inside holds, given whether a point
+// is inside each of the paths. As in the tessellator, the plane is split into
+// slabs with no crossings, and the spans inside each are traced around.
+fn combine<F: Fn(&[bool]) -> bool>(paths: &[&Path], tolerance: f64, inside: F) -> Vec<Vec<Point>> {
+    let mut edges = Vec::new();
+    let mut vertices = HashSet::new();
+    let mut smooth = HashSet::new();
+
+    for (source, path) in paths.iter().enumerate() {
+        for points in Some(&path.points).into_iter().chain(path.contours.iter()) {
+            let line = flatten_outline(points, tolerance);
+
+            for (i, &(p, curved)) in line.iter().enumerate() {
+                let q = line[(i + 1) % line.len()].0;
+
+                vertices.insert(key(p));
+                if curved {
+                    smooth.insert(key(p));
+                }
+
+                if p.1 < q.1 {
+                    edges.push(Edge { x0: p.0, y0: p.1, x1: q.0, y1: q.1, winding: 1, source: source });
+                } else if p.1 > q.1 {
+                    edges.push(Edge { x0: q.0, y0: q.1, x1: p.0, y1: p.1, winding: -1, source: source });
+                }
+            }
+        }
+    }
+
+    let mut ys = Vec::with_capacity(edges.len() * 2);
+    for (i, edge) in edges.iter().enumerate() {
+        ys.push(edge.y0);
+        ys.push(edge.y1);
+
+        for other in &edges[i + 1..] {
+            if let Some(y) = edge.crossing(other) {
+                ys.push(y);
+            }
+        }
+    }
+
+    ys.sort_by(|a, b| a.partial_cmp(b).unwrap());
+    ys.dedup();
+
+    // The spans inside each slab, as the x of their left and right sides at
+    // the bottom and top
+    let mut slabs = Vec::with_capacity(ys.len());
+    for w in ys.windows(2) {
+        let (y0, y1) = (w[0], w[1]);
+        let mid = (y0 + y1) / 2.0;
+
+        let mut active = edges.iter()
+            .filter(|e| e.y0 <= y0 && e.y1 >= y1)
+            .map(|e| (e.x_at(mid), e.x_at(y0), e.x_at(y1), e))
+            .collect::<Vec<_>>();
+        active.sort_by(|a, b| a.0.partial_cmp(&b.0).unwrap());
+
+        let mut windings = vec![0; paths.len()];
+        let state = |windings: &[i32]| {
+            let insides = paths.iter().zip(windings).map(|(p, &w)| p.fill_rule.is_inside(w)).collect::<Vec<_>>();
+            inside(&insides)
+        };
+
+        let mut spans = Vec::new();
+        let mut left = (0.0, 0.0);
+        for &(_, x0, x1, edge) in &active {
+            let was_inside = state(&windings);
+            windings[edge.source] += edge.winding;
+            let inside = state(&windings);
+
+            if !was_inside && inside {
+                left = (x0, x1);
+            } else if was_inside && !inside {
+                spans.push((left.0, left.1, x0, x1));
+            }
+        }
+
+        slabs.push(spans);
+    }
+
+    // Edges crossing at a level can come out the wrong way round there, so
+    // nearby values are snapped together
+    for k in 0..ys.len() {
+        let mut xs = Vec::new();
+        if k > 0 {
+            xs.extend(slabs[k - 1].iter().flat_map(|s| vec![s.1, s.3]));
+        }
+        if k < slabs.len() {
+            xs.extend(slabs[k].iter().flat_map(|s| vec![s.0, s.2]));
+        }
+        xs.sort_by(|a, b| a.partial_cmp(b).unwrap());
+
+        let snap = |x: f64| {
+            let i = xs.iter().position(|&v| v >= x - tolerance * 1e-6).unwrap_or(0);
+            xs[i]
+        };
+        if k > 0 {
+            for span in &mut slabs[k - 1] {
+                *span = (span.0, snap(span.1), span.2, snap(span.3));
+            }
+        }
+        if k < slabs.len() {
+            for span in &mut slabs[k] {
+                *span = (snap(span.0), span.1, snap(span.2), span.3);
+            }
+        }
+    }
+
+    // The sides of the spans, and the parts of each level covered on only
+    // one side, go around the area anticlockwise
+    let mut segments = Vec::new();
+    {
+        let mut add = |a: Vec2, b: Vec2| if a != b { segments.push((a, b)); };
+
+        for (k, spans) in slabs.iter().enumerate() {
+            let (y0, y1) = (ys[k], ys[k + 1]);
+            for &(l0, l1, r0, r1) in spans {
+                add((l1, y1), (l0, y0));
+                add((r0, y0), (r1, y1));
+            }
+        }
+
+        for (k, &y) in ys.iter().enumerate() {
+            let below = if k > 0 { slabs[k - 1].iter().map(|s| (s.1, s.3)).collect() } else { Vec::new() };
+            let above = if k < slabs.len() { slabs[k].iter().map(|s| (s.0, s.2)).collect() } else { Vec::new() };
+
+            let mut xs = below.iter().chain(above.iter()).flat_map(|&(l, r)| vec![l, r]).collect::<Vec<_>>();
+            xs.sort_by(|a, b| a.partial_cmp(b).unwrap());
+            xs.dedup();
+
+            let covered = |spans: &Vec<(f64, f64)>, x: f64| spans.iter().any(|&(l, r)| l < x && x < r);
+            for w in xs.windows(2) {
+                let mid = (w[0] + w[1]) / 2.0;
+                match (covered(&above, mid), covered(&below, mid)) {
+                    (true, false) => add((w[0], y), (w[1], y)),
+                    (false, true) => add((w[1], y), (w[0], y)),
+                    _ => ()
+                }
+            }
+        }
+    }
+
+    let mut starts: HashMap<(u64, u64), Vec<usize>> = HashMap::new();
+    for (i, &(a, _)) in segments.iter().enumerate() {
+        starts.entry(key(a)).or_insert_with(Vec::new).push(i);
+    }
+
+    let mut used = vec![false; segments.len()];
+    let mut outlines = Vec::new();
+
+    for i in 0..segments.len() {
+        let mut line = Vec::new();
+        let mut j = i;
+
+        while !used[j] {
+            used[j] = true;
+            line.push(segments[j].0);
+
+            match starts.get(&key(segments[j].1)).and_then(|next| next.iter().find(|&&k| !used[k])) {
+                Some(&k) => j = k,
+                None => break
+            }
+        }
+
+        // Drop the tiny steps left where edges cross
+        let mut k = 0;
+        while line.len() > 1 && k < line.len() {
+            let (p, q) = (line[k], line[(k + 1) % line.len()]);
+            if (p.0 - q.0).abs() + (p.1 - q.1).abs() < tolerance * 1e-6 {
+                line.remove((k + 1) % line.len());
+            } else {
+                k += 1;
+            }
+        }
+
+        if line.len() < 3 {
+            continue;
+        }
+
+        // Points of the originals keep their corners, and new points where
+        // the outlines cross are corners too
+        let corners = (0..line.len())
+            .map(|k| {
+                let p = line[k];
+                if vertices.contains(&key(p)) {
+                    return !smooth.contains(&key(p));
+                }
+
+                let (prev, next) = (line[(k + line.len() - 1) % line.len()], line[(k + 1) % line.len()]);
+                let (u, v) = ((p.0 - prev.0, p.1 - prev.1), (next.0 - p.0, next.1 - p.1));
+                (u.0 * v.1 - u.1 * v.0).atan2(u.0 * v.0 + u.1 * v.1).abs() > 1e-6
+            })
+            .collect::<Vec<_>>();
+
+        outlines.push(fit_curves(&line, &corners, true, tolerance));
+    }
+
+    outlines
+}
+
+#[cfg(test)]
+mod tests {
+    use model::{Model, Path, FillRule, Paint, Layer, path};
+
+    fn square(x: f64, y: f64, size: f64) -> Path {
+        path(&[(x, y, 0.0), (x + size, y, 0.0), (x + size, y + size, 0.0), (x, y + size, 0.0)])
+    }
+
+    // A circle-like outline from four controls
+    fn blob(x: f64, y: f64, r: f64) -> Path {
+        let mut path = square(x - r, y - r, 2.0 * r);
+        for point in &mut path.points {
+            point.curve_bias = 0.5;
+        }
+        path
+    }
+
+    fn area_near(path: &Path, area: f64, tolerance: f64) -> bool {
+        (path.area() - area).abs() < tolerance
+    }
+
+    #[test]
+    fn squares() {
+        let (a, b) = (square(0.0, 0.0, 2.0), square(1.0, 1.0, 2.0));
+
+        let union = a.union(&b, 0.01);
+        assert!(area_near(&union, 7.0, 1e-9));
+        assert_eq!(union.points.len(), 8);
+        assert_eq!(union.contours.len(), 0);
+        assert!(union.points.iter().all(|p| p.curve_bias == 0.0));
+
+        let intersection = a.intersection(&b, 0.01);
+        assert!(area_near(&intersection, 1.0, 1e-9));
+        assert_eq!(intersection.bounds(), Some(((1.0, 1.0), (2.0, 2.0))));
+        assert_eq!(intersection.points.len(), 4);
+
+        let difference = a.difference(&b, 0.01);
+        assert!(area_near(&difference, 3.0, 1e-9));
+        assert!(difference.contains((0.5, 1.5)) && !difference.contains((1.5, 1.5)));
+
+        let xor = a.xor(&b, 0.01);
+        assert!(area_near(&xor, 6.0, 1e-9));
+        assert!(!xor.contains((1.5, 1.5)) && xor.contains((2.5, 2.5)));
+
+        // Disjoint results are separate outlines
+        let apart = a.union(&square(5.0, 0.0, 1.0), 0.01);
+        assert_eq!(apart.contours.len(), 1);
+        assert!(area_near(&apart, 5.0, 1e-9));
+        assert!(area_near(&a.intersection(&square(5.0, 0.0, 1.0), 0.01), 0.0, 1e-9));
+    }
+
+    #[test]
+    fn holes() {
+        let frame = square(0.0, 0.0, 4.0).difference(&square(1.0, 1.0, 2.0), 0.01);
+        assert_eq!(frame.contours.len(), 1);
+        assert!(area_near(&frame, 12.0, 1e-9));
+        assert!(!frame.contains((2.0, 2.0)) && frame.contains((0.5, 2.0)));
+
+        // Holes in the inputs follow their fill rules
+        let mut even_odd = square(0.0, 0.0, 4.0);
+        even_odd.contours.push(square(1.0, 1.0, 2.0).points);
+        even_odd.fill_rule = FillRule::EvenOdd;
+        let filled = even_odd.union(&square(1.5, 1.5, 1.0), 0.01);
+        assert!(area_near(&filled, 13.0, 1e-9));
+        assert!(filled.contains((2.0, 2.0)) && !filled.contains((1.2, 2.0)));
+    }
+
+    #[test]
+    fn curves() {
+        let (a, b) = (blob(0.0, 0.0, 2.0), blob(1.5, 0.0, 2.0));
+        let tolerance = 0.01;
+
+        let union = a.union(&b, tolerance);
+        let intersection = a.intersection(&b, tolerance);
+        // Each curve is within tolerance of the flattened outline, which is
+        // within tolerance of the original
+        let error = 2.0 * tolerance * (a.perimeter() + b.perimeter());
+        assert!(area_near(&union, a.area() + b.area() - intersection.area(), error));
+        assert!(union.contains((3.2, 0.0)) && !union.contains((0.75, 1.95)));
+
+        // The curves are refitted, with corners where the outlines cross
+        for path in &[&union, &intersection] {
+            assert!(path.points.len() < a.flatten(tolerance).points.len());
+            assert!(path.points.iter().any(|p| p.curve_bias > 0.0));
+            assert!(path.points.iter().any(|p| p.curve_bias == 0.0));
+        }
+
+        let half = a.difference(&square(0.0, -5.0, 10.0), tolerance);
+        assert!(area_near(&half, a.area() / 2.0, error / 2.0));
+        assert_eq!(half.bounds().map(|b| (b.1).0), Some(0.0));
+    }
+
+    #[test]
+    fn merge() {
+        let mut blue = square(10.0, 0.0, 1.0);
+        blue.paint = Paint::solid((0.0, 0.0, 1.0));
+
+        let mut model = Model {
+            paths: vec![square(0.0, 0.0, 2.0), square(1.0, 1.0, 2.0), square(1.5, 0.0, 1.0),
+                        blue, square(20.0, 0.0, 1.0)],
+            .. Default::default()
+        };
+        let merged = model.merge(0.01);
+
+        assert_eq!(merged.paths.len(), 3);
+        assert!(area_near(&merged.paths[0], 7.5, 1e-9));
+        assert_eq!(merged.paths[1].paint, Paint::solid((0.0, 0.0, 1.0)));
+        assert!(area_near(&merged.paths[2], 1.0, 1e-9));
+
+        // Paths in different layers stay apart, and layers follow their paths
+        model.layers = vec![Layer { name: "top".to_string(), paths: vec![1, 4], visible: true, locked: false }];
+        let merged = model.merge(0.01);
+        assert_eq!(merged.paths.len(), 5);
+
+        model.layers[0].paths = vec![0, 1, 2, 4];
+        let merged = model.merge(0.01);
+        assert_eq!(merged.paths.len(), 3);
+        assert_eq!(merged.layers[0].paths, vec![0, 2]);
+
+        // Translucent paths blend twice where they overlap, so stay apart
+        model.layers.clear();
+        for path in &mut model.paths[..3] {
+            path.paint = Paint::Solid((1.0, 0.0, 0.0, 0.5));
+        }
+        assert_eq!(model.merge(0.01).paths.len(), 5);
+    }
+}
@@ -0,0 +1,149 @@
+use std::io;
+use data::{Value, Tag, Reader, Writer};
+use super::{Model, PATHS};
+
+// A path used as a mask for others, which are only drawn where they're
+// inside its outlines. The clip path itself isn't drawn.
+#[derive(Clone, PartialEq, Debug)]
+pub struct Clip {
+    pub path: usize,
+    pub paths: Vec<usize>
+}
+
+const MASK: Tag = tag!(M A S K);
+
+impl Model {
+    pub fn is_clip_path(&self, path: usize) -> bool {
+        self.clips.iter().any(|clip| clip.path == path)
+    }
+
+    // The paths that a path is clipped by, all of which it must be inside
+    pub fn clip_paths(&self, path: usize) -> Vec<usize> {
+        self.clips.iter().filter(|clip| clip.paths.contains(&path)).map(|clip| clip.path).collect()
+    }
+
+    // Whether every clip refers to paths that exist, and no clip path is
+    // itself clipped
+    pub fn has_valid_clips(&self) -> bool {
+        self.clips.iter().all(|clip| {
+            clip.path < self.paths.len() &&
+                clip.paths.iter().all(|&i| i < self.paths.len() && !self.is_clip_path(i))
+        })
+    }
+}
+
+impl Clip {
+    pub fn write(&self, writer: &mut Writer) -> io::Result<()> {
+        try!(writer.write_start());
+
+        try!(writer.write_start());
+        try!(writer.write_value(&Value::Tag(MASK)));
+        try!(writer.write_value(&Value::Int(self.path as i32)));
+        try!(writer.write_end());
+
+        try!(writer.write_start());
+        try!(writer.write_value(&Value::Tag(PATHS)));
+        let paths = self.paths.iter().map(|&i| i as i32).collect::<Vec<_>>().into_boxed_slice();
+        try!(writer.write_value(&Value::IntArray(paths)));
+        try!(writer.write_end());
+
+        writer.write_end()
+    }
+
+    pub fn read(reader: &mut Reader) -> io::Result<Clip> {
+        let mut path = None;
+        let mut paths = Vec::new();
+
+        try!(reader.expect_start());
+
+        while try!(reader.expect_start_or_end()) {
+            match try!(reader.expect_tag()) {
+                MASK => {
+                    let index = try!(reader.expect_int());
+                    if index < 0 {
+                        return Err(io::Error::new(io::ErrorKind::InvalidData, "Unexpected data"));
+                    }
+                    path = Some(index as usize);
+                    try!(reader.skip_to_end());
+                },
+                PATHS => {
+                    let indices = try!(reader.expect_int_array());
+                    if indices.iter().any(|&i| i < 0) {
+                        return Err(io::Error::new(io::ErrorKind::InvalidData, "Unexpected data"));
+                    }
+                    paths = indices.iter().map(|&i| i as usize).collect();
+                    try!(reader.skip_to_end());
+                },
+                _ => try!(reader.skip_to_end())
+            }
+        }
+
+        match path {
+            Some(path) => Ok(Clip { path: path, paths: paths }),
+            None => Err(io::Error::new(io::ErrorKind::InvalidData, "Unexpected data"))
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Cursor;
+    use data::{BinaryReader, BinaryWriter, TextReader};
+    use model::{Model, Path, path};
+    use model::svg::write_svg;
+
+    fn square(x: f64, size: f64) -> Path {
+        path(&[(x, 0.0, 0.0), (x + size, 0.0, 0.0), (x + size, size, 0.0), (x, size, 0.0)])
+    }
+
+    // A window showing part of a square, beside one that isn't clipped
+    fn window() -> Model {
+        Model {
+            paths: vec![square(0.0, 4.0), square(1.0, 2.0), square(3.0, 4.0)],
+            clips: vec![Clip { path: 1, paths: vec![0] }],
+            .. Default::default()
+        }
+    }
+
+    #[test]
+    fn clip_paths() {
+        let model = window();
+        assert!(model.has_valid_clips());
+        assert!(model.is_clip_path(1) && !model.is_clip_path(0));
+        assert_eq!(model.clip_paths(0), vec![1]);
+        assert_eq!(model.clip_paths(2), Vec::<usize>::new());
+
+        // Clip paths aren't drawn or hit, and clipped paths are only hit
+        // inside them
+        assert_eq!(model.hit_test((2.0, 1.0)), Some(0));
+        assert_eq!(model.hit_test((0.5, 1.0)), None);
+        assert_eq!(model.hit_test((3.5, 1.0)), Some(2));
+        let mut svg = Vec::new();
+        write_svg(&model, &mut svg).unwrap();
+        let svg = String::from_utf8(svg).unwrap();
+        assert!(svg.contains("<clipPath id=\"clip1\">"));
+        assert!(svg.contains("clip-path=\"url(#clip1)\""));
+
+        let mut invalid = window();
+        invalid.clips.push(Clip { path: 0, paths: vec![1] });
+        assert!(!invalid.has_valid_clips());
+        invalid.clips = vec![Clip { path: 3, paths: vec![0] }];
+        assert!(!invalid.has_valid_clips());
+    }
+
+    #[test]
+    fn write_and_read() {
+        let model = window();
+        let mut writer = BinaryWriter::new(Vec::new());
+        model.write(&mut writer).unwrap();
+        let data = writer.into_inner();
+
+        let read = Model::read(&mut BinaryReader::new(Cursor::new(&data[..]))).unwrap();
+        assert_eq!(read.clips, model.clips);
+
+        let data = b"(SHAP (PTHS 1 ((COLR [1.0 1.0 1.0]) (PNTS {[0.0 0.0]} {0.0})))
+                           (CLPS 1 ((MASK 0) (PTHS {0}))))";
+        assert!(Model::read(&mut TextReader::new(Cursor::new(&data[..]))).is_err());
+    }
+}
@@ -0,0 +1,660 @@
+use std::io;
+use super::{Model, Path, Point, FillRule, Paint, Affine2, NO_WEIGHTS};
+
+// A TrueType font, or an OpenType one with TrueType outlines, read from its
+// bytes. Glyphs are read as they're needed. Distances are in font units,
+// with the y-axis pointing up from the baseline.
+pub struct Font {
+    data: Vec<u8>,
+    pub units_per_em: f64,
+    pub ascent: f64,
+    pub descent: f64,
+    pub line_gap: f64,
+    glyph_count: usize,
+    long_offsets: bool,
+    // Offsets and lengths of the tables used after loading
+    cmap: (usize, usize),
+    loca: (usize, usize),
+    glyf: (usize, usize),
+    hmtx: (usize, usize),
+    metric_count: usize,
+    // Kerning pairs from the kern table, sorted by left then right glyph
+    kerning: Vec<(u32, i16)>
+}
+
+// Glyphs can be made of other glyphs, but not too deeply
+const MAX_COMPONENT_DEPTH: usize = 8;
+
+const ON_CURVE: u8 = 1;
+const X_SHORT: u8 = 2;
+const Y_SHORT: u8 = 4;
+const REPEAT: u8 = 8;
+const X_SAME_OR_POSITIVE: u8 = 16;
+const Y_SAME_OR_POSITIVE: u8 = 32;
+
+const ARGS_ARE_WORDS: u16 = 1;
+const ARGS_ARE_XY_VALUES: u16 = 2;
+const HAVE_A_SCALE: u16 = 8;
+const MORE_COMPONENTS: u16 = 0x20;
+const HAVE_AN_X_AND_Y_SCALE: u16 = 0x40;
+const HAVE_A_TWO_BY_TWO: u16 = 0x80;
+
+impl Font {
+    pub fn from_bytes(data: Vec<u8>) -> io::Result<Font> {
+        match try!(u32_at(&data, 0)) {
+            0x00010000 | 0x74727565 => (),
+            0x4f54544f => return Err(io::Error::new(io::ErrorKind::InvalidData, "CFF outlines aren't supported")),
+            _ => return Err(invalid())
+        }
+
+        let head = try!(find_table(&data, b"head"));
+        let maxp = try!(find_table(&data, b"maxp"));
+        let hhea = try!(find_table(&data, b"hhea"));
+        let cmap = try!(find_table(&data, b"cmap"));
+        let kerning = match find_table(&data, b"kern") {
+            Ok(kern) => try!(read_kerning(&data[kern.0..kern.0 + kern.1])),
+            Err(_) => Vec::new()
+        };
+
+        let units_per_em = try!(u16_at(&data, head.0 + 18));
+        if units_per_em == 0 {
+            return Err(invalid());
+        }
+
+        Ok(Font {
+            units_per_em: units_per_em as f64,
+            ascent: try!(i16_at(&data, hhea.0 + 4)) as f64,
+            descent: try!(i16_at(&data, hhea.0 + 6)) as f64,
+            line_gap: try!(i16_at(&data, hhea.0 + 8)) as f64,
+            glyph_count: try!(u16_at(&data, maxp.0 + 4)) as usize,
+            long_offsets: try!(i16_at(&data, head.0 + 50)) != 0,
+            cmap: try!(best_cmap(&data[cmap.0..cmap.0 + cmap.1])).map_or((0, 0), |(o, l)| (cmap.0 + o, l)),
+            loca: try!(find_table(&data, b"loca")),
+            glyf: try!(find_table(&data, b"glyf")),
+            hmtx: try!(find_table(&data, b"hmtx")),
+            metric_count: try!(u16_at(&data, hhea.0 + 34)) as usize,
+            kerning: kerning,
+            data: data
+        })
+    }
+
+    // The glyph for a character, or 0 for the missing glyph
+    pub fn glyph_index(&self, c: char) -> u16 {
+        let cmap = &self.data[self.cmap.0..self.cmap.0 + self.cmap.1];
+        let glyph = match u16_at(cmap, 0) {
+            Ok(4) => format_4_glyph(cmap, c as u32),
+            Ok(12) => format_12_glyph(cmap, c as u32),
+            _ => Ok(0)
+        };
+
+        match glyph {
+            Ok(glyph) if (glyph as usize) < self.glyph_count => glyph,
+            _ => 0
+        }
+    }
+
+    // How far the pen moves after drawing a glyph
+    pub fn advance(&self, glyph: u16) -> f64 {
+        let hmtx = &self.data[self.hmtx.0..self.hmtx.0 + self.hmtx.1];
+        let metric = (glyph as usize).min(self.metric_count.saturating_sub(1));
+        u16_at(hmtx, metric * 4).unwrap_or(0) as f64
+    }
+
+    // The adjustment to the advance between two glyphs
+    pub fn kerning(&self, left: u16, right: u16) -> f64 {
+        let key = (left as u32) << 16 | right as u32;
+        match self.kerning.binary_search_by(|&(k, _)| k.cmp(&key)) {
+            Ok(i) => self.kerning[i].1 as f64,
+            Err(_) => 0.0
+        }
+    }
+
+    // The outlines of a glyph as a path, filled with the paint, or None if
+    // it has none, as for a space. Contours are reversed from TrueType's, so
+    // outer ones wind anticlockwise.
+    pub fn glyph_path(&self, glyph: u16, paint: Paint) -> io::Result<Option<Path>> {
+        let mut outlines = try!(self.glyph_outlines(glyph, 0));
+        if outlines.len() == 0 {
+            return Ok(None);
+        }
+
+        for outline in &mut outlines {
+            outline.reverse();
+        }
+        let points = outlines.remove(0);
+
+        Ok(Some(Path {
+            name: None,
+            paint: paint,
+            points: points,
+            contours: outlines,
+            fill_rule: FillRule::NonZero,
+            filled: true,
+            stroke: None
+        }))
+    }
+
+    // Lays out lines of text with kerning, starting at the origin on the
+    // first baseline, at a size in model units per em. Each glyph is a path
+    // named after its character.
+    pub fn layout(&self, text: &str, size: f64, paint: Paint) -> io::Result<Model> {
+        let scale = size / self.units_per_em;
+        let mut paths = Vec::new();
+
+        for (i, line) in text.lines().enumerate() {
+            let y = -(i as f64) * (self.ascent - self.descent + self.line_gap);
+            let mut x = 0.0;
+            let mut previous = None;
+
+            for c in line.chars() {
+                let glyph = self.glyph_index(c);
+                if let Some(previous) = previous {
+                    x += self.kerning(previous, glyph);
+                }
+
+                if let Some(mut path) = try!(self.glyph_path(glyph, paint.clone())) {
+                    path.name = Some(c.to_string());
+                    path.transform(&Affine2::translate(x, y).then(&Affine2::scale(scale, scale)));
+                    paths.push(path);
+                }
+
+                x += self.advance(glyph);
+                previous = Some(glyph);
+            }
+        }
+
+        Ok(Model { paths: paths, .. Default::default() })
+    }
+
+    // TrueType's quadratic outlines are already points and controls, with
+    // the implied on-curve points halfway between controls
+    fn glyph_outlines(&self, glyph: u16, depth: usize) -> io::Result<Vec<Vec<Point>>> {
+        if glyph as usize >= self.glyph_count || depth > MAX_COMPONENT_DEPTH {
+            return Err(invalid());
+        }
+
+        let loca = &self.data[self.loca.0..self.loca.0 + self.loca.1];
+        let (start, end) = if self.long_offsets {
+            (try!(u32_at(loca, glyph as usize * 4)) as usize, try!(u32_at(loca, glyph as usize * 4 + 4)) as usize)
+        } else {
+            (try!(u16_at(loca, glyph as usize * 2)) as usize * 2, try!(u16_at(loca, glyph as usize * 2 + 2)) as usize * 2)
+        };
+
+        if start >= end {
+            return Ok(Vec::new());
+        }
+        if end > self.glyf.1 {
+            return Err(invalid());
+        }
+        let data = &self.data[self.glyf.0 + start..self.glyf.0 + end];
+
+        let contour_count = try!(i16_at(data, 0));
+        if contour_count >= 0 {
+            simple_outlines(data, contour_count as usize)
+        } else {
+            self.composite_outlines(data, depth)
+        }
+    }
+
+    fn composite_outlines(&self, data: &[u8], depth: usize) -> io::Result<Vec<Vec<Point>>> {
+        let mut outlines = Vec::new();
+        let mut offset = 10;
+
+        loop {
+            let flags = try!(u16_at(data, offset));
+            let glyph = try!(u16_at(data, offset + 2));
+            offset += 4;
+
+            let (dx, dy) = if flags & ARGS_ARE_WORDS != 0 {
+                offset += 4;
+                (try!(i16_at(data, offset - 4)) as f64, try!(i16_at(data, offset - 2)) as f64)
+            } else {
+                offset += 2;
+                (try!(u8_at(data, offset - 2)) as i8 as f64, try!(u8_at(data, offset - 1)) as i8 as f64)
+            };
+
+            let mut matrix = [1.0, 0.0, 0.0, 1.0];
+            if flags & HAVE_A_SCALE != 0 {
+                let scale = try!(f2dot14_at(data, offset));
+                matrix = [scale, 0.0, 0.0, scale];
+                offset += 2;
+            } else if flags & HAVE_AN_X_AND_Y_SCALE != 0 {
+                matrix = [try!(f2dot14_at(data, offset)), 0.0, 0.0, try!(f2dot14_at(data, offset + 2))];
+                offset += 4;
+            } else if flags & HAVE_A_TWO_BY_TWO != 0 {
+                matrix = [try!(f2dot14_at(data, offset)), try!(f2dot14_at(data, offset + 2)),
+                          try!(f2dot14_at(data, offset + 4)), try!(f2dot14_at(data, offset + 6))];
+                offset += 8;
+            }
+
+            // Components placed by matching points are left where they are
+            let (dx, dy) = if flags & ARGS_ARE_XY_VALUES != 0 { (dx, dy) } else { (0.0, 0.0) };
+            let transform = Affine2 { matrix: [matrix[0], matrix[1], matrix[2], matrix[3], dx, dy] };
+
+            for mut outline in try!(self.glyph_outlines(glyph, depth + 1)) {
+                for point in &mut outline {
+                    point.location = transform.apply(point.location);
+                }
+                outlines.push(outline);
+            }
+
+            if flags & MORE_COMPONENTS == 0 {
+                return Ok(outlines);
+            }
+        }
+    }
+}
+
+fn simple_outlines(data: &[u8], contour_count: usize) -> io::Result<Vec<Vec<Point>>> {
+    let mut ends = Vec::with_capacity(contour_count);
+    for i in 0..contour_count {
+        ends.push(try!(u16_at(data, 10 + i * 2)) as usize);
+    }
+    let point_count = ends.last().map_or(0, |&end| end + 1);
+
+    let instructions = try!(u16_at(data, 10 + contour_count * 2)) as usize;
+    let mut offset = 12 + contour_count * 2 + instructions;
+
+    let mut flags = Vec::with_capacity(point_count);
+    while flags.len() < point_count {
+        let flag = try!(u8_at(data, offset));
+        offset += 1;
+        flags.push(flag);
+
+        if flag & REPEAT != 0 {
+            let count = try!(u8_at(data, offset));
+            offset += 1;
+            for _ in 0..count {
+                flags.push(flag);
+            }
+        }
+    }
+    flags.truncate(point_count);
+
+    // Each coordinate is a byte with a separate sign, a word, or the same as
+    // the last
+    let mut coordinates = |short: u8, same_or_positive: u8| -> io::Result<Vec<f64>> {
+        let mut value = 0i32;
+        let mut values = Vec::with_capacity(point_count);
+        for &flag in &flags {
+            if flag & short != 0 {
+                let delta = try!(u8_at(data, offset)) as i32;
+                offset += 1;
+                value += if flag & same_or_positive != 0 { delta } else { -delta };
+            } else if flag & same_or_positive == 0 {
+                value += try!(i16_at(data, offset)) as i32;
+                offset += 2;
+            }
+            values.push(value as f64);
+        }
+        Ok(values)
+    };
+    let xs = try!(coordinates(X_SHORT, X_SAME_OR_POSITIVE));
+    let ys = try!(coordinates(Y_SHORT, Y_SAME_OR_POSITIVE));
+
+    let mut outlines = Vec::with_capacity(contour_count);
+    let mut start = 0;
+    for end in ends {
+        if end < start || end >= point_count {
+            return Err(invalid());
+        }
+
+        outlines.push((start..end + 1).map(|i| Point {
+            location: (xs[i], ys[i]),
+            curve_bias: if flags[i] & ON_CURVE != 0 { 0.0 } else { 0.5 },
+            weights: NO_WEIGHTS
+        }).collect());
+        start = end + 1;
+    }
+
+    Ok(outlines)
+}
+
+// The offset and length of the Unicode subtable to use, preferring the full
+// range of format 12 to format 4
+fn best_cmap(cmap: &[u8]) -> io::Result<Option<(usize, usize)>> {
+    let mut best = None;
+
+    for i in 0..try!(u16_at(cmap, 2)) as usize {
+        let platform = try!(u16_at(cmap, 4 + i * 8));
+        let encoding = try!(u16_at(cmap, 6 + i * 8));
+        let offset = try!(u32_at(cmap, 8 + i * 8)) as usize;
+
+        let unicode = platform == 0 || (platform == 3 && (encoding == 1 || encoding == 10));
+        let (score, length) = match try!(u16_at(cmap, offset)) {
+            4 if unicode => (1, try!(u16_at(cmap, offset + 2)) as usize),
+            12 if unicode => (2, try!(u32_at(cmap, offset + 4)) as usize),
+            _ => continue
+        };
+
+        if best.map_or(true, |(best, _, _)| score > best) {
+            try!(bytes(cmap, offset, length));
+            best = Some((score, offset, length));
+        }
+    }
+
+    Ok(best.map(|(_, offset, length)| (offset, length)))
+}
+
+fn format_4_glyph(cmap: &[u8], c: u32) -> io::Result<u16> {
+    if c > 0xffff {
+        return Ok(0);
+    }
+
+    let segments = try!(u16_at(cmap, 6)) as usize / 2;
+    let (ends, starts, deltas, ranges) = (14, 16 + segments * 2, 16 + segments * 4, 16 + segments * 6);
+
+    for i in 0..segments {
+        if try!(u16_at(cmap, ends + i * 2)) as u32 >= c {
+            let start = try!(u16_at(cmap, starts + i * 2)) as u32;
+            if start > c {
+                return Ok(0);
+            }
+
+            let delta = try!(u16_at(cmap, deltas + i * 2)) as u32;
+            let range = try!(u16_at(cmap, ranges + i * 2)) as usize;
+            if range == 0 {
+                return Ok((c + delta) as u16);
+            }
+
+            let glyph = try!(u16_at(cmap, ranges + i * 2 + range + (c - start) as usize * 2)) as u32;
+            return Ok(if glyph == 0 { 0 } else { (glyph + delta) as u16 });
+        }
+    }
+
+    Ok(0)
+}
+
+fn format_12_glyph(cmap: &[u8], c: u32) -> io::Result<u16> {
+    for i in 0..try!(u32_at(cmap, 12)) as usize {
+        let start = try!(u32_at(cmap, 16 + i * 12));
+        let end = try!(u32_at(cmap, 20 + i * 12));
+        if start <= c && c <= end {
+            // Glyphs past the last that can be numbered are missing
+            return Ok(match try!(u32_at(cmap, 24 + i * 12)).checked_add(c - start) {
+                Some(glyph) if glyph <= 0xffff => glyph as u16,
+                _ => 0
+            });
+        }
+    }
+
+    Ok(0)
+}
+
+// Horizontal pairs from the format 0 subtables of a version 0 kern table
+fn read_kerning(kern: &[u8]) -> io::Result<Vec<(u32, i16)>> {
+    let mut pairs = Vec::new();
+    if try!(u16_at(kern, 0)) != 0 {
+        return Ok(pairs);
+    }
+
+    let mut offset = 4;
+    for _ in 0..try!(u16_at(kern, 2)) {
+        let length = try!(u16_at(kern, offset + 2)) as usize;
+        let coverage = try!(u16_at(kern, offset + 4));
+
+        // Horizontal, format 0, and neither minimum values nor cross-stream
+        if coverage & 0xff07 == 1 {
+            for i in 0..try!(u16_at(kern, offset + 6)) as usize {
+                let pair = offset + 14 + i * 6;
+                let key = try!(u32_at(kern, pair));
+                pairs.push((key, try!(i16_at(kern, pair + 4))));
+            }
+        }
+
+        offset += length;
+    }
+
+    pairs.sort_by(|a, b| a.0.cmp(&b.0));
+    Ok(pairs)
+}
+
+fn find_table(data: &[u8], tag: &[u8]) -> io::Result<(usize, usize)> {
+    for i in 0..try!(u16_at(data, 4)) as usize {
+        let record = 12 + i * 16;
+        if try!(bytes(data, record, 4)) == tag {
+            let offset = try!(u32_at(data, record + 8)) as usize;
+            let length = try!(u32_at(data, record + 12)) as usize;
+            try!(bytes(data, offset, length));
+            return Ok((offset, length));
+        }
+    }
+
+    Err(invalid())
+}
+
+fn invalid() -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, "Invalid font")
+}
+
+fn bytes(data: &[u8], offset: usize, length: usize) -> io::Result<&[u8]> {
+    match offset.checked_add(length) {
+        Some(end) if end <= data.len() => Ok(&data[offset..end]),
+        _ => Err(invalid())
+    }
+}
+
+fn u8_at(data: &[u8], offset: usize) -> io::Result<u8> {
+    bytes(data, offset, 1).map(|b| b[0])
+}
+
+// Fonts are big-endian
+fn u16_at(data: &[u8], offset: usize) -> io::Result<u16> {
+    bytes(data, offset, 2).map(|b| (b[0] as u16) << 8 | b[1] as u16)
+}
+
+fn i16_at(data: &[u8], offset: usize) -> io::Result<i16> {
+    u16_at(data, offset).map(|v| v as i16)
+}
+
+fn u32_at(data: &[u8], offset: usize) -> io::Result<u32> {
+    bytes(data, offset, 4).map(|b| (b[0] as u32) << 24 | (b[1] as u32) << 16 | (b[2] as u32) << 8 | b[3] as u32)
+}
+
+fn f2dot14_at(data: &[u8], offset: usize) -> io::Result<f64> {
+    i16_at(data, offset).map(|v| v as f64 / 16384.0)
+}
+
+// A font with glyphs for ' ', 'A', 'V' and 'W', for tests elsewhere
+#[cfg(test)]
+pub fn sample_font() -> Font {
+    Font::from_bytes(tests::font_data(false)).unwrap()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use byteorder::{BigEndian, WriteBytesExt};
+    use data::Vec2;
+    use model::Paint;
+    use model::tessellate::signed_area;
+
+    fn push(data: &mut Vec<u8>, values: &[u16]) {
+        for &value in values {
+            data.write_u16::<BigEndian>(value).unwrap();
+        }
+    }
+
+    // Points as deltas from the last, each a byte or the same where they can
+    // be
+    fn simple_glyph(contours: &[&[(i16, i16, bool)]]) -> Vec<u8> {
+        let mut data = Vec::new();
+        push(&mut data, &[contours.len() as u16, 0, 0, 0, 0]);
+
+        let mut end = 0;
+        for contour in contours {
+            end += contour.len();
+            push(&mut data, &[end as u16 - 1]);
+        }
+        push(&mut data, &[0]);
+
+        let encode = |delta: i16, short: u8, same: u8, flag: &mut u8, output: &mut Vec<u8>| {
+            if delta == 0 {
+                *flag |= same;
+            } else if delta.abs() < 256 {
+                *flag |= short | if delta > 0 { same } else { 0 };
+                output.push(delta.abs() as u8);
+            } else {
+                output.write_i16::<BigEndian>(delta).unwrap();
+            }
+        };
+
+        let (mut flags, mut xs, mut ys) = (Vec::new(), Vec::new(), Vec::new());
+        let mut last = (0, 0);
+        for &(x, y, on) in contours.iter().flat_map(|c| c.iter()) {
+            let mut flag = if on { ON_CURVE } else { 0 };
+            encode(x - last.0, X_SHORT, X_SAME_OR_POSITIVE, &mut flag, &mut xs);
+            encode(y - last.1, Y_SHORT, Y_SAME_OR_POSITIVE, &mut flag, &mut ys);
+            flags.push(flag);
+            last = (x, y);
+        }
+
+        data.extend(flags.into_iter().chain(xs).chain(ys));
+        data
+    }
+
+    fn table_data(format_12: bool) -> Vec<(&'static [u8], Vec<u8>)> {
+        // A box with a hole, a curved V, and a W of two Vs, the second
+        // shrunk and moved right
+        let glyphs = vec![
+            Vec::new(),
+            simple_glyph(&[&[(0, 0, true), (0, 700, true), (500, 700, true), (500, 0, true)],
+                           &[(100, 100, true), (400, 100, true), (400, 600, true), (100, 600, true)]]),
+            simple_glyph(&[&[(0, 700, true), (500, 700, true), (250, 0, false)]]),
+            {
+                let mut data = Vec::new();
+                push(&mut data, &[0xffff, 0, 0, 0, 0]);
+                push(&mut data, &[ARGS_ARE_WORDS | ARGS_ARE_XY_VALUES | MORE_COMPONENTS, 2, 0, 0]);
+                push(&mut data, &[ARGS_ARE_XY_VALUES | HAVE_A_SCALE, 2, 100 << 8, 8192]);
+                data
+            },
+            Vec::new()
+        ];
+
+        let mut glyf = Vec::new();
+        let mut loca = Vec::new();
+        for glyph in &glyphs {
+            push(&mut loca, &[glyf.len() as u16 / 2]);
+            glyf.extend(glyph);
+            if glyf.len() % 2 == 1 {
+                glyf.push(0);
+            }
+        }
+        push(&mut loca, &[glyf.len() as u16 / 2]);
+
+        // ' ', 'A', 'V' and 'W' are glyphs 4, 1, 2 and 3
+        let mut cmap = Vec::new();
+        if format_12 {
+            push(&mut cmap, &[0, 1, 3, 10, 0, 12, 12, 0, 0, 52, 0, 0, 0, 3]);
+            for &(start, end, glyph) in &[(32, 32, 4), (65, 65, 1), (86, 87, 2)] {
+                push(&mut cmap, &[0, start, 0, end, 0, glyph]);
+            }
+        } else {
+            push(&mut cmap, &[0, 1, 3, 1, 0, 12, 4, 48, 0, 8, 0, 0, 0]);
+            push(&mut cmap, &[32, 65, 87, 0xffff, 0, 32, 65, 86, 0xffff]);
+            push(&mut cmap, &[4u16.wrapping_sub(32), 1u16.wrapping_sub(65), 2u16.wrapping_sub(86), 1, 0, 0, 0, 0]);
+        }
+
+        let mut head = vec![0; 54];
+        head[18] = (1000 >> 8) as u8;
+        head[19] = (1000 & 0xff) as u8;
+        let mut hhea = Vec::new();
+        push(&mut hhea, &[1, 0, 800, (-200i16) as u16, 100]);
+        hhea.extend(vec![0; 24]);
+        push(&mut hhea, &[5]);
+        let mut maxp = Vec::new();
+        push(&mut maxp, &[0, 0x5000, 5]);
+        let mut hmtx = Vec::new();
+        push(&mut hmtx, &[500, 0, 500, 0, 600, 0, 1100, 0, 250, 0]);
+        let mut kern = Vec::new();
+        push(&mut kern, &[0, 1, 0, 20, 1, 1, 6, 0, 0, 1, 2, (-80i16) as u16]);
+
+        vec![(b"cmap", cmap), (b"glyf", glyf), (b"head", head), (b"hhea", hhea),
+             (b"hmtx", hmtx), (b"kern", kern), (b"loca", loca), (b"maxp", maxp)]
+    }
+
+    pub fn font_data(format_12: bool) -> Vec<u8> {
+        let tables = table_data(format_12);
+        let mut data = Vec::new();
+        push(&mut data, &[1, 0, tables.len() as u16, 0, 0, 0]);
+
+        let mut offset = 12 + tables.len() * 16;
+        for &(tag, ref table) in &tables {
+            data.extend(tag);
+            push(&mut data, &[0, 0, 0, offset as u16, 0, table.len() as u16]);
+            offset += (table.len() + 3) / 4 * 4;
+        }
+        for &(_, ref table) in &tables {
+            data.extend(table);
+            while data.len() % 4 != 0 {
+                data.push(0);
+            }
+        }
+
+        data
+    }
+
+    fn locations(points: &[Point]) -> Vec<Vec2> {
+        points.iter().map(|p| p.location).collect()
+    }
+
+    #[test]
+    fn glyphs() {
+        for &format_12 in &[false, true] {
+            let font = Font::from_bytes(font_data(format_12)).unwrap();
+            let indices = " AVWZ".chars().map(|c| font.glyph_index(c)).collect::<Vec<_>>();
+            assert_eq!(indices, vec![4, 1, 2, 3, 0]);
+        }
+
+        let font = Font::from_bytes(font_data(false)).unwrap();
+        assert_eq!((font.units_per_em, font.ascent, font.descent, font.line_gap), (1000.0, 800.0, -200.0, 100.0));
+        assert_eq!((font.advance(1), font.advance(4)), (500.0, 250.0));
+        assert_eq!((font.kerning(1, 2), font.kerning(2, 1)), (-80.0, 0.0));
+
+        // Outer contours are turned anticlockwise and holes clockwise
+        let a = font.glyph_path(1, Paint::solid((1.0, 1.0, 1.0))).unwrap().unwrap();
+        assert_eq!(locations(&a.points), vec![(500.0, 0.0), (500.0, 700.0), (0.0, 700.0), (0.0, 0.0)]);
+        assert_eq!(a.contours.len(), 1);
+        assert!(signed_area(&locations(&a.contours[0])) < 0.0);
+
+        let v = font.glyph_path(2, Paint::solid((1.0, 1.0, 1.0))).unwrap().unwrap();
+        let biases = v.points.iter().map(|p| p.curve_bias).collect::<Vec<_>>();
+        assert_eq!(biases, vec![0.5, 0.0, 0.0]);
+
+        let w = font.glyph_path(3, Paint::solid((1.0, 1.0, 1.0))).unwrap().unwrap();
+        assert_eq!(locations(&w.contours[0]), vec![(225.0, 0.0), (350.0, 350.0), (100.0, 350.0)]);
+
+        assert!(font.glyph_path(4, Paint::solid((1.0, 1.0, 1.0))).unwrap().is_none());
+    }
+
+    #[test]
+    fn layout() {
+        let font = Font::from_bytes(font_data(false)).unwrap();
+        let model = font.layout("AV A\nW", 10.0, Paint::solid((1.0, 0.0, 0.0))).unwrap();
+
+        let names = model.paths.iter().map(|p| p.name.clone().unwrap()).collect::<Vec<_>>();
+        assert_eq!(names, vec!["A", "V", "A", "W"]);
+        assert!(model.validate().is_empty());
+
+        // V is kerned towards A, and the space moves the pen on without a
+        // path. The next line is one line height down.
+        let near = |a: Vec2, b: Vec2| (a.0 - b.0).abs() < 1e-9 && (a.1 - b.1).abs() < 1e-9;
+        assert!(near(model.paths[1].points[0].location, (6.7, 0.0)));
+        assert!(near(model.paths[2].points[3].location, (12.7, 0.0)));
+        assert!(near(model.paths[3].points[1].location, (5.0, -4.0)));
+    }
+
+    #[test]
+    fn invalid() {
+        let data = font_data(false);
+        assert!(Font::from_bytes(data[..100].to_vec()).is_err());
+        assert!(Font::from_bytes(b"OTTO\0\0\0\0\0\0\0\0".to_vec()).is_err());
+        assert!(Font::from_bytes(Vec::new()).is_err());
+
+        // A group starting at a glyph too large to add to
+        let mut cmap = Vec::new();
+        push(&mut cmap, &[12, 0, 0, 28, 0, 0, 0, 1, 0, 65, 0, 70, 0xffff, 0xffff]);
+        assert_eq!(format_12_glyph(&cmap, 65).unwrap(), 0);
+        assert_eq!(format_12_glyph(&cmap, 70).unwrap(), 0);
+    }
+}
@@ -0,0 +1,496 @@
+use data::{Vec2, Box2};
+use super::{Model, Path, Point};
+use super::tessellate::{Segment, Triangle, fill_triangles, path_segments};
+
+// An affine transform as in SVG's matrix(a b c d e f), which maps (x, y) to
+// (a x + c y + e, b x + d y + f)
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub struct Affine2 {
+    pub matrix: [f64; 6]
+}
+
+impl Affine2 {
+    pub fn identity() -> Affine2 {
+        Affine2 { matrix: [1.0, 0.0, 0.0, 1.0, 0.0, 0.0] }
+    }
+
+    pub fn translate(x: f64, y: f64) -> Affine2 {
+        Affine2 { matrix: [1.0, 0.0, 0.0, 1.0, x, y] }
+    }
+
+    pub fn scale(x: f64, y: f64) -> Affine2 {
+        Affine2 { matrix: [x, 0.0, 0.0, y, 0.0, 0.0] }
+    }
+
+    // Anticlockwise about the origin, in radians
+    pub fn rotate(angle: f64) -> Affine2 {
+        let (sin, cos) = angle.sin_cos();
+        Affine2 { matrix: [cos, sin, -sin, cos, 0.0, 0.0] }
+    }
+
+    // Mirrors in the line through the origin at the given angle, so 0 flips
+    // the y-axis and π/2 flips the x-axis
+    pub fn mirror(angle: f64) -> Affine2 {
+        let (sin, cos) = (2.0 * angle).sin_cos();
+        Affine2 { matrix: [cos, sin, sin, -cos, 0.0, 0.0] }
+    }
+
+    // Shears x by y and y by x, with the angles in radians
+    pub fn skew(x: f64, y: f64) -> Affine2 {
+        Affine2 { matrix: [1.0, y.tan(), x.tan(), 1.0, 0.0, 0.0] }
+    }
+
+    // The transform that applies this one and then the other
+    pub fn then(&self, other: &Affine2) -> Affine2 {
+        let (m, n) = (&other.matrix, &self.matrix);
+        Affine2 {
+            matrix: [m[0] * n[0] + m[2] * n[1],
+                     m[1] * n[0] + m[3] * n[1],
+                     m[0] * n[2] + m[2] * n[3],
+                     m[1] * n[2] + m[3] * n[3],
+                     m[0] * n[4] + m[2] * n[5] + m[4],
+                     m[1] * n[4] + m[3] * n[5] + m[5]]
+        }
+    }
+
+    pub fn apply(&self, p: Vec2) -> Vec2 {
+        let m = &self.matrix;
+        (m[0] * p.0 + m[2] * p.1 + m[4], m[1] * p.0 + m[3] * p.1 + m[5])
+    }
+
+    pub fn determinant(&self) -> f64 {
+        let m = &self.matrix;
+        m[0] * m[3] - m[1] * m[2]
+    }
+
+    // How much lengths grow on average, used to scale stroke widths
+    pub fn stretch(&self) -> f64 {
+        self.determinant().abs().sqrt()
+    }
+
+    pub fn inverse(&self) -> Option<Affine2> {
+        let det = self.determinant();
+        if det == 0.0 || !det.is_finite() {
+            return None;
+        }
+
+        let m = &self.matrix;
+        Some(Affine2 {
+            matrix: [m[3] / det,
+                     -m[1] / det,
+                     -m[2] / det,
+                     m[0] / det,
+                     (m[2] * m[5] - m[3] * m[4]) / det,
+                     (m[1] * m[4] - m[0] * m[5]) / det]
+        })
+    }
+}
+
+impl Model {
+    pub fn transform(&mut self, transform: &Affine2) {
+        for path in &mut self.paths {
+            path.transform(transform);
+        }
+
+        if let Some(view_box) = self.metadata.view_box {
+            let ((x0, y0), (x1, y1)) = view_box;
+            let corners = [(x0, y0), (x1, y0), (x0, y1), (x1, y1)];
+            self.metadata.view_box = corners.iter()
+                .map(|&corner| transform.apply(corner))
+                .fold(None, |bounds, p| Some(union(bounds, (p, p))));
+        }
+
+        if let Some(anchor) = self.metadata.anchor {
+            self.metadata.anchor = Some(transform.apply(anchor));
+        }
+
+        // Symbols stay in their own space
+        for instance in &mut self.instances {
+            instance.transform = instance.transform.then(transform);
+        }
+
+        // Moving the root bones moves the rest of the skeleton with them
+        if let Some(ref mut skeleton) = self.skeleton {
+            for bone in skeleton.bones.iter_mut().filter(|bone| bone.parent.is_none()) {
+                bone.rest = bone.rest.then(transform);
+            }
+        }
+    }
+
+    // The exact bounds of the outlines, without strokes
+    pub fn bounds(&self) -> Option<Box2> {
+        self.paths.iter()
+            .filter_map(|path| path.bounds())
+            .fold(None, |bounds, b| Some(union(bounds, b)))
+    }
+
+    // The sum of the paths' areas, so overlapping paths count more than once
+    pub fn area(&self) -> f64 {
+        self.paths.iter().map(|path| path.area()).sum()
+    }
+
+    // The centre of mass of the paths' areas, if there is any area
+    pub fn centroid(&self) -> Option<Vec2> {
+        let mut total = (0.0, (0.0, 0.0));
+        for path in &self.paths {
+            let (area, moment) = path.mass();
+            total = (total.0 + area, ((total.1).0 + moment.0, (total.1).1 + moment.1));
+        }
+
+        centre(total)
+    }
+
+    pub fn perimeter(&self) -> f64 {
+        self.paths.iter().map(|path| path.perimeter()).sum()
+    }
+}
+
+impl Path {
+    // Quadratic béziers and the implied points between controls are kept by
+    // affine transforms, so only the points, paint and stroke need to change
+    pub fn transform(&mut self, transform: &Affine2) {
+        let apply = |points: &mut Vec<Point>| {
+            for point in points.iter_mut() {
+                point.location = transform.apply(point.location);
+            }
+        };
+
+        apply(&mut self.points);
+        for contour in &mut self.contours {
+            apply(contour);
+        }
+        self.paint.transform(transform);
+
+        if let Some(ref mut stroke) = self.stroke {
+            let stretch = transform.stretch();
+            stroke.width *= stretch;
+            for dash in &mut stroke.dashes {
+                *dash *= stretch;
+            }
+        }
+    }
+
+    // The exact bounds of the outlines, including the extremes of curves but
+    // not the stroke
+    pub fn bounds(&self) -> Option<Box2> {
+        let mut bounds = None;
+
+        for segments in path_segments(self) {
+            for (i, segment) in segments.iter().enumerate() {
+                let end = segments[(i + 1) % segments.len()].start;
+                bounds = Some(union(bounds, (segment.start, segment.start)));
+
+                if let Some(c) = segment.control {
+                    let axis = |p0: f64, c: f64, p2: f64| {
+                        let t = (p0 - c) / (p0 - 2.0 * c + p2);
+                        if t > 0.0 && t < 1.0 {
+                            Some((1.0 - t) * (1.0 - t) * p0 + 2.0 * t * (1.0 - t) * c + t * t * p2)
+                        } else {
+                            None
+                        }
+                    };
+
+                    let x = axis(segment.start.0, c.0, end.0).unwrap_or(segment.start.0);
+                    let y = axis(segment.start.1, c.1, end.1).unwrap_or(segment.start.1);
+                    bounds = Some(union(bounds, ((x, y), (x, y))));
+                }
+            }
+        }
+
+        bounds
+    }
+
+    // The area enclosed by the outlines under the fill rule, whether or not
+    // the path is filled
+    pub fn area(&self) -> f64 {
+        self.mass().0
+    }
+
+    pub fn centroid(&self) -> Option<Vec2> {
+        centre(self.mass())
+    }
+
+    // The total length of the outlines, each closed back to its start
+    pub fn perimeter(&self) -> f64 {
+        path_segments(self).iter()
+            .map(|segments| {
+                segments.iter()
+                    .enumerate()
+                    .map(|(i, segment)| segment_length(segment, segments[(i + 1) % segments.len()].start))
+                    .sum::<f64>()
+            })
+            .sum()
+    }
+
+    // The area and its first moment, summed over the same triangles that are
+    // drawn so that fill rules and holes are accounted for
+    fn mass(&self) -> (f64, Vec2) {
+        let contours = Some(&self.points[..]).into_iter()
+            .chain(self.contours.iter().map(|c| &c[..]))
+            .collect::<Vec<_>>();
+
+        let mut total = (0.0, (0.0, 0.0));
+        for triangle in fill_triangles(&contours, self.fill_rule) {
+            let (area, centroid) = triangle_mass(&triangle);
+            total = (total.0 + area, ((total.1).0 + area * centroid.0, (total.1).1 + area * centroid.1));
+        }
+
+        total
+    }
+}
+
+// The area and centroid of the filled part of a triangle. Curve triangles
+// run from (0, 0) to (1, 1) in (u, v), and fill either the parabolic segment
+// between the chord and the curve, which is 2/3 of the triangle, or the
+// third on the control point's side of it.
+fn triangle_mass(triangle: &Triangle) -> (f64, Vec2) {
+    let (p0, c, p2) = (triangle[0].position, triangle[1].position, triangle[2].position);
+    let area = ((c.0 - p0.0) * (p2.1 - p0.1) - (c.1 - p0.1) * (p2.0 - p0.0)).abs() / 2.0;
+    let weighted = |w0: f64, w1: f64, w2: f64| {
+        ((w0 * p0.0 + w1 * c.0 + w2 * p2.0), (w0 * p0.1 + w1 * c.1 + w2 * p2.1))
+    };
+
+    if triangle[0].param.1 != 0.0 {
+        (area, weighted(1.0 / 3.0, 1.0 / 3.0, 1.0 / 3.0))
+    } else if triangle[0].param.2 < 0.0 {
+        (area * 2.0 / 3.0, weighted(0.4, 0.2, 0.4))
+    } else {
+        (area / 3.0, weighted(0.2, 0.6, 0.2))
+    }
+}
+
+fn centre(mass: (f64, Vec2)) -> Option<Vec2> {
+    let (area, moment) = mass;
+    if area > 0.0 {
+        Some((moment.0 / area, moment.1 / area))
+    } else {
+        None
+    }
+}
+
+fn union(bounds: Option<Box2>, other: Box2) -> Box2 {
+    match bounds {
+        Some((min, max)) => ((min.0.min((other.0).0), min.1.min((other.0).1)),
+                             (max.0.max((other.1).0), max.1.max((other.1).1))),
+        None => other
+    }
+}
+
+// The arc length of a segment ending at end. For a curve B'(t) = 2(b + a t),
+// which integrates in closed form unless a and b are parallel, when the curve
+// runs back and forth along a line.
+fn segment_length(segment: &Segment, end: Vec2) -> f64 {
+    let p0 = segment.start;
+    let c = match segment.control {
+        Some(c) => c,
+        None => return ((end.0 - p0.0).powi(2) + (end.1 - p0.1).powi(2)).sqrt()
+    };
+
+    let a = (p0.0 - 2.0 * c.0 + end.0, p0.1 - 2.0 * c.1 + end.1);
+    let b = (c.0 - p0.0, c.1 - p0.1);
+    let aa = a.0 * a.0 + a.1 * a.1;
+    let ab = a.0 * b.0 + a.1 * b.1;
+    let bb = b.0 * b.0 + b.1 * b.1;
+
+    if (a.0 * b.1 - a.1 * b.0).abs() <= 1e-12 * (aa + bb) {
+        // Speed along the line is 2 (s + r t), which may change sign once
+        let dir = if bb > 0.0 { b } else { a };
+        let len = (dir.0 * dir.0 + dir.1 * dir.1).sqrt();
+        if len == 0.0 {
+            return 0.0;
+        }
+
+        let unit = (dir.0 / len, dir.1 / len);
+        let (s, r) = (b.0 * unit.0 + b.1 * unit.1, a.0 * unit.0 + a.1 * unit.1);
+        let distance = |t: f64| 2.0 * (s * t + r * t * t / 2.0);
+        let turn = -s / r;
+
+        return if r != 0.0 && turn > 0.0 && turn < 1.0 {
+            (distance(turn) - distance(0.0)).abs() + (distance(1.0) - distance(turn)).abs()
+        } else {
+            (distance(1.0) - distance(0.0)).abs()
+        };
+    }
+
+    // 2 ∫ sqrt(aa t² + 2 ab t + bb) dt, by completing the square. k is
+    // taken from the cross product, as bb / aa - (ab / aa)² cancels badly
+    // for nearly straight curves, and ln(u + root) is reflected for negative
+    // u, where u + root can round to 0.
+    let k = ((a.0 * b.1 - a.1 * b.0) / aa).powi(2);
+    let integral = |u: f64| {
+        let root = (u * u + k).sqrt();
+        let log = if u >= 0.0 { (u + root).ln() } else { k.ln() - (root - u).ln() };
+        (u * root + k * log) / 2.0
+    };
+    let offset = ab / aa;
+
+    2.0 * aa.sqrt() * (integral(1.0 + offset) - integral(offset))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::f64::consts::PI;
+    use std::io::Cursor;
+    use data::BinaryReader;
+    use model::{Model, Point, FillRule, Stroke, LineJoin, LineCap, NO_WEIGHTS, path};
+
+    fn close(a: Vec2, b: Vec2) -> bool {
+        (a.0 - b.0).abs() < 1e-9 && (a.1 - b.1).abs() < 1e-9
+    }
+
+    #[test]
+    fn transforms() {
+        let p = (2.0, 1.0);
+        assert!(close(Affine2::rotate(PI / 2.0).apply(p), (-1.0, 2.0)));
+        assert!(close(Affine2::mirror(0.0).apply(p), (2.0, -1.0)));
+        assert!(close(Affine2::mirror(PI / 4.0).apply(p), (1.0, 2.0)));
+        assert!(close(Affine2::skew(PI / 4.0, 0.0).apply(p), (3.0, 1.0)));
+
+        // Scaled and then moved
+        let t = Affine2::scale(2.0, 3.0).then(&Affine2::translate(1.0, 1.0));
+        assert!(close(t.apply(p), (5.0, 4.0)));
+        assert_eq!(t.determinant(), 6.0);
+
+        let inverse = t.inverse().unwrap();
+        assert!(close(inverse.apply(t.apply(p)), p));
+        assert!(close(t.then(&inverse).apply((7.0, -3.0)), (7.0, -3.0)));
+        assert_eq!(Affine2::scale(0.0, 1.0).inverse(), None);
+    }
+
+    #[test]
+    fn transform_paths() {
+        let mut p = path(&[(0.0, 0.0, 0.0), (1.0, 0.0, 0.0), (1.0, 1.0, 0.5)]);
+        p.contours.push(vec![Point { location: (0.5, 0.5), curve_bias: 0.0, weights: NO_WEIGHTS }]);
+        p.stroke = Some(Stroke {
+            colour: (0.0, 0.0, 0.0),
+            width: 1.0,
+            join: LineJoin::Miter,
+            cap: LineCap::Butt,
+            dashes: vec![1.0, 2.0],
+            closed: true
+        });
+
+        let mut model = Model { paths: vec![p], .. Default::default() };
+        model.transform(&Affine2::scale(2.0, -2.0).then(&Affine2::translate(0.0, 1.0)));
+
+        let p = &model.paths[0];
+        assert_eq!(p.points.iter().map(|p| p.location).collect::<Vec<_>>(),
+                   vec![(0.0, 1.0), (2.0, 1.0), (2.0, -1.0)]);
+        assert_eq!(p.points[2].curve_bias, 0.5);
+        assert_eq!(p.contours[0][0].location, (1.0, 0.0));
+
+        let stroke = p.stroke.as_ref().unwrap();
+        assert_eq!(stroke.width, 2.0);
+        assert_eq!(stroke.dashes, vec![2.0, 4.0]);
+    }
+
+    #[test]
+    fn transform_metadata() {
+        let mut model = Model::default();
+        model.metadata.view_box = Some(((0.0, 0.0), (2.0, 1.0)));
+        model.metadata.anchor = Some((1.0, 0.0));
+        model.transform(&Affine2::scale(2.0, -2.0).then(&Affine2::translate(0.0, 1.0)));
+
+        assert_eq!(model.metadata.view_box, Some(((0.0, -1.0), (4.0, 1.0))));
+        assert_eq!(model.metadata.anchor, Some((2.0, 1.0)));
+    }
+
+    #[test]
+    fn bounds() {
+        // A curve through (0, 0) and (2, 0) peaking at y = 1
+        let p = path(&[(0.0, 0.0, 0.0), (1.0, 2.0, 0.5), (2.0, 0.0, 0.0)]);
+        assert_eq!(p.bounds(), Some(((0.0, 0.0), (2.0, 1.0))));
+
+        let model = Model { paths: vec![p, path(&[(-1.0, 3.0, 0.0)]), path(&[])], .. Default::default() };
+        assert_eq!(model.bounds(), Some(((-1.0, 0.0), (2.0, 3.0))));
+        assert_eq!(Model { paths: Vec::new(), .. Default::default() }.bounds(), None);
+    }
+
+    #[test]
+    fn areas() {
+        let mut square = path(&[(0.0, 0.0, 0.0), (2.0, 0.0, 0.0), (2.0, 2.0, 0.0), (0.0, 2.0, 0.0)]);
+        assert!((square.area() - 4.0).abs() < 1e-9);
+        assert!(close(square.centroid().unwrap(), (1.0, 1.0)));
+        assert!((square.perimeter() - 8.0).abs() < 1e-9);
+
+        // A hole in one corner moves the centroid away from it
+        square.contours.push(vec![
+            Point { location: (0.0, 0.0), curve_bias: 0.0, weights: NO_WEIGHTS },
+            Point { location: (1.0, 0.0), curve_bias: 0.0, weights: NO_WEIGHTS },
+            Point { location: (1.0, 1.0), curve_bias: 0.0, weights: NO_WEIGHTS },
+            Point { location: (0.0, 1.0), curve_bias: 0.0, weights: NO_WEIGHTS }
+        ]);
+        square.fill_rule = FillRule::EvenOdd;
+        assert!((square.area() - 3.0).abs() < 1e-9);
+        assert!(close(square.centroid().unwrap(), (7.0 / 6.0, 7.0 / 6.0)));
+        assert!((square.perimeter() - 12.0).abs() < 1e-9);
+
+        // A parabolic segment, bulging out and then in
+        let segment = path(&[(0.0, 0.0, 0.0), (1.0, 2.0, 0.5), (2.0, 0.0, 0.0)]);
+        assert!((segment.area() - 4.0 / 3.0).abs() < 1e-9);
+        assert!(close(segment.centroid().unwrap(), (1.0, 0.4)));
+
+        let notch = path(&[(0.0, 0.0, 0.0), (2.0, 0.0, 0.0), (2.0, 2.0, 0.0),
+                           (1.0, 0.0, 0.5), (0.0, 2.0, 0.0)]);
+        assert!((notch.area() - (4.0 - 4.0 / 3.0)).abs() < 1e-9);
+
+        let model = Model { paths: vec![segment, notch], .. Default::default() };
+        assert!((model.area() - 4.0).abs() < 1e-9);
+        assert_eq!(Model { paths: Vec::new(), .. Default::default() }.centroid(), None);
+    }
+
+    #[test]
+    fn lengths() {
+        let length = |c: Vec2, end: Vec2| segment_length(&Segment { start: (0.0, 0.0), control: Some(c) }, end);
+
+        // Against the sum of many small chords
+        let numeric = |c: Vec2, end: Vec2| {
+            let point = |t: f64| (2.0 * t * (1.0 - t) * c.0 + t * t * end.0, 2.0 * t * (1.0 - t) * c.1 + t * t * end.1);
+            (0..10000).map(|i| {
+                let (a, b) = (point(i as f64 / 10000.0), point((i + 1) as f64 / 10000.0));
+                ((b.0 - a.0).powi(2) + (b.1 - a.1).powi(2)).sqrt()
+            }).sum::<f64>()
+        };
+
+        for &(c, end) in &[((1.0, 2.0), (2.0, 0.0)), ((5.0, -1.0), (1.0, 1.0)), ((0.0, 3.0), (3.0, 3.0))] {
+            assert!((length(c, end) - numeric(c, end)).abs() < 1e-6);
+        }
+
+        // Straight curves, including one that turns back on itself
+        assert!((length((1.0, 1.0), (2.0, 2.0)) - 8f64.sqrt()).abs() < 1e-9);
+        assert!((length((2.0, 0.0), (1.0, 0.0)) - numeric((2.0, 0.0), (1.0, 0.0))).abs() < 1e-6);
+        assert_eq!(length((0.0, 0.0), (0.0, 0.0)), 0.0);
+
+        // Nearly straight curves that turn back
+        for &offset in &[0.0, 1e-11, 1e-9, 1e-7, 1e-5] {
+            let p = path(&[(0.0, 0.0, 0.0), (2.0, offset, 0.5), (1.0, 0.0, 0.0)]);
+            assert!((p.perimeter() - 1.0 - numeric((2.0, offset), (1.0, 0.0))).abs() < 1e-6);
+        }
+    }
+
+    #[test]
+    fn cat() {
+        let bytes = include_bytes!("../bin/cat.model");
+        let mut model = Model::read(&mut BinaryReader::new(Cursor::new(&bytes[..]))).unwrap();
+
+        let (area, centroid, perimeter) = (model.area(), model.centroid().unwrap(), model.perimeter());
+        let (min, max) = model.bounds().unwrap();
+
+        // Rotating and mirroring keeps the measurements, and moves the
+        // centroid along with the shape
+        let t = Affine2::rotate(1.0).then(&Affine2::mirror(0.3)).then(&Affine2::translate(5.0, -2.0));
+        model.transform(&t);
+
+        assert!((model.area() - area).abs() < 1e-6 * area);
+        assert!((model.perimeter() - perimeter).abs() < 1e-6 * perimeter);
+        let moved = t.apply(centroid);
+        let new = model.centroid().unwrap();
+        assert!((new.0 - moved.0).abs() < 1e-6 && (new.1 - moved.1).abs() < 1e-6);
+
+        // Scaling scales the bounds exactly
+        model.transform(&t.inverse().unwrap().then(&Affine2::scale(2.0, 2.0)));
+        let (min2, max2) = model.bounds().unwrap();
+        assert!((min2.0 - 2.0 * min.0).abs() < 1e-6 && (max2.1 - 2.0 * max.1).abs() < 1e-6);
+    }
+}
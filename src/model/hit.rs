@@ -0,0 +1,441 @@
+use data::Vec2;
+use super::{Model, Path, Point, FillRule, Instance};
+use super::stroke::stroke_contours;
+use super::tessellate::{Segment, outline_segments};
+
+// The closest of a model's points, which may be a control point. The outline
+// is 0 for the path's points and i + 1 for contours[i].
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub struct NearestPoint {
+    pub path: usize,
+    pub outline: usize,
+    pub index: usize,
+    pub distance: f64
+}
+
+// The closest place on a model's outlines, as the index of a segment from
+// outline_segments and the parameter along it
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub struct NearestSegment {
+    pub path: usize,
+    pub outline: usize,
+    pub segment: usize,
+    pub t: f64,
+    pub location: Vec2,
+    pub distance: f64
+}
+
+impl Model {
+    // The index of the topmost path drawn at a point, either by its fill or
+    // its stroke, and not clipped away there. Clip paths aren't drawn so
+    // can't be hit. Hidden and locked paths are passed over, here and when
+    // finding the nearest point or segment. Instances are drawn above the
+    // paths, and their paths are numbered after them as in the resolved
+    // model, or left out if the symbols aren't valid.
+    pub fn hit_test(&self, p: Vec2) -> Option<usize> {
+        if self.has_valid_symbols() {
+            if let Some(hit) = self.hit_instances(&self.instances, self.paths.len(), p) {
+                return Some(hit);
+            }
+        }
+
+        (0..self.paths.len()).rev().find(|&i| {
+            self.is_editable(i) && !self.is_clip_path(i) && self.paths[i].contains(p) &&
+                self.clip_paths(i).iter().all(|&clip| self.paths[clip].encloses(p))
+        })
+    }
+
+    // The number of paths an instance of a symbol resolves to
+    fn resolved_len(&self, symbol: usize) -> usize {
+        let symbol = &self.symbols[symbol];
+        symbol.paths.len() + symbol.instances.iter().map(|i| self.resolved_len(i.symbol)).sum::<usize>()
+    }
+
+    // The topmost path of some instances at a point, numbering their paths
+    // from first. Each instance is tested in its symbol's space.
+    fn hit_instances(&self, instances: &[Instance], first: usize, p: Vec2) -> Option<usize> {
+        let mut starts = Vec::with_capacity(instances.len());
+        let mut next = first;
+        for instance in instances {
+            starts.push(next);
+            next += self.resolved_len(instance.symbol);
+        }
+
+        for (instance, &start) in instances.iter().zip(&starts).rev() {
+            let p = match instance.transform.inverse() {
+                Some(inverse) => inverse.apply(p),
+                None => continue
+            };
+
+            let symbol = &self.symbols[instance.symbol];
+            if let Some(hit) = self.hit_instances(&symbol.instances, start + symbol.paths.len(), p) {
+                return Some(hit);
+            }
+            if let Some(i) = (0..symbol.paths.len()).rev().find(|&i| symbol.paths[i].contains(p)) {
+                return Some(start + i);
+            }
+        }
+
+        None
+    }
+
+    pub fn nearest_point(&self, p: Vec2) -> Option<NearestPoint> {
+        let mut nearest: Option<NearestPoint> = None;
+
+        for (i, path) in self.paths.iter().enumerate().filter(|&(i, _)| self.is_editable(i)) {
+            for (j, points) in outlines(path).enumerate() {
+                for (k, point) in points.iter().enumerate() {
+                    let distance = distance(point.location, p);
+                    if distance.is_finite() && nearest.map(|n| distance < n.distance).unwrap_or(true) {
+                        nearest = Some(NearestPoint { path: i, outline: j, index: k, distance: distance });
+                    }
+                }
+            }
+        }
+
+        nearest
+    }
+
+    pub fn nearest_segment(&self, p: Vec2) -> Option<NearestSegment> {
+        let mut nearest: Option<NearestSegment> = None;
+
+        for (i, path) in self.paths.iter().enumerate().filter(|&(i, _)| self.is_editable(i)) {
+            for (j, points) in outlines(path).enumerate() {
+                let segments = outline_segments(points);
+
+                for (k, segment) in segments.iter().enumerate() {
+                    let end = segments[(k + 1) % segments.len()].start;
+                    let (t, location) = closest(segment, end, p);
+                    let distance = distance(location, p);
+
+                    if nearest.map(|n| distance < n.distance).unwrap_or(true) {
+                        nearest = Some(NearestSegment {
+                            path: i,
+                            outline: j,
+                            segment: k,
+                            t: t,
+                            location: location,
+                            distance: distance
+                        });
+                    }
+                }
+            }
+        }
+
+        nearest
+    }
+}
+
+impl Path {
+    // Whether a point is inside the path's fill or stroke, following the
+    // exact curves of the fill
+    pub fn contains(&self, p: Vec2) -> bool {
+        if self.filled && self.encloses(p) {
+            return true;
+        }
+
+        match self.stroke {
+            Some(ref stroke) => {
+                let w: i32 = stroke_contours(self, stroke).iter()
+                    .map(|points| winding(&outline_segments(points), p))
+                    .sum();
+                FillRule::NonZero.is_inside(w)
+            },
+            None => false
+        }
+    }
+
+    // Whether a point is inside the outlines under the fill rule, whether or
+    // not the path is filled
+    pub fn encloses(&self, p: Vec2) -> bool {
+        let w: i32 = outlines(self).map(|points| winding(&outline_segments(points), p)).sum();
+        self.fill_rule.is_inside(w)
+    }
+}
+
+fn outlines<'a>(path: &'a Path) -> Box<Iterator<Item = &'a Vec<Point>> + 'a> {
+    Box::new(Some(&path.points).into_iter().chain(path.contours.iter()))
+}
+
+fn distance(a: Vec2, b: Vec2) -> f64 {
+    ((a.0 - b.0) * (a.0 - b.0) + (a.1 - b.1) * (a.1 - b.1)).sqrt()
+}
+
+fn quad_point(p0: Vec2, c: Vec2, p2: Vec2, t: f64) -> Vec2 {
+    let s = 1.0 - t;
+    (s * s * p0.0 + 2.0 * s * t * c.0 + t * t * p2.0,
+     s * s * p0.1 + 2.0 * s * t * c.1 + t * t * p2.1)
+}
+
+// The winding number of an outline around a point, counting crossings to
+// the right of it as the tessellator does. Curves are split where they turn
+// in y so that each piece crosses at most once.
+fn winding(segments: &[Segment], p: Vec2) -> i32 {
+    let mut winding = 0;
+
+    for (i, segment) in segments.iter().enumerate() {
+        let (p0, p2) = (segment.start, segments[(i + 1) % segments.len()].start);
+        let c = segment.control.unwrap_or(((p0.0 + p2.0) / 2.0, (p0.1 + p2.1) / 2.0));
+
+        let turn = (p0.1 - c.1) / (p0.1 - 2.0 * c.1 + p2.1);
+        if turn > 0.0 && turn < 1.0 {
+            let mid = quad_point(p0, c, p2, turn);
+            let (c0, c1) = (lerp(p0, c, turn), lerp(c, p2, turn));
+            winding += monotone_winding(p0, c0, mid, p) + monotone_winding(mid, c1, p2, p);
+        } else {
+            winding += monotone_winding(p0, c, p2, p);
+        }
+    }
+
+    winding
+}
+
+fn lerp(a: Vec2, b: Vec2, t: f64) -> Vec2 {
+    (a.0 + t * (b.0 - a.0), a.1 + t * (b.1 - a.1))
+}
+
+fn monotone_winding(p0: Vec2, c: Vec2, p2: Vec2, p: Vec2) -> i32 {
+    let direction = if p0.1 <= p.1 && p2.1 > p.1 {
+        1
+    } else if p0.1 > p.1 && p2.1 <= p.1 {
+        -1
+    } else {
+        return 0;
+    };
+
+    // Solve a t² + 2 b t + (p0 - p) = 0 in y for the crossing
+    let (a, b, k) = (p0.1 - 2.0 * c.1 + p2.1, c.1 - p0.1, p0.1 - p.1);
+    let t = if a.abs() < 1e-12 * (b.abs() + k.abs()) {
+        -k / (2.0 * b)
+    } else {
+        let root = (b * b - a * k).max(0.0).sqrt();
+        let (t0, t1) = ((-b - root) / a, (-b + root) / a);
+        if (t0 - 0.5).abs() < (t1 - 0.5).abs() { t0 } else { t1 }
+    };
+
+    let x = quad_point(p0, c, p2, t.max(0.0).min(1.0)).0;
+    if x > p.0 { direction } else { 0 }
+}
+
+// The closest point on a segment, found by sampling and then refining with
+// Newton's method on the derivative of the squared distance
+fn closest(segment: &Segment, end: Vec2, p: Vec2) -> (f64, Vec2) {
+    let p0 = segment.start;
+    let c = match segment.control {
+        Some(c) => c,
+        None => {
+            let d = (end.0 - p0.0, end.1 - p0.1);
+            let length = d.0 * d.0 + d.1 * d.1;
+            let t = if length > 0.0 {
+                (((p.0 - p0.0) * d.0 + (p.1 - p0.1) * d.1) / length).max(0.0).min(1.0)
+            } else {
+                0.0
+            };
+            return (t, lerp(p0, end, t));
+        }
+    };
+
+    let a = (p0.0 - 2.0 * c.0 + end.0, p0.1 - 2.0 * c.1 + end.1);
+    let b = (c.0 - p0.0, c.1 - p0.1);
+    let dot = |u: Vec2, v: Vec2| u.0 * v.0 + u.1 * v.1;
+
+    let mut best = (0.0, distance(p0, p));
+    for i in 1..17 {
+        let t = i as f64 / 16.0;
+        let d = distance(quad_point(p0, c, end, t), p);
+        if d < best.1 {
+            best = (t, d);
+        }
+    }
+
+    let mut t = best.0;
+    for _ in 0..8 {
+        // With B(t) = p0 + 2 b t + a t², the distance is stationary where
+        // (B - p) . B' = 0
+        let m = (p0.0 - p.0, p0.1 - p.1);
+        let f = dot(a, a) * t * t * t + 3.0 * dot(a, b) * t * t + (2.0 * dot(b, b) + dot(a, m)) * t + dot(b, m);
+        let df = 3.0 * dot(a, a) * t * t + 6.0 * dot(a, b) * t + 2.0 * dot(b, b) + dot(a, m);
+        if df <= 0.0 {
+            break;
+        }
+
+        let next = (t - f / df).max(0.0).min(1.0);
+        if distance(quad_point(p0, c, end, next), p) > distance(quad_point(p0, c, end, t), p) {
+            break;
+        }
+        t = next;
+    }
+
+    (t, quad_point(p0, c, end, t))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Cursor;
+    use data::BinaryReader;
+    use model::{Model, Point, FillRule, Stroke, LineJoin, LineCap, Symbol, Instance, Affine2, NO_WEIGHTS, path};
+    use model::tessellate::model_triangles;
+
+    #[test]
+    fn curves() {
+        // A parabolic arch over (0, 0) to (2, 0), peaking at y = 1
+        let arch = path(&[(0.0, 0.0, 0.0), (1.0, 2.0, 0.5), (2.0, 0.0, 0.0)]);
+        assert!(arch.contains((1.0, 0.99)));
+        assert!(!arch.contains((1.0, 1.01)));
+        assert!(arch.contains((0.2, 0.3)));
+        assert!(!arch.contains((0.2, 0.4)));
+        assert!(!arch.contains((1.0, -0.01)));
+
+        // Points at the height of the peak and of the ends
+        assert!(!arch.contains((3.0, 1.0)));
+        assert!(!arch.contains((-1.0, 0.0)));
+
+        // The same arch pushed into a square
+        let notch = path(&[(0.0, 0.0, 0.0), (2.0, 0.0, 0.0), (2.0, 2.0, 0.0),
+                           (1.0, 0.0, 0.5), (0.0, 2.0, 0.0)]);
+        assert!(!notch.contains((1.0, 1.5)));
+        assert!(notch.contains((1.0, 0.9)));
+        assert!(notch.contains((0.1, 1.7)));
+    }
+
+    #[test]
+    fn paint_order() {
+        let square = |x: f64| path(&[(x, 0.0, 0.0), (x + 2.0, 0.0, 0.0), (x + 2.0, 2.0, 0.0), (x, 2.0, 0.0)]);
+
+        let mut holed = square(1.0);
+        holed.contours.push(vec![
+            Point { location: (1.5, 0.5), curve_bias: 0.0, weights: NO_WEIGHTS },
+            Point { location: (2.5, 0.5), curve_bias: 0.0, weights: NO_WEIGHTS },
+            Point { location: (2.5, 1.5), curve_bias: 0.0, weights: NO_WEIGHTS },
+            Point { location: (1.5, 1.5), curve_bias: 0.0, weights: NO_WEIGHTS }
+        ]);
+        holed.fill_rule = FillRule::EvenOdd;
+
+        let mut outline = square(10.0);
+        outline.filled = false;
+        outline.stroke = Some(Stroke {
+            colour: (0.0, 0.0, 0.0),
+            width: 0.5,
+            join: LineJoin::Miter,
+            cap: LineCap::Butt,
+            dashes: Vec::new(),
+            closed: true
+        });
+
+        let model = Model { paths: vec![square(0.0), holed, outline], .. Default::default() };
+        assert_eq!(model.hit_test((0.5, 1.0)), Some(0));
+        assert_eq!(model.hit_test((1.2, 1.0)), Some(1));
+        // Through the hole to the path underneath
+        assert_eq!(model.hit_test((1.8, 1.0)), Some(0));
+        assert_eq!(model.hit_test((2.8, 1.0)), Some(1));
+        assert_eq!(model.hit_test((2.8, 3.0)), None);
+
+        // Only the stroke of the unfilled path
+        assert_eq!(model.hit_test((10.1, 1.0)), Some(2));
+        assert_eq!(model.hit_test((11.0, 1.0)), None);
+    }
+
+    #[test]
+    fn instances() {
+        let square = path(&[(0.0, 0.0, 0.0), (1.0, 0.0, 0.0), (1.0, 1.0, 0.0), (0.0, 1.0, 0.0)]);
+        let instance = |symbol, transform| Instance { symbol: symbol, transform: transform, paint: None };
+
+        // A square within a symbol of two squares, placed twice over a path
+        let model = Model {
+            paths: vec![path(&[(0.0, 0.0, 0.0), (20.0, 0.0, 0.0), (20.0, 20.0, 0.0)])],
+            symbols: vec![
+                Symbol { name: "inner".to_string(), paths: vec![square.clone()], instances: Vec::new() },
+                Symbol {
+                    name: "outer".to_string(),
+                    paths: vec![square.clone()],
+                    instances: vec![instance(0, Affine2::translate(2.0, 0.0))]
+                }
+            ],
+            instances: vec![
+                instance(1, Affine2::translate(10.0, 1.0)),
+                instance(1, Affine2::scale(2.0, 2.0).then(&Affine2::translate(14.0, 1.0)))
+            ],
+            .. Default::default()
+        };
+
+        // Numbered as in the resolved model
+        let resolved = model.resolve().unwrap();
+        assert_eq!(model_triangles(&model).len(), resolved.paths.len());
+        for &p in &[(10.5, 1.5), (12.5, 1.5), (15.0, 2.0), (19.0, 2.0), (18.5, 1.5), (5.0, 1.0), (30.0, 1.0)] {
+            assert_eq!(model.hit_test(p), resolved.hit_test(p));
+        }
+
+        assert_eq!(model.hit_test((10.5, 1.5)), Some(1));
+        assert_eq!(model.hit_test((12.5, 1.5)), Some(2));
+        assert_eq!(model.hit_test((15.0, 2.0)), Some(3));
+        assert_eq!(model.hit_test((19.0, 2.0)), Some(4));
+        assert_eq!(model.hit_test((5.0, 1.0)), Some(0));
+    }
+
+    #[test]
+    fn nearest() {
+        let mut arch = path(&[(0.0, 0.0, 0.0), (1.0, 2.0, 0.5), (2.0, 0.0, 0.0)]);
+        arch.contours.push(vec![Point { location: (5.0, 5.0), curve_bias: 0.0, weights: NO_WEIGHTS }]);
+        let model = Model { paths: vec![path(&[(-4.0, 0.0, 0.0)]), arch], .. Default::default() };
+
+        let point = model.nearest_point((1.0, 1.5)).unwrap();
+        assert_eq!((point.path, point.outline, point.index), (1, 0, 1));
+        assert!((point.distance - 0.5).abs() < 1e-9);
+        assert_eq!(model.nearest_point((6.0, 5.0)).unwrap().outline, 1);
+
+        // The top of the curve, rather than its control point
+        let segment = model.nearest_segment((1.0, 1.5)).unwrap();
+        assert_eq!((segment.path, segment.outline, segment.segment), (1, 0, 0));
+        assert!((segment.t - 0.5).abs() < 1e-9);
+        assert!((segment.distance - 0.5).abs() < 1e-9);
+
+        // The closing edge along the bottom
+        let segment = model.nearest_segment((1.5, -0.5)).unwrap();
+        assert_eq!(segment.segment, 1);
+        assert!((segment.location.0 - 1.5).abs() < 1e-9 && segment.location.1 == 0.0);
+
+        assert_eq!(Model { paths: Vec::new(), .. Default::default() }.nearest_segment((0.0, 0.0)), None);
+    }
+
+    #[test]
+    fn closest_points() {
+        let segment = Segment { start: (0.0, 0.0), control: Some((3.0, 4.0)) };
+        let end = (5.0, -1.0);
+
+        for &p in &[(0.0, 3.0), (4.0, 3.0), (2.5, 0.0), (10.0, -10.0), (-3.0, 1.0)] {
+            let (_, location) = closest(&segment, end, p);
+            let best = (0..10001)
+                .map(|i| distance(quad_point(segment.start, (3.0, 4.0), end, i as f64 / 10000.0), p))
+                .fold(1.0 / 0.0, f64::min);
+            assert!((distance(location, p) - best).abs() < 1e-6);
+        }
+    }
+
+    #[test]
+    fn cat() {
+        let bytes = include_bytes!("../bin/cat.model");
+        let model = Model::read(&mut BinaryReader::new(Cursor::new(&bytes[..]))).unwrap();
+
+        // Agrees with the rasterizer, which fills from the same triangles
+        let mut image = ::image::Image::new(100, 100, (0.0, 0.0, 0.0, 0.0));
+        let ((x0, y0), (x1, y1)) = model.bounds().unwrap();
+        let scale = 100.0 / (x1 - x0).max(y1 - y0);
+        let model = Model { paths: model.paths.into_iter().map(|mut p| { p.stroke = None; p }).collect(), .. Default::default() };
+        ::model::raster::draw_model(&mut image, -x0 * scale, -y0 * scale, scale, &model);
+
+        let mut differences = 0;
+        for y in 0..100 {
+            for x in 0..100 {
+                let p = ((x as f64 + 0.5) / scale + x0, (y as f64 + 0.5) / scale + y0);
+                let covered = image.pixel(x, y).3 == 1.0;
+                let empty = image.pixel(x, y).3 == 0.0;
+                let hit = model.hit_test(p).is_some();
+                if (hit && empty) || (!hit && covered) {
+                    differences += 1;
+                }
+            }
+        }
+        assert_eq!(differences, 0);
+    }
+}
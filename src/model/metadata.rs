@@ -0,0 +1,272 @@
+use std::io;
+use data::{Value, Tag, Vec2, Box2, Reader, Writer};
+use super::{Model, Path, NAME, read_string, write_string};
+
+// Optional information about a model as a whole
+#[derive(Clone, PartialEq, Debug, Default)]
+pub struct Metadata {
+    pub name: Option<String>,
+    pub author: Option<String>,
+    // The area the model is drawn in, e.g. the page when it's exported
+    pub view_box: Option<Box2>,
+    // The point that's placed at a model's location, e.g. its feet
+    pub anchor: Option<Vec2>
+}
+
+// A named group of paths, by index, that can be hidden or locked together
+#[derive(Clone, PartialEq, Debug)]
+pub struct Layer {
+    pub name: String,
+    pub paths: Vec<usize>,
+    pub visible: bool,
+    pub locked: bool
+}
+
+const AUTHOR: Tag = tag!(A U T H);
+const VIEW_BOX: Tag = tag!(V B O X);
+const ANCHOR: Tag = tag!(A N C H);
+const LAYER_PATHS: Tag = tag!(P T H S);
+const VISIBLE: Tag = tag!(V I S I);
+const LOCKED: Tag = tag!(L O C K);
+
+impl Model {
+    // The index of the first path with the name
+    pub fn find_path(&self, name: &str) -> Option<usize> {
+        self.paths.iter().position(|path| path.name.as_ref().map_or(false, |n| n == name))
+    }
+
+    pub fn path_named(&self, name: &str) -> Option<&Path> {
+        self.find_path(name).map(move |i| &self.paths[i])
+    }
+
+    pub fn path_named_mut(&mut self, name: &str) -> Option<&mut Path> {
+        match self.find_path(name) {
+            Some(i) => Some(&mut self.paths[i]),
+            None => None
+        }
+    }
+
+    pub fn layer_named(&self, name: &str) -> Option<&Layer> {
+        self.layers.iter().find(|layer| layer.name == name)
+    }
+
+    pub fn layer_named_mut(&mut self, name: &str) -> Option<&mut Layer> {
+        self.layers.iter_mut().find(|layer| layer.name == name)
+    }
+
+    // A path is hidden if any layer it's in is hidden, and likewise for
+    // locked, so paths outside every layer are shown and unlocked
+    pub fn is_visible(&self, path: usize) -> bool {
+        !self.layers.iter().any(|layer| !layer.visible && layer.paths.contains(&path))
+    }
+
+    pub fn is_locked(&self, path: usize) -> bool {
+        self.layers.iter().any(|layer| layer.locked && layer.paths.contains(&path))
+    }
+
+    // Whether a path can be picked out for editing
+    pub fn is_editable(&self, path: usize) -> bool {
+        self.is_visible(path) && !self.is_locked(path)
+    }
+}
+
+impl Metadata {
+    pub fn is_empty(&self) -> bool {
+        *self == Metadata::default()
+    }
+
+    // Writes the fields that are set into a group whose tag has been written
+    pub fn write(&self, writer: &mut Writer) -> io::Result<()> {
+        if let Some(ref name) = self.name {
+            try!(write_string(writer, NAME, name));
+        }
+
+        if let Some(ref author) = self.author {
+            try!(write_string(writer, AUTHOR, author));
+        }
+
+        if let Some(view_box) = self.view_box {
+            try!(writer.write_start());
+            try!(writer.write_value(&Value::Tag(VIEW_BOX)));
+            try!(writer.write_value(&Value::Box2(view_box)));
+            try!(writer.write_end());
+        }
+
+        if let Some(anchor) = self.anchor {
+            try!(writer.write_start());
+            try!(writer.write_value(&Value::Tag(ANCHOR)));
+            try!(writer.write_value(&Value::Vec2(anchor)));
+            try!(writer.write_end());
+        }
+
+        Ok(())
+    }
+
+    // Reads the rest of a metadata group once its tag has been read
+    pub fn read_tagged(reader: &mut Reader) -> io::Result<Metadata> {
+        let mut metadata = Metadata::default();
+
+        while try!(reader.expect_start_or_end()) {
+            match try!(reader.expect_tag()) {
+                NAME => metadata.name = Some(try!(read_string(reader))),
+                AUTHOR => metadata.author = Some(try!(read_string(reader))),
+                VIEW_BOX => {
+                    metadata.view_box = Some(try!(reader.expect_box2()));
+                    try!(reader.skip_to_end());
+                },
+                ANCHOR => {
+                    metadata.anchor = Some(try!(reader.expect_vec2()));
+                    try!(reader.skip_to_end());
+                },
+                _ => try!(reader.skip_to_end())
+            }
+        }
+
+        Ok(metadata)
+    }
+}
+
+impl Layer {
+    pub fn write(&self, writer: &mut Writer) -> io::Result<()> {
+        try!(writer.write_start());
+
+        try!(write_string(writer, NAME, &self.name));
+
+        try!(writer.write_start());
+        try!(writer.write_value(&Value::Tag(LAYER_PATHS)));
+        let paths = self.paths.iter().map(|&i| i as i32).collect::<Vec<_>>().into_boxed_slice();
+        try!(writer.write_value(&Value::IntArray(paths)));
+        try!(writer.write_end());
+
+        if !self.visible {
+            try!(writer.write_start());
+            try!(writer.write_value(&Value::Tag(VISIBLE)));
+            try!(writer.write_value(&Value::Bool(false)));
+            try!(writer.write_end());
+        }
+
+        if self.locked {
+            try!(writer.write_start());
+            try!(writer.write_value(&Value::Tag(LOCKED)));
+            try!(writer.write_value(&Value::Bool(true)));
+            try!(writer.write_end());
+        }
+
+        writer.write_end()
+    }
+
+    pub fn read(reader: &mut Reader) -> io::Result<Layer> {
+        let mut layer = Layer {
+            name: String::new(),
+            paths: Vec::new(),
+            visible: true,
+            locked: false
+        };
+
+        try!(reader.expect_start());
+
+        while try!(reader.expect_start_or_end()) {
+            match try!(reader.expect_tag()) {
+                NAME => layer.name = try!(read_string(reader)),
+                LAYER_PATHS => {
+                    let paths = try!(reader.expect_int_array());
+                    if paths.iter().any(|&i| i < 0) {
+                        return Err(io::Error::new(io::ErrorKind::InvalidData, "Unexpected data"));
+                    }
+                    layer.paths = paths.iter().map(|&i| i as usize).collect();
+                    try!(reader.skip_to_end());
+                },
+                VISIBLE => {
+                    layer.visible = try!(reader.expect_bool());
+                    try!(reader.skip_to_end());
+                },
+                LOCKED => {
+                    layer.locked = try!(reader.expect_bool());
+                    try!(reader.skip_to_end());
+                },
+                _ => try!(reader.skip_to_end())
+            }
+        }
+
+        Ok(layer)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Cursor;
+    use data::{BinaryReader, BinaryWriter, TextReader};
+    use model::{Model, Path, path};
+    use model::tessellate::model_triangles;
+
+    fn square(name: &str, x: f64) -> Path {
+        let mut square = path(&[(x, 0.0, 0.0), (x + 1.0, 0.0, 0.0), (x + 1.0, 1.0, 0.0), (x, 1.0, 0.0)]);
+        square.name = Some(name.to_string());
+        square
+    }
+
+    fn cat() -> Model {
+        Model {
+            paths: vec![square("head", 0.0), square("left ear", 0.0), square("right ear", 2.0)],
+            metadata: Metadata {
+                name: Some("Cat".to_string()),
+                author: Some("Alice".to_string()),
+                view_box: Some(((-1.0, -1.0), (4.0, 3.0))),
+                anchor: Some((1.5, 0.0))
+            },
+            layers: vec![
+                Layer { name: "ears".to_string(), paths: vec![1, 2], visible: true, locked: true },
+                Layer { name: "hidden".to_string(), paths: vec![2], visible: false, locked: false }
+            ],
+            .. Default::default()
+        }
+    }
+
+    #[test]
+    fn names_and_layers() {
+        let mut model = cat();
+        assert_eq!(model.find_path("left ear"), Some(1));
+        assert_eq!(model.find_path("tail"), None);
+        assert_eq!(model.path_named("right ear").unwrap().points[0].location, (2.0, 0.0));
+        model.path_named_mut("head").unwrap().filled = false;
+        assert!(!model.paths[0].filled);
+
+        assert!(model.is_visible(0) && model.is_visible(1) && !model.is_visible(2));
+        assert!(!model.is_locked(0) && model.is_locked(1) && model.is_locked(2));
+        let drawn = model_triangles(&model).iter().map(|t| t.path).collect::<Vec<_>>();
+        assert_eq!(drawn, vec![0, 1]);
+
+        // Hidden and locked paths can't be hit
+        assert_eq!(model.hit_test((0.5, 0.5)), None);
+        assert_eq!(model.hit_test((2.5, 0.5)), None);
+        model.paths[0].filled = true;
+        assert_eq!(model.hit_test((0.5, 0.5)), Some(0));
+        model.layer_named_mut("ears").unwrap().locked = false;
+        assert_eq!(model.hit_test((0.5, 0.5)), Some(1));
+        assert_eq!(model.hit_test((2.5, 0.5)), None);
+    }
+
+    #[test]
+    fn write_and_read() {
+        let model = cat();
+        let mut writer = BinaryWriter::new(Vec::new());
+        model.write(&mut writer).unwrap();
+        let data = writer.into_inner();
+
+        let read = Model::read(&mut BinaryReader::new(Cursor::new(&data[..]))).unwrap();
+        assert_eq!(read.metadata, model.metadata);
+        assert_eq!(read.layers, model.layers);
+        assert_eq!(read.paths[1].name, Some("left ear".to_string()));
+
+        // Everything is optional
+        let data = b"(SHAP (META (NAME \"Dot\") (XTRA 1))
+                           (PTHS 1 ((COLR [1.0 1.0 1.0]) (PNTS {[0.0 0.0]} {0.0})))
+                           (LAYR 1 ((NAME \"all\"))))";
+        let read = Model::read(&mut TextReader::new(Cursor::new(&data[..]))).unwrap();
+        assert_eq!(read.metadata.name, Some("Dot".to_string()));
+        assert_eq!(read.metadata.author, None);
+        assert_eq!(read.paths[0].name, None);
+        assert_eq!(read.layers[0], Layer { name: "all".to_string(), paths: Vec::new(), visible: true, locked: false });
+    }
+}
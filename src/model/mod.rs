@@ -1,27 +1,134 @@
 pub mod rendering;
+pub mod tessellate;
+pub mod stroke;
+pub mod svg;
+pub mod pdf;
+pub mod raster;
+pub mod geometry;
+pub mod hit;
+pub mod simplify;
+pub mod boolean;
+pub mod anim;
+pub mod softbody;
+pub mod skeleton;
+pub mod paint;
+pub mod metadata;
+pub mod symbol;
+pub mod clip;
+pub mod validate;
+pub mod font;
 
 use std::io;
-use data::{Value, Tag, Vec2, Vec3, Reader, Writer};
-
+use data::{Value, Tag, Token, Vec2, Vec3, Reader, Writer};
+
+pub use self::geometry::Affine2;
+pub use self::paint::{Paint, Stop};
+pub use self::metadata::{Metadata, Layer};
+pub use self::symbol::{Symbol, Instance};
+pub use self::clip::Clip;
+pub use self::validate::{Issue, Problem};
+pub use self::font::Font;
+use self::skeleton::Skeleton;
+
+#[derive(Clone, Default)]
 pub struct Model {
-    pub paths: Vec<Path>
+    pub paths: Vec<Path>,
+    // Bones that points can be weighted to for posing the model
+    pub skeleton: Option<Skeleton>,
+    pub metadata: Metadata,
+    // Groups of paths, which may overlap or leave paths out
+    pub layers: Vec<Layer>,
+    pub symbols: Vec<Symbol>,
+    // Placements of symbols, drawn above the paths
+    pub instances: Vec<Instance>,
+    pub clips: Vec<Clip>
 }
 
+#[derive(Clone)]
 pub struct Path {
-    pub colour: Vec3,
+    pub name: Option<String>,
+    pub paint: Paint,
     pub points: Vec<Point>,
+    // Further outlines filled together with points, e.g. holes
+    pub contours: Vec<Vec<Point>>,
+    pub fill_rule: FillRule,
+    pub filled: bool,
+    pub stroke: Option<Stroke>
 }
 
 #[derive(Copy, Clone)]
 pub struct Point {
     pub location: Vec2,
-    pub curve_bias: f64
+    pub curve_bias: f64,
+    pub weights: Weights
+}
+
+// Up to four bones, by index into the model's skeleton, that a point moves
+// with and how much. Unused entries have zero weight.
+pub type Weights = [(usize, f64); 4];
+
+pub const NO_WEIGHTS: Weights = [(0, 0.0); 4];
+
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub enum FillRule {
+    NonZero,
+    EvenOdd
+}
+
+#[derive(Clone, PartialEq, Debug)]
+pub struct Stroke {
+    pub colour: Vec3,
+    pub width: f64,
+    pub join: LineJoin,
+    pub cap: LineCap,
+    // Alternating on and off lengths, empty for a solid line
+    pub dashes: Vec<f64>,
+    pub closed: bool
+}
+
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub enum LineJoin {
+    Miter,
+    Round,
+    Bevel
+}
+
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub enum LineCap {
+    Butt,
+    Round,
+    Square
 }
 
 const SHAPE: Tag = tag!(S H A P);
 const PATHS: Tag = tag!(P T H S);
 const COLOUR: Tag = tag!(C O L R);
 const POINTS: Tag = tag!(P N T S);
+const CONTOURS: Tag = tag!(C T R S);
+const FILLED: Tag = tag!(F I L L);
+const STROKE: Tag = tag!(S T R K);
+const WIDTH: Tag = tag!(W D T H);
+const JOIN: Tag = tag!(J O I N);
+const CAP: Tag = tag!(C A P _);
+const DASHES: Tag = tag!(D A S H);
+const CLOSED: Tag = tag!(C L S D);
+const BONES: Tag = tag!(B O N S);
+const PAINT: Tag = tag!(P A I N);
+const NAME: Tag = tag!(N A M E);
+const METADATA: Tag = tag!(M E T A);
+const LAYERS: Tag = tag!(L A Y R);
+const SYMBOLS: Tag = tag!(S Y M S);
+const INSTANCES: Tag = tag!(I N S T);
+const CLIPS: Tag = tag!(C L P S);
+const WEIGHTS: Tag = tag!(W G H T);
+
+const NON_ZERO: Tag = tag!(N Z R O);
+const EVEN_ODD: Tag = tag!(E V O D);
+const MITER: Tag = tag!(M I T R);
+const ROUND: Tag = tag!(R O N D);
+const BEVEL: Tag = tag!(B E V L);
+const BUTT: Tag = tag!(B U T T);
+const SQUARE: Tag = tag!(S Q A R);
 
 impl Model {
     pub fn write(&self, writer: &mut Writer) -> io::Result<()> {
@@ -38,6 +145,57 @@ impl Model {
 
         try!(writer.write_end());
 
+        if let Some(ref skeleton) = self.skeleton {
+            try!(skeleton.write(writer));
+        }
+
+        if !self.metadata.is_empty() {
+            try!(writer.write_start());
+            try!(writer.write_value(&Value::Tag(METADATA)));
+            try!(self.metadata.write(writer));
+            try!(writer.write_end());
+        }
+
+        if self.layers.len() > 0 {
+            try!(writer.write_start());
+            try!(writer.write_value(&Value::Tag(LAYERS)));
+            try!(writer.write_value(&Value::Int(self.layers.len() as i32)));
+
+            for layer in &self.layers {
+                try!(layer.write(writer));
+            }
+
+            try!(writer.write_end());
+        }
+
+        if self.symbols.len() > 0 {
+            try!(writer.write_start());
+            try!(writer.write_value(&Value::Tag(SYMBOLS)));
+            try!(writer.write_value(&Value::Int(self.symbols.len() as i32)));
+
+            for symbol in &self.symbols {
+                try!(symbol.write(writer));
+            }
+
+            try!(writer.write_end());
+        }
+
+        if self.instances.len() > 0 {
+            try!(Instance::write_all(writer, &self.instances));
+        }
+
+        if self.clips.len() > 0 {
+            try!(writer.write_start());
+            try!(writer.write_value(&Value::Tag(CLIPS)));
+            try!(writer.write_value(&Value::Int(self.clips.len() as i32)));
+
+            for clip in &self.clips {
+                try!(clip.write(writer));
+            }
+
+            try!(writer.write_end());
+        }
+
         try!(writer.write_end());
         Ok(())
     }
@@ -52,25 +210,88 @@ impl Model {
             return Err(io::Error::new(io::ErrorKind::Other, "Unexpected data"));
         }
 
+        Model::read_tagged(reader)
+    }
+
+    // Reads the rest of a model group once its tag has been read
+    pub fn read_tagged(reader: &mut Reader) -> io::Result<Model> {
         let mut paths = None;
+        let mut skeleton = None;
+        let mut metadata = Metadata::default();
+        let mut layers = Vec::new();
+        let mut symbols = Vec::new();
+        let mut instances = Vec::new();
+        let mut clips = Vec::new();
 
         while try!(reader.expect_start_or_end()) {
             match try!(reader.expect_tag()) {
                 PATHS => paths = Some(try!(read_paths(reader))),
+                BONES => skeleton = Some(try!(Skeleton::read_tagged(reader))),
+                METADATA => metadata = try!(Metadata::read_tagged(reader)),
+                LAYERS => layers = try!(read_layers(reader)),
+                SYMBOLS => symbols = try!(read_symbols(reader)),
+                INSTANCES => instances = try!(read_instances(reader)),
+                CLIPS => clips = try!(read_clips(reader)),
                 _ => try!(reader.skip_to_end())
             }
         }
 
         if let Some(paths) = paths {
-            Ok(Model {
-                paths: paths
-            })
+            let model = Model {
+                paths: paths,
+                skeleton: skeleton,
+                metadata: metadata,
+                layers: layers,
+                symbols: symbols,
+                instances: instances,
+                clips: clips
+            };
+
+            if !model.has_valid_weights() {
+                return Err(io::Error::new(io::ErrorKind::InvalidData, "Weight for missing bone"));
+            }
+
+            if !model.has_valid_symbols() {
+                return Err(io::Error::new(io::ErrorKind::InvalidData, "Missing or recursive symbol"));
+            }
+
+            if !model.has_valid_clips() {
+                return Err(io::Error::new(io::ErrorKind::InvalidData, "Clip of missing path"));
+            }
+
+            Ok(model)
         } else {
             Err(io::Error::new(io::ErrorKind::Other, "Unexpected data"))
         }
     }
 }
 
+// The model's view box, or else the bounds of its paths with room for their
+// strokes, used to size exported pages
+fn extent(model: &Model) -> Option<(Vec2, Vec2)> {
+    if model.metadata.view_box.is_some() {
+        return model.metadata.view_box;
+    }
+
+    let mut extent: Option<(Vec2, Vec2)> = None;
+
+    for path in &model.paths {
+        let margin = match path.stroke {
+            Some(ref stroke) if stroke.join == LineJoin::Miter => stroke.width / 2.0 * stroke::MITER_LIMIT,
+            Some(ref stroke) => stroke.width / 2.0 * 2f64.sqrt(),
+            None => 0.0
+        };
+
+        if let Some((p, q)) = path.bounds() {
+            let (min, max) = extent.unwrap_or((p, q));
+            extent = Some(((min.0.min(p.0 - margin), min.1.min(p.1 - margin)),
+                           (max.0.max(q.0 + margin), max.1.max(q.1 + margin))));
+        }
+    }
+
+    extent
+}
+
 fn read_paths(reader: &mut Reader) -> io::Result<Vec<Path>> {
     let count = try!(reader.expect_int());
     let mut paths = Vec::with_capacity(count as usize);
@@ -85,53 +306,150 @@ fn read_paths(reader: &mut Reader) -> io::Result<Vec<Path>> {
     Ok(paths)
 }
 
+fn read_layers(reader: &mut Reader) -> io::Result<Vec<Layer>> {
+    let count = try!(reader.expect_int());
+    let mut layers = Vec::new();
+
+    for _ in 0..count {
+        layers.push(try!(Layer::read(reader)));
+    }
+
+    try!(reader.skip_to_end());
+
+    Ok(layers)
+}
+
+fn read_symbols(reader: &mut Reader) -> io::Result<Vec<Symbol>> {
+    let count = try!(reader.expect_int());
+    let mut symbols = Vec::new();
+
+    for _ in 0..count {
+        symbols.push(try!(Symbol::read(reader)));
+    }
+
+    try!(reader.skip_to_end());
+
+    Ok(symbols)
+}
+
+fn read_instances(reader: &mut Reader) -> io::Result<Vec<Instance>> {
+    let count = try!(reader.expect_int());
+    let mut instances = Vec::new();
+
+    for _ in 0..count {
+        instances.push(try!(Instance::read(reader)));
+    }
+
+    try!(reader.skip_to_end());
+
+    Ok(instances)
+}
+
+fn read_clips(reader: &mut Reader) -> io::Result<Vec<Clip>> {
+    let count = try!(reader.expect_int());
+    let mut clips = Vec::new();
+
+    for _ in 0..count {
+        clips.push(try!(Clip::read(reader)));
+    }
+
+    try!(reader.skip_to_end());
+
+    Ok(clips)
+}
+
 impl Path {
-    fn write(&self, writer: &mut Writer) -> io::Result<()> {
+    pub fn write(&self, writer: &mut Writer) -> io::Result<()> {
         try!(writer.write_start());
 
+        if let Some(ref name) = self.name {
+            try!(write_string(writer, NAME, name));
+        }
+
+        // Readers that don't know about paints use the flat colour instead
+        let (r, g, b, _) = self.paint.flat_colour();
         try!(writer.write_start());
         try!(writer.write_value(&Value::Tag(COLOUR)));
-        try!(writer.write_value(&Value::Vec3(self.colour)));
+        try!(writer.write_value(&Value::Vec3((r, g, b))));
         try!(writer.write_end());
 
+        if self.paint != Paint::Solid((r, g, b, 1.0)) {
+            try!(writer.write_start());
+            try!(writer.write_value(&Value::Tag(PAINT)));
+            try!(self.paint.write(writer));
+            try!(writer.write_end());
+        }
+
         try!(writer.write_start());
         try!(writer.write_value(&Value::Tag(POINTS)));
+        try!(write_points(writer, &self.points));
+        try!(writer.write_end());
 
-        let locations = self.points.iter()
-            .map(|p| p.location)
-            .collect::<Vec<_>>()
-            .into_boxed_slice();
-        let curve_biases = self.points.iter()
-            .map(|p| p.curve_bias)
-            .collect::<Vec<_>>()
-            .into_boxed_slice();
+        if self.contours.len() > 0 || self.fill_rule != FillRule::NonZero {
+            try!(writer.write_start());
+            try!(writer.write_value(&Value::Tag(CONTOURS)));
+            try!(self.fill_rule.write(writer));
 
-        try!(writer.write_value(&Value::Vec2Array(locations)));
-        try!(writer.write_value(&Value::DoubleArray(curve_biases)));
-        try!(writer.write_end());
+            for contour in &self.contours {
+                try!(writer.write_start());
+                try!(write_points(writer, contour));
+                try!(writer.write_end());
+            }
+
+            try!(writer.write_end());
+        }
+
+        if !self.filled {
+            try!(writer.write_start());
+            try!(writer.write_value(&Value::Tag(FILLED)));
+            try!(writer.write_value(&Value::Bool(false)));
+            try!(writer.write_end());
+        }
+
+        if let Some(ref stroke) = self.stroke {
+            try!(stroke.write(writer));
+        }
 
         try!(writer.write_end());
         Ok(())
     }
 
-    fn read(reader: &mut Reader) -> io::Result<Path> {
+    pub fn read(reader: &mut Reader) -> io::Result<Path> {
+        let mut name = None;
         let mut colour = None;
+        let mut paint = None;
         let mut points = None;
+        let mut contours = (Vec::new(), FillRule::NonZero);
+        let mut filled = true;
+        let mut stroke = None;
 
         try!(reader.expect_start());
 
         while try!(reader.expect_start_or_end()) {
             match try!(reader.expect_tag()) {
+                NAME => name = Some(try!(read_string(reader))),
                 COLOUR => colour = Some(try!(read_colour(reader))),
+                PAINT => {
+                    paint = Some(try!(Paint::read(reader)));
+                    try!(reader.skip_to_end());
+                },
                 POINTS => points = Some(try!(read_points(reader))),
+                CONTOURS => contours = try!(read_contours(reader)),
+                FILLED => filled = try!(read_bool(reader)),
+                STROKE => stroke = Some(try!(Stroke::read(reader))),
                 _ => try!(reader.skip_to_end())
             }
         }
 
         if let (Some(colour), Some(points)) = (colour, points) {
             Ok(Path {
-                colour: colour,
-                points: points
+                name: name,
+                paint: paint.unwrap_or(Paint::solid(colour)),
+                points: points,
+                contours: contours.0,
+                fill_rule: contours.1,
+                filled: filled,
+                stroke: stroke
             })
         } else {
             Err(io::Error::new(io::ErrorKind::InvalidData, "Unexpected data"))
@@ -146,17 +464,396 @@ fn read_colour(reader: &mut Reader) -> io::Result<Vec3> {
     Ok(colour)
 }
 
+fn read_bool(reader: &mut Reader) -> io::Result<bool> {
+    let value = try!(reader.expect_bool());
+    try!(reader.skip_to_end());
+
+    Ok(value)
+}
+
+fn read_double(reader: &mut Reader) -> io::Result<f64> {
+    let value = try!(reader.expect_double());
+    try!(reader.skip_to_end());
+
+    Ok(value)
+}
+
+fn read_string(reader: &mut Reader) -> io::Result<String> {
+    let value = try!(reader.expect_string());
+    try!(reader.skip_to_end());
+
+    Ok(value.into_string())
+}
+
+fn write_string(writer: &mut Writer, tag: Tag, value: &str) -> io::Result<()> {
+    try!(writer.write_start());
+    try!(writer.write_value(&Value::Tag(tag)));
+    try!(writer.write_value(&Value::String(value.to_string().into_boxed_str())));
+    writer.write_end()
+}
+
+fn read_tag(reader: &mut Reader) -> io::Result<Tag> {
+    let value = try!(reader.expect_tag());
+    try!(reader.skip_to_end());
+
+    Ok(value)
+}
+
 fn read_points(reader: &mut Reader) -> io::Result<Vec<Point>> {
     let locations = try!(reader.expect_vec2_array());
     let biases = try!(reader.expect_double_array());
 
-    try!(reader.skip_to_end());
-
-    Ok(locations.iter()
+    let mut points: Vec<Point> = locations.iter()
         .zip(biases.iter())
         .map(|(&l, &b)| Point {
           location: l,
-          curve_bias: b
+          curve_bias: b,
+          weights: NO_WEIGHTS
         })
-        .collect())
+        .collect();
+
+    // Anything after the biases is skipped but the weights
+    loop {
+        match try!(reader.read_next()) {
+            Token::Start => match try!(reader.expect_tag()) {
+                WEIGHTS => try!(read_weights(reader, &mut points)),
+                _ => try!(reader.skip_to_end())
+            },
+            Token::Value(_) => (),
+            Token::End | Token::EndOfFile => break
+        }
+    }
+
+    Ok(points)
+}
+
+// Four bones and four weights per point, left out when no point has any
+fn read_weights(reader: &mut Reader, points: &mut [Point]) -> io::Result<()> {
+    let bones = try!(reader.expect_int_array());
+    let weights = try!(reader.expect_double_array());
+    if bones.len() != points.len() * 4 || weights.len() != points.len() * 4 || bones.iter().any(|&b| b < 0) {
+        return Err(io::Error::new(io::ErrorKind::InvalidData, "Unexpected data"));
+    }
+
+    for (i, point) in points.iter_mut().enumerate() {
+        for j in 0..4 {
+            point.weights[j] = (bones[i * 4 + j] as usize, weights[i * 4 + j]);
+        }
+    }
+
+    reader.skip_to_end()
+}
+
+fn write_points(writer: &mut Writer, points: &[Point]) -> io::Result<()> {
+    let locations = points.iter()
+        .map(|p| p.location)
+        .collect::<Vec<_>>()
+        .into_boxed_slice();
+    let curve_biases = points.iter()
+        .map(|p| p.curve_bias)
+        .collect::<Vec<_>>()
+        .into_boxed_slice();
+
+    try!(writer.write_value(&Value::Vec2Array(locations)));
+    try!(writer.write_value(&Value::DoubleArray(curve_biases)));
+
+    if points.iter().any(|p| p.weights.iter().any(|&(_, w)| w != 0.0)) {
+        let bones = points.iter()
+            .flat_map(|p| p.weights.iter().map(|&(b, _)| b as i32))
+            .collect::<Vec<_>>()
+            .into_boxed_slice();
+        let weights = points.iter()
+            .flat_map(|p| p.weights.iter().map(|&(_, w)| w))
+            .collect::<Vec<_>>()
+            .into_boxed_slice();
+
+        try!(writer.write_start());
+        try!(writer.write_value(&Value::Tag(WEIGHTS)));
+        try!(writer.write_value(&Value::IntArray(bones)));
+        try!(writer.write_value(&Value::DoubleArray(weights)));
+        try!(writer.write_end());
+    }
+
+    Ok(())
+}
+
+fn read_contours(reader: &mut Reader) -> io::Result<(Vec<Vec<Point>>, FillRule)> {
+    let fill_rule = match try!(reader.expect_tag()) {
+        NON_ZERO => FillRule::NonZero,
+        EVEN_ODD => FillRule::EvenOdd,
+        _ => return Err(io::Error::new(io::ErrorKind::InvalidData, "Unexpected data"))
+    };
+
+    let mut contours = Vec::new();
+    while try!(reader.expect_start_or_end()) {
+        contours.push(try!(read_points(reader)));
+    }
+
+    Ok((contours, fill_rule))
+}
+
+impl FillRule {
+    fn write(self, writer: &mut Writer) -> io::Result<()> {
+        let tag = match self {
+            FillRule::NonZero => NON_ZERO,
+            FillRule::EvenOdd => EVEN_ODD
+        };
+        writer.write_value(&Value::Tag(tag))
+    }
+}
+
+impl Stroke {
+    fn write(&self, writer: &mut Writer) -> io::Result<()> {
+        try!(writer.write_start());
+        try!(writer.write_value(&Value::Tag(STROKE)));
+
+        try!(writer.write_start());
+        try!(writer.write_value(&Value::Tag(COLOUR)));
+        try!(writer.write_value(&Value::Vec3(self.colour)));
+        try!(writer.write_end());
+
+        try!(writer.write_start());
+        try!(writer.write_value(&Value::Tag(WIDTH)));
+        try!(writer.write_value(&Value::Double(self.width)));
+        try!(writer.write_end());
+
+        let join = match self.join {
+            LineJoin::Miter => MITER,
+            LineJoin::Round => ROUND,
+            LineJoin::Bevel => BEVEL
+        };
+        try!(writer.write_start());
+        try!(writer.write_value(&Value::Tag(JOIN)));
+        try!(writer.write_value(&Value::Tag(join)));
+        try!(writer.write_end());
+
+        let cap = match self.cap {
+            LineCap::Butt => BUTT,
+            LineCap::Round => ROUND,
+            LineCap::Square => SQUARE
+        };
+        try!(writer.write_start());
+        try!(writer.write_value(&Value::Tag(CAP)));
+        try!(writer.write_value(&Value::Tag(cap)));
+        try!(writer.write_end());
+
+        if self.dashes.len() > 0 {
+            try!(writer.write_start());
+            try!(writer.write_value(&Value::Tag(DASHES)));
+            try!(writer.write_value(&Value::DoubleArray(self.dashes.clone().into_boxed_slice())));
+            try!(writer.write_end());
+        }
+
+        try!(writer.write_start());
+        try!(writer.write_value(&Value::Tag(CLOSED)));
+        try!(writer.write_value(&Value::Bool(self.closed)));
+        try!(writer.write_end());
+
+        writer.write_end()
+    }
+
+    fn read(reader: &mut Reader) -> io::Result<Stroke> {
+        let mut stroke = Stroke {
+            colour: (0.0, 0.0, 0.0),
+            width: 1.0,
+            join: LineJoin::Miter,
+            cap: LineCap::Butt,
+            dashes: Vec::new(),
+            closed: true
+        };
+
+        while try!(reader.expect_start_or_end()) {
+            match try!(reader.expect_tag()) {
+                COLOUR => stroke.colour = try!(read_colour(reader)),
+                WIDTH => stroke.width = try!(read_double(reader)),
+                JOIN => stroke.join = match try!(read_tag(reader)) {
+                    MITER => LineJoin::Miter,
+                    ROUND => LineJoin::Round,
+                    BEVEL => LineJoin::Bevel,
+                    _ => return Err(io::Error::new(io::ErrorKind::InvalidData, "Unexpected data"))
+                },
+                CAP => stroke.cap = match try!(read_tag(reader)) {
+                    BUTT => LineCap::Butt,
+                    ROUND => LineCap::Round,
+                    SQUARE => LineCap::Square,
+                    _ => return Err(io::Error::new(io::ErrorKind::InvalidData, "Unexpected data"))
+                },
+                DASHES => {
+                    stroke.dashes = try!(reader.expect_double_array()).to_vec();
+                    try!(reader.skip_to_end());
+                },
+                CLOSED => stroke.closed = try!(read_bool(reader)),
+                _ => try!(reader.skip_to_end())
+            }
+        }
+
+        Ok(stroke)
+    }
+}
+
+// A filled white path through (x, y, curve bias) points, for the tests
+#[cfg(test)]
+fn path(points: &[(f64, f64, f64)]) -> Path {
+    Path {
+        name: None,
+        paint: Paint::solid((1.0, 1.0, 1.0)),
+        points: points.iter()
+            .map(|&(x, y, bias)| Point { location: (x, y), curve_bias: bias, weights: NO_WEIGHTS })
+            .collect(),
+        contours: Vec::new(),
+        fill_rule: FillRule::NonZero,
+        filled: true,
+        stroke: None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use data::{BinaryReader, BinaryWriter, TextReader, Token};
+    use std::io::Cursor;
+
+    #[test]
+    fn round_trip() {
+        let data = include_bytes!("../bin/cat.model");
+        let model = Model::read(&mut BinaryReader::new(Cursor::new(&data[..]))).unwrap();
+
+        let mut writer = BinaryWriter::new(Vec::new());
+        model.write(&mut writer).unwrap();
+
+        assert_eq!(&writer.into_inner()[..], &data[..]);
+    }
+
+    #[test]
+    fn contours() {
+        let point = |x, y| Point { location: (x, y), curve_bias: 0.0, weights: NO_WEIGHTS };
+        let model = Model {
+            paths: vec![
+                Path {
+                    name: None,
+                    paint: Paint::solid((1.0, 0.0, 0.0)),
+                    points: vec![point(0.0, 0.0), point(2.0, 0.0), point(2.0, 2.0)],
+                    contours: vec![
+                        vec![point(0.5, 0.5), point(1.0, 0.5), point(1.0, 1.0)],
+                        vec![point(1.5, 0.5), point(1.8, 0.5), point(1.8, 0.8)],
+                    ],
+                    fill_rule: FillRule::EvenOdd,
+                    filled: true,
+                    stroke: None
+                }
+            ],
+            .. Default::default()
+        };
+
+        let mut writer = BinaryWriter::new(Vec::new());
+        model.write(&mut writer).unwrap();
+        let data = writer.into_inner();
+
+        let read = Model::read(&mut BinaryReader::new(Cursor::new(&data[..]))).unwrap();
+        let path = &read.paths[0];
+        assert_eq!(path.points.len(), 3);
+        assert_eq!(path.contours.len(), 2);
+        assert_eq!(path.contours[1][2].location, (1.8, 0.8));
+        assert_eq!(path.fill_rule, FillRule::EvenOdd);
+    }
+
+    #[test]
+    fn stroke() {
+        let point = |x, y| Point { location: (x, y), curve_bias: 0.0, weights: NO_WEIGHTS };
+        let model = Model {
+            paths: vec![
+                Path {
+                    name: None,
+                    paint: Paint::solid((1.0, 1.0, 1.0)),
+                    points: vec![point(0.0, 0.0), point(2.0, 0.0), point(2.0, 2.0)],
+                    contours: Vec::new(),
+                    fill_rule: FillRule::NonZero,
+                    filled: false,
+                    stroke: Some(Stroke {
+                        colour: (0.0, 0.5, 1.0),
+                        width: 0.25,
+                        join: LineJoin::Round,
+                        cap: LineCap::Square,
+                        dashes: vec![0.5, 0.25],
+                        closed: false
+                    })
+                }
+            ],
+            .. Default::default()
+        };
+
+        let mut writer = BinaryWriter::new(Vec::new());
+        model.write(&mut writer).unwrap();
+        let data = writer.into_inner();
+
+        let read = Model::read(&mut BinaryReader::new(Cursor::new(&data[..]))).unwrap();
+        assert!(!read.paths[0].filled);
+        assert_eq!(read.paths[0].stroke, model.paths[0].stroke);
+
+        let data = b"(SHAP (PTHS 1 ((COLR [1.0 1.0 1.0])
+                                    (PNTS {[0.0 0.0] [1.0 0.0]} {0.0 0.0})
+                                    (STRK (WDTH 2.0)))))";
+
+        let read = Model::read(&mut TextReader::new(Cursor::new(&data[..]))).unwrap();
+        let stroke = read.paths[0].stroke.as_ref().unwrap();
+        assert!(read.paths[0].filled);
+        assert_eq!(stroke.width, 2.0);
+        assert_eq!(stroke.join, LineJoin::Miter);
+        assert_eq!(stroke.cap, LineCap::Butt);
+        assert!(stroke.closed);
+    }
+
+    #[test]
+    fn paint() {
+        let data = b"(SHAP (PTHS 2 ((COLR [1.0 0.0 0.0])
+                                    (PAIN RADG [1.0 2.0] 3.0 {0.0 1.0} {[0.0 0.0 1.0 1.0] [0.0 1.0 0.0 0.5]})
+                                    (PNTS {[0.0 0.0] [1.0 0.0] [0.0 1.0]} {0.0 0.0 0.0}))
+                                   ((COLR [1.0 0.0 0.0])
+                                    (PNTS {[0.0 0.0] [1.0 0.0] [0.0 1.0]} {0.0 0.0 0.0})))))";
+
+        let model = Model::read(&mut TextReader::new(Cursor::new(&data[..]))).unwrap();
+        assert_eq!(model.paths[0].paint, Paint::RadialGradient {
+            centre: (1.0, 2.0),
+            radius: 3.0,
+            stops: vec![
+                Stop { offset: 0.0, colour: (0.0, 0.0, 1.0, 1.0) },
+                Stop { offset: 1.0, colour: (0.0, 1.0, 0.0, 0.5) }
+            ]
+        });
+        assert_eq!(model.paths[1].paint, Paint::solid((1.0, 0.0, 0.0)));
+
+        // The colour is kept up to date for readers that don't know paints
+        let mut writer = BinaryWriter::new(Vec::new());
+        model.write(&mut writer).unwrap();
+        let data = writer.into_inner();
+
+        let mut reader = BinaryReader::new(Cursor::new(&data[..]));
+        let read = Model::read(&mut reader).unwrap();
+        assert_eq!(read.paths[0].paint, model.paths[0].paint);
+        assert_eq!(read.paths[1].paint, model.paths[1].paint);
+
+        let mut reader = BinaryReader::new(Cursor::new(&data[..]));
+        let mut colours = Vec::new();
+        loop {
+            match reader.read_next().unwrap() {
+                Token::Value(Value::Vec3(colour)) => colours.push(colour),
+                Token::EndOfFile => break,
+                _ => ()
+            }
+        }
+        assert_eq!(colours, vec![(0.0, 0.5, 0.5), (1.0, 0.0, 0.0)]);
+    }
+
+    #[test]
+    fn skip_unknown() {
+        let data = b"(SHAP (PTHS 1 ((COLR [1.0 1.0 1.0])
+                                    (PNTS {[0.0 0.0] [1.0 0.0] [0.0 1.0]} {0.0 0.0 0.0})
+                                    (XTRA 1 (2 3))
+                                    (CTRS NZRO ({[0.1 0.1]} {0.0})))))";
+
+        let model = Model::read(&mut TextReader::new(Cursor::new(&data[..]))).unwrap();
+        assert_eq!(model.paths[0].points.len(), 3);
+        assert_eq!(model.paths[0].contours.len(), 1);
+        assert_eq!(model.paths[0].fill_rule, FillRule::NonZero);
+    }
 }
@@ -1,8 +1,11 @@
 #version 140
 
-uniform vec3 colour;
+uniform sampler2D ramp;
+uniform int paint_kind;
+uniform vec4 gradient;
 
 in vec3 p;
+in vec2 model_position;
 
 out vec4 color;
 
@@ -10,5 +13,23 @@ void main() {
     float s = p.x * p.x - p.y;
     float a = step(0.0, p.z * s);
 
-    color = vec4(colour, a);
+    // Leaves the stencil buffer alone outside the curve when drawing clips
+    if (a == 0.0) {
+        discard;
+    }
+
+    // How far along the gradient this is, as in Paint::offset_at
+    float t = 0.0;
+    if (paint_kind == 1) {
+        vec2 d = gradient.zw - gradient.xy;
+        t = dot(model_position - gradient.xy, d) / dot(d, d);
+    } else if (paint_kind == 2) {
+        t = length(model_position - gradient.xy) / gradient.z;
+    }
+
+    // Texel centres run from half a texel in at each end
+    float size = float(textureSize(ramp, 0).x);
+    vec4 paint = texture(ramp, vec2((clamp(t, 0.0, 1.0) * (size - 1.0) + 0.5) / size, 0.5));
+
+    color = vec4(paint.rgb, paint.a * a);
 }
\ No newline at end of file
@@ -8,9 +8,11 @@ in vec2 position;
 in vec3 param;
 
 out vec3 p;
+out vec2 model_position;
 
 void main() {
     vec2 pos = translate + scale * position;
     gl_Position = vec4(vec2(2) * pos / viewport_size - vec2(1), 0, 1);
     p = param;
+    model_position = position;
 }
\ No newline at end of file
@@ -0,0 +1,255 @@
+use std::io;
+use data::{Value, Tag, Vec2, Vec3, Vec4, Reader, Writer};
+use super::Affine2;
+
+// How a path is filled, with colours as RGBA
+#[derive(Clone, PartialEq, Debug)]
+pub enum Paint {
+    Solid(Vec4),
+    // Varies from the first stop at start to the last at end, and is
+    // constant along lines at right angles to that
+    LinearGradient {
+        start: Vec2,
+        end: Vec2,
+        stops: Vec<Stop>
+    },
+    // Varies from the first stop at the centre to the last at the radius
+    RadialGradient {
+        centre: Vec2,
+        radius: f64,
+        stops: Vec<Stop>
+    }
+}
+
+// A colour at an offset from 0 to 1 along a gradient. Stops are in order of
+// offset, and the colour between two is mixed from both.
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub struct Stop {
+    pub offset: f64,
+    pub colour: Vec4
+}
+
+const SOLID: Tag = tag!(S O L D);
+const LINEAR_GRADIENT: Tag = tag!(L I N G);
+const RADIAL_GRADIENT: Tag = tag!(R A D G);
+
+impl Paint {
+    pub fn solid((r, g, b): Vec3) -> Paint {
+        Paint::Solid((r, g, b, 1.0))
+    }
+
+    // How far along the gradient a point is, from 0 to 1, or 0 for a solid
+    // paint
+    pub fn offset_at(&self, (x, y): Vec2) -> f64 {
+        let offset = match *self {
+            Paint::Solid(_) => 0.0,
+            Paint::LinearGradient { start, end, .. } => {
+                let (dx, dy) = (end.0 - start.0, end.1 - start.1);
+                ((x - start.0) * dx + (y - start.1) * dy) / (dx * dx + dy * dy)
+            },
+            Paint::RadialGradient { centre, radius, .. } => {
+                ((x - centre.0) * (x - centre.0) + (y - centre.1) * (y - centre.1)).sqrt() / radius
+            }
+        };
+
+        // A gradient of no length is the colour of its last stop
+        if offset.is_nan() { 1.0 } else { offset.max(0.0).min(1.0) }
+    }
+
+    // The colour at an offset along the gradient
+    pub fn colour_at_offset(&self, offset: f64) -> Vec4 {
+        let stops = match *self {
+            Paint::Solid(colour) => return colour,
+            Paint::LinearGradient { ref stops, .. } => stops,
+            Paint::RadialGradient { ref stops, .. } => stops
+        };
+
+        match stops.iter().position(|stop| stop.offset > offset) {
+            None => stops.last().map_or((0.0, 0.0, 0.0, 0.0), |stop| stop.colour),
+            Some(0) => stops[0].colour,
+            Some(i) => {
+                let (a, b) = (&stops[i - 1], &stops[i]);
+                let t = (offset - a.offset) / (b.offset - a.offset);
+                let mix = |c: f64, d: f64| c + (d - c) * t;
+                (mix(a.colour.0, b.colour.0), mix(a.colour.1, b.colour.1),
+                 mix(a.colour.2, b.colour.2), mix(a.colour.3, b.colour.3))
+            }
+        }
+    }
+
+    pub fn colour_at(&self, p: Vec2) -> Vec4 {
+        self.colour_at_offset(self.offset_at(p))
+    }
+
+    // Whether everything painted is covered completely
+    pub fn is_opaque(&self) -> bool {
+        match *self {
+            Paint::Solid(colour) => colour.3 >= 1.0,
+            Paint::LinearGradient { ref stops, .. } | Paint::RadialGradient { ref stops, .. } =>
+                stops.len() > 0 && stops.iter().all(|stop| stop.colour.3 >= 1.0)
+        }
+    }
+
+    // A single colour to stand in for the paint where gradients can't be
+    // drawn, which is the colour halfway along a gradient
+    pub fn flat_colour(&self) -> Vec4 {
+        match *self {
+            Paint::Solid(colour) => colour,
+            _ => self.colour_at_offset(0.5)
+        }
+    }
+
+    // Moves a gradient's geometry with its path. Radial gradients stay
+    // circular, scaled by the transform's average stretch.
+    pub fn transform(&mut self, transform: &Affine2) {
+        match *self {
+            Paint::Solid(_) => (),
+            Paint::LinearGradient { ref mut start, ref mut end, .. } => {
+                // The gradient runs at right angles to its lines of constant
+                // colour, which a shear or uneven scale doesn't preserve, so
+                // the end is placed on the transformed line through it
+                let (dx, dy) = (end.0 - start.0, end.1 - start.1);
+                let (s, e, across) = (transform.apply(*start), transform.apply(*end),
+                                      transform.apply((end.0 - dy, end.1 + dx)));
+                let (lx, ly) = (across.0 - e.0, across.1 - e.1);
+                let length = lx * lx + ly * ly;
+                let along = ((s.0 - e.0) * lx + (s.1 - e.1) * ly) / length;
+                *start = s;
+                *end = if length > 0.0 { (e.0 + along * lx, e.1 + along * ly) } else { e };
+            },
+            Paint::RadialGradient { ref mut centre, ref mut radius, .. } => {
+                *centre = transform.apply(*centre);
+                *radius *= transform.stretch();
+            }
+        }
+    }
+
+    pub fn write(&self, writer: &mut Writer) -> io::Result<()> {
+        let write_stops = |writer: &mut Writer, stops: &[Stop]| -> io::Result<()> {
+            let offsets = stops.iter().map(|s| s.offset).collect::<Vec<_>>().into_boxed_slice();
+            let colours = stops.iter().map(|s| s.colour).collect::<Vec<_>>().into_boxed_slice();
+            try!(writer.write_value(&Value::DoubleArray(offsets)));
+            writer.write_value(&Value::Vec4Array(colours))
+        };
+
+        match *self {
+            Paint::Solid(colour) => {
+                try!(writer.write_value(&Value::Tag(SOLID)));
+                writer.write_value(&Value::Vec4(colour))
+            },
+            Paint::LinearGradient { start, end, ref stops } => {
+                try!(writer.write_value(&Value::Tag(LINEAR_GRADIENT)));
+                try!(writer.write_value(&Value::Vec2(start)));
+                try!(writer.write_value(&Value::Vec2(end)));
+                write_stops(writer, stops)
+            },
+            Paint::RadialGradient { centre, radius, ref stops } => {
+                try!(writer.write_value(&Value::Tag(RADIAL_GRADIENT)));
+                try!(writer.write_value(&Value::Vec2(centre)));
+                try!(writer.write_value(&Value::Double(radius)));
+                write_stops(writer, stops)
+            }
+        }
+    }
+
+    // Reads the contents of a paint group, leaving its end to the caller
+    pub fn read(reader: &mut Reader) -> io::Result<Paint> {
+        let read_stops = |reader: &mut Reader| -> io::Result<Vec<Stop>> {
+            let offsets = try!(reader.expect_double_array());
+            let colours = try!(reader.expect_vec4_array());
+
+            let ordered = offsets.windows(2).all(|w| w[0] <= w[1]);
+            if offsets.len() != colours.len() || !ordered {
+                return Err(io::Error::new(io::ErrorKind::InvalidData, "Unexpected data"));
+            }
+
+            Ok(offsets.iter()
+                .zip(colours.iter())
+                .map(|(&offset, &colour)| Stop {
+                    offset: offset,
+                    colour: colour
+                })
+                .collect())
+        };
+
+        match try!(reader.expect_tag()) {
+            SOLID => Ok(Paint::Solid(try!(reader.expect_vec4()))),
+            LINEAR_GRADIENT => {
+                let start = try!(reader.expect_vec2());
+                let end = try!(reader.expect_vec2());
+                Ok(Paint::LinearGradient {
+                    start: start,
+                    end: end,
+                    stops: try!(read_stops(reader))
+                })
+            },
+            RADIAL_GRADIENT => {
+                let centre = try!(reader.expect_vec2());
+                let radius = try!(reader.expect_double());
+                Ok(Paint::RadialGradient {
+                    centre: centre,
+                    radius: radius,
+                    stops: try!(read_stops(reader))
+                })
+            },
+            _ => Err(io::Error::new(io::ErrorKind::InvalidData, "Unknown paint"))
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::f64::consts::PI;
+    use model::Affine2;
+
+    fn stops() -> Vec<Stop> {
+        vec![
+            Stop { offset: 0.25, colour: (1.0, 0.0, 0.0, 1.0) },
+            Stop { offset: 0.75, colour: (0.0, 0.0, 1.0, 0.0) }
+        ]
+    }
+
+    #[test]
+    fn colours() {
+        let linear = Paint::LinearGradient { start: (0.0, 0.0), end: (4.0, 0.0), stops: stops() };
+        assert_eq!(linear.colour_at((0.0, 5.0)), (1.0, 0.0, 0.0, 1.0));
+        assert_eq!(linear.colour_at((2.0, -1.0)), (0.5, 0.0, 0.5, 0.5));
+        assert_eq!(linear.colour_at((10.0, 0.0)), (0.0, 0.0, 1.0, 0.0));
+        assert_eq!(linear.flat_colour(), (0.5, 0.0, 0.5, 0.5));
+
+        let radial = Paint::RadialGradient { centre: (1.0, 1.0), radius: 2.0, stops: stops() };
+        assert_eq!(radial.colour_at((1.0, 1.0)), (1.0, 0.0, 0.0, 1.0));
+        assert_eq!(radial.colour_at((1.0, 2.0)), (0.5, 0.0, 0.5, 0.5));
+
+        assert_eq!(Paint::solid((0.1, 0.2, 0.3)).colour_at((5.0, 5.0)), (0.1, 0.2, 0.3, 1.0));
+        let empty = Paint::LinearGradient { start: (0.0, 0.0), end: (0.0, 0.0), stops: Vec::new() };
+        assert_eq!(empty.colour_at((1.0, 1.0)), (0.0, 0.0, 0.0, 0.0));
+
+        assert!(Paint::solid((0.1, 0.2, 0.3)).is_opaque());
+        assert!(!Paint::Solid((0.1, 0.2, 0.3, 0.5)).is_opaque());
+        assert!(!linear.is_opaque() && !empty.is_opaque());
+        let mut stops = stops();
+        stops[1].colour.3 = 1.0;
+        assert!(Paint::RadialGradient { centre: (1.0, 1.0), radius: 2.0, stops: stops }.is_opaque());
+    }
+
+    #[test]
+    fn transform() {
+        // Along a sheared gradient the colour still matches the points it
+        // was at before
+        let mut linear = Paint::LinearGradient { start: (0.0, 0.0), end: (4.0, 0.0), stops: stops() };
+        let original = linear.clone();
+        let transform = Affine2::skew(PI / 6.0, 0.3).then(&Affine2::scale(2.0, 0.5)).then(&Affine2::rotate(1.0));
+        linear.transform(&transform);
+
+        for &p in &[(1.0, 0.0), (2.0, 3.0), (2.5, -7.0)] {
+            let (a, b) = (original.colour_at(p), linear.colour_at(transform.apply(p)));
+            assert!((a.0 - b.0).abs() < 1e-9 && (a.3 - b.3).abs() < 1e-9);
+        }
+
+        let mut radial = Paint::RadialGradient { centre: (1.0, 1.0), radius: 2.0, stops: stops() };
+        radial.transform(&Affine2::translate(1.0, 0.0).then(&Affine2::scale(3.0, 3.0)));
+        assert_eq!(radial, Paint::RadialGradient { centre: (6.0, 3.0), radius: 6.0, stops: stops() });
+    }
+}
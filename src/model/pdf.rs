@@ -0,0 +1,283 @@
+use std::io::{self, Write};
+use data::{Vec2, Vec3};
+use super::{Model, FillRule, Stroke, LineJoin, LineCap, extent};
+use super::stroke::MITER_LIMIT;
+use super::tessellate::{Segment, path_segments, stroke_segments};
+
+// Writes a model as a single page PDF sized to fit it, one unit to a point.
+// PDF has no quadratic béziers, so each is raised to the equivalent cubic.
+pub fn write_pdf(model: &Model, output: &mut Write) -> io::Result<()> {
+    // Instances are drawn as copies of their symbols' paths
+    let resolved = try!(model.resolve().ok_or(io::Error::new(io::ErrorKind::InvalidData,
+                                                             "Missing or recursive symbol")));
+    let model = &resolved;
+    if !model.has_valid_clips() {
+        return Err(io::Error::new(io::ErrorKind::InvalidData, "Clip of missing path"));
+    }
+    let (min, max) = extent(model).unwrap_or(((0.0, 0.0), (0.0, 0.0)));
+
+    // The y-axis already points up, so only the origin moves
+    let mut content = format!("1 0 0 1 {} {} cm\n{} M\n", -min.0 + 0.0, -min.1 + 0.0, MITER_LIMIT);
+
+    // The fill alphas used, each with a graphics state named by its index
+    let mut alphas: Vec<f64> = Vec::new();
+
+    for (i, path) in model.paths.iter().enumerate() {
+        let outlines = path_segments(path);
+        if outlines.len() == 0 || (!path.filled && path.stroke.is_none()) || !model.is_visible(i) ||
+            model.is_clip_path(i) {
+            continue;
+        }
+
+        // Gradients are drawn with the paint's flat colour. Strokes are
+        // opaque, as they are when rendered.
+        let (r, g, b, a) = path.paint.flat_colour();
+        let alpha = if path.filled { a.max(0.0).min(1.0) } else { 1.0 };
+
+        // Each clip narrows the clipping path, and the alpha applies, until
+        // the state is restored
+        let clips = model.clip_paths(i);
+        let scoped = clips.len() > 0 || alpha != 1.0;
+        if scoped {
+            content.push_str("q\n");
+        }
+        if alpha != 1.0 {
+            let state = match alphas.iter().position(|&a| a == alpha) {
+                Some(state) => state,
+                None => {
+                    alphas.push(alpha);
+                    alphas.len() - 1
+                }
+            };
+            content.push_str(&format!("/GS{} gs\n", state));
+        }
+        for clip in clips.iter().map(|&c| &model.paths[c]) {
+            path_content(&path_segments(clip), true, &mut content);
+            content.push_str(if clip.fill_rule == FillRule::EvenOdd { "W* n\n" } else { "W n\n" });
+        }
+
+        let even_odd = path.fill_rule == FillRule::EvenOdd;
+        let open = path.stroke.as_ref().map(|s| !s.closed).unwrap_or(false);
+        let curved = outlines.iter().any(|s| s[s.len() - 1].control.is_some());
+        let lines = if open { stroke_segments(path, false) } else { outlines.clone() };
+
+        if path.filled {
+            content.push_str(&format!("{} rg\n", colour((r, g, b))));
+        }
+        if let Some(ref stroke) = path.stroke {
+            stroke_state(stroke, &mut content);
+        }
+
+        // As in SVG, open strokes need the fill drawn separately when it
+        // closes with a curve
+        let operator = match (path.filled, &path.stroke) {
+            (true, &Some(_)) if open && curved => {
+                path_content(&outlines, true, &mut content);
+                content.push_str(if even_odd { "f*\n" } else { "f\n" });
+                "S"
+            },
+            (true, &Some(_)) => if even_odd { "B*" } else { "B" },
+            (true, &None) => if even_odd { "f*" } else { "f" },
+            (false, _) => "S"
+        };
+
+        path_content(&lines, !open, &mut content);
+        content.push_str(operator);
+        content.push('\n');
+
+        if scoped {
+            content.push_str("Q\n");
+        }
+    }
+
+    let states = alphas.iter().enumerate()
+        .map(|(i, alpha)| format!("/GS{} << /Type /ExtGState /ca {} /CA 1 >>", i, alpha))
+        .collect::<Vec<_>>();
+    let resources = if states.len() > 0 {
+        format!("<< /ExtGState << {} >> >>", states.join(" "))
+    } else {
+        "<< >>".to_string()
+    };
+
+    let objects = vec![
+        "<< /Type /Catalog /Pages 2 0 R >>".to_string(),
+        "<< /Type /Pages /Kids [3 0 R] /Count 1 >>".to_string(),
+        format!("<< /Type /Page /Parent 2 0 R /MediaBox [0 0 {} {}] /Resources {} /Contents 4 0 R >>",
+                max.0 - min.0, max.1 - min.1, resources),
+        format!("<< /Length {} >>\nstream\n{}endstream", content.len(), content)
+    ];
+
+    let mut data = b"%PDF-1.4\n".to_vec();
+    let mut offsets = Vec::with_capacity(objects.len());
+
+    for (i, object) in objects.iter().enumerate() {
+        offsets.push(data.len());
+        data.extend_from_slice(format!("{} 0 obj\n{}\nendobj\n", i + 1, object).as_bytes());
+    }
+
+    let xref = data.len();
+    data.extend_from_slice(format!("xref\n0 {}\n0000000000 65535 f \n", objects.len() + 1).as_bytes());
+    for offset in offsets {
+        data.extend_from_slice(format!("{:010} 00000 n \n", offset).as_bytes());
+    }
+    data.extend_from_slice(format!("trailer\n<< /Size {} /Root 1 0 R >>\nstartxref\n{}\n%%EOF\n",
+                                   objects.len() + 1, xref).as_bytes());
+
+    output.write_all(&data)
+}
+
+fn colour(colour: Vec3) -> String {
+    let channel = |c: f64| c.max(0.0).min(1.0);
+    format!("{} {} {}", channel(colour.0), channel(colour.1), channel(colour.2))
+}
+
+fn stroke_state(stroke: &Stroke, content: &mut String) {
+    let dashes = stroke.dashes.iter().map(|d| d.to_string()).collect::<Vec<_>>();
+
+    content.push_str(&format!("{} RG {} w {} j {} J [{}] 0 d\n",
+                              colour(stroke.colour),
+                              stroke.width,
+                              match stroke.join {
+                                  LineJoin::Miter => 0,
+                                  LineJoin::Round => 1,
+                                  LineJoin::Bevel => 2
+                              },
+                              match stroke.cap {
+                                  LineCap::Butt => 0,
+                                  LineCap::Round => 1,
+                                  LineCap::Square => 2
+                              },
+                              dashes.join(" ")));
+}
+
+fn path_content(outlines: &[Vec<Segment>], closed: bool, content: &mut String) {
+    let point = |p: Vec2| format!("{} {}", p.0, p.1);
+
+    for segments in outlines {
+        content.push_str(&format!("{} m\n", point(segments[0].start)));
+
+        for (i, segment) in segments.iter().enumerate() {
+            let start = segment.start;
+            let end = segments[(i + 1) % segments.len()].start;
+            let last = i == segments.len() - 1;
+
+            match segment.control {
+                _ if last && !closed => (),
+                Some(c) => {
+                    let c1 = (start.0 + 2.0 / 3.0 * (c.0 - start.0), start.1 + 2.0 / 3.0 * (c.1 - start.1));
+                    let c2 = (end.0 + 2.0 / 3.0 * (c.0 - end.0), end.1 + 2.0 / 3.0 * (c.1 - end.1));
+                    content.push_str(&format!("{} {} {} c\n", point(c1), point(c2), point(end)));
+                },
+                None if last => (),
+                None => content.push_str(&format!("{} l\n", point(end)))
+            }
+        }
+
+        if closed {
+            content.push_str("h\n");
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use model::{Model, Point, Paint, FillRule, Stroke, LineJoin, LineCap, Clip, NO_WEIGHTS, path};
+
+    fn pdf(model: &Model) -> String {
+        let mut output = Vec::new();
+        write_pdf(model, &mut output).unwrap();
+        String::from_utf8(output).unwrap()
+    }
+
+    #[test]
+    fn structure() {
+        let data = pdf(&Model {
+            paths: vec![path(&[(0.0, 0.0, 0.0), (10.0, 0.0, 0.0), (10.0, 10.0, 0.0)])],
+            .. Default::default()
+        });
+
+        assert!(data.starts_with("%PDF-1.4\n"));
+        assert!(data.ends_with("%%EOF\n"));
+
+        // Each cross reference entry points at its object
+        let xref = data[data.rfind("startxref\n").unwrap() + 10..].lines().next().unwrap()
+            .parse::<usize>().unwrap();
+        assert!(data[xref..].starts_with("xref\n0 5\n"));
+        for (i, line) in data[xref..].lines().skip(3).take(4).enumerate() {
+            let offset = line[..10].parse::<usize>().unwrap();
+            assert!(data[offset..].starts_with(&format!("{} 0 obj\n", i + 1)));
+        }
+
+        let length = data[data.find("/Length ").unwrap() + 8..].split(' ').next().unwrap()
+            .parse::<usize>().unwrap();
+        let stream = data.find("stream\n").unwrap() + 7;
+        assert!(data[stream + length..].starts_with("endstream"));
+
+        assert!(data.contains("/MediaBox [0 0 10 10]"));
+        assert!(data.contains("1 1 1 rg\n0 0 m\n10 0 l\n10 10 l\nh\nf\n"));
+    }
+
+    #[test]
+    fn curves_and_strokes() {
+        let mut curve = path(&[(0.0, 0.0, 0.0), (3.0, 3.0, 0.5), (6.0, 0.0, 0.0)]);
+        curve.fill_rule = FillRule::EvenOdd;
+        curve.stroke = Some(Stroke {
+            colour: (0.0, 0.0, 1.0),
+            width: 0.5,
+            join: LineJoin::Round,
+            cap: LineCap::Square,
+            dashes: vec![1.0, 2.0],
+            closed: true
+        });
+
+        let model = Model { paths: vec![curve], .. Default::default() };
+        let data = pdf(&model);
+        assert!(data.contains("0 0 1 RG 0.5 w 1 j 2 J [1 2] 0 d\n0 0 m\n2 2 4 2 6 0 c\nh\nB*\n"));
+
+        // An open stroke is drawn without the closing curve, which the fill
+        // still needs
+        let mut curve = model.paths.into_iter().next().unwrap();
+        curve.points.push(Point { location: (3.0, -3.0), curve_bias: 0.5, weights: NO_WEIGHTS });
+        curve.stroke.as_mut().unwrap().closed = false;
+        let data = pdf(&Model { paths: vec![curve], .. Default::default() });
+        assert!(data.contains("0 0 m\n2 2 4 2 6 0 c\n4 -2 2 -2 0 0 c\nh\nf*\n0 0 m\n2 2 4 2 6 0 c\nS\n"));
+    }
+
+    #[test]
+    fn clips() {
+        let mut mask = path(&[(0.0, 0.0, 0.0), (5.0, 0.0, 0.0), (5.0, 5.0, 0.0)]);
+        mask.fill_rule = FillRule::EvenOdd;
+        let data = pdf(&Model {
+            paths: vec![path(&[(0.0, 0.0, 0.0), (10.0, 0.0, 0.0), (10.0, 10.0, 0.0)]), mask],
+            clips: vec![Clip { path: 1, paths: vec![0] }],
+            .. Default::default()
+        });
+
+        // The clip path is only used to clip
+        assert!(data.contains("q\n0 0 m\n5 0 l\n5 5 l\nh\nW* n\n1 1 1 rg\n0 0 m\n10 0 l\n10 10 l\nh\nf\nQ\n"));
+        assert_eq!(data.matches(" rg\n").count(), 1);
+    }
+
+    #[test]
+    fn alpha() {
+        let mut paths = Vec::new();
+        for &a in &[0.5, 1.0, 0.25, 0.5] {
+            let mut p = path(&[(0.0, 0.0, 0.0), (10.0, 0.0, 0.0), (10.0, 10.0, 0.0)]);
+            p.paint = Paint::Solid((1.0, 0.0, 0.0, a));
+            paths.push(p);
+        }
+        let data = pdf(&Model { paths: paths, .. Default::default() });
+
+        // Each alpha has one graphics state, only set for the paths using it
+        assert!(data.contains("/Resources << /ExtGState << /GS0 << /Type /ExtGState /ca 0.5 /CA 1 >> \
+                               /GS1 << /Type /ExtGState /ca 0.25 /CA 1 >> >> >>"));
+        assert_eq!(data.matches("q\n/GS0 gs\n1 0 0 rg\n").count(), 2);
+        assert_eq!(data.matches("q\n/GS1 gs\n1 0 0 rg\n").count(), 1);
+        assert_eq!(data.matches("h\nf\nQ\n").count(), 3);
+        assert_eq!(data.matches(" rg\n").count(), 4);
+
+        // Opaque models need no resources
+        assert!(pdf(&Model::default()).contains("/Resources << >>"));
+    }
+}
@@ -0,0 +1,278 @@
+use data::{Vec2, Vec3, Vec4, Box2};
+use image::Image;
+use super::Model;
+use super::tessellate::{Triangle, model_triangles};
+
+// Samples per pixel along each axis
+const SAMPLES: usize = 4;
+
+// Draws a model into an image as ModelRenderer::draw does, with each pixel's
+// coverage averaged over a grid of samples for anti-aliasing.
+pub fn draw_model(image: &mut Image, x: f64, y: f64, scale: f64, model: &Model) {
+    let area = ((0.0, 0.0), (image.width as f64, image.height as f64));
+    draw_model_clipped(image, x, y, scale, model, area);
+}
+
+// As draw_model, but only drawing inside an area of the image given in
+// pixels, as a scissor rectangle does on the GPU
+pub fn draw_model_clipped(image: &mut Image, x: f64, y: f64, scale: f64, model: &Model, area: Box2) {
+    let mut layer = Layer::new(image.width, image.height, area);
+    let mut mask = Layer::new(image.width, image.height, area);
+
+    // Instances' paths are numbered as in the resolved model
+    let resolved = model.resolve();
+    let paths = resolved.as_ref().map_or(&model.paths, |resolved| &resolved.paths);
+
+    for path in model_triangles(model) {
+        let source = &paths[path.path];
+
+        layer.fill(&path.triangles, (x, y), scale);
+        layer.clip(&mut mask, &path.clips, (x, y), scale);
+        layer.draw(image, |(px, py)| source.paint.colour_at(((px - x) / scale, (py - y) / scale)));
+
+        if let Some(ref stroke) = source.stroke {
+            let (r, g, b) = stroke.colour;
+            layer.fill(&path.stroke, (x, y), scale);
+            layer.clip(&mut mask, &path.clips, (x, y), scale);
+            layer.draw(image, |_| (r, g, b, 1.0));
+        }
+    }
+}
+
+// A mask of the samples covered by a set of triangles. Triangles sharing an
+// edge cover each sample only once, so there are no seams between them.
+struct Layer {
+    width: usize,
+    height: usize,
+    mask: Vec<u16>,
+    // The pixels that can be covered, and the bounds of those that are
+    area: ((usize, usize), (usize, usize)),
+    min: (usize, usize),
+    max: (usize, usize)
+}
+
+impl Layer {
+    fn new(width: usize, height: usize, area: Box2) -> Layer {
+        let clamp = |v: f64, limit: usize| v.floor().max(0.0).min(limit as f64) as usize;
+
+        Layer {
+            width: width,
+            height: height,
+            mask: vec![0; width * height],
+            area: ((clamp((area.0).0, width), clamp((area.0).1, height)),
+                   (clamp((area.1).0, width), clamp((area.1).1, height))),
+            min: (width, height),
+            max: (0, 0)
+        }
+    }
+
+    fn fill(&mut self, triangles: &[Triangle], translate: Vec2, scale: f64) {
+        for triangle in triangles {
+            let p = [
+                (translate.0 + scale * triangle[0].position.0, translate.1 + scale * triangle[0].position.1),
+                (translate.0 + scale * triangle[1].position.0, translate.1 + scale * triangle[1].position.1),
+                (translate.0 + scale * triangle[2].position.0, translate.1 + scale * triangle[2].position.1)
+            ];
+
+            let area = edge(p[0], p[1], p[2]);
+            if area == 0.0 || !area.is_finite() {
+                continue;
+            }
+
+            let (lo, hi) = self.area;
+            let clamp = |v: f64, lo: usize, hi: usize| v.max(lo as f64).min(hi as f64) as usize;
+            let x0 = clamp(p[0].0.min(p[1].0).min(p[2].0).floor(), lo.0, hi.0);
+            let x1 = clamp(p[0].0.max(p[1].0).max(p[2].0).ceil(), lo.0, hi.0);
+            let y0 = clamp(p[0].1.min(p[1].1).min(p[2].1).floor(), lo.1, hi.1);
+            let y1 = clamp(p[0].1.max(p[1].1).max(p[2].1).ceil(), lo.1, hi.1);
+
+            for py in y0..y1 {
+                for px in x0..x1 {
+                    let mut bits = 0;
+
+                    for i in 0..SAMPLES * SAMPLES {
+                        let s = (px as f64 + ((i % SAMPLES) as f64 + 0.5) / SAMPLES as f64,
+                                 py as f64 + ((i / SAMPLES) as f64 + 0.5) / SAMPLES as f64);
+
+                        let l0 = edge(p[1], p[2], s) / area;
+                        let l1 = edge(p[2], p[0], s) / area;
+                        let l2 = edge(p[0], p[1], s) / area;
+                        if l0 < 0.0 || l1 < 0.0 || l2 < 0.0 {
+                            continue;
+                        }
+
+                        // The same test as model.frag
+                        let param = |f: fn(&Vec3) -> f64| {
+                            l0 * f(&triangle[0].param) + l1 * f(&triangle[1].param) + l2 * f(&triangle[2].param)
+                        };
+                        let u = param(|p| p.0);
+                        let v = param(|p| p.1);
+                        if triangle[0].param.2 * (u * u - v) >= 0.0 {
+                            bits |= 1 << i;
+                        }
+                    }
+
+                    if bits != 0 {
+                        self.mask[py * self.width + px] |= bits;
+                        self.min = (self.min.0.min(px), self.min.1.min(py));
+                        self.max = (self.max.0.max(px + 1), self.max.1.max(py + 1));
+                    }
+                }
+            }
+        }
+    }
+
+    // Keeps only the samples that are also inside every clip, using the
+    // other layer to draw each one
+    fn clip(&mut self, mask: &mut Layer, clips: &[Vec<Triangle>], translate: Vec2, scale: f64) {
+        for clip in clips {
+            mask.fill(clip, translate, scale);
+
+            for py in self.min.1..self.max.1 {
+                for px in self.min.0..self.max.0 {
+                    self.mask[py * self.width + px] &= mask.mask[py * self.width + px];
+                }
+            }
+
+            mask.clear();
+        }
+    }
+
+    fn clear(&mut self) {
+        for py in self.min.1..self.max.1 {
+            for px in self.min.0..self.max.0 {
+                self.mask[py * self.width + px] = 0;
+            }
+        }
+
+        self.min = (self.width, self.height);
+        self.max = (0, 0);
+    }
+
+    // Blends the colour at each pixel's centre into the image by coverage
+    // and clears the mask
+    fn draw<F: Fn(Vec2) -> Vec4>(&mut self, image: &mut Image, colour: F) {
+        for py in self.min.1..self.max.1 {
+            for px in self.min.0..self.max.0 {
+                let bits = self.mask[py * self.width + px];
+                if bits != 0 {
+                    let coverage = bits.count_ones() as f64 / (SAMPLES * SAMPLES) as f64;
+                    let (r, g, b, a) = colour((px as f64 + 0.5, py as f64 + 0.5));
+                    image.blend(px, py, (r, g, b), coverage * a);
+                }
+            }
+        }
+
+        self.clear();
+    }
+}
+
+fn edge(a: Vec2, b: Vec2, p: Vec2) -> f64 {
+    (b.0 - a.0) * (p.1 - a.1) - (b.1 - a.1) * (p.0 - a.0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Cursor;
+    use data::BinaryReader;
+    use image::{Image, check_golden};
+    use model::{Model, Paint, Stop, Clip, path};
+
+    fn square(curve_bias: f64) -> Model {
+        let b = curve_bias;
+        let mut square = path(&[(-1.0, -1.0, b), (1.0, -1.0, b), (1.0, 1.0, b), (-1.0, 1.0, b)]);
+        square.paint = Paint::solid((1.0, 0.0, 0.0));
+        Model { paths: vec![square], .. Default::default() }
+    }
+
+    #[test]
+    fn coverage() {
+        // A square from 2.5 to 7.5, so the edge pixels are half covered
+        let mut image = Image::new(10, 10, (0.0, 0.0, 0.0, 1.0));
+        draw_model(&mut image, 5.0, 5.0, 2.5, &square(0.0));
+
+        assert_eq!(image.pixel(5, 5), (1.0, 0.0, 0.0, 1.0));
+        assert_eq!(image.pixel(2, 5), (0.5, 0.0, 0.0, 1.0));
+        assert_eq!(image.pixel(7, 7), (0.25, 0.0, 0.0, 1.0));
+        assert_eq!(image.pixel(1, 5), (0.0, 0.0, 0.0, 1.0));
+
+        // Inner triangles share edges without seams
+        let total = image.pixels.iter().map(|p| p.0).sum::<f64>();
+        assert_eq!(total, 25.0);
+    }
+
+    #[test]
+    fn paints() {
+        let mut model = square(0.0);
+        model.paths[0].paint = Paint::Solid((1.0, 0.0, 0.0, 0.5));
+        let mut image = Image::new(10, 10, (0.0, 0.0, 0.0, 1.0));
+        draw_model(&mut image, 5.0, 5.0, 2.5, &model);
+        assert_eq!(image.pixel(5, 5), (0.5, 0.0, 0.0, 1.0));
+        assert_eq!(image.pixel(2, 5), (0.25, 0.0, 0.0, 1.0));
+
+        // Pixel centres at x = 3.5 and 6.5 are a fifth of the way from each
+        // end of the gradient
+        model.paths[0].paint = Paint::LinearGradient {
+            start: (-1.0, 0.0),
+            end: (1.0, 0.0),
+            stops: vec![
+                Stop { offset: 0.0, colour: (1.0, 0.0, 0.0, 1.0) },
+                Stop { offset: 1.0, colour: (0.0, 0.0, 1.0, 1.0) }
+            ]
+        };
+        let mut image = Image::new(10, 10, (0.0, 0.0, 0.0, 1.0));
+        draw_model(&mut image, 5.0, 5.0, 2.5, &model);
+        let near = |(r, g, b, a): (f64, f64, f64, f64), (s, h, c): Vec3| {
+            (r - s).abs() < 1e-9 && (g - h).abs() < 1e-9 && (b - c).abs() < 1e-9 && a == 1.0
+        };
+        assert!(near(image.pixel(3, 5), (0.8, 0.0, 0.2)));
+        assert!(near(image.pixel(6, 4), (0.2, 0.0, 0.8)));
+    }
+
+    #[test]
+    fn clips() {
+        // The square is clipped to its right half, then to the area's top
+        let mut model = square(0.0);
+        let mut clip = model.paths[0].clone();
+        for point in &mut clip.points {
+            point.location.0 = point.location.0.max(0.0);
+        }
+        model.paths.push(clip);
+        model.clips.push(Clip { path: 1, paths: vec![0] });
+
+        let mut image = Image::new(10, 10, (0.0, 0.0, 0.0, 1.0));
+        draw_model_clipped(&mut image, 5.0, 5.0, 2.5, &model, ((0.0, 4.0), (10.0, 10.0)));
+        assert_eq!(image.pixel(6, 5), (1.0, 0.0, 0.0, 1.0));
+        assert_eq!(image.pixel(4, 5), (0.0, 0.0, 0.0, 1.0));
+        assert_eq!(image.pixel(6, 3), (0.0, 0.0, 0.0, 1.0));
+
+        let total = image.pixels.iter().map(|p| p.0).sum::<f64>();
+        assert_eq!(total, 2.5 * 3.5);
+    }
+
+    #[test]
+    fn curves() {
+        // With all points as controls the square becomes a circle through the
+        // edge midpoints
+        let mut image = Image::new(100, 100, (0.0, 0.0, 0.0, 1.0));
+        draw_model(&mut image, 50.0, 50.0, 40.0, &square(0.5));
+
+        let total = image.pixels.iter().map(|p| p.0).sum::<f64>();
+        // The diamond between the midpoints plus four parabolic segments
+        let expected = 40.0 * 40.0 * (2.0 + 4.0 / 3.0);
+        assert!((total - expected).abs() < 20.0);
+        assert_eq!(image.pixel(50, 50).0, 1.0);
+        assert_eq!(image.pixel(88, 88).0, 0.0);
+    }
+
+    #[test]
+    fn golden() {
+        let bytes = include_bytes!("../bin/cat.model");
+        let model = Model::read(&mut BinaryReader::new(Cursor::new(&bytes[..]))).unwrap();
+
+        let mut image = Image::new(200, 180, (0.02, 0.02, 0.02, 1.0));
+        draw_model(&mut image, 126.375, 108.75, 0.5, &model);
+        check_golden(&image, "cat");
+    }
+}
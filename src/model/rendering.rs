@@ -1,9 +1,14 @@
-use glium::{VertexBuffer, Program, Surface, Blend};
+use glium::{VertexBuffer, Program, Surface, Blend, Rect};
 use glium::backend::Facade;
 use glium::index::{NoIndices, PrimitiveType};
-use glium::draw_parameters::DrawParameters;
-use data::Vec2;
-use super::{Model, Point};
+use glium::draw_parameters::{DrawParameters, Stencil, StencilTest, StencilOperation};
+use glium::texture::{Texture2d, RawImage2d};
+use glium::uniforms::{Sampler, SamplerWrapFunction};
+use super::{Model, Paint};
+use super::tessellate::{Triangle, model_triangles};
+
+// Texels in the colour ramp that gradients are looked up in
+const RAMP_SIZE: usize = 256;
 
 #[derive(Copy, Clone)]
 struct Vertex {
@@ -15,7 +20,14 @@ implement_vertex!(Vertex, position, param);
 
 struct PathBuffer {
     vertices: VertexBuffer<Vertex>,
-    colour: [f32; 3]
+    // The colours along the gradient, or the one colour of a solid paint
+    ramp: Texture2d,
+    // 0 for solid, 1 for linear with start and end points, and 2 for radial
+    // with the centre and radius
+    paint_kind: i32,
+    gradient: [f32; 4],
+    // The areas the path is drawn inside, marked in the stencil buffer first
+    clips: Vec<VertexBuffer<Vertex>>
 }
 
 pub struct ModelBuffers {
@@ -55,205 +67,135 @@ impl<'a> ModelRenderer<'a> {
     }
 
     pub fn draw<S: Surface>(&self, surface: &mut S, x: f32, y: f32, scale: f32, model: &ModelBuffers) {
+        self.draw_clipped(surface, x, y, scale, model, None);
+    }
+
+    // Draws only inside the scissor rectangle, in pixels, if there is one
+    pub fn draw_clipped<S: Surface>(&self, surface: &mut S, x: f32, y: f32, scale: f32, model: &ModelBuffers,
+                                    scissor: Option<Rect>) {
         for path in &model.paths {
             let uniforms = uniform! {
                 viewport_size: [self.width, self.height],
                 translate: [x, y],
                 scale: scale,
-                colour: path.colour
+                ramp: Sampler::new(&path.ramp).wrap_function(SamplerWrapFunction::Clamp),
+                paint_kind: path.paint_kind,
+                gradient: path.gradient
             };
 
+            let mut params = DrawParameters {
+                scissor: scissor,
+                .. self.params.clone()
+            };
+
+            // Each clip counts up where the ones before it were drawn, so
+            // the path is drawn where all of them were
+            if path.clips.len() > 0 {
+                surface.clear(scissor.as_ref(), None, false, None, Some(0));
+
+                for (i, clip) in path.clips.iter().enumerate() {
+                    let clip_params = DrawParameters {
+                        color_mask: (false, false, false, false),
+                        stencil: stencil(i as i32, StencilOperation::Increment),
+                        .. params.clone()
+                    };
+
+                    surface.draw(clip, self.indices, &self.program, &uniforms, &clip_params).unwrap();
+                }
+
+                params.stencil = stencil(path.clips.len() as i32, StencilOperation::Keep);
+            }
+
             surface.draw(
                 &path.vertices,
                 self.indices,
                 &self.program,
                 &uniforms,
-                &self.params)
+                &params)
                 .unwrap();
         }
     }
 }
 
-pub fn prepare_model<F: Facade>(display: &F, model: &Model) -> ModelBuffers {
-    let paths = model
-        .paths
-        .iter()
-        .map(|path| {
-            let vertices = build_path_vertices(&path.points);
-
-            PathBuffer {
-                vertices: VertexBuffer::new(display, &vertices).unwrap(),
-                colour: [path.colour.0 as f32, path.colour.1 as f32, path.colour.2 as f32]
-            }
-        })
-        .collect();
-
-    ModelBuffers {
-        paths: paths
+// Passes where the stencil buffer holds the reference value, on both faces
+// since triangles can wind either way
+fn stencil(reference: i32, pass: StencilOperation) -> Stencil {
+    Stencil {
+        test_clockwise: StencilTest::IfEqual { mask: 0xff },
+        reference_value_clockwise: reference,
+        depth_pass_operation_clockwise: pass,
+        test_counter_clockwise: StencilTest::IfEqual { mask: 0xff },
+        reference_value_counter_clockwise: reference,
+        depth_pass_operation_counter_clockwise: pass,
+        .. Default::default()
     }
 }
 
-struct VertexNode {
-    next: usize,
-    point: Point,
-}
-
-fn build_path_vertices(points: &Vec<Point>) -> Vec<Vertex> {
-    let mut nodes = build_vertex_nodes(points);
-    let first = if nodes[0].point.curve_bias > 0.0 { 1 } else { 0 };
-
-    let mut output = Vec::new();
-
-    build_curve_triangles(&mut nodes, first, &mut output);
-    build_inner_triangles(&mut nodes, first, &mut output);
-
-    output
-}
-
-fn build_vertex_nodes(points: &Vec<Point>) -> Vec<VertexNode> {
-    let mut nodes = Vec::with_capacity(points.len() * 2);
-    nodes.push(VertexNode {
-        next: 0,
-        point: points[points.len() - 1]
-    });
-    let mut last = 0;
-
-    for (i, point) in points.iter().enumerate() {
-        if point.curve_bias > 0.0 && nodes[last].point.curve_bias > 0.0 {
-            let a = nodes[last].point.location;
-            let b = point.location;
-            let t = nodes[last].point.curve_bias;
-            let x = a.0 + t * (b.0 - a.0);
-            let y = a.1 + t * (b.1 - a.1);
-
-            nodes.push(VertexNode {
-                next: 0,
-                point: Point {
-                    location: (x, y),
-                    curve_bias: 0.0
-                }
-            });
-            nodes[last].next = last + 1;
-            last += 1;
-        }
-
-        if i != points.len() - 1 {
-            nodes.push(VertexNode {
-                next: 0,
-                point: *point
-            });
-            nodes[last].next = last + 1;
-            last += 1;
-        }
-    }
+fn vertex_buffer<F: Facade>(display: &F, triangles: &[Triangle]) -> VertexBuffer<Vertex> {
+    let vertices = triangles
+        .iter()
+        .flat_map(|triangle| triangle.iter())
+        .map(|v| Vertex {
+            position: [v.position.0 as f32, v.position.1 as f32],
+            param: [v.param.0 as f32, v.param.1 as f32, v.param.2 as f32]
+        })
+        .collect::<Vec<_>>();
 
-    nodes
+    VertexBuffer::new(display, &vertices).unwrap()
 }
 
-fn build_curve_triangles(nodes: &mut Vec<VertexNode>, first: usize, output: &mut Vec<Vertex>) {
-    let mut is_first = true;
-    let mut i = first;
-    while is_first || i != first {
-        if nodes[nodes[i].next].point.curve_bias > 0.0 {
-            let p1 = nodes[i].point.location;
-            let p2 = nodes[nodes[i].next].point.location;
-            let p3 = nodes[nodes[nodes[i].next].next].point.location;
-
-            let cross = vec2_cross(p1, p2, p3);
-            let sign = if cross == 0.0 {
-                nodes[i].next = nodes[nodes[i].next].next;
-                continue;
-            } else if cross > 0.0 {
-                nodes[i].next = nodes[nodes[i].next].next;
-                -1
-            } else {
-                1
-            };
-
-            output.push(Vertex {
-                position: [p1.0 as f32, p1.1 as f32],
-                param: [0.0, 0.0, sign as f32],
-            });
-            output.push(Vertex {
-                position: [p2.0 as f32, p2.1 as f32],
-                param: [0.5, 0.0, sign as f32],
-            });
-            output.push(Vertex {
-                position: [p3.0 as f32, p3.1 as f32],
-                param: [1.0, 1.0, sign as f32],
-            });
+fn path_buffer<F: Facade>(display: &F, triangles: &[Triangle], paint: &Paint, clips: &[Vec<Triangle>]) -> PathBuffer {
+    let texel = |offset: f64| {
+        let (r, g, b, a) = paint.colour_at_offset(offset);
+        let channel = |c: f64| (c.max(0.0).min(1.0) * 255.0).round() as u8;
+        (channel(r), channel(g), channel(b), channel(a))
+    };
+
+    let (ramp, paint_kind, gradient) = match *paint {
+        Paint::Solid(_) => (vec![texel(0.0)], 0, [0.0; 4]),
+        Paint::LinearGradient { start, end, .. } => {
+            let ramp = (0..RAMP_SIZE).map(|i| texel(i as f64 / (RAMP_SIZE - 1) as f64)).collect();
+            (ramp, 1, [start.0 as f32, start.1 as f32, end.0 as f32, end.1 as f32])
+        },
+        Paint::RadialGradient { centre, radius, .. } => {
+            let ramp = (0..RAMP_SIZE).map(|i| texel(i as f64 / (RAMP_SIZE - 1) as f64)).collect();
+            (ramp, 2, [centre.0 as f32, centre.1 as f32, radius as f32, 0.0])
         }
-
-        i = nodes[i].next;
-        is_first = false;
+    };
+
+    PathBuffer {
+        vertices: vertex_buffer(display, triangles),
+        ramp: Texture2d::new(display, RawImage2d::from_raw_rgba(
+            ramp.iter().flat_map(|&(r, g, b, a)| vec![r, g, b, a]).collect::<Vec<u8>>(),
+            (ramp.len() as u32, 1))).unwrap(),
+        paint_kind: paint_kind,
+        gradient: gradient,
+        clips: clips.iter().map(|clip| vertex_buffer(display, clip)).collect()
     }
 }
 
-fn build_inner_triangles(nodes: &mut Vec<VertexNode>, first: usize, output: &mut Vec<Vertex>) {
-    let mut v1 = first;
-    let mut last_success = v1;
-
-    loop {
-        let v3 = nodes[nodes[v1].next].next;
-
-        if v1 == v3 {
-            return;
-        }
-
-        let p1 = nodes[v1].point.location;
-        let p2 = nodes[nodes[v1].next].point.location;
-        let p3 = nodes[v3].point.location;
-        let mut success = false;
-
-        if vec2_cross(p1, p3, p2) < 0.0 {
-            let mut empty = true;
-
-            let mut v = nodes[v3].next;
-            while v != v1 {
-                if triangle_contains(p1, p2, p3, nodes[v].point.location) {
-                    empty = false;
-                    break;
-                }
+pub fn prepare_model<F: Facade>(display: &F, model: &Model) -> ModelBuffers {
+    let mut paths = Vec::new();
 
-                v = nodes[v].next;
-            }
+    // Instances' paths are numbered as in the resolved model
+    let resolved = model.resolve();
+    let sources = resolved.as_ref().map_or(&model.paths, |resolved| &resolved.paths);
 
-            if empty {
-                output.push(Vertex {
-                    position: [p1.0 as f32, p1.1 as f32],
-                    param: [0.0f32, 1.0f32, -1.0f32]
-                });
-                output.push(Vertex {
-                    position: [p2.0 as f32, p2.1 as f32],
-                    param: [0.0f32, 1.0f32, -1.0f32]
-                });
-                output.push(Vertex {
-                    position: [p3.0 as f32, p3.1 as f32],
-                    param: [0.0f32, 1.0f32, -1.0f32]
-                });
+    for path in model_triangles(model) {
+        let source = &sources[path.path];
 
-                nodes[v1].next = v3;
-                success = true;
-                last_success = v1;
-            }
+        if path.triangles.len() > 0 {
+            paths.push(path_buffer(display, &path.triangles, &source.paint, &path.clips));
         }
 
-        if !success {
-            v1 = nodes[v1].next;
-
-            if v1 == last_success {
-                return;
+        if let Some(ref stroke) = source.stroke {
+            if path.stroke.len() > 0 {
+                paths.push(path_buffer(display, &path.stroke, &Paint::solid(stroke.colour), &path.clips));
             }
         }
     }
-}
-
-fn vec2_cross(a: Vec2, b: Vec2, c: Vec2) -> f64 {
-    (b.0 - a.0) * (c.1 - a.1) - (b.1 - a.1) * (c.0 - a.0)
-}
 
-fn triangle_contains(t1: Vec2, t2: Vec2, t3: Vec2, p: Vec2) -> bool {
-    vec2_cross(t1, p, t2) < 0.0 &&
-        vec2_cross(t2, p, t3) < 0.0 &&
-        vec2_cross(t3, p, t1) < 0.0
+    ModelBuffers {
+        paths: paths
+    }
 }
@@ -0,0 +1,352 @@
+use std::f64::consts::PI;
+use data::Vec2;
+use super::{Path, Point, NO_WEIGHTS};
+use super::tessellate::outline_segments;
+
+// Turns sharper than this are kept as corners when smoothing
+const CORNER_ANGLE: f64 = PI * 5.0 / 12.0;
+
+impl Path {
+    // A copy of the path with each curve replaced by straight lines, split
+    // until they are within tolerance of the curve
+    pub fn flatten(&self, tolerance: f64) -> Path {
+        self.with_outlines(|points, _| flatten(points, tolerance))
+    }
+
+    // A copy of the path with straight runs of points reduced by
+    // Douglas-Peucker to within tolerance. Curves are kept as they are.
+    pub fn simplify(&self, tolerance: f64) -> Path {
+        self.with_outlines(|points, closed| {
+            let locations = points.iter().map(|p| p.location).collect::<Vec<_>>();
+            let n = points.len();
+
+            // Control points and the points either side of them shape the
+            // curves, so only the points between them can go
+            let mut fixed = (0..n)
+                .map(|i| {
+                    let control = |j: usize| points[j % n].curve_bias > 0.0;
+                    control(i) || control(i + 1) || control(i + n - 1) || (!closed && (i == 0 || i == n - 1))
+                })
+                .collect::<Vec<_>>();
+
+            points.iter()
+                .zip(reduce(&locations, &mut fixed, tolerance))
+                .filter(|&(_, keep)| keep)
+                .map(|(&p, _)| p)
+                .collect()
+        })
+    }
+
+    // Fits a path of curves to within tolerance of the points of this one,
+    // treating them as a noisy polyline such as freehand input
+    pub fn smooth(&self, tolerance: f64) -> Path {
+        self.with_outlines(|points, closed| {
+            let line = points.iter()
+                .map(|p| p.location)
+                .filter(|p| p.0.is_finite() && p.1.is_finite())
+                .collect::<Vec<_>>();
+
+            fit_curves(&line, &vec![false; line.len()], closed, tolerance)
+        })
+    }
+
+    fn with_outlines<F: Fn(&[Point], bool) -> Vec<Point>>(&self, f: F) -> Path {
+        // Outlines are closed unless they are only drawn as open strokes
+        let closed = self.filled || self.stroke.as_ref().map(|s| s.closed).unwrap_or(true);
+
+        Path {
+            name: self.name.clone(),
+            paint: self.paint.clone(),
+            points: f(&self.points, closed),
+            contours: self.contours.iter().map(|c| f(c, closed)).collect(),
+            fill_rule: self.fill_rule,
+            filled: self.filled,
+            stroke: self.stroke.clone()
+        }
+    }
+}
+
+fn flatten(points: &[Point], tolerance: f64) -> Vec<Point> {
+    flatten_outline(points, tolerance).iter()
+        .map(|&(p, _)| Point { location: p, curve_bias: 0.0, weights: NO_WEIGHTS })
+        .collect()
+}
+
+// Flattens a closed outline, marking the points that lie on or between
+// curves rather than at corners
+pub fn flatten_outline(points: &[Point], tolerance: f64) -> Vec<(Vec2, bool)> {
+    let segments = outline_segments(points);
+    let mut output = Vec::with_capacity(segments.len());
+
+    for (i, segment) in segments.iter().enumerate() {
+        let previous = &segments[(i + segments.len() - 1) % segments.len()];
+        output.push((segment.start, segment.control.is_some() && previous.control.is_some()));
+
+        if let Some(c) = segment.control {
+            let end = segments[(i + 1) % segments.len()].start;
+            subdivide(segment.start, c, end, tolerance, 0, &mut output);
+        }
+    }
+
+    output
+}
+
+// Fits curves to a polyline, keeping the marked corners as they are. Points
+// are dropped and the rest made into controls, except at sharp turns, until
+// the curves are within tolerance of the original points.
+pub fn fit_curves(line: &[Vec2], corners: &[bool], closed: bool, tolerance: f64) -> Vec<Point> {
+    if line.len() < 3 {
+        return line.iter().map(|&p| Point { location: p, curve_bias: 0.0, weights: NO_WEIGHTS }).collect();
+    }
+
+    let fixed = (0..line.len())
+        .map(|i| corners[i] || (!closed && (i == 0 || i == line.len() - 1)))
+        .collect::<Vec<_>>();
+    let mut keep = reduce(line, &mut fixed.clone(), tolerance);
+
+    loop {
+        let fitted = fit(line, &keep, &fixed);
+
+        // Add back the original point furthest from the curves
+        let flat = flatten(&fitted, tolerance / 10.0).iter().map(|p| p.location).collect::<Vec<_>>();
+        let worst = (0..line.len())
+            .filter(|&i| !keep[i])
+            .map(|i| (i, polyline_distance(line[i], &flat, closed)))
+            .fold(None, |worst: Option<(usize, f64)>, (i, d)| match worst {
+                Some((_, w)) if w >= d => worst,
+                _ => Some((i, d))
+            });
+
+        match worst {
+            Some((i, d)) if d > tolerance => keep[i] = true,
+            _ => return fitted
+        }
+    }
+}
+
+fn midpoint(a: Vec2, b: Vec2) -> Vec2 {
+    ((a.0 + b.0) / 2.0, (a.1 + b.1) / 2.0)
+}
+
+// A quadratic is within |p0 - 2c + p2| / 4 of its chord, and each half of it
+// within a quarter of that of theirs
+fn subdivide(p0: Vec2, c: Vec2, p2: Vec2, tolerance: f64, depth: usize, output: &mut Vec<(Vec2, bool)>) {
+    let d = (p0.0 - 2.0 * c.0 + p2.0, p0.1 - 2.0 * c.1 + p2.1);
+    if (d.0 * d.0 + d.1 * d.1).sqrt() / 4.0 <= tolerance || depth >= 16 {
+        return;
+    }
+
+    let (c0, c1) = (midpoint(p0, c), midpoint(c, p2));
+    let mid = midpoint(c0, c1);
+
+    subdivide(p0, c0, mid, tolerance, depth + 1, output);
+    output.push((mid, true));
+    subdivide(mid, c1, p2, tolerance, depth + 1, output);
+}
+
+fn line_distance(p: Vec2, a: Vec2, b: Vec2) -> f64 {
+    let d = (b.0 - a.0, b.1 - a.1);
+    let length = d.0 * d.0 + d.1 * d.1;
+    let t = if length > 0.0 {
+        (((p.0 - a.0) * d.0 + (p.1 - a.1) * d.1) / length).max(0.0).min(1.0)
+    } else {
+        0.0
+    };
+
+    ((a.0 + t * d.0 - p.0).powi(2) + (a.1 + t * d.1 - p.1).powi(2)).sqrt()
+}
+
+fn polyline_distance(p: Vec2, line: &[Vec2], closed: bool) -> f64 {
+    let count = if closed { line.len() } else { line.len() - 1 };
+    (0..count)
+        .map(|i| line_distance(p, line[i], line[(i + 1) % line.len()]))
+        .fold(1.0 / 0.0, f64::min)
+}
+
+// Marks the points to keep between the fixed ones of a closed line, with
+// Douglas-Peucker on each run. With nothing fixed, the first point and the
+// one furthest from it start things off.
+fn reduce(line: &[Vec2], fixed: &mut Vec<bool>, tolerance: f64) -> Vec<bool> {
+    let n = line.len();
+    if n < 3 {
+        return vec![true; n];
+    }
+
+    if !fixed.iter().any(|&f| f) {
+        let furthest = (1..n)
+            .max_by(|&i, &j| {
+                line_distance(line[i], line[0], line[0])
+                    .partial_cmp(&line_distance(line[j], line[0], line[0]))
+                    .unwrap_or(::std::cmp::Ordering::Equal)
+            })
+            .unwrap();
+        fixed[0] = true;
+        fixed[furthest] = true;
+    }
+
+    let mut keep = fixed.clone();
+    let anchors = (0..n).filter(|&i| fixed[i]).collect::<Vec<_>>();
+
+    for (k, &start) in anchors.iter().enumerate() {
+        let end = anchors[(k + 1) % anchors.len()];
+        let length = (end + n - start - 1) % n + 1;
+        let run = (0..length + 1).map(|j| (start + j) % n).collect::<Vec<_>>();
+        douglas_peucker(line, &run, tolerance, &mut keep);
+    }
+
+    keep
+}
+
+fn douglas_peucker(line: &[Vec2], run: &[usize], tolerance: f64, keep: &mut Vec<bool>) {
+    if run.len() < 3 {
+        return;
+    }
+
+    let (a, b) = (line[run[0]], line[run[run.len() - 1]]);
+    let (i, distance) = (1..run.len() - 1)
+        .map(|i| (i, line_distance(line[run[i]], a, b)))
+        .fold((0, -1.0), |best, d| if d.1 > best.1 { d } else { best });
+
+    if distance > tolerance {
+        keep[run[i]] = true;
+        douglas_peucker(line, &run[..i + 1], tolerance, keep);
+        douglas_peucker(line, &run[i..], tolerance, keep);
+    }
+}
+
+// Makes the kept points of a line into a curved outline, with the implied
+// points halfway between controls
+fn fit(line: &[Vec2], keep: &[bool], corners: &[bool]) -> Vec<Point> {
+    let indices = (0..line.len()).filter(|&i| keep[i]).collect::<Vec<_>>();
+    let n = indices.len();
+
+    indices.iter()
+        .enumerate()
+        .map(|(k, &i)| {
+            let (prev, next) = (line[indices[(k + n - 1) % n]], line[indices[(k + 1) % n]]);
+            let p = line[i];
+            let (u, v) = ((p.0 - prev.0, p.1 - prev.1), (next.0 - p.0, next.1 - p.1));
+            let turn = (u.0 * v.1 - u.1 * v.0).atan2(u.0 * v.0 + u.1 * v.1).abs();
+
+            let corner = n < 3 || corners[i] || turn > CORNER_ANGLE;
+            Point { location: p, curve_bias: if corner { 0.0 } else { 0.5 }, weights: NO_WEIGHTS }
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::f64::consts::PI;
+    use model::{Point, Stroke, LineJoin, LineCap, path};
+    use rand::{Rng, SeedableRng, XorShiftRng};
+
+    fn locations(points: &[Point]) -> Vec<Vec2> {
+        points.iter().map(|p| p.location).collect()
+    }
+
+    #[test]
+    fn flatten() {
+        // The arch y = x (2 - x)
+        let mut arch = path(&[(0.0, 0.0, 0.0), (1.0, 2.0, 0.5), (2.0, 0.0, 0.0)]);
+        arch.contours.push(arch.points.clone());
+
+        let flat = arch.flatten(0.01);
+        assert_eq!(flat.contours.len(), 1);
+        assert!(flat.points.iter().all(|p| p.curve_bias == 0.0));
+        assert!(flat.points.len() > 8 && flat.points.len() < 20);
+
+        for (i, p) in flat.points.iter().enumerate() {
+            let (x, y) = p.location;
+            assert!((y - x * (2.0 - x)).abs() < 1e-9);
+
+            // The chords stay close to the curve
+            let q = flat.points[(i + 1) % flat.points.len()].location;
+            let (mx, my) = ((x + q.0) / 2.0, (y + q.1) / 2.0);
+            if my > 0.0 {
+                assert!((my - mx * (2.0 - mx)).abs() < 0.01 * 5f64.sqrt());
+            }
+        }
+
+        let square = path(&[(0.0, 0.0, 0.0), (1.0, 0.0, 0.0), (1.0, 1.0, 0.0)]);
+        assert_eq!(locations(&square.flatten(0.01).points), locations(&square.points));
+    }
+
+    #[test]
+    fn simplify() {
+        // A wobbly line along the bottom and a curve over the top
+        let simplified = path(&[(0.0, 0.0, 0.0), (1.0, 0.05, 0.0), (2.0, -0.05, 0.0), (3.0, 1.0, 0.0),
+                                (4.0, 0.0, 0.0), (5.0, 0.0, 0.0), (5.0, 2.0, 0.0), (2.5, 5.0, 0.5),
+                                (0.0, 2.0, 0.0)]).simplify(0.1);
+        assert_eq!(locations(&simplified.points),
+                   vec![(0.0, 0.0), (2.0, -0.05), (3.0, 1.0), (4.0, 0.0), (5.0, 0.0),
+                        (5.0, 2.0), (2.5, 5.0), (0.0, 2.0)]);
+        assert_eq!(simplified.points[6].curve_bias, 0.5);
+
+        // Open strokes keep their ends
+        let mut open = path(&[(0.0, 0.0, 0.0), (1.0, 0.0, 0.0), (2.0, 0.0, 0.0), (3.0, 0.01, 0.0)]);
+        open.filled = false;
+        open.stroke = Some(Stroke {
+            colour: (0.0, 0.0, 0.0),
+            width: 1.0,
+            join: LineJoin::Miter,
+            cap: LineCap::Butt,
+            dashes: Vec::new(),
+            closed: false
+        });
+        assert_eq!(locations(&open.simplify(0.1).points), vec![(0.0, 0.0), (3.0, 0.01)]);
+
+        // Without anything fixed, from the first point and the far side
+        let circle = (0..100)
+            .map(|i| {
+                let a = i as f64 * PI / 50.0;
+                (a.cos(), a.sin(), 0.0)
+            })
+            .collect::<Vec<_>>();
+        let simplified = path(&circle).simplify(0.01);
+        assert_eq!(simplified.points[0].location, (1.0, 0.0));
+        assert!(simplified.points.len() > 20 && simplified.points.len() < 40);
+    }
+
+    #[test]
+    fn smooth() {
+        let mut rng = XorShiftRng::from_seed([1, 2, 3, 4]);
+
+        // A freehand circle becomes a few curves
+        let circle = (0..200)
+            .map(|i| {
+                let a = i as f64 * PI / 100.0;
+                let r = 10.0 + rng.gen_range(-0.05, 0.05);
+                (r * a.cos(), r * a.sin(), 0.0)
+            })
+            .collect::<Vec<_>>();
+        let smooth = path(&circle).smooth(0.2);
+
+        assert!(smooth.points.len() < 30);
+        assert!(smooth.points.iter().all(|p| p.curve_bias == 0.5));
+        assert!((smooth.area() - PI * 100.0).abs() < PI * 100.0 * 0.02);
+
+        // A freehand square keeps its corners
+        let mut square = Vec::new();
+        for side in 0..4 {
+            let (start, dir) = match side {
+                0 => ((0.0, 0.0), (1.0, 0.0)),
+                1 => ((10.0, 0.0), (0.0, 1.0)),
+                2 => ((10.0, 10.0), (-1.0, 0.0)),
+                _ => ((0.0, 10.0), (0.0, -1.0))
+            };
+            for i in 0..50 {
+                let t = i as f64 / 5.0;
+                let noise = rng.gen_range(-0.05, 0.05);
+                square.push((start.0 + t * dir.0 - noise * dir.1, start.1 + t * dir.1 + noise * dir.0, 0.0));
+            }
+        }
+        let smooth = path(&square).smooth(0.2);
+        for &corner in &[(0.0, 0.0), (10.0, 0.0), (10.0, 10.0), (0.0, 10.0)] {
+            assert!(smooth.points.iter().any(|p| {
+                p.curve_bias == 0.0 && (p.location.0 - corner.0).abs() < 0.3 && (p.location.1 - corner.1).abs() < 0.3
+            }));
+        }
+        assert!((smooth.area() - 100.0).abs() < 2.0);
+    }
+}
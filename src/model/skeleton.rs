@@ -0,0 +1,325 @@
+use std::io;
+use data::{Value, Tag, Reader, Writer};
+use super::{Model, Point, Affine2, BONES};
+
+// Bones that a model's points can be weighted to, so that moving the bones
+// deforms the model. Each bone's parent comes before it.
+#[derive(Clone, PartialEq, Debug)]
+pub struct Skeleton {
+    pub bones: Vec<Bone>
+}
+
+#[derive(Clone, PartialEq, Debug)]
+pub struct Bone {
+    pub parent: Option<usize>,
+    // Maps the bone's space to its parent's, or to the model's for a root
+    pub rest: Affine2
+}
+
+// A transform for each bone, used in place of its rest transform
+#[derive(Clone, PartialEq, Debug)]
+pub struct Pose {
+    pub bones: Vec<Affine2>
+}
+
+const PARENT: Tag = tag!(P R N T);
+const REST: Tag = tag!(R E S T);
+
+impl Skeleton {
+    pub fn rest_pose(&self) -> Pose {
+        Pose {
+            bones: self.bones.iter().map(|bone| bone.rest).collect()
+        }
+    }
+
+    // Whether every parent comes before its child, which also rules out
+    // cycles
+    pub fn is_valid(&self) -> bool {
+        self.bones.iter().enumerate().all(|(i, bone)| bone.parent.map_or(true, |p| p < i))
+    }
+
+    // Maps each bone's space to the model's, given transforms relative to
+    // the bones' parents
+    pub fn world_transforms(&self, pose: &Pose) -> Vec<Affine2> {
+        let mut world: Vec<Affine2> = Vec::with_capacity(self.bones.len());
+
+        for (bone, local) in self.bones.iter().zip(&pose.bones) {
+            let transform = match bone.parent {
+                Some(parent) => local.then(&world[parent]),
+                None => *local
+            };
+            world.push(transform);
+        }
+
+        world
+    }
+
+    pub fn write(&self, writer: &mut Writer) -> io::Result<()> {
+        try!(writer.write_start());
+        try!(writer.write_value(&Value::Tag(BONES)));
+        try!(writer.write_value(&Value::Int(self.bones.len() as i32)));
+
+        for bone in &self.bones {
+            try!(writer.write_start());
+
+            if let Some(parent) = bone.parent {
+                try!(writer.write_start());
+                try!(writer.write_value(&Value::Tag(PARENT)));
+                try!(writer.write_value(&Value::Int(parent as i32)));
+                try!(writer.write_end());
+            }
+
+            try!(writer.write_start());
+            try!(writer.write_value(&Value::Tag(REST)));
+            try!(writer.write_value(&Value::DoubleArray(bone.rest.matrix.to_vec().into_boxed_slice())));
+            try!(writer.write_end());
+
+            try!(writer.write_end());
+        }
+
+        writer.write_end()
+    }
+
+    // Reads the rest of a skeleton group once its tag has been read
+    pub fn read_tagged(reader: &mut Reader) -> io::Result<Skeleton> {
+        let count = try!(reader.expect_int());
+        let mut bones = Vec::new();
+
+        for _ in 0..count {
+            bones.push(try!(read_bone(reader)));
+        }
+
+        try!(reader.skip_to_end());
+
+        let skeleton = Skeleton {
+            bones: bones
+        };
+
+        if skeleton.is_valid() {
+            Ok(skeleton)
+        } else {
+            Err(io::Error::new(io::ErrorKind::InvalidData, "Bone before its parent"))
+        }
+    }
+}
+
+fn read_bone(reader: &mut Reader) -> io::Result<Bone> {
+    let mut bone = Bone {
+        parent: None,
+        rest: Affine2::identity()
+    };
+
+    try!(reader.expect_start());
+
+    while try!(reader.expect_start_or_end()) {
+        match try!(reader.expect_tag()) {
+            PARENT => {
+                let parent = try!(reader.expect_int());
+                if parent < 0 {
+                    return Err(io::Error::new(io::ErrorKind::InvalidData, "Unexpected data"));
+                }
+                bone.parent = Some(parent as usize);
+                try!(reader.skip_to_end());
+            },
+            REST => {
+                let matrix = try!(reader.expect_double_array());
+                if matrix.len() != 6 {
+                    return Err(io::Error::new(io::ErrorKind::InvalidData, "Unexpected data"));
+                }
+                bone.rest.matrix.copy_from_slice(&matrix);
+                try!(reader.skip_to_end());
+            },
+            _ => try!(reader.skip_to_end())
+        }
+    }
+
+    Ok(bone)
+}
+
+impl Model {
+    // Whether every weighted point refers to a bone of the skeleton
+    pub fn has_valid_weights(&self) -> bool {
+        let count = self.skeleton.as_ref().map_or(0, |s| s.bones.len());
+
+        self.paths.iter().all(|path| {
+            Some(&path.points).into_iter().chain(&path.contours).all(|points| {
+                points.iter().all(|p| p.weights.iter().all(|&(bone, weight)| weight == 0.0 || bone < count))
+            })
+        })
+    }
+
+    // The model with each point moved by the blend of its bones' movements
+    // from their rest transforms to the pose. Points without weights stay
+    // where they are. Gives None without a skeleton, a transform for each
+    // bone or a bone for each weight.
+    pub fn pose(&self, pose: &Pose) -> Option<Model> {
+        let skeleton = match self.skeleton {
+            Some(ref skeleton) if skeleton.bones.len() == pose.bones.len() => skeleton,
+            _ => return None
+        };
+        if !self.has_valid_weights() {
+            return None;
+        }
+
+        let rest = skeleton.world_transforms(&skeleton.rest_pose());
+        let posed = skeleton.world_transforms(pose);
+
+        let mut skins = Vec::with_capacity(rest.len());
+        for (rest, posed) in rest.iter().zip(&posed) {
+            skins.push(match rest.inverse() {
+                Some(inverse) => inverse.then(posed),
+                None => return None
+            });
+        }
+
+        let skin_points = |points: &[Point]| -> Vec<Point> {
+            points.iter()
+                .map(|point| {
+                    let (mut x, mut y, mut total) = (0.0, 0.0, 0.0);
+                    for &(bone, weight) in point.weights.iter().filter(|&&(_, w)| w != 0.0) {
+                        let (px, py) = skins[bone].apply(point.location);
+                        x += px * weight;
+                        y += py * weight;
+                        total += weight;
+                    }
+
+                    Point {
+                        location: if total == 0.0 { point.location } else { (x / total, y / total) },
+                        ..*point
+                    }
+                })
+                .collect()
+        };
+
+        let mut posed = self.clone();
+        for path in &mut posed.paths {
+            path.points = skin_points(&path.points);
+            path.contours = path.contours.iter().map(|c| skin_points(c)).collect();
+        }
+
+        Some(posed)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::f64::consts::PI;
+    use std::io::Cursor;
+    use data::{BinaryReader, BinaryWriter, TextReader, TextWriter};
+    use model::{Model, Path, Point, FillRule, Paint, NO_WEIGHTS};
+
+    // A horizontal strip from 0 to 2 with an upper arm bone from 0 to 1 and
+    // a forearm from 1 to 2, and a point at the elbow shared between them
+    fn arm() -> Model {
+        let point = |x, y, weights| Point { location: (x, y), curve_bias: 0.0, weights: weights };
+        let upper = [(0, 1.0), (0, 0.0), (0, 0.0), (0, 0.0)];
+        let fore = [(1, 1.0), (0, 0.0), (0, 0.0), (0, 0.0)];
+        let elbow = [(0, 0.5), (1, 0.5), (0, 0.0), (0, 0.0)];
+
+        Model {
+            paths: vec![
+                Path {
+                    name: None,
+                    paint: Paint::solid((1.0, 1.0, 1.0)),
+                    points: vec![point(0.0, 0.0, upper), point(1.0, 0.0, elbow), point(2.0, 0.0, fore),
+                                 point(2.0, 0.1, fore), point(0.0, 0.1, NO_WEIGHTS)],
+                    contours: Vec::new(),
+                    fill_rule: FillRule::NonZero,
+                    filled: true,
+                    stroke: None
+                }
+            ],
+            skeleton: Some(Skeleton {
+                bones: vec![
+                    Bone { parent: None, rest: Affine2::identity() },
+                    Bone { parent: Some(0), rest: Affine2::translate(1.0, 0.0) }
+                ]
+            }),
+            .. Default::default()
+        }
+    }
+
+    fn near((ax, ay): (f64, f64), (bx, by): (f64, f64)) -> bool {
+        (ax - bx).abs() < 1e-9 && (ay - by).abs() < 1e-9
+    }
+
+    #[test]
+    fn pose() {
+        let model = arm();
+        let skeleton = model.skeleton.as_ref().unwrap();
+
+        let rest = model.pose(&skeleton.rest_pose()).unwrap();
+        for (p, q) in rest.paths[0].points.iter().zip(&model.paths[0].points) {
+            assert!(near(p.location, q.location));
+        }
+
+        // Bending the elbow moves the hand and half moves the elbow point
+        let mut bent = skeleton.rest_pose();
+        bent.bones[1] = Affine2::rotate(PI / 2.0).then(&bent.bones[1]);
+        let points = model.pose(&bent).unwrap().paths[0].points.clone();
+        assert!(near(points[0].location, (0.0, 0.0)));
+        assert!(near(points[1].location, (1.0, 0.0)));
+        assert!(near(points[2].location, (1.0, 1.0)));
+        assert!(near(points[3].location, (0.9, 1.0)));
+        assert!(near(points[4].location, (0.0, 0.1)));
+
+        // Moving the shoulder carries the forearm with it
+        let mut moved = skeleton.rest_pose();
+        moved.bones[0] = Affine2::translate(0.0, 5.0);
+        let points = model.pose(&moved).unwrap().paths[0].points.clone();
+        assert!(near(points[1].location, (1.0, 5.0)));
+        assert!(near(points[2].location, (2.0, 5.0)));
+
+        assert!(model.pose(&Pose { bones: Vec::new() }).is_none());
+
+        let mut broken = arm();
+        broken.paths[0].points[0].weights[1] = (2, 1.0);
+        assert!(broken.pose(&skeleton.rest_pose()).is_none());
+    }
+
+    #[test]
+    fn validity() {
+        let mut model = arm();
+        assert!(model.skeleton.as_ref().unwrap().is_valid());
+        assert!(model.has_valid_weights());
+
+        model.paths[0].points[0].weights[1] = (2, 1.0);
+        assert!(!model.has_valid_weights());
+
+        model.skeleton.as_mut().unwrap().bones[0].parent = Some(1);
+        assert!(!model.skeleton.as_ref().unwrap().is_valid());
+    }
+
+    #[test]
+    fn write_and_read() {
+        let model = arm();
+        let mut writer = BinaryWriter::new(Vec::new());
+        model.write(&mut writer).unwrap();
+        let data = writer.into_inner();
+
+        let read = Model::read(&mut BinaryReader::new(Cursor::new(&data[..]))).unwrap();
+        assert_eq!(read.skeleton, model.skeleton);
+        for (p, q) in read.paths[0].points.iter().zip(&model.paths[0].points) {
+            assert_eq!(p.weights, q.weights);
+        }
+
+        // Values and groups after the biases are skipped around the weights
+        let mut output = Vec::new();
+        model.write(&mut TextWriter::new(&mut output)).unwrap();
+        let text = String::from_utf8(output).unwrap().replace("(WGHT", "12 (XTRA 1) (WGHT");
+        assert!(text.contains("XTRA"));
+        let read = Model::read(&mut TextReader::new(Cursor::new(text.as_bytes()))).unwrap();
+        for (p, q) in read.paths[0].points.iter().zip(&model.paths[0].points) {
+            assert_eq!(p.weights, q.weights);
+        }
+
+        // A bone whose parent comes after it is rejected
+        let mut model = arm();
+        model.skeleton.as_mut().unwrap().bones[0].parent = Some(1);
+        let mut output = Vec::new();
+        model.write(&mut TextWriter::new(&mut output)).unwrap();
+        let mut reader = TextReader::new(Cursor::new(&output[..]));
+        assert_eq!(Model::read(&mut reader).err().unwrap().kind(), io::ErrorKind::InvalidData);
+    }
+}
@@ -0,0 +1,368 @@
+use rand::{Rng, SeedableRng, XorShiftRng};
+use data::Vec2;
+use super::{Model, Point};
+
+// A model whose points are pulled back to their rest locations by springs,
+// e.g. to make it wobble when pushed. Outline points are numbered as in
+// hit testing: 0 for a path's points and i + 1 for contours[i].
+//
+// Rates are per second and the simulation advances in steps of timestep,
+// so the motion doesn't depend on how often advance is called.
+pub struct SoftBody {
+    // Acceleration towards the rest location per unit of offset
+    pub spring: f64,
+    // Rate at which velocity decays
+    pub damping: f64,
+    // Acceleration per unit of stretch between neighbouring points, or 0
+    // to let points move independently
+    pub edge_stiffness: f64,
+    // Acceleration pulling each outline back to its rest area, per unit of
+    // relative change in area, or 0 to let it grow and shrink
+    pub area_stiffness: f64,
+    pub timestep: f64,
+    rest: Model,
+    outlines: Vec<Vec<Outline>>,
+    fields: Vec<Box<Fn(Vec2) -> Vec2>>,
+    pending: f64,
+    rng: XorShiftRng
+}
+
+struct Outline {
+    points: Vec<Body>,
+    edge_lengths: Vec<f64>,
+    area: f64
+}
+
+#[derive(Copy, Clone)]
+struct Body {
+    rest: Vec2,
+    offset: Vec2,
+    velocity: Vec2
+}
+
+// The most time simulated by one call to advance, so that a long pause
+// doesn't turn into a burst of steps
+const MAX_ADVANCE: f64 = 0.25;
+
+fn gauss(c: f64, x: f64) -> f64 {
+    (-x * x / (2.0 * c * c)).exp()
+}
+
+fn location(body: &Body) -> Vec2 {
+    (body.rest.0 + body.offset.0, body.rest.1 + body.offset.1)
+}
+
+fn distance((ax, ay): Vec2, (bx, by): Vec2) -> f64 {
+    ((bx - ax) * (bx - ax) + (by - ay) * (by - ay)).sqrt()
+}
+
+fn signed_area(points: &[Vec2]) -> f64 {
+    let n = points.len();
+    (0..n).fold(0.0, |sum, i| {
+        let ((ax, ay), (bx, by)) = (points[i], points[(i + 1) % n]);
+        sum + (ax * by - bx * ay) / 2.0
+    })
+}
+
+fn seeded(seed: u32) -> XorShiftRng {
+    // XorShiftRng rejects an all zero seed, so the seed is mixed with fixed
+    // non-zero words
+    XorShiftRng::from_seed([seed, 0x193a6754, 0xa8a7d469, 0x97830e05])
+}
+
+impl Outline {
+    fn new(points: &[Point]) -> Outline {
+        let locations: Vec<Vec2> = points.iter().map(|p| p.location).collect();
+        let n = locations.len();
+
+        Outline {
+            points: locations.iter()
+                .map(|&location| Body {
+                    rest: location,
+                    offset: (0.0, 0.0),
+                    velocity: (0.0, 0.0)
+                })
+                .collect(),
+            edge_lengths: (0..n).map(|i| distance(locations[i], locations[(i + 1) % n])).collect(),
+            area: signed_area(&locations)
+        }
+    }
+
+    fn accelerations(&self, body: &SoftBody) -> Vec<Vec2> {
+        let locations: Vec<Vec2> = self.points.iter().map(location).collect();
+        let n = locations.len();
+
+        let mut result: Vec<Vec2> = self.points
+            .iter()
+            .zip(&locations)
+            .map(|(point, &p)| {
+                let mut a = (-body.spring * point.offset.0, -body.spring * point.offset.1);
+                for field in &body.fields {
+                    let (fx, fy) = field(p);
+                    a = (a.0 + fx, a.1 + fy);
+                }
+                a
+            })
+            .collect();
+
+        // Two points share a single edge, so it's only counted once
+        let edges = if n > 2 { n } else if n == 2 { 1 } else { 0 };
+        if body.edge_stiffness != 0.0 {
+            for i in 0..edges {
+                let j = (i + 1) % n;
+                let length = distance(locations[i], locations[j]);
+                if length == 0.0 {
+                    continue;
+                }
+
+                let s = body.edge_stiffness * (length - self.edge_lengths[i]) / length;
+                let (dx, dy) = (locations[j].0 - locations[i].0, locations[j].1 - locations[i].1);
+                result[i] = (result[i].0 + s * dx, result[i].1 + s * dy);
+                result[j] = (result[j].0 - s * dx, result[j].1 - s * dy);
+            }
+        }
+
+        // Each point moves along the gradient of the area, out when the
+        // outline has shrunk and in when it has grown
+        // The change in area is taken relative to the rest area, so that the
+        // stiffness doesn't depend on the outline's size
+        if body.area_stiffness != 0.0 && n > 2 && self.area != 0.0 {
+            let error = (signed_area(&locations) - self.area) / self.area.abs();
+            for i in 0..n {
+                let (prev, next) = (locations[(i + n - 1) % n], locations[(i + 1) % n]);
+                let gradient = ((next.1 - prev.1) / 2.0, (prev.0 - next.0) / 2.0);
+                let s = -body.area_stiffness * error;
+                result[i] = (result[i].0 + s * gradient.0, result[i].1 + s * gradient.1);
+            }
+        }
+
+        result
+    }
+}
+
+impl SoftBody {
+    pub fn new(model: &Model) -> SoftBody {
+        SoftBody {
+            spring: 1800.0,
+            damping: 3.0,
+            edge_stiffness: 0.0,
+            area_stiffness: 0.0,
+            timestep: 1.0 / 60.0,
+            rest: model.clone(),
+            outlines: model.paths
+                .iter()
+                .map(|path| {
+                    Some(&path.points[..])
+                        .into_iter()
+                        .chain(path.contours.iter().map(|c| &c[..]))
+                        .map(Outline::new)
+                        .collect()
+                })
+                .collect(),
+            fields: Vec::new(),
+            pending: 0.0,
+            rng: seeded(0)
+        }
+    }
+
+    // Restarts the random sequence used by shuffle
+    pub fn reseed(&mut self, seed: u32) {
+        self.rng = seeded(seed);
+    }
+
+    // The model with every point at its current location
+    pub fn model(&self) -> Model {
+        let moved = |rest: &[Point], outline: &Outline| -> Vec<Point> {
+            rest.iter()
+                .zip(&outline.points)
+                .map(|(point, body)| Point {
+                    location: location(body),
+                    curve_bias: point.curve_bias,
+                    weights: point.weights
+                })
+                .collect()
+        };
+
+        let mut model = self.rest.clone();
+        for (path, outlines) in model.paths.iter_mut().zip(&self.outlines) {
+            path.points = moved(&path.points, &outlines[0]);
+            path.contours = path.contours.iter().zip(&outlines[1..]).map(|(c, o)| moved(c, o)).collect();
+        }
+
+        model
+    }
+
+    // Whether every point is within tolerance of its rest location and
+    // moving slower than tolerance per second
+    pub fn is_at_rest(&self, tolerance: f64) -> bool {
+        self.outlines.iter().flat_map(|o| o).flat_map(|o| &o.points).all(|body| {
+            distance((0.0, 0.0), body.offset) <= tolerance &&
+                distance((0.0, 0.0), body.velocity) <= tolerance
+        })
+    }
+
+    // Adds to each point's velocity the result of a function of its location
+    pub fn impulse<F: FnMut(Vec2) -> Vec2>(&mut self, mut f: F) {
+        for body in self.outlines.iter_mut().flat_map(|o| o).flat_map(|o| &mut o.points) {
+            let (dx, dy) = f(location(body));
+            body.velocity = (body.velocity.0 + dx, body.velocity.1 + dy);
+        }
+    }
+
+    // Pushes points away from a centre, most strongly within about radius
+    // of it
+    pub fn push(&mut self, (x, y): Vec2, strength: f64, radius: f64) {
+        self.impulse(|(px, py)| {
+            let (dx, dy) = (px - x, py - y);
+            let s = strength * gauss(radius, (dx * dx + dy * dy).sqrt());
+            (s * dx, s * dy)
+        })
+    }
+
+    // Gives each point a random velocity of up to speed in x and y
+    pub fn shuffle(&mut self, speed: f64) {
+        let mut rng = self.rng.clone();
+        self.impulse(|_| (rng.gen_range(-speed, speed), rng.gen_range(-speed, speed)));
+        self.rng = rng;
+    }
+
+    // Adds an acceleration applied at every step from then on, e.g. gravity
+    pub fn add_force_field<F: Fn(Vec2) -> Vec2 + 'static>(&mut self, field: F) {
+        self.fields.push(Box::new(field));
+    }
+
+    pub fn clear_force_fields(&mut self) {
+        self.fields.clear();
+    }
+
+    // Moves the simulation on by a number of seconds, as a whole number of
+    // steps. Any remainder is carried over to the next call.
+    pub fn advance(&mut self, seconds: f64) {
+        self.pending = (self.pending + seconds).min(MAX_ADVANCE);
+        while self.pending >= self.timestep {
+            self.pending -= self.timestep;
+            self.step();
+        }
+    }
+
+    // Moves the simulation on by one timestep
+    pub fn step(&mut self) {
+        let dt = self.timestep;
+        let decay = (-self.damping * dt).exp();
+
+        let accelerations: Vec<Vec<Vec<Vec2>>> = self.outlines
+            .iter()
+            .map(|outlines| outlines.iter().map(|o| o.accelerations(self)).collect())
+            .collect();
+
+        for (outlines, accelerations) in self.outlines.iter_mut().zip(accelerations) {
+            for (outline, accelerations) in outlines.iter_mut().zip(accelerations) {
+                for (body, (ax, ay)) in outline.points.iter_mut().zip(accelerations) {
+                    let (vx, vy) = ((body.velocity.0 + ax * dt) * decay, (body.velocity.1 + ay * dt) * decay);
+                    body.velocity = (vx, vy);
+                    body.offset = (body.offset.0 + vx * dt, body.offset.1 + vy * dt);
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use model::{Model, path};
+
+    fn square() -> Model {
+        let mut square = path(&[(0.0, 0.0, 0.0), (10.0, 0.0, 0.0), (10.0, 10.0, 0.0), (0.0, 10.0, 0.0)]);
+        square.contours = vec![path(&[(4.0, 4.0, 0.0), (4.0, 6.0, 0.0), (6.0, 6.0, 0.0)]).points];
+        Model { paths: vec![square], .. Default::default() }
+    }
+
+    fn locations(model: &Model) -> Vec<Vec2> {
+        let path = &model.paths[0];
+        path.points.iter().chain(&path.contours[0]).map(|p| p.location).collect()
+    }
+
+    #[test]
+    fn settles() {
+        let mut body = SoftBody::new(&square());
+        assert!(body.is_at_rest(0.0));
+
+        body.push((1.0, 1.0), 10.0, 5.0);
+        body.step();
+        assert!(!body.is_at_rest(0.01));
+        assert!(locations(&body.model())[0].0 < 0.0);
+
+        for _ in 0..100 {
+            body.advance(0.1);
+        }
+        assert!(body.is_at_rest(1e-3));
+    }
+
+    #[test]
+    fn fixed_timestep() {
+        let mut a = SoftBody::new(&square());
+        let mut b = SoftBody::new(&square());
+        a.timestep = 1.0 / 64.0;
+        b.timestep = 1.0 / 64.0;
+        a.shuffle(100.0);
+        b.shuffle(100.0);
+        assert_eq!(locations(&a.model()), locations(&b.model()));
+
+        a.advance(0.25);
+        for _ in 0..8 {
+            b.advance(0.03125);
+        }
+        assert_eq!(locations(&a.model()), locations(&b.model()));
+
+        // A different seed gives a different shuffle
+        b.reseed(1);
+        a.shuffle(100.0);
+        b.shuffle(100.0);
+        a.advance(0.1);
+        b.advance(0.1);
+        assert!(locations(&a.model()) != locations(&b.model()));
+    }
+
+    #[test]
+    fn edges_and_area() {
+        let spread = |edge_stiffness, area_stiffness| {
+            let mut body = SoftBody::new(&square());
+            body.edge_stiffness = edge_stiffness;
+            body.area_stiffness = area_stiffness;
+            body.impulse(|(x, _)| if x == 0.0 { (-50.0, 0.0) } else { (0.0, 0.0) });
+            body.advance(0.05);
+
+            let outline = locations(&body.model())[..4].to_vec();
+            (distance(outline[0], outline[1]), signed_area(&outline))
+        };
+
+        let (length, area) = spread(0.0, 0.0);
+        let (held_length, _) = spread(5000.0, 0.0);
+        let (_, held_area) = spread(0.0, 500.0);
+        assert!(length > 10.5);
+        assert!(held_length < length);
+        assert!((held_area - 100.0).abs() < (area - 100.0).abs());
+    }
+
+    #[test]
+    fn force_fields() {
+        let mut body = SoftBody::new(&square());
+        body.add_force_field(|_| (0.0, -180.0));
+        for _ in 0..100 {
+            body.advance(0.1);
+        }
+
+        // Gravity balanced by the springs
+        for (p, q) in locations(&body.model()).into_iter().zip(locations(&square())) {
+            assert!((p.0 - q.0).abs() < 1e-6);
+            assert!((p.1 - q.1 + 0.1).abs() < 1e-6);
+        }
+
+        body.clear_force_fields();
+        for _ in 0..100 {
+            body.advance(0.1);
+        }
+        assert!(body.is_at_rest(1e-3));
+    }
+}
@@ -0,0 +1,488 @@
+use std::f64::consts::PI;
+use std::mem;
+use data::Vec2;
+use super::{Path, Point, Stroke, LineJoin, LineCap, NO_WEIGHTS};
+use super::tessellate::{Segment, outline_segments, line_segments};
+
+pub const MITER_LIMIT: f64 = 4.0;
+
+// Converts the stroke of a path into closed polygons that cover it when
+// filled together with the non-zero rule, one running round each dash or
+// open line and two for each closed line. Curves are flattened to within a
+// fraction of the stroke width.
+pub fn stroke_contours(path: &Path, stroke: &Stroke) -> Vec<Vec<Point>> {
+    let mut output = Vec::new();
+    if !(stroke.width > 0.0) || !stroke.width.is_finite() {
+        return output;
+    }
+
+    let tolerance = tolerance(stroke);
+
+    for points in Some(&path.points).into_iter().chain(path.contours.iter()) {
+        let segments = if stroke.closed { outline_segments(points) } else { line_segments(points) };
+        let line = flatten(&segments, stroke.closed, tolerance);
+
+        for (piece, closed) in dash(line, stroke.closed, &stroke.dashes, tolerance) {
+            stroke_polyline(&piece, closed, stroke, tolerance, &mut output);
+        }
+    }
+
+    output
+}
+
+fn tolerance(stroke: &Stroke) -> f64 {
+    stroke.width / 50.0
+}
+
+// Whether a stroke's dashes repeat more finely than its curves are
+// flattened, so that they're drawn solid rather than split into more
+// pieces than could be seen
+pub fn has_fine_dashes(stroke: &Stroke) -> bool {
+    let total = stroke.dashes.iter().fold(0.0, |a, &b| a + b);
+    stroke.dashes.len() > 0 && total < tolerance(stroke)
+}
+
+fn add(a: Vec2, b: Vec2) -> Vec2 {
+    (a.0 + b.0, a.1 + b.1)
+}
+
+fn sub(a: Vec2, b: Vec2) -> Vec2 {
+    (a.0 - b.0, a.1 - b.1)
+}
+
+fn scale(a: Vec2, s: f64) -> Vec2 {
+    (a.0 * s, a.1 * s)
+}
+
+fn length(a: Vec2) -> f64 {
+    (a.0 * a.0 + a.1 * a.1).sqrt()
+}
+
+fn lerp(a: Vec2, b: Vec2, t: f64) -> Vec2 {
+    (a.0 + t * (b.0 - a.0), a.1 + t * (b.1 - a.1))
+}
+
+fn normalise(a: Vec2) -> Vec2 {
+    scale(a, 1.0 / length(a))
+}
+
+fn left(d: Vec2) -> Vec2 {
+    (-d.1, d.0)
+}
+
+fn flatten(segments: &[Segment], closed: bool, tolerance: f64) -> Vec<Vec2> {
+    let mut line = Vec::new();
+    if segments.len() == 0 {
+        return line;
+    }
+
+    let count = if closed { segments.len() } else { segments.len() - 1 };
+    for i in 0..count {
+        let a = segments[i].start;
+        let b = segments[(i + 1) % segments.len()].start;
+        line.push(a);
+
+        if let Some(c) = segments[i].control {
+            // A quadratic's distance from its chords is at most
+            // |a - 2c + b| / 4n^2 for n equal steps
+            let d = length(add(sub(a, scale(c, 2.0)), b));
+            let n = ((d / (4.0 * tolerance)).sqrt().ceil() as usize).max(1).min(64);
+
+            for k in 1..n {
+                let t = k as f64 / n as f64;
+                line.push(lerp(lerp(a, c, t), lerp(c, b, t), t));
+            }
+        }
+    }
+
+    if !closed {
+        line.push(segments[segments.len() - 1].start);
+    }
+
+    line
+}
+
+fn dash(line: Vec<Vec2>, closed: bool, dashes: &[f64], tolerance: f64) -> Vec<(Vec<Vec2>, bool)> {
+    let total = dashes.iter().fold(0.0, |a, &b| a + b);
+    if line.len() < 2 || !(total > 0.0 && total >= tolerance) || dashes.iter().any(|&d| !(d >= 0.0)) {
+        return vec![(line, closed)];
+    }
+
+    let mut pattern = dashes.to_vec();
+    if pattern.len() % 2 == 1 {
+        pattern.extend_from_slice(dashes);
+    }
+
+    let mut points = line;
+    if closed {
+        let first = points[0];
+        points.push(first);
+    }
+
+    let mut pieces = Vec::new();
+    let mut k = 0;
+    let mut remaining = pattern[0];
+    let mut on = true;
+    let mut current = vec![points[0]];
+
+    for w in points.windows(2) {
+        let (mut a, b) = (w[0], w[1]);
+        let mut l = length(sub(b, a));
+
+        while l > remaining {
+            let p = lerp(a, b, remaining / l);
+            if on {
+                current.push(p);
+                pieces.push((mem::replace(&mut current, Vec::new()), false));
+            } else {
+                current = vec![p];
+            }
+
+            on = !on;
+            k = (k + 1) % pattern.len();
+            l -= remaining;
+            remaining = pattern[k];
+            a = p;
+        }
+
+        remaining -= l;
+        if on {
+            current.push(b);
+        }
+    }
+
+    if on && current.len() > 0 {
+        pieces.push((current, false));
+    }
+
+    pieces
+}
+
+fn contour(points: Vec<Vec2>) -> Vec<Point> {
+    points.into_iter()
+        .map(|p| Point {
+            location: p,
+            curve_bias: 0.0,
+            weights: NO_WEIGHTS
+        })
+        .collect()
+}
+
+// Adds the points strictly between the ends of an arc about c, turning
+// anticlockwise from c + from through the sweep
+fn arc(c: Vec2, from: Vec2, sweep: f64, tolerance: f64, output: &mut Vec<Vec2>) {
+    let r = length(from);
+    let step = (1.0 - (tolerance / r).min(1.0)).acos();
+    let n = ((sweep / (2.0 * step)).ceil() as usize).max(1).min(64);
+
+    for k in 1..n {
+        let (sin, cos) = (sweep * k as f64 / n as f64).sin_cos();
+        output.push((c.0 + from.0 * cos - from.1 * sin, c.1 + from.0 * sin + from.1 * cos));
+    }
+}
+
+// Adds the cap at the end p of a line heading in direction d, from its right
+// side round to its left
+fn cap(p: Vec2, d: Vec2, stroke: &Stroke, tolerance: f64, output: &mut Vec<Vec2>) {
+    let hw = stroke.width / 2.0;
+    let n = scale(left(d), hw);
+
+    output.push(sub(p, n));
+    match stroke.cap {
+        LineCap::Butt => (),
+        LineCap::Round => arc(p, scale(n, -1.0), PI, tolerance, output),
+        LineCap::Square => {
+            let e = scale(d, hw);
+            output.push(add(sub(p, n), e));
+            output.push(add(add(p, n), e));
+        }
+    }
+    output.push(add(p, n));
+}
+
+// Adds the right side of the join at p, turning from direction d1 to d2. The
+// inside of a turn goes through p itself, so the outline loops back on
+// itself there and still covers the segments, with the non-zero rule, as if
+// each had been filled on its own.
+fn join(p: Vec2, d1: Vec2, d2: Vec2, stroke: &Stroke, tolerance: f64, output: &mut Vec<Vec2>) {
+    let hw = stroke.width / 2.0;
+    let cross = d1.0 * d2.1 - d1.1 * d2.0;
+    let dot = d1.0 * d2.0 + d1.1 * d2.1;
+    let (o1, o2) = (scale(left(d1), -hw), scale(left(d2), -hw));
+
+    output.push(add(p, o1));
+    if cross == 0.0 && dot > 0.0 {
+        return;
+    }
+
+    if cross < 0.0 {
+        output.push(p);
+    } else {
+        match stroke.join {
+            LineJoin::Round => arc(p, o1, cross.atan2(dot).abs(), tolerance, output),
+            LineJoin::Miter if length(add(o1, o2)) > 0.0 => {
+                let m = normalise(add(o1, o2));
+                let cos = (m.0 * o1.0 + m.1 * o1.1) / hw;
+
+                if 1.0 / cos <= MITER_LIMIT {
+                    output.push(add(p, scale(m, hw / cos)));
+                }
+            },
+            _ => ()
+        }
+    }
+    output.push(add(p, o2));
+}
+
+// Adds the right side of a line with its joins, leaving out the ends of an
+// open one for the caps
+fn side(points: &[Vec2], closed: bool, stroke: &Stroke, tolerance: f64, output: &mut Vec<Vec2>) {
+    let count = if closed { points.len() } else { points.len() - 1 };
+    let directions = (0..count)
+        .map(|i| normalise(sub(points[(i + 1) % points.len()], points[i])))
+        .collect::<Vec<_>>();
+
+    let first = if closed { 0 } else { 1 };
+    for i in first..count {
+        join(points[i], directions[(i + count - 1) % count], directions[i], stroke, tolerance, output);
+    }
+}
+
+// Outlines a line with one contour running round it, or two for a closed
+// line, one along each side
+fn stroke_polyline(line: &[Vec2], closed: bool, stroke: &Stroke, tolerance: f64,
+                   output: &mut Vec<Vec<Point>>) {
+    let mut points = Vec::with_capacity(line.len());
+    for &p in line {
+        if points.last() != Some(&p) {
+            points.push(p);
+        }
+    }
+    while closed && points.len() > 1 && points.first() == points.last() {
+        points.pop();
+    }
+
+    if points.len() == 0 {
+        return;
+    }
+
+    if points.len() == 1 {
+        if stroke.cap != LineCap::Butt {
+            let mut outline = Vec::new();
+            cap(points[0], (1.0, 0.0), stroke, tolerance, &mut outline);
+            cap(points[0], (-1.0, 0.0), stroke, tolerance, &mut outline);
+            output.push(contour(outline));
+        }
+        return;
+    }
+
+    let reversed = points.iter().rev().cloned().collect::<Vec<_>>();
+    let mut outline = Vec::new();
+
+    if closed {
+        side(&points, true, stroke, tolerance, &mut outline);
+        output.push(contour(mem::replace(&mut outline, Vec::new())));
+        side(&reversed, true, stroke, tolerance, &mut outline);
+    } else {
+        let n = points.len();
+        cap(points[0], normalise(sub(points[0], points[1])), stroke, tolerance, &mut outline);
+        side(&points, false, stroke, tolerance, &mut outline);
+        cap(points[n - 1], normalise(sub(points[n - 1], points[n - 2])), stroke, tolerance, &mut outline);
+        side(&reversed, false, stroke, tolerance, &mut outline);
+    }
+
+    output.push(contour(outline));
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use super::super::{Path, Point, Stroke, LineJoin, LineCap, FillRule, Paint};
+    use super::super::tessellate::{Triangle, stroke_triangles};
+
+    fn path(points: &[Vec2]) -> Path {
+        Path {
+            name: None,
+            paint: Paint::solid((1.0, 1.0, 1.0)),
+            points: points.iter()
+                .map(|&p| Point { location: p, curve_bias: 0.0, weights: NO_WEIGHTS })
+                .collect(),
+            contours: Vec::new(),
+            fill_rule: FillRule::NonZero,
+            filled: false,
+            stroke: None
+        }
+    }
+
+    fn stroke(width: f64, join: LineJoin, cap: LineCap, closed: bool) -> Stroke {
+        Stroke {
+            colour: (0.0, 0.0, 0.0),
+            width: width,
+            join: join,
+            cap: cap,
+            dashes: Vec::new(),
+            closed: closed
+        }
+    }
+
+    fn area(triangles: &[Triangle]) -> f64 {
+        triangles.iter()
+            .map(|t| {
+                let (a, b, c) = (t[0].position, t[1].position, t[2].position);
+                ((b.0 - a.0) * (c.1 - a.1) - (b.1 - a.1) * (c.0 - a.0)).abs() / 2.0
+            })
+            .sum()
+    }
+
+    fn close(a: f64, b: f64) -> bool {
+        (a - b).abs() < 1e-9
+    }
+
+    #[test]
+    fn line_caps() {
+        let line = path(&[(0.0, 0.0), (10.0, 0.0)]);
+
+        let butt = stroke_triangles(&line, &stroke(2.0, LineJoin::Miter, LineCap::Butt, false));
+        assert!(close(area(&butt), 20.0));
+
+        let square = stroke_triangles(&line, &stroke(2.0, LineJoin::Miter, LineCap::Square, false));
+        assert!(close(area(&square), 24.0));
+
+        // Polygonal circles are slightly smaller than the real thing
+        let round = stroke_triangles(&line, &stroke(2.0, LineJoin::Miter, LineCap::Round, false));
+        assert!(area(&round) > 20.0 + PI * 0.95 && area(&round) < 20.0 + PI);
+    }
+
+    #[test]
+    fn outline_joins() {
+        let square = path(&[(0.0, 0.0), (10.0, 0.0), (10.0, 10.0), (0.0, 10.0)]);
+
+        // The outline covers a 12x12 square minus the 8x8 hole, less the
+        // corners for bevels
+        let miter = stroke_triangles(&square, &stroke(2.0, LineJoin::Miter, LineCap::Butt, true));
+        assert!(close(area(&miter), 144.0 - 64.0));
+
+        let bevel = stroke_triangles(&square, &stroke(2.0, LineJoin::Bevel, LineCap::Butt, true));
+        assert!(close(area(&bevel), 144.0 - 64.0 - 4.0 * 0.5));
+
+        let round = stroke_triangles(&square, &stroke(2.0, LineJoin::Round, LineCap::Butt, true));
+        assert!(area(&round) > area(&bevel) && area(&round) < area(&miter));
+
+        // Without closing, the ends of the open line are left bare
+        let open = stroke_triangles(&square, &stroke(2.0, LineJoin::Miter, LineCap::Butt, false));
+        assert!(close(area(&open), 144.0 - 64.0 - 12.0 - 8.0));
+    }
+
+    #[test]
+    fn miter_limit() {
+        // A sharp spike exceeds the miter limit and falls back to a bevel
+        let spike = path(&[(0.0, 0.0), (10.0, 0.5), (0.0, 1.0)]);
+
+        let miter = stroke_triangles(&spike, &stroke(0.2, LineJoin::Miter, LineCap::Butt, false));
+        let bevel = stroke_triangles(&spike, &stroke(0.2, LineJoin::Bevel, LineCap::Butt, false));
+        assert!(close(area(&miter), area(&bevel)));
+
+        let wide = path(&[(0.0, 0.0), (10.0, 0.0), (10.0, 10.0)]);
+        let miter = stroke_triangles(&wide, &stroke(2.0, LineJoin::Miter, LineCap::Butt, false));
+        let bevel = stroke_triangles(&wide, &stroke(2.0, LineJoin::Bevel, LineCap::Butt, false));
+        assert!(close(area(&miter) - area(&bevel), 0.5));
+    }
+
+    #[test]
+    fn dashes() {
+        let line = path(&[(0.0, 0.0), (10.0, 0.0)]);
+        let mut dashed = stroke(2.0, LineJoin::Miter, LineCap::Butt, false);
+        dashed.dashes = vec![1.0, 1.0];
+
+        let contours = stroke_contours(&line, &dashed);
+        assert_eq!(contours.len(), 5);
+        assert!(close(area(&stroke_triangles(&line, &dashed)), 10.0));
+
+        // An odd pattern repeats with on and off swapped, so 3 on, 1 off,
+        // 3 on, 3 off
+        dashed.dashes = vec![3.0, 1.0, 3.0];
+        let contours = stroke_contours(&line, &dashed);
+        assert_eq!(contours.len(), 2);
+        assert!(close(area(&stroke_triangles(&line, &dashed)), 2.0 * (3.0 + 3.0)));
+
+        // Dashes continue around corners and across the closing edge
+        let square = path(&[(0.0, 0.0), (10.0, 0.0), (10.0, 10.0), (0.0, 10.0)]);
+        dashed.dashes = vec![5.0, 5.0];
+        dashed.closed = true;
+        assert_eq!(stroke_contours(&square, &dashed).len(), 4);
+
+        // Zero length dashes with round caps become dots
+        dashed.dashes = vec![0.0, 2.0];
+        dashed.cap = LineCap::Round;
+        dashed.closed = false;
+        assert_eq!(stroke_contours(&line, &dashed).len(), 5);
+
+        // Patterns finer than the curves are flattened are drawn solid
+        let long = path(&[(0.0, 0.0), (1000.0, 0.0)]);
+        dashed.dashes = vec![1e-12, 1e-12];
+        assert!(has_fine_dashes(&dashed));
+        assert_eq!(stroke_contours(&long, &dashed).len(), 1);
+        dashed.dashes = vec![1e-12, 1.0];
+        assert!(!has_fine_dashes(&dashed));
+    }
+
+    #[test]
+    fn curves() {
+        // A half circle approximated by the shader's quadratic
+        let mut arc = path(&[(0.0, 0.0), (5.0, 5.0), (10.0, 0.0)]);
+        arc.points[1].curve_bias = 0.5;
+
+        let contours = stroke_contours(&arc, &stroke(0.5, LineJoin::Bevel, LineCap::Butt, false));
+        assert_eq!(contours.len(), 1);
+        assert!(contours[0].len() > 8);
+
+        for contour in &contours {
+            for point in contour {
+                let (x, y) = point.location;
+                assert!(x >= -0.5 && x <= 10.5 && y >= -0.5 && y <= 3.0);
+            }
+        }
+    }
+
+    #[test]
+    fn long_outlines() {
+        // Each side is one outline, so a finely divided circle tessellates
+        // to a few dozen triangles per point, none of them overlapping
+        let points = (0..1600)
+            .map(|i| {
+                let a = 2.0 * PI * i as f64 / 1600.0;
+                (100.0 * a.cos(), 100.0 * a.sin())
+            })
+            .collect::<Vec<_>>();
+        let ring = path(&points);
+
+        let closed = stroke(2.0, LineJoin::Round, LineCap::Butt, true);
+        assert_eq!(stroke_contours(&ring, &closed).len(), 2);
+
+        let triangles = stroke_triangles(&ring, &closed);
+        assert!(triangles.len() < 25 * points.len());
+        assert!((area(&triangles) - 4.0 * PI * 100.0).abs() < 1.0);
+
+        let open = stroke(2.0, LineJoin::Miter, LineCap::Round, false);
+        assert_eq!(stroke_contours(&ring, &open).len(), 1);
+    }
+
+    #[test]
+    fn degenerate() {
+        let empty = path(&[]);
+        assert_eq!(stroke_contours(&empty, &stroke(1.0, LineJoin::Round, LineCap::Round, true)).len(), 0);
+
+        let dot = path(&[(1.0, 1.0), (1.0, 1.0)]);
+        assert_eq!(stroke_contours(&dot, &stroke(1.0, LineJoin::Miter, LineCap::Butt, false)).len(), 0);
+        assert_eq!(stroke_contours(&dot, &stroke(1.0, LineJoin::Miter, LineCap::Round, false)).len(), 1);
+
+        let line = path(&[(0.0, 0.0), (10.0, 0.0)]);
+        assert_eq!(stroke_contours(&line, &stroke(0.0, LineJoin::Miter, LineCap::Butt, false)).len(), 0);
+        assert_eq!(stroke_contours(&line, &stroke(-1.0, LineJoin::Miter, LineCap::Butt, false)).len(), 0);
+
+        // Doubling back on a closed two point outline must not blow up the
+        // miter
+        let back = stroke_triangles(&line, &stroke(2.0, LineJoin::Miter, LineCap::Butt, true));
+        assert!(close(area(&back), 20.0));
+    }
+}
@@ -0,0 +1,1437 @@
+use std::io::{self, Read, Write};
+use std::f64::consts::PI;
+use data::{Vec2, Vec3};
+use super::{Model, Path, Point, FillRule, Stroke, LineJoin, LineCap, Affine2, Paint, extent, NO_WEIGHTS};
+use super::tessellate::{Segment, path_segments, stroke_segments};
+
+// Reads the filled and stroked shapes of an SVG document into a model. Curves
+// are approximated by quadratics to within tolerance, in user units with the
+// y-axis flipped to point up.
+pub fn read_svg(input: &mut Read, tolerance: f64) -> io::Result<Model> {
+    let mut text = String::new();
+    try!(input.read_to_string(&mut text));
+
+    let mut xml = Xml {
+        text: &text,
+        pos: 0
+    };
+    let mut styles = vec![Style::new()];
+    let mut skip = 0;
+    let mut paths = Vec::new();
+
+    while let Some(tag) = try!(xml.next()) {
+        match tag {
+            Tag::Start(name, attributes, empty) => {
+                if skip > 0 {
+                    if !empty {
+                        skip += 1;
+                    }
+                    continue;
+                }
+
+                let style = try!(styles.last().unwrap().apply(&attributes));
+
+                match &name[..] {
+                    "svg" | "g" | "a" | "switch" if style.visible => {
+                        if !empty {
+                            styles.push(style);
+                        }
+                    },
+                    "path" | "rect" | "circle" | "ellipse" | "line" | "polyline" | "polygon"
+                        if style.visible => {
+                        if let Some(path) = try!(shape(&name, &attributes, &style, tolerance)) {
+                            paths.push(path);
+                        }
+                        if !empty {
+                            skip = 1;
+                        }
+                    },
+                    _ => if !empty {
+                        skip = 1;
+                    }
+                }
+            },
+            Tag::End => {
+                if skip > 0 {
+                    skip -= 1;
+                } else if styles.len() > 1 {
+                    styles.pop();
+                } else {
+                    return Err(invalid("Unexpected end tag"));
+                }
+            }
+        }
+    }
+
+    Ok(Model {
+        paths: paths,
+        .. Default::default()
+    })
+}
+
+fn invalid(message: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message)
+}
+
+enum Tag {
+    Start(String, Vec<(String, String)>, bool),
+    End
+}
+
+struct Xml<'a> {
+    text: &'a str,
+    pos: usize
+}
+
+impl<'a> Xml<'a> {
+    fn skip_past(&mut self, end: &str) -> io::Result<()> {
+        match self.text[self.pos..].find(end) {
+            Some(i) => {
+                self.pos += i + end.len();
+                Ok(())
+            },
+            None => Err(invalid("Unexpected end of document"))
+        }
+    }
+
+    fn skip_whitespace(&mut self) {
+        let rest = &self.text[self.pos..];
+        self.pos += rest.len() - rest.trim_left().len();
+    }
+
+    fn name(&mut self) -> &'a str {
+        let text = self.text;
+        let rest = &text[self.pos..];
+        let end = rest.find(|c: char| c.is_whitespace() || c == '/' || c == '>' || c == '=')
+            .unwrap_or(rest.len());
+        self.pos += end;
+
+        // Namespace prefixes are ignored
+        let name = &rest[..end];
+        match name.find(':') {
+            Some(i) if !name.starts_with("xml") => &name[i + 1..],
+            _ => name
+        }
+    }
+
+    fn next(&mut self) -> io::Result<Option<Tag>> {
+        loop {
+            match self.text[self.pos..].find('<') {
+                Some(i) => self.pos += i,
+                None => return Ok(None)
+            }
+
+            let text = self.text;
+            let rest = &text[self.pos..];
+            if rest.starts_with("<!--") {
+                try!(self.skip_past("-->"));
+            } else if rest.starts_with("<![CDATA[") {
+                try!(self.skip_past("]]>"));
+            } else if rest.starts_with("<?") {
+                try!(self.skip_past("?>"));
+            } else if rest.starts_with("<!") {
+                // A doctype may have an internal subset in brackets
+                match (rest.find('['), rest.find('>')) {
+                    (Some(i), Some(j)) if i < j => try!(self.skip_past("]>")),
+                    _ => try!(self.skip_past(">"))
+                }
+            } else if rest.starts_with("</") {
+                try!(self.skip_past(">"));
+                return Ok(Some(Tag::End));
+            } else {
+                return self.start().map(Some);
+            }
+        }
+    }
+
+    fn start(&mut self) -> io::Result<Tag> {
+        self.pos += 1;
+        let name = self.name().to_string();
+        let mut attributes = Vec::new();
+
+        loop {
+            self.skip_whitespace();
+            let rest = &self.text[self.pos..];
+
+            if rest.starts_with("/>") {
+                self.pos += 2;
+                return Ok(Tag::Start(name, attributes, true));
+            } else if rest.starts_with(">") {
+                self.pos += 1;
+                return Ok(Tag::Start(name, attributes, false));
+            } else if rest.len() == 0 {
+                return Err(invalid("Unexpected end of document"));
+            }
+
+            let key = self.name().to_string();
+            if key.len() == 0 {
+                return Err(invalid("Invalid attribute"));
+            }
+
+            self.skip_whitespace();
+            if !self.text[self.pos..].starts_with("=") {
+                return Err(invalid("Invalid attribute"));
+            }
+            self.pos += 1;
+            self.skip_whitespace();
+
+            let quote = match self.text[self.pos..].chars().next() {
+                Some(c) if c == '"' || c == '\'' => c,
+                _ => return Err(invalid("Invalid attribute"))
+            };
+            self.pos += 1;
+
+            let length = match self.text[self.pos..].find(quote) {
+                Some(length) => length,
+                None => return Err(invalid("Unexpected end of document"))
+            };
+            let value = unescape(&self.text[self.pos..self.pos + length]);
+            self.pos += length + 1;
+
+            attributes.push((key, value));
+        }
+    }
+}
+
+fn unescape(text: &str) -> String {
+    let mut result = String::with_capacity(text.len());
+    let mut rest = text;
+
+    while let Some(i) = rest.find('&') {
+        result.push_str(&rest[..i]);
+        rest = &rest[i..];
+
+        let end = match rest.find(';') {
+            Some(end) => end,
+            None => break
+        };
+
+        let c = match &rest[1..end] {
+            "amp" => Some('&'),
+            "lt" => Some('<'),
+            "gt" => Some('>'),
+            "quot" => Some('"'),
+            "apos" => Some('\''),
+            entity if entity.starts_with("#x") => u32::from_str_radix(&entity[2..], 16).ok()
+                .and_then(::std::char::from_u32),
+            entity if entity.starts_with("#") => entity[1..].parse().ok()
+                .and_then(::std::char::from_u32),
+            _ => None
+        };
+
+        match c {
+            Some(c) => {
+                result.push(c);
+                rest = &rest[end + 1..];
+            },
+            None => {
+                result.push('&');
+                rest = &rest[1..];
+            }
+        }
+    }
+
+    result.push_str(rest);
+    result
+}
+
+#[derive(Clone)]
+struct Style {
+    transform: Affine2,
+    fill: Option<Vec3>,
+    fill_rule: FillRule,
+    stroke: Option<Vec3>,
+    stroke_width: f64,
+    join: LineJoin,
+    cap: LineCap,
+    dashes: Vec<f64>,
+    visible: bool
+}
+
+impl Style {
+    fn new() -> Style {
+        Style {
+            transform: Affine2::scale(1.0, -1.0),
+            fill: Some((0.0, 0.0, 0.0)),
+            fill_rule: FillRule::NonZero,
+            stroke: None,
+            stroke_width: 1.0,
+            join: LineJoin::Miter,
+            cap: LineCap::Butt,
+            dashes: Vec::new(),
+            visible: true
+        }
+    }
+
+    fn apply(&self, attributes: &[(String, String)]) -> io::Result<Style> {
+        let mut style = self.clone();
+
+        // The visibility of an element isn't inherited
+        style.visible = true;
+
+        for &(ref name, ref value) in attributes {
+            if name == "transform" {
+                style.transform = try!(parse_transform(value)).then(&self.transform);
+            } else if name != "style" {
+                try!(style.property(name, value));
+            }
+        }
+
+        // Style declarations take precedence over presentation attributes
+        if let Some(declarations) = attribute(attributes, "style") {
+            for declaration in declarations.split(';') {
+                if let Some(i) = declaration.find(':') {
+                    try!(style.property(declaration[..i].trim(), &declaration[i + 1..]));
+                }
+            }
+        }
+
+        Ok(style)
+    }
+
+    fn property(&mut self, name: &str, value: &str) -> io::Result<()> {
+        let value = value.trim();
+        if value == "inherit" {
+            return Ok(());
+        }
+
+        match name {
+            "fill" => self.fill = try!(parse_paint(value)),
+            "fill-rule" => self.fill_rule = match value {
+                "evenodd" => FillRule::EvenOdd,
+                _ => FillRule::NonZero
+            },
+            "stroke" => self.stroke = try!(parse_paint(value)),
+            "stroke-width" => self.stroke_width = try!(parse_length(value)),
+            "stroke-linejoin" => self.join = match value {
+                "round" => LineJoin::Round,
+                "bevel" => LineJoin::Bevel,
+                _ => LineJoin::Miter
+            },
+            "stroke-linecap" => self.cap = match value {
+                "round" => LineCap::Round,
+                "square" => LineCap::Square,
+                _ => LineCap::Butt
+            },
+            "stroke-dasharray" => self.dashes = if value == "none" {
+                Vec::new()
+            } else {
+                try!(value.split(|c: char| c == ',' || c.is_whitespace())
+                     .filter(|s| s.len() > 0)
+                     .map(parse_length)
+                     .collect())
+            },
+            "display" => self.visible = value != "none",
+            _ => ()
+        }
+
+        Ok(())
+    }
+}
+
+fn attribute<'a>(attributes: &'a [(String, String)], name: &str) -> Option<&'a str> {
+    attributes.iter()
+        .find(|&&(ref key, _)| key == name)
+        .map(|&(_, ref value)| &value[..])
+}
+
+fn length_attribute(attributes: &[(String, String)], name: &str) -> io::Result<Option<f64>> {
+    match attribute(attributes, name) {
+        Some(value) => parse_length(value).map(Some),
+        None => Ok(None)
+    }
+}
+
+fn parse_length(value: &str) -> io::Result<f64> {
+    let value = value.trim();
+    let end = value.find(|c: char| c.is_alphabetic() && c != 'e' && c != 'E' || c == '%')
+        .unwrap_or(value.len());
+
+    let scale = match &value[end..] {
+        "" | "px" => 1.0,
+        "pt" => 4.0 / 3.0,
+        "pc" => 16.0,
+        "mm" => 96.0 / 25.4,
+        "cm" => 96.0 / 2.54,
+        "in" => 96.0,
+        _ => return Err(invalid("Unsupported length"))
+    };
+
+    match value[..end].parse::<f64>() {
+        Ok(n) if n.is_finite() => Ok(n * scale),
+        _ => Err(invalid("Invalid length"))
+    }
+}
+
+fn parse_paint(value: &str) -> io::Result<Option<Vec3>> {
+    if value == "none" {
+        Ok(None)
+    } else if value.starts_with("url(") {
+        // Only the fallback of a paint server can be used
+        match value.find(')') {
+            Some(i) if value[i + 1..].trim().len() > 0 => parse_paint(value[i + 1..].trim()),
+            _ => Err(invalid("Unsupported paint"))
+        }
+    } else {
+        parse_colour(value).map(Some)
+    }
+}
+
+fn parse_colour(value: &str) -> io::Result<Vec3> {
+    let hex = |s: &str| u8::from_str_radix(s, 16).map(|n| n as f64 / 255.0);
+
+    // The hex forms are sliced by byte
+    if !value.is_ascii() {
+        return Err(invalid("Unsupported colour"));
+    }
+
+    if value.starts_with("#") && value.len() == 7 {
+        if let (Ok(r), Ok(g), Ok(b)) = (hex(&value[1..3]), hex(&value[3..5]), hex(&value[5..7])) {
+            return Ok((r, g, b));
+        }
+    } else if value.starts_with("#") && value.len() == 4 {
+        let digit = |i| hex(&value[i..i + 1]).map(|n| n * 17.0);
+        if let (Ok(r), Ok(g), Ok(b)) = (digit(1), digit(2), digit(3)) {
+            return Ok((r, g, b));
+        }
+    } else if value.starts_with("rgb(") && value.ends_with(")") {
+        let components = value[4..value.len() - 1]
+            .split(',')
+            .map(|s| {
+                let s = s.trim();
+                if s.ends_with("%") {
+                    s[..s.len() - 1].parse::<f64>().map(|n| n / 100.0)
+                } else {
+                    s.parse::<f64>().map(|n| n / 255.0)
+                }
+            })
+            .collect::<Vec<_>>();
+
+        if components.len() == 3 {
+            if let (&Ok(r), &Ok(g), &Ok(b)) = (&components[0], &components[1], &components[2]) {
+                return Ok((r.max(0.0).min(1.0), g.max(0.0).min(1.0), b.max(0.0).min(1.0)));
+            }
+        }
+    } else {
+        let named = match &value.to_lowercase()[..] {
+            "black" | "currentcolor" => Some("#000000"),
+            "silver" => Some("#c0c0c0"),
+            "gray" | "grey" => Some("#808080"),
+            "white" => Some("#ffffff"),
+            "maroon" => Some("#800000"),
+            "red" => Some("#ff0000"),
+            "purple" => Some("#800080"),
+            "fuchsia" | "magenta" => Some("#ff00ff"),
+            "green" => Some("#008000"),
+            "lime" => Some("#00ff00"),
+            "olive" => Some("#808000"),
+            "yellow" => Some("#ffff00"),
+            "navy" => Some("#000080"),
+            "blue" => Some("#0000ff"),
+            "teal" => Some("#008080"),
+            "aqua" | "cyan" => Some("#00ffff"),
+            "orange" => Some("#ffa500"),
+            "pink" => Some("#ffc0cb"),
+            "brown" => Some("#a52a2a"),
+            _ => None
+        };
+
+        if let Some(named) = named {
+            return parse_colour(named);
+        }
+    }
+
+    Err(invalid("Unsupported colour"))
+}
+
+fn parse_transform(text: &str) -> io::Result<Affine2> {
+    let mut result = Affine2::identity();
+    let mut rest = text;
+
+    loop {
+        rest = rest.trim_left_matches(|c: char| c == ',' || c.is_whitespace());
+        if rest.len() == 0 {
+            return Ok(result);
+        }
+
+        let (open, close) = match (rest.find('('), rest.find(')')) {
+            (Some(open), Some(close)) if open < close => (open, close),
+            _ => return Err(invalid("Invalid transform"))
+        };
+
+        let args = try!(Numbers::new(&rest[open + 1..close]).all());
+        let t = match (rest[..open].trim(), args.len()) {
+            ("matrix", 6) => Affine2 { matrix: [args[0], args[1], args[2], args[3], args[4], args[5]] },
+            ("translate", 1) => Affine2::translate(args[0], 0.0),
+            ("translate", 2) => Affine2::translate(args[0], args[1]),
+            ("scale", 1) => Affine2::scale(args[0], args[0]),
+            ("scale", 2) => Affine2::scale(args[0], args[1]),
+            ("rotate", 1) => Affine2::rotate(args[0].to_radians()),
+            ("rotate", 3) => Affine2::translate(-args[1], -args[2])
+                .then(&Affine2::rotate(args[0].to_radians()))
+                .then(&Affine2::translate(args[1], args[2])),
+            ("skewX", 1) => Affine2::skew(args[0].to_radians(), 0.0),
+            ("skewY", 1) => Affine2::skew(0.0, args[0].to_radians()),
+            _ => return Err(invalid("Invalid transform"))
+        };
+
+        result = t.then(&result);
+        rest = &rest[close + 1..];
+    }
+}
+
+struct Numbers<'a> {
+    text: &'a [u8],
+    pos: usize
+}
+
+impl<'a> Numbers<'a> {
+    fn new(text: &'a str) -> Numbers<'a> {
+        Numbers {
+            text: text.as_bytes(),
+            pos: 0
+        }
+    }
+
+    fn peek(&mut self) -> Option<u8> {
+        while self.pos < self.text.len() {
+            match self.text[self.pos] {
+                b' ' | b'\t' | b'\r' | b'\n' | b',' => self.pos += 1,
+                c => return Some(c)
+            }
+        }
+
+        None
+    }
+
+    fn number(&mut self) -> io::Result<f64> {
+        self.peek();
+        let start = self.pos;
+        let digits = |text: &[u8], mut pos: usize| {
+            while pos < text.len() && text[pos] >= b'0' && text[pos] <= b'9' {
+                pos += 1;
+            }
+            pos
+        };
+
+        let mut end = start;
+        if end < self.text.len() && (self.text[end] == b'+' || self.text[end] == b'-') {
+            end += 1;
+        }
+        end = digits(self.text, end);
+        if end < self.text.len() && self.text[end] == b'.' {
+            end = digits(self.text, end + 1);
+        }
+        if end < self.text.len() && (self.text[end] == b'e' || self.text[end] == b'E') {
+            let mut exponent = end + 1;
+            if exponent < self.text.len() && (self.text[exponent] == b'+' || self.text[exponent] == b'-') {
+                exponent += 1;
+            }
+            if digits(self.text, exponent) > exponent {
+                end = digits(self.text, exponent);
+            }
+        }
+
+        let number = String::from_utf8_lossy(&self.text[start..end]);
+        match number.parse::<f64>() {
+            Ok(n) if n.is_finite() => {
+                self.pos = end;
+                Ok(n)
+            },
+            _ => Err(invalid("Invalid number"))
+        }
+    }
+
+    fn point(&mut self) -> io::Result<Vec2> {
+        let x = try!(self.number());
+        let y = try!(self.number());
+        Ok((x, y))
+    }
+
+    fn flag(&mut self) -> io::Result<bool> {
+        match self.peek() {
+            Some(b'0') => {
+                self.pos += 1;
+                Ok(false)
+            },
+            Some(b'1') => {
+                self.pos += 1;
+                Ok(true)
+            },
+            _ => Err(invalid("Invalid flag"))
+        }
+    }
+
+    fn all(&mut self) -> io::Result<Vec<f64>> {
+        let mut numbers = Vec::new();
+        while self.peek().is_some() {
+            numbers.push(try!(self.number()));
+        }
+
+        Ok(numbers)
+    }
+}
+
+enum Curve {
+    Line(Vec2),
+    Quad(Vec2, Vec2),
+    Cubic(Vec2, Vec2, Vec2)
+}
+
+struct Subpath {
+    start: Vec2,
+    curves: Vec<Curve>,
+    closed: bool
+}
+
+struct Builder {
+    subpaths: Vec<Subpath>,
+    current: Vec2,
+    last_cubic: Option<Vec2>,
+    last_quad: Option<Vec2>
+}
+
+impl Builder {
+    fn new() -> Builder {
+        Builder {
+            subpaths: Vec::new(),
+            current: (0.0, 0.0),
+            last_cubic: None,
+            last_quad: None
+        }
+    }
+
+    fn move_to(&mut self, p: Vec2) {
+        self.subpaths.push(Subpath {
+            start: p,
+            curves: Vec::new(),
+            closed: false
+        });
+        self.current = p;
+        self.last_cubic = None;
+        self.last_quad = None;
+    }
+
+    fn push(&mut self, curve: Curve, end: Vec2) {
+        // Drawing after a close starts a new subpath at the same point
+        let current = self.current;
+        if self.subpaths.last().map(|s| s.closed).unwrap_or(true) {
+            self.move_to(current);
+        }
+
+        self.subpaths.last_mut().unwrap().curves.push(curve);
+        self.current = end;
+        self.last_cubic = None;
+        self.last_quad = None;
+    }
+
+    fn line_to(&mut self, p: Vec2) {
+        self.push(Curve::Line(p), p);
+    }
+
+    fn quad_to(&mut self, c: Vec2, p: Vec2) {
+        self.push(Curve::Quad(c, p), p);
+        self.last_quad = Some(c);
+    }
+
+    fn cubic_to(&mut self, c1: Vec2, c2: Vec2, p: Vec2) {
+        self.push(Curve::Cubic(c1, c2, p), p);
+        self.last_cubic = Some(c2);
+    }
+
+    fn arc_to(&mut self, radii: Vec2, rotation: f64, large: bool, sweep: bool, p: Vec2) {
+        let a = self.current;
+        let (mut rx, mut ry) = (radii.0.abs(), radii.1.abs());
+
+        if a == p {
+            return;
+        }
+        if rx == 0.0 || ry == 0.0 {
+            return self.line_to(p);
+        }
+
+        // Convert from endpoints to centre and angles as in the SVG spec
+        let (sin, cos) = rotation.to_radians().sin_cos();
+        let (dx, dy) = ((a.0 - p.0) / 2.0, (a.1 - p.1) / 2.0);
+        let (x1, y1) = (cos * dx + sin * dy, -sin * dx + cos * dy);
+
+        let lambda = (x1 * x1) / (rx * rx) + (y1 * y1) / (ry * ry);
+        if lambda > 1.0 {
+            rx *= lambda.sqrt();
+            ry *= lambda.sqrt();
+        }
+
+        let numerator = rx * rx * ry * ry - rx * rx * y1 * y1 - ry * ry * x1 * x1;
+        let denominator = rx * rx * y1 * y1 + ry * ry * x1 * x1;
+        let mut k = (numerator / denominator).max(0.0).sqrt();
+        if large == sweep {
+            k = -k;
+        }
+
+        let (cx1, cy1) = (k * rx * y1 / ry, -k * ry * x1 / rx);
+        let (cx, cy) = (cos * cx1 - sin * cy1 + (a.0 + p.0) / 2.0,
+                        sin * cx1 + cos * cy1 + (a.1 + p.1) / 2.0);
+
+        let angle = |u: Vec2, v: Vec2| (u.0 * v.1 - u.1 * v.0).atan2(u.0 * v.0 + u.1 * v.1);
+        let u = ((x1 - cx1) / rx, (y1 - cy1) / ry);
+        let v = ((-x1 - cx1) / rx, (-y1 - cy1) / ry);
+        let start = angle((1.0, 0.0), u);
+        let mut sweep_angle = angle(u, v);
+        if !sweep && sweep_angle > 0.0 {
+            sweep_angle -= 2.0 * PI;
+        } else if sweep && sweep_angle < 0.0 {
+            sweep_angle += 2.0 * PI;
+        }
+
+        // Each piece of up to a quarter turn is approximated by a cubic
+        let n = (sweep_angle.abs() / (PI / 2.0) - 1e-9).ceil().max(1.0) as usize;
+        let step = sweep_angle / n as f64;
+        let handle = 4.0 / 3.0 * (step / 4.0).tan();
+
+        let point = |t: f64| (cx + rx * t.cos() * cos - ry * t.sin() * sin,
+                              cy + rx * t.cos() * sin + ry * t.sin() * cos);
+        let tangent = |t: f64| (-rx * t.sin() * cos - ry * t.cos() * sin,
+                                -rx * t.sin() * sin + ry * t.cos() * cos);
+
+        for i in 0..n {
+            let (t0, t1) = (start + i as f64 * step, start + (i + 1) as f64 * step);
+            let (p0, d0) = (point(t0), tangent(t0));
+            let (p1, d1) = (point(t1), tangent(t1));
+            let end = if i == n - 1 { p } else { p1 };
+
+            self.cubic_to((p0.0 + handle * d0.0, p0.1 + handle * d0.1),
+                          (p1.0 - handle * d1.0, p1.1 - handle * d1.1),
+                          end);
+        }
+    }
+
+    fn close(&mut self) {
+        if let Some(subpath) = self.subpaths.last_mut() {
+            if !subpath.closed {
+                subpath.closed = true;
+                self.current = subpath.start;
+            }
+        }
+        self.last_cubic = None;
+        self.last_quad = None;
+    }
+
+    fn ellipse(&mut self, c: Vec2, rx: f64, ry: f64) {
+        self.move_to((c.0 + rx, c.1));
+        self.arc_to((rx, ry), 0.0, false, true, (c.0 - rx, c.1));
+        self.arc_to((rx, ry), 0.0, false, true, (c.0 + rx, c.1));
+        self.close();
+    }
+}
+
+fn parse_path_data(data: &str, builder: &mut Builder) -> io::Result<()> {
+    let mut numbers = Numbers::new(data);
+    let mut command = None;
+
+    while let Some(c) = numbers.peek() {
+        if (c as char).is_alphabetic() {
+            numbers.pos += 1;
+            command = Some(c);
+        } else if command.is_none() {
+            return Err(invalid("Invalid path data"));
+        }
+
+        let c = command.unwrap();
+        if builder.subpaths.len() == 0 && c != b'M' && c != b'm' {
+            return Err(invalid("Path data must start with a move"));
+        }
+
+        let relative = c >= b'a';
+        let current = builder.current;
+        let base = if relative { current } else { (0.0, 0.0) };
+        let offset = |p: Vec2| (base.0 + p.0, base.1 + p.1);
+        let reflect = |c: Option<Vec2>| match c {
+            Some(c) => (2.0 * current.0 - c.0, 2.0 * current.1 - c.1),
+            None => current
+        };
+
+        match c.to_ascii_uppercase() {
+            b'M' => {
+                builder.move_to(offset(try!(numbers.point())));
+                // Further coordinates are implicit line commands
+                command = Some(if relative { b'l' } else { b'L' });
+            },
+            b'L' => builder.line_to(offset(try!(numbers.point()))),
+            b'H' => {
+                let x = try!(numbers.number());
+                builder.line_to((base.0 + x, current.1));
+            },
+            b'V' => {
+                let y = try!(numbers.number());
+                builder.line_to((current.0, base.1 + y));
+            },
+            b'C' => {
+                let c1 = offset(try!(numbers.point()));
+                let c2 = offset(try!(numbers.point()));
+                let p = offset(try!(numbers.point()));
+                builder.cubic_to(c1, c2, p);
+            },
+            b'S' => {
+                let c1 = reflect(builder.last_cubic);
+                let c2 = offset(try!(numbers.point()));
+                let p = offset(try!(numbers.point()));
+                builder.cubic_to(c1, c2, p);
+            },
+            b'Q' => {
+                let c = offset(try!(numbers.point()));
+                let p = offset(try!(numbers.point()));
+                builder.quad_to(c, p);
+            },
+            b'T' => {
+                let c = reflect(builder.last_quad);
+                let p = offset(try!(numbers.point()));
+                builder.quad_to(c, p);
+            },
+            b'A' => {
+                let radii = try!(numbers.point());
+                let rotation = try!(numbers.number());
+                let large = try!(numbers.flag());
+                let sweep = try!(numbers.flag());
+                let p = offset(try!(numbers.point()));
+                builder.arc_to(radii, rotation, large, sweep, p);
+            },
+            b'Z' => {
+                builder.close();
+                command = None;
+            },
+            _ => return Err(invalid("Invalid path data"))
+        }
+    }
+
+    Ok(())
+}
+
+fn on_curve(p: Vec2) -> Point {
+    Point {
+        location: p,
+        curve_bias: 0.0,
+        weights: NO_WEIGHTS
+    }
+}
+
+fn control(p: Vec2) -> Point {
+    Point {
+        location: p,
+        curve_bias: 0.5,
+        weights: NO_WEIGHTS
+    }
+}
+
+fn cubic_to_quads(p0: Vec2, c1: Vec2, c2: Vec2, p3: Vec2, tolerance: f64, output: &mut Vec<Point>) {
+    // The quadratic through a cubic's end points with control at
+    // (3(c1 + c2) - (p0 + p3)) / 4 is within sqrt(3)/36 |p3 - 3c2 + 3c1 - p0|
+    // of it, and splitting into n pieces divides that by n^3
+    let d = (p3.0 - 3.0 * c2.0 + 3.0 * c1.0 - p0.0, p3.1 - 3.0 * c2.1 + 3.0 * c1.1 - p0.1);
+    let error = (d.0 * d.0 + d.1 * d.1).sqrt() * 3f64.sqrt() / 36.0;
+    let n = (error / tolerance).cbrt().ceil();
+    let n = if n.is_finite() { n.max(1.0).min(64.0) as usize } else { 1 };
+
+    let point = |t: f64| {
+        let s = 1.0 - t;
+        let (a, b, c, d) = (s * s * s, 3.0 * s * s * t, 3.0 * s * t * t, t * t * t);
+        (a * p0.0 + b * c1.0 + c * c2.0 + d * p3.0, a * p0.1 + b * c1.1 + c * c2.1 + d * p3.1)
+    };
+    let tangent = |t: f64| {
+        let s = 1.0 - t;
+        let (a, b, c) = (3.0 * s * s, 6.0 * s * t, 3.0 * t * t);
+        (a * (c1.0 - p0.0) + b * (c2.0 - c1.0) + c * (p3.0 - c2.0),
+         a * (c1.1 - p0.1) + b * (c2.1 - c1.1) + c * (p3.1 - c2.1))
+    };
+
+    let h = 1.0 / n as f64;
+    for i in 0..n {
+        let (t0, t1) = (i as f64 * h, (i + 1) as f64 * h);
+        let (q0, d0) = (point(t0), tangent(t0));
+        let (q3, d3) = (if i == n - 1 { p3 } else { point(t1) }, tangent(t1));
+        let q1 = (q0.0 + d0.0 * h / 3.0, q0.1 + d0.1 * h / 3.0);
+        let q2 = (q3.0 - d3.0 * h / 3.0, q3.1 - d3.1 * h / 3.0);
+
+        output.push(control(((3.0 * (q1.0 + q2.0) - (q0.0 + q3.0)) / 4.0,
+                             (3.0 * (q1.1 + q2.1) - (q0.1 + q3.1)) / 4.0)));
+        output.push(on_curve(q3));
+    }
+}
+
+fn contour(subpath: &Subpath, transform: &Affine2, tolerance: f64) -> Vec<Point> {
+    let t = |p| transform.apply(p);
+    let mut current = t(subpath.start);
+    let mut points = vec![on_curve(current)];
+
+    for curve in &subpath.curves {
+        match *curve {
+            Curve::Line(p) => {
+                current = t(p);
+                points.push(on_curve(current));
+            },
+            Curve::Quad(c, p) => {
+                current = t(p);
+                points.push(control(t(c)));
+                points.push(on_curve(current));
+            },
+            Curve::Cubic(c1, c2, p) => {
+                let start = current;
+                current = t(p);
+                cubic_to_quads(start, t(c1), t(c2), current, tolerance, &mut points);
+            }
+        }
+    }
+
+    // A closed outline returns to its start implicitly
+    if subpath.closed && points.len() > 1 && points[points.len() - 1].location == points[0].location {
+        points.pop();
+    }
+
+    points
+}
+
+fn shape(name: &str, attributes: &[(String, String)], style: &Style, tolerance: f64)
+         -> io::Result<Option<Path>> {
+    let stroke = if style.stroke_width > 0.0 { style.stroke } else { None };
+    if style.fill.is_none() && stroke.is_none() {
+        return Ok(None);
+    }
+
+    let number = |name| length_attribute(attributes, name).map(|n| n.unwrap_or(0.0));
+    let mut builder = Builder::new();
+
+    match name {
+        "path" => try!(parse_path_data(attribute(attributes, "d").unwrap_or(""), &mut builder)),
+        "rect" => {
+            let (x, y) = (try!(number("x")), try!(number("y")));
+            let (w, h) = (try!(number("width")), try!(number("height")));
+            if w <= 0.0 || h <= 0.0 {
+                return Ok(None);
+            }
+
+            let (rx, ry) = match (try!(length_attribute(attributes, "rx")),
+                                  try!(length_attribute(attributes, "ry"))) {
+                (Some(rx), Some(ry)) => (rx, ry),
+                (Some(r), None) | (None, Some(r)) => (r, r),
+                (None, None) => (0.0, 0.0)
+            };
+            let (rx, ry) = (rx.max(0.0).min(w / 2.0), ry.max(0.0).min(h / 2.0));
+
+            builder.move_to((x + rx, y));
+            builder.line_to((x + w - rx, y));
+            builder.arc_to((rx, ry), 0.0, false, true, (x + w, y + ry));
+            builder.line_to((x + w, y + h - ry));
+            builder.arc_to((rx, ry), 0.0, false, true, (x + w - rx, y + h));
+            builder.line_to((x + rx, y + h));
+            builder.arc_to((rx, ry), 0.0, false, true, (x, y + h - ry));
+            builder.line_to((x, y + ry));
+            builder.arc_to((rx, ry), 0.0, false, true, (x + rx, y));
+            builder.close();
+        },
+        "circle" => {
+            let r = try!(number("r"));
+            if r > 0.0 {
+                builder.ellipse((try!(number("cx")), try!(number("cy"))), r, r);
+            }
+        },
+        "ellipse" => {
+            let (rx, ry) = (try!(number("rx")), try!(number("ry")));
+            if rx > 0.0 && ry > 0.0 {
+                builder.ellipse((try!(number("cx")), try!(number("cy"))), rx, ry);
+            }
+        },
+        "line" => {
+            builder.move_to((try!(number("x1")), try!(number("y1"))));
+            builder.line_to((try!(number("x2")), try!(number("y2"))));
+        },
+        _ => {
+            let mut numbers = Numbers::new(attribute(attributes, "points").unwrap_or(""));
+            let mut first = true;
+            while numbers.peek().is_some() {
+                let p = try!(numbers.point());
+                if first {
+                    builder.move_to(p);
+                    first = false;
+                } else {
+                    builder.line_to(p);
+                }
+            }
+
+            if name == "polygon" {
+                builder.close();
+            }
+        }
+    }
+
+    let closed = builder.subpaths.iter().all(|s| s.closed);
+    let mut contours = builder.subpaths.iter()
+        .filter(|s| s.curves.len() > 0)
+        .map(|s| contour(s, &style.transform, tolerance))
+        .collect::<Vec<_>>();
+
+    if contours.len() == 0 {
+        return Ok(None);
+    }
+
+    // Stroke widths scale with the transform's average stretch
+    let scale = style.transform.stretch();
+
+    Ok(Some(Path {
+        name: None,
+        paint: Paint::solid(style.fill.unwrap_or((0.0, 0.0, 0.0))),
+        points: contours.remove(0),
+        contours: contours,
+        fill_rule: style.fill_rule,
+        filled: style.fill.is_some(),
+        stroke: stroke.map(|colour| Stroke {
+            colour: colour,
+            width: style.stroke_width * scale,
+            join: style.join,
+            cap: style.cap,
+            dashes: style.dashes.iter().map(|d| d * scale).collect(),
+            closed: closed
+        })
+    }))
+}
+
+// Writes each path of a model as an SVG <path> of exact quadratic béziers,
+// flipping the y-axis back to point down.
+pub fn write_svg(model: &Model, output: &mut Write) -> io::Result<()> {
+    // Instances are drawn as copies of their symbols' paths
+    let resolved = try!(model.resolve().ok_or(io::Error::new(io::ErrorKind::InvalidData,
+                                                             "Missing or recursive symbol")));
+    let model = &resolved;
+    if !model.has_valid_clips() {
+        return Err(io::Error::new(io::ErrorKind::InvalidData, "Clip of missing path"));
+    }
+    let (min, max) = extent(model).unwrap_or(((0.0, 0.0), (0.0, 0.0)));
+
+    try!(writeln!(output,
+                  "<svg xmlns=\"http://www.w3.org/2000/svg\" width=\"{w}\" height=\"{h}\" \
+                   viewBox=\"{x} {y} {w} {h}\">",
+                  x = min.0, y = -max.1 + 0.0, w = max.0 - min.0, h = max.1 - min.1));
+
+    for (i, clip) in model.paths.iter().enumerate().filter(|&(i, _)| model.is_clip_path(i)) {
+        try!(write!(output, "  <clipPath id=\"clip{}\"><path d=\"{}\"", i, outline_data(&path_segments(clip), true)));
+        if clip.fill_rule == FillRule::EvenOdd {
+            try!(write!(output, " clip-rule=\"evenodd\""));
+        }
+        try!(writeln!(output, "/></clipPath>"));
+    }
+
+    for (i, path) in model.paths.iter().enumerate() {
+        let outlines = path_segments(path);
+        if outlines.len() == 0 || (!path.filled && path.stroke.is_none()) || !model.is_visible(i) ||
+            model.is_clip_path(i) {
+            continue;
+        }
+
+        let clips = model.clip_paths(i);
+        for clip in &clips {
+            try!(writeln!(output, "  <g clip-path=\"url(#clip{})\">", clip));
+        }
+
+        // An open stroke leaves out each closing segment, which the fill
+        // would draw straight rather than curved, so curved ones need the
+        // fill separately
+        let open = path.stroke.as_ref().map(|s| !s.closed).unwrap_or(false);
+        let curved = outlines.iter().any(|s| s[s.len() - 1].control.is_some());
+        let lines = if open { stroke_segments(path, false) } else { outlines.clone() };
+
+        if open && path.filled && curved {
+            try!(write_svg_path(output, &outlines, true, path, true, None));
+            try!(write_svg_path(output, &lines, false, path, false, path.stroke.as_ref()));
+        } else {
+            try!(write_svg_path(output, &lines, !open, path, path.filled, path.stroke.as_ref()));
+        }
+
+        for _ in &clips {
+            try!(writeln!(output, "  </g>"));
+        }
+    }
+
+    try!(writeln!(output, "</svg>"));
+    Ok(())
+}
+
+fn outline_data(outlines: &[Vec<Segment>], closed: bool) -> String {
+    let coordinates = |p: Vec2| format!("{} {}", p.0, -p.1 + 0.0);
+    let mut data = String::new();
+
+    for segments in outlines {
+        data.push_str(&format!("M{}", coordinates(segments[0].start)));
+
+        for (i, segment) in segments.iter().enumerate() {
+            let end = segments[(i + 1) % segments.len()].start;
+            let last = i == segments.len() - 1;
+
+            match segment.control {
+                _ if last && !closed => (),
+                Some(c) => data.push_str(&format!(" Q{} {}", coordinates(c), coordinates(end))),
+                None if last => (),
+                None => data.push_str(&format!(" L{}", coordinates(end)))
+            }
+        }
+
+        if closed {
+            data.push_str(" Z");
+        }
+    }
+
+    data
+}
+
+fn write_svg_path(output: &mut Write, outlines: &[Vec<Segment>], closed: bool,
+                  path: &Path, filled: bool, stroke: Option<&Stroke>) -> io::Result<()> {
+    let data = outline_data(outlines, closed);
+
+    // Gradients are written with the paint's flat colour
+    let (r, g, b, a) = path.paint.flat_colour();
+    let fill = if filled { hex_colour((r, g, b)) } else { "none".to_string() };
+    try!(write!(output, "  <path d=\"{}\" fill=\"{}\"", data, fill));
+
+    if filled && a != 1.0 {
+        try!(write!(output, " fill-opacity=\"{}\"", a));
+    }
+
+    if filled && path.fill_rule == FillRule::EvenOdd {
+        try!(write!(output, " fill-rule=\"evenodd\""));
+    }
+
+    if let Some(stroke) = stroke {
+        try!(write!(output, " stroke=\"{}\" stroke-width=\"{}\" stroke-linejoin=\"{}\" stroke-linecap=\"{}\"",
+                    hex_colour(stroke.colour),
+                    stroke.width,
+                    match stroke.join {
+                        LineJoin::Miter => "miter",
+                        LineJoin::Round => "round",
+                        LineJoin::Bevel => "bevel"
+                    },
+                    match stroke.cap {
+                        LineCap::Butt => "butt",
+                        LineCap::Round => "round",
+                        LineCap::Square => "square"
+                    }));
+
+        if stroke.dashes.len() > 0 {
+            let dashes = stroke.dashes.iter().map(|d| d.to_string()).collect::<Vec<_>>();
+            try!(write!(output, " stroke-dasharray=\"{}\"", dashes.join(" ")));
+        }
+    }
+
+    try!(writeln!(output, "/>"));
+    Ok(())
+}
+
+fn hex_colour(colour: Vec3) -> String {
+    let channel = |c: f64| (c.max(0.0).min(1.0) * 255.0).round() as u8;
+    format!("#{:02x}{:02x}{:02x}", channel(colour.0), channel(colour.1), channel(colour.2))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Cursor;
+    use data::{BinaryReader, BinaryWriter};
+    use model::{Model, FillRule, LineJoin, LineCap};
+    use model::tessellate::{Segment, outline_segments, path_segments, path_triangles};
+
+    fn read(svg: &str) -> Model {
+        read_svg(&mut Cursor::new(svg.as_bytes()), 0.01).unwrap()
+    }
+
+    fn locations(points: &[Point]) -> Vec<Vec2> {
+        points.iter().map(|p| p.location).collect()
+    }
+
+    fn close(a: Vec2, b: Vec2, tolerance: f64) -> bool {
+        ((a.0 - b.0).powi(2) + (a.1 - b.1).powi(2)).sqrt() <= tolerance
+    }
+
+    fn sample(segments: &[Segment]) -> Vec<Vec2> {
+        let mut samples = Vec::new();
+        for (i, s) in segments.iter().enumerate() {
+            let b = segments[(i + 1) % segments.len()].start;
+            let c = s.control.unwrap_or(s.start);
+            for k in 0..20 {
+                let t = k as f64 / 20.0;
+                let u = 1.0 - t;
+                samples.push((u * u * s.start.0 + 2.0 * u * t * c.0 + t * t * b.0,
+                              u * u * s.start.1 + 2.0 * u * t * c.1 + t * t * b.1));
+            }
+        }
+        samples
+    }
+
+    #[test]
+    fn shapes() {
+        let model = read(r##"<?xml version="1.0"?>
+            <!DOCTYPE svg>
+            <svg xmlns="http://www.w3.org/2000/svg" width="100" height="100">
+              <!-- <rect/> -->
+              <rect x="1" y="2" width="3" height="4" fill="#ff0000"/>
+              <polygon points="0,0 10,0 10,10" fill="rgb(0, 255, 0)"/>
+              <circle cx="5" cy="5" r="2" fill="blue"></circle>
+              <ellipse cx="0" cy="0" rx="4" ry="1" style="fill: #00f"/>
+            </svg>"##);
+
+        assert_eq!(model.paths.len(), 4);
+        assert_eq!(model.paths[0].paint, Paint::solid((1.0, 0.0, 0.0)));
+        assert_eq!(locations(&model.paths[0].points),
+                   vec![(1.0, -2.0), (4.0, -2.0), (4.0, -6.0), (1.0, -6.0)]);
+        assert_eq!(model.paths[1].paint, Paint::solid((0.0, 1.0, 0.0)));
+        assert_eq!(locations(&model.paths[1].points),
+                   vec![(0.0, 0.0), (10.0, 0.0), (10.0, -10.0)]);
+
+        for path in &model.paths[2..] {
+            assert_eq!(path.paint, Paint::solid((0.0, 0.0, 1.0)));
+        }
+
+        for p in sample(&outline_segments(&model.paths[2].points)) {
+            let r = ((p.0 - 5.0).powi(2) + (p.1 + 5.0).powi(2)).sqrt();
+            assert!((r - 2.0).abs() < 0.01);
+        }
+
+        for p in sample(&outline_segments(&model.paths[3].points)) {
+            let r = (p.0 * p.0 / 16.0 + p.1 * p.1).sqrt();
+            assert!((r - 1.0).abs() < 0.01);
+        }
+    }
+
+    #[test]
+    fn path_data() {
+        let model = read(r##"<svg>
+              <path d="M0 0 L10 0 l0 10 H5 v-5 h-5z"/>
+              <path d="m 0,0 q 5,5 10,0 t 10,0 Z"/>
+              <path d="M1-1.5.5.5e1 3 4"/>
+            </svg>"##);
+
+        assert_eq!(locations(&model.paths[0].points),
+                   vec![(0.0, 0.0), (10.0, 0.0), (10.0, -10.0), (5.0, -10.0), (5.0, -5.0), (0.0, -5.0)]);
+        assert_eq!(model.paths[0].paint, Paint::solid((0.0, 0.0, 0.0)));
+
+        let quads = &model.paths[1].points;
+        assert_eq!(locations(quads),
+                   vec![(0.0, 0.0), (5.0, -5.0), (10.0, 0.0), (15.0, 5.0), (20.0, 0.0)]);
+        assert_eq!(quads.iter().map(|p| p.curve_bias > 0.0).collect::<Vec<_>>(),
+                   vec![false, true, false, true, false]);
+
+        assert_eq!(locations(&model.paths[2].points),
+                   vec![(1.0, 1.5), (0.5, -5.0), (3.0, -4.0)]);
+
+        for data in &["L 0 0", "M 0", "M 0 0 L 1 x", "M 0 0 A 1 1 0 2 0 1 1", "0 0"] {
+            let svg = format!(r##"<svg><path d="{}"/></svg>"##, data);
+            assert!(read_svg(&mut Cursor::new(svg.as_bytes()), 0.01).is_err());
+        }
+    }
+
+    #[test]
+    fn cubics() {
+        let model = read(r##"<svg><path d="M 0 0 C 0 10 10 10 10 0 S 20 -10 20 0 Z"/></svg>"##);
+        let segments = outline_segments(&model.paths[0].points);
+        assert!(segments.len() > 2);
+
+        let cubic = |p0: Vec2, c1: Vec2, c2: Vec2, p3: Vec2, t: f64| {
+            let s = 1.0 - t;
+            (s * s * s * p0.0 + 3.0 * s * s * t * c1.0 + 3.0 * s * t * t * c2.0 + t * t * t * p3.0,
+             s * s * s * p0.1 + 3.0 * s * s * t * c1.1 + 3.0 * s * t * t * c2.1 + t * t * t * p3.1)
+        };
+        let exact = (0..201)
+            .flat_map(|i| {
+                let t = i as f64 / 200.0;
+                vec![cubic((0.0, 0.0), (0.0, -10.0), (10.0, -10.0), (10.0, 0.0), t),
+                     cubic((10.0, 0.0), (10.0, 10.0), (20.0, 10.0), (20.0, 0.0), t)]
+            })
+            .collect::<Vec<_>>();
+
+        for p in sample(&segments) {
+            if p.1 == 0.0 {
+                continue;
+            }
+            // Allow for the spacing of the samples along the exact curve
+            assert!(exact.iter().any(|&q| close(p, q, 0.05)));
+        }
+    }
+
+    #[test]
+    fn arcs() {
+        let model = read(r##"<svg>
+              <path d="M 0 0 A 5 5 0 0 1 10 0 Z"/>
+              <path d="M 0 0 A 5 5 0 1 0 10 0 Z"/>
+              <path d="M 0 0 A 1 1 0 0 0 10 0"/>
+              <path d="M 0 0 A 0 5 0 0 0 10 0"/>
+            </svg>"##);
+
+        // Half circles above and below the x-axis after flipping
+        for (path, sign) in model.paths[..3].iter().zip(&[1.0, -1.0, -1.0]) {
+            for p in sample(&outline_segments(&path.points)) {
+                if p.1 == 0.0 {
+                    continue;
+                }
+                let r = ((p.0 - 5.0).powi(2) + p.1.powi(2)).sqrt();
+                assert!((r - 5.0).abs() < 0.01);
+                assert!(p.1 * sign > 0.0);
+            }
+        }
+
+        assert_eq!(locations(&model.paths[3].points), vec![(0.0, 0.0), (10.0, 0.0)]);
+    }
+
+    #[test]
+    fn groups() {
+        let model = read(r##"<svg>
+              <g transform="translate(10, 0) scale(2)" fill="red" stroke="#000">
+                <rect width="1" height="1" stroke-width="0.5"/>
+                <g style="fill:none;stroke-dasharray:1 2" transform="rotate(90)">
+                  <polyline points="0 0 1 0" stroke-linecap="round" stroke-linejoin="bevel"/>
+                </g>
+                <rect width="1" height="1" display="none"/>
+                <defs><rect width="1" height="1"/></defs>
+                <svg:rect width="1" height="1" fill-rule="evenodd"><title>A</title></svg:rect>
+              </g>
+              <rect width="1" height="1"/>
+            </svg>"##);
+
+        assert_eq!(model.paths.len(), 4);
+
+        let rect = &model.paths[0];
+        assert_eq!(rect.paint, Paint::solid((1.0, 0.0, 0.0)));
+        assert_eq!(locations(&rect.points), vec![(10.0, 0.0), (12.0, 0.0), (12.0, -2.0), (10.0, -2.0)]);
+        let stroke = rect.stroke.as_ref().unwrap();
+        assert_eq!(stroke.width, 1.0);
+        assert!(stroke.closed);
+
+        let line = &model.paths[1];
+        assert!(!line.filled);
+        assert_eq!(locations(&line.points), vec![(10.0, 0.0), (10.0, -2.0)]);
+        let stroke = line.stroke.as_ref().unwrap();
+        assert_eq!(stroke.width, 2.0);
+        assert_eq!(stroke.dashes, vec![2.0, 4.0]);
+        assert_eq!(stroke.cap, LineCap::Round);
+        assert_eq!(stroke.join, LineJoin::Bevel);
+        assert!(!stroke.closed);
+
+        assert_eq!(model.paths[2].fill_rule, FillRule::EvenOdd);
+        assert_eq!(model.paths[3].paint, Paint::solid((0.0, 0.0, 0.0)));
+        assert_eq!(model.paths[3].stroke, None);
+    }
+
+    #[test]
+    fn compound() {
+        let model = read(r##"<svg>
+              <path fill-rule="evenodd" d="M0 0h10v10h-10z M2 2h6v6h-6z m1 1h1v1h-1"/>
+            </svg>"##);
+
+        let path = &model.paths[0];
+        assert_eq!(path.fill_rule, FillRule::EvenOdd);
+        assert_eq!(path.points.len(), 4);
+        assert_eq!(path.contours.len(), 2);
+        assert_eq!(locations(&path.contours[1]), vec![(3.0, -3.0), (4.0, -3.0), (4.0, -4.0), (3.0, -4.0)]);
+    }
+
+    #[test]
+    fn errors() {
+        let bad = [
+            r##"<svg><rect width="1" height="1" fill="hsl(0, 0%, 0%)"/></svg>"##,
+            r##"<svg><rect width="1" height="1" fill="url(#gradient)"/></svg>"##,
+            r##"<svg><rect width="1%" height="1"/></svg>"##,
+            r##"<svg><rect width="1" height="1" transform="spin(3)"/></svg>"##,
+            r##"<svg><rect width="1" height="1"/></svg></svg>"##,
+            r##"<svg><rect width="1" height="1"##,
+            r##"<svg><rect width="1" height="1" fill="#é0"/></svg>"##,
+            r##"<svg><rect width="1" height="1" fill="#00000é"/></svg>"##
+        ];
+
+        for svg in bad.iter() {
+            assert!(read_svg(&mut Cursor::new(svg.as_bytes()), 0.01).is_err());
+        }
+
+        let model = read(r##"<svg><rect width="1" height="1" fill="url(#gradient) #fff"/></svg>"##);
+        assert_eq!(model.paths[0].paint, Paint::solid((1.0, 1.0, 1.0)));
+    }
+
+    #[test]
+    fn write_model() {
+        let model = read(r##"<svg><circle r="10" fill="none" stroke="red" stroke-width="2mm"/></svg>"##);
+
+        let mut writer = BinaryWriter::new(Vec::new());
+        model.write(&mut writer).unwrap();
+        let data = writer.into_inner();
+
+        let read = Model::read(&mut BinaryReader::new(Cursor::new(&data[..]))).unwrap();
+        assert_eq!(read.paths.len(), 1);
+        assert_eq!(read.paths[0].points.len(), model.paths[0].points.len());
+        assert_eq!(read.paths[0].stroke, model.paths[0].stroke);
+    }
+
+    fn cat() -> Model {
+        let bytes = include_bytes!("../bin/cat.model");
+        Model::read(&mut BinaryReader::new(Cursor::new(&bytes[..]))).unwrap()
+    }
+
+    #[test]
+    fn export() {
+        let model = cat();
+        let mut output = Vec::new();
+        write_svg(&model, &mut output).unwrap();
+
+        // Quadratics are written exactly, so the segments survive a round trip
+        let read = read_svg(&mut Cursor::new(&output[..]), 0.01).unwrap();
+        assert_eq!(read.paths.len(), model.paths.len());
+
+        for (a, b) in model.paths.iter().zip(&read.paths) {
+            assert_eq!(path_segments(a), path_segments(b));
+            let (c, d) = (a.paint.flat_colour(), b.paint.flat_colour());
+            assert_eq!(hex_colour((c.0, c.1, c.2)), hex_colour((d.0, d.1, d.2)));
+        }
+    }
+
+    #[test]
+    fn export_matches_tessellation() {
+        // Every curve the tessellator draws is one of the exported quadratics
+        for path in &cat().paths {
+            let segments = path_segments(path);
+            let curves = segments.iter()
+                .flat_map(|outline| outline.iter().enumerate().filter_map(move |(i, s)| {
+                    s.control.map(|c| (s.start, c, outline[(i + 1) % outline.len()].start))
+                }))
+                .collect::<Vec<_>>();
+
+            let triangles = path_triangles(path).into_iter()
+                .filter(|t| t[1].param.0 == 0.5)
+                .map(|t| (t[0].position, t[1].position, t[2].position))
+                .collect::<Vec<_>>();
+
+            assert!(triangles.len() > 0);
+            assert_eq!(triangles.len(), curves.len());
+            for triangle in &triangles {
+                assert!(curves.contains(triangle));
+            }
+        }
+    }
+
+    #[test]
+    fn export_strokes() {
+        let model = read(r##"<svg>
+              <path d="M0 0 Q5 5 10 0 Q5 -5 0 0 Z" fill="red" stroke="blue" stroke-linejoin="round"/>
+              <path d="M0 0 Q5 5 10 0 Q5 -5 0 0" stroke="blue" stroke-dasharray="1 2"/>
+              <path d="M0 0 L10 0" fill="none" stroke="blue" stroke-linecap="square"/>
+            </svg>"##);
+
+        let mut output = Vec::new();
+        write_svg(&model, &mut output).unwrap();
+        let svg = String::from_utf8(output).unwrap();
+        let lines = svg.lines().collect::<Vec<_>>();
+
+        assert_eq!(lines[1], "  <path d=\"M0 0 Q5 5 10 0 Q5 -5 0 0 Z\" fill=\"#ff0000\" \
+                              stroke=\"#0000ff\" stroke-width=\"1\" stroke-linejoin=\"round\" stroke-linecap=\"butt\"/>");
+
+        // The open stroke leaves out the closing curve, so the fill is drawn
+        // on its own
+        assert_eq!(lines[2], "  <path d=\"M0 0 Q5 5 10 0 Q5 -5 0 0 Z\" fill=\"#000000\"/>");
+        assert_eq!(lines[3], "  <path d=\"M0 0 Q5 5 10 0 Q5 -5 0 0\" fill=\"none\" \
+                              stroke=\"#0000ff\" stroke-width=\"1\" stroke-linejoin=\"miter\" stroke-linecap=\"butt\" \
+                              stroke-dasharray=\"1 2\"/>");
+        assert_eq!(lines[4], "  <path d=\"M0 0 L10 0\" fill=\"none\" \
+                              stroke=\"#0000ff\" stroke-width=\"1\" stroke-linejoin=\"miter\" stroke-linecap=\"square\"/>");
+    }
+}
@@ -0,0 +1,346 @@
+use std::io;
+use data::{Value, Tag, Reader, Writer};
+use super::{Model, Path, Paint, Affine2, NAME, PATHS, PAINT, INSTANCES, read_paths, read_instances, read_string,
+            write_string};
+
+// Paths that can be placed any number of times in a model, each time with
+// its own transform, e.g. an icon
+#[derive(Clone)]
+pub struct Symbol {
+    pub name: String,
+    pub paths: Vec<Path>,
+    // Placements of other symbols, drawn above the paths
+    pub instances: Vec<Instance>
+}
+
+// A placement of a symbol, by index into the model's symbols
+#[derive(Clone, PartialEq, Debug)]
+pub struct Instance {
+    pub symbol: usize,
+    // Maps the symbol's space to the space the instance is in
+    pub transform: Affine2,
+    // Replaces the paint of every filled path in the symbol, given in the
+    // symbol's space
+    pub paint: Option<Paint>
+}
+
+const SYMBOL: Tag = tag!(S Y M B);
+const TRANSFORM: Tag = tag!(X F R M);
+
+impl Model {
+    pub fn find_symbol(&self, name: &str) -> Option<usize> {
+        self.symbols.iter().position(|symbol| symbol.name == name)
+    }
+
+    // Whether every instance refers to a symbol that exists, and no symbol
+    // contains itself however deeply
+    pub fn has_valid_symbols(&self) -> bool {
+        // 0 for unvisited, 1 while a symbol's instances are being visited
+        // and 2 once they're known to be fine
+        fn visit(symbols: &[Symbol], instances: &[Instance], state: &mut [u8]) -> bool {
+            instances.iter().all(|instance| {
+                match state.get(instance.symbol).cloned() {
+                    None | Some(1) => false,
+                    Some(2) => true,
+                    _ => {
+                        state[instance.symbol] = 1;
+                        let valid = visit(symbols, &symbols[instance.symbol].instances, state);
+                        state[instance.symbol] = 2;
+                        valid
+                    }
+                }
+            })
+        }
+
+        let mut state = vec![0; self.symbols.len()];
+        visit(&self.symbols, &self.instances, &mut state) &&
+            (0..self.symbols.len()).all(|i| visit(&self.symbols, &self.symbols[i].instances, &mut state))
+    }
+
+    // The model with its instances replaced by copies of their symbols'
+    // paths, placed above its own paths in order. Gives None if a symbol is
+    // missing or contains itself.
+    pub fn resolve(&self) -> Option<Model> {
+        if !self.has_valid_symbols() {
+            return None;
+        }
+
+        let mut resolved = self.clone();
+        for instance in &self.instances {
+            resolved.paths.extend(self.instance_paths(instance));
+        }
+        resolved.symbols.clear();
+        resolved.instances.clear();
+
+        Some(resolved)
+    }
+
+    // The paths an instance draws, in the space it's in. The symbols must
+    // be valid.
+    fn instance_paths(&self, instance: &Instance) -> Vec<Path> {
+        let symbol = &self.symbols[instance.symbol];
+        let mut paths = symbol.paths.clone();
+        for inner in &symbol.instances {
+            paths.extend(self.instance_paths(inner));
+        }
+
+        for path in &mut paths {
+            if let Some(ref paint) = instance.paint {
+                if path.filled {
+                    path.paint = paint.clone();
+                }
+            }
+            path.transform(&instance.transform);
+        }
+
+        paths
+    }
+}
+
+impl Symbol {
+    pub fn write(&self, writer: &mut Writer) -> io::Result<()> {
+        try!(writer.write_start());
+
+        try!(write_string(writer, NAME, &self.name));
+
+        try!(writer.write_start());
+        try!(writer.write_value(&Value::Tag(PATHS)));
+        try!(writer.write_value(&Value::Int(self.paths.len() as i32)));
+        for path in &self.paths {
+            try!(path.write(writer));
+        }
+        try!(writer.write_end());
+
+        if self.instances.len() > 0 {
+            try!(Instance::write_all(writer, &self.instances));
+        }
+
+        writer.write_end()
+    }
+
+    pub fn read(reader: &mut Reader) -> io::Result<Symbol> {
+        let mut symbol = Symbol {
+            name: String::new(),
+            paths: Vec::new(),
+            instances: Vec::new()
+        };
+
+        try!(reader.expect_start());
+
+        while try!(reader.expect_start_or_end()) {
+            match try!(reader.expect_tag()) {
+                NAME => symbol.name = try!(read_string(reader)),
+                PATHS => symbol.paths = try!(read_paths(reader)),
+                INSTANCES => symbol.instances = try!(read_instances(reader)),
+                _ => try!(reader.skip_to_end())
+            }
+        }
+
+        Ok(symbol)
+    }
+}
+
+impl Instance {
+    // Writes a group of instances with its tag
+    pub fn write_all(writer: &mut Writer, instances: &[Instance]) -> io::Result<()> {
+        try!(writer.write_start());
+        try!(writer.write_value(&Value::Tag(INSTANCES)));
+        try!(writer.write_value(&Value::Int(instances.len() as i32)));
+
+        for instance in instances {
+            try!(instance.write(writer));
+        }
+
+        writer.write_end()
+    }
+
+    pub fn write(&self, writer: &mut Writer) -> io::Result<()> {
+        try!(writer.write_start());
+
+        try!(writer.write_start());
+        try!(writer.write_value(&Value::Tag(SYMBOL)));
+        try!(writer.write_value(&Value::Int(self.symbol as i32)));
+        try!(writer.write_end());
+
+        try!(writer.write_start());
+        try!(writer.write_value(&Value::Tag(TRANSFORM)));
+        try!(writer.write_value(&Value::DoubleArray(self.transform.matrix.to_vec().into_boxed_slice())));
+        try!(writer.write_end());
+
+        if let Some(ref paint) = self.paint {
+            try!(writer.write_start());
+            try!(writer.write_value(&Value::Tag(PAINT)));
+            try!(paint.write(writer));
+            try!(writer.write_end());
+        }
+
+        writer.write_end()
+    }
+
+    pub fn read(reader: &mut Reader) -> io::Result<Instance> {
+        let mut symbol = None;
+        let mut instance = Instance {
+            symbol: 0,
+            transform: Affine2::identity(),
+            paint: None
+        };
+
+        try!(reader.expect_start());
+
+        while try!(reader.expect_start_or_end()) {
+            match try!(reader.expect_tag()) {
+                SYMBOL => {
+                    let index = try!(reader.expect_int());
+                    if index < 0 {
+                        return Err(io::Error::new(io::ErrorKind::InvalidData, "Unexpected data"));
+                    }
+                    symbol = Some(index as usize);
+                    try!(reader.skip_to_end());
+                },
+                TRANSFORM => {
+                    let matrix = try!(reader.expect_double_array());
+                    if matrix.len() != 6 {
+                        return Err(io::Error::new(io::ErrorKind::InvalidData, "Unexpected data"));
+                    }
+                    instance.transform.matrix.copy_from_slice(&matrix);
+                    try!(reader.skip_to_end());
+                },
+                PAINT => {
+                    instance.paint = Some(try!(Paint::read(reader)));
+                    try!(reader.skip_to_end());
+                },
+                _ => try!(reader.skip_to_end())
+            }
+        }
+
+        match symbol {
+            Some(symbol) => Ok(Instance { symbol: symbol, .. instance }),
+            None => Err(io::Error::new(io::ErrorKind::InvalidData, "Unexpected data"))
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Cursor;
+    use data::{BinaryReader, BinaryWriter, TextReader, TextWriter};
+    use model::{Model, Path, Paint, Affine2, path};
+    use model::tessellate::{model_triangles, path_triangles};
+
+    fn square(x: f64, y: f64) -> Path {
+        path(&[(x, y, 0.0), (x + 1.0, y, 0.0), (x + 1.0, y + 1.0, 0.0), (x, y + 1.0, 0.0)])
+    }
+
+    fn instance(symbol: usize, x: f64, y: f64) -> Instance {
+        Instance { symbol: symbol, transform: Affine2::translate(x, y), paint: None }
+    }
+
+    // A button made of a square with a dot on it, placed twice
+    fn buttons() -> Model {
+        let dot = Symbol { name: "dot".to_string(), paths: vec![square(0.0, 0.0)], instances: Vec::new() };
+        let button = Symbol {
+            name: "button".to_string(),
+            paths: vec![square(0.0, 0.0)],
+            instances: vec![Instance { paint: Some(Paint::solid((1.0, 0.0, 0.0))), .. instance(0, 0.5, 0.5) }]
+        };
+
+        Model {
+            paths: vec![square(-5.0, 0.0)],
+            symbols: vec![dot, button],
+            instances: vec![
+                instance(1, 10.0, 0.0),
+                Instance { paint: Some(Paint::solid((0.0, 0.0, 1.0))), .. instance(1, 20.0, 0.0) }
+            ],
+            .. Default::default()
+        }
+    }
+
+    #[test]
+    fn resolve() {
+        let model = buttons();
+        assert!(model.has_valid_symbols());
+        assert_eq!(model.find_symbol("button"), Some(1));
+
+        let resolved = model.resolve().unwrap();
+        assert_eq!(resolved.instances.len(), 0);
+        assert_eq!(resolved.paths.len(), 5);
+        assert_eq!(resolved.paths[1].points[0].location, (10.0, 0.0));
+        assert_eq!(resolved.paths[2].points[0].location, (10.5, 0.5));
+        assert_eq!(resolved.paths[2].paint, Paint::solid((1.0, 0.0, 0.0)));
+
+        // The outer override wins over the inner one
+        assert_eq!(resolved.paths[3].paint, Paint::solid((0.0, 0.0, 1.0)));
+        assert_eq!(resolved.paths[4].paint, Paint::solid((0.0, 0.0, 1.0)));
+        assert_eq!(resolved.paths[4].points[2].location, (21.5, 1.5));
+    }
+
+    #[test]
+    fn invalid_symbols() {
+        let mut model = buttons();
+        model.instances.push(instance(2, 0.0, 0.0));
+        assert!(!model.has_valid_symbols());
+        assert!(model.resolve().is_none());
+
+        let mut model = buttons();
+        model.symbols[0].instances.push(instance(1, 0.0, 0.0));
+        assert!(!model.has_valid_symbols());
+
+        // A symbol can be placed many times as long as it doesn't contain
+        // itself
+        let mut model = buttons();
+        model.symbols[1].instances.push(instance(0, 0.0, 0.0));
+        assert!(model.has_valid_symbols());
+        model.symbols[0].instances.push(instance(0, 0.0, 0.0));
+        assert!(!model.has_valid_symbols());
+
+        // Unused symbols are checked too
+        let mut model = buttons();
+        model.instances.clear();
+        model.symbols[1].instances[0].symbol = 1;
+        assert!(!model.has_valid_symbols());
+    }
+
+    #[test]
+    fn triangles() {
+        // Reused triangles match tessellating the resolved paths
+        let model = buttons();
+        let resolved = model.resolve().unwrap();
+        let triangles = model_triangles(&model);
+        assert_eq!(triangles.len(), 5);
+
+        for (i, path) in triangles.iter().enumerate() {
+            assert_eq!(path.path, i);
+            let expected = path_triangles(&resolved.paths[i]);
+            assert_eq!(path.triangles.len(), expected.len());
+            for (a, b) in path.triangles.iter().zip(&expected) {
+                for (p, q) in a.iter().zip(b) {
+                    assert!((p.position.0 - q.position.0).abs() < 1e-9 && (p.position.1 - q.position.1).abs() < 1e-9);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn write_and_read() {
+        let model = buttons();
+        let mut writer = BinaryWriter::new(Vec::new());
+        model.write(&mut writer).unwrap();
+        let data = writer.into_inner();
+
+        let read = Model::read(&mut BinaryReader::new(Cursor::new(&data[..]))).unwrap();
+        assert_eq!(read.instances, model.instances);
+        assert_eq!(read.symbols.len(), 2);
+        assert_eq!(read.symbols[1].name, "button");
+        assert_eq!(read.symbols[1].instances, model.symbols[1].instances);
+        assert_eq!(read.resolve().unwrap().paths.len(), 5);
+
+        // Symbols that contain themselves are rejected
+        let mut model = buttons();
+        model.symbols[0].instances.push(instance(1, 0.0, 0.0));
+        let mut output = Vec::new();
+        model.write(&mut TextWriter::new(&mut output)).unwrap();
+        let mut reader = TextReader::new(Cursor::new(&output[..]));
+        assert_eq!(Model::read(&mut reader).err().unwrap().kind(), io::ErrorKind::InvalidData);
+    }
+}
@@ -0,0 +1,914 @@
+use data::{Vec2, Vec3};
+use super::{Model, Path, Point, FillRule, Stroke, Instance};
+use super::stroke::stroke_contours;
+
+// Each vertex carries the curve parameters (u, v, sign) used by the fragment
+// shader: a point is filled when sign * (u * u - v) >= 0. Inner triangles use
+// (0, 1, -1) so they are always filled.
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub struct Vertex {
+    pub position: Vec2,
+    pub param: Vec3
+}
+
+pub type Triangle = [Vertex; 3];
+
+#[derive(PartialEq, Debug)]
+pub struct PathTriangles {
+    pub path: usize,
+    pub triangles: Vec<Triangle>,
+    pub stroke: Vec<Triangle>,
+    // The areas of the paths clipping this one, which it's only drawn inside
+    pub clips: Vec<Vec<Triangle>>
+}
+
+const INNER: Vec3 = (0.0, 1.0, -1.0);
+
+// Triangles for each visible path, then for the paths of each instance,
+// numbered as in the resolved model. Each symbol is tessellated once however
+// often it's placed, so strokes are scaled with their instances rather than
+// widened evenly. Instances are left out if the symbols aren't valid, and
+// clip paths are only drawn as clips.
+pub fn model_triangles(model: &Model) -> Vec<PathTriangles> {
+    let valid_clips = model.has_valid_clips();
+    let mut clips = model.paths.iter().map(|_| None).collect::<Vec<_>>();
+
+    let mut output = Vec::new();
+    for (i, path) in model.paths.iter().enumerate() {
+        if !model.is_visible(i) || (valid_clips && model.is_clip_path(i)) {
+            continue;
+        }
+
+        let mut triangles = single_path_triangles(i, path);
+        if valid_clips {
+            for clip in model.clip_paths(i) {
+                if clips[clip].is_none() {
+                    clips[clip] = Some(clip_triangles(&model.paths[clip]));
+                }
+                triangles.clips.push(clips[clip].clone().unwrap());
+            }
+        }
+
+        output.push(triangles);
+    }
+
+    if model.has_valid_symbols() {
+        let mut prepared = model.symbols.iter().map(|_| None).collect::<Vec<_>>();
+        let mut first = model.paths.len();
+        for instance in &model.instances {
+            first += place_instance(model, instance, first, &mut prepared, &mut output);
+        }
+    }
+
+    output
+}
+
+fn single_path_triangles(index: usize, path: &Path) -> PathTriangles {
+    PathTriangles {
+        path: index,
+        triangles: path_triangles(path),
+        stroke: match path.stroke {
+            Some(ref stroke) => stroke_triangles(path, stroke),
+            None => Vec::new()
+        },
+        clips: Vec::new()
+    }
+}
+
+// A symbol's triangles in its own space, with how many paths it resolves to
+type PreparedSymbol = (usize, Vec<PathTriangles>);
+
+// Adds the triangles of an instance, numbering its paths from first, and
+// gives the number of paths
+fn place_instance(model: &Model, instance: &Instance, first: usize, prepared: &mut Vec<Option<PreparedSymbol>>,
+                  output: &mut Vec<PathTriangles>) -> usize {
+    if prepared[instance.symbol].is_none() {
+        let symbol = &model.symbols[instance.symbol];
+        let mut triangles = symbol.paths.iter()
+            .enumerate()
+            .map(|(i, path)| single_path_triangles(i, path))
+            .collect::<Vec<_>>();
+
+        let mut count = symbol.paths.len();
+        for inner in &symbol.instances {
+            count += place_instance(model, inner, count, prepared, &mut triangles);
+        }
+
+        prepared[instance.symbol] = Some((count, triangles));
+    }
+
+    let moved = |triangles: &[Triangle]| -> Vec<Triangle> {
+        triangles.iter()
+            .map(|triangle| {
+                let mut triangle = *triangle;
+                for vertex in &mut triangle {
+                    vertex.position = instance.transform.apply(vertex.position);
+                }
+                triangle
+            })
+            .collect()
+    };
+
+    let &(count, ref triangles) = prepared[instance.symbol].as_ref().unwrap();
+    output.extend(triangles.iter().map(|path| PathTriangles {
+        path: first + path.path,
+        triangles: moved(&path.triangles),
+        stroke: moved(&path.stroke),
+        clips: path.clips.iter().map(|clip| moved(clip)).collect()
+    }));
+
+    count
+}
+
+pub fn path_triangles(path: &Path) -> Vec<Triangle> {
+    if path.filled { clip_triangles(path) } else { Vec::new() }
+}
+
+// The area inside a path's outlines, whether or not it's filled
+pub fn clip_triangles(path: &Path) -> Vec<Triangle> {
+    let contours = Some(&path.points[..]).into_iter()
+        .chain(path.contours.iter().map(|c| &c[..]))
+        .collect::<Vec<_>>();
+
+    fill_triangles(&contours, path.fill_rule)
+}
+
+// The exact quadratic outlines of a path's points and contours, as drawn by
+// the shader, skipping any that are empty
+pub fn path_segments(path: &Path) -> Vec<Vec<Segment>> {
+    stroke_segments(path, true)
+}
+
+// The outlines as a stroke follows them, where open ones run from the first
+// point to the last without joining them
+pub fn stroke_segments(path: &Path, closed: bool) -> Vec<Vec<Segment>> {
+    Some(&path.points).into_iter()
+        .chain(path.contours.iter())
+        .map(|points| segments(points, closed))
+        .filter(|segments| segments.len() > 0)
+        .collect()
+}
+
+pub fn stroke_triangles(path: &Path, stroke: &Stroke) -> Vec<Triangle> {
+    let contours = stroke_contours(path, stroke);
+    let contours = contours.iter().map(|c| &c[..]).collect::<Vec<_>>();
+
+    fill_triangles(&contours, FillRule::NonZero)
+}
+
+// Tessellates any number of closed contours together, so that they can
+// overlap, cross themselves or cut holes in each other according to the
+// fill rule. Curved edges are added on top of the filled polygon.
+pub fn fill_triangles(contours: &[&[Point]], fill_rule: FillRule) -> Vec<Triangle> {
+    let mut output = Vec::new();
+    let mut edges = Vec::new();
+
+    let outlines = contours.iter()
+        .map(|points| outline_segments(points))
+        .collect::<Vec<_>>();
+    let chords = outlines.iter()
+        .map(|outline| outline.iter().map(|s| s.start).collect::<Vec<_>>())
+        .collect::<Vec<_>>();
+
+    for outline in &outlines {
+        let filled_left = is_filled_left(outline, &chords, fill_rule);
+        let polygon = build_curve_triangles(outline, filled_left, &mut output);
+        add_edges(&polygon, &mut edges);
+    }
+
+    build_inner_triangles(&edges, fill_rule, &mut output);
+
+    output
+}
+
+#[derive(Copy, Clone)]
+struct Node {
+    location: Vec2,
+    control: bool
+}
+
+// A straight edge, or a quadratic bézier curve if there is a control point,
+// from start to the start of the next segment
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub struct Segment {
+    pub start: Vec2,
+    pub control: Option<Vec2>
+}
+
+struct Edge {
+    x0: f64, y0: f64,
+    x1: f64, y1: f64,
+    winding: i32
+}
+
+fn vertex(position: Vec2, param: Vec3) -> Vertex {
+    Vertex {
+        position: position,
+        param: param
+    }
+}
+
+// Converts a closed outline of points into segments, inserting the implied
+// on-curve points and dropping unusable and coincident points
+pub fn outline_segments(points: &[Point]) -> Vec<Segment> {
+    segments(points, true)
+}
+
+// As outline_segments for an open line, whose last segment only marks where
+// it ends, so a line can end where it started
+pub fn line_segments(points: &[Point]) -> Vec<Segment> {
+    segments(points, false)
+}
+
+fn segments(points: &[Point], closed: bool) -> Vec<Segment> {
+    let points = points.iter()
+        .filter(|p| p.location.0.is_finite() && p.location.1.is_finite())
+        .collect::<Vec<_>>();
+
+    // Consecutive control points have an implied on-curve point between
+    // them, placed according to the first one's curve bias, which is kept
+    // between them
+    let mut nodes = Vec::with_capacity(points.len() * 2);
+    for (i, point) in points.iter().enumerate() {
+        let next = points[(i + 1) % points.len()];
+
+        nodes.push(Node {
+            location: point.location,
+            control: point.curve_bias > 0.0
+        });
+
+        if !closed && i == points.len() - 1 {
+            break;
+        }
+
+        if point.curve_bias > 0.0 && next.curve_bias > 0.0 {
+            let (a, b) = (point.location, next.location);
+            let t = point.curve_bias.min(1.0);
+
+            nodes.push(Node {
+                location: (a.0 + t * (b.0 - a.0), a.1 + t * (b.1 - a.1)),
+                control: false
+            });
+        }
+    }
+
+    // Drop coincident neighbours, keeping on-curve points over controls and
+    // otherwise the outline's start
+    let mut i = 0;
+    let end = |len: usize| if closed { len } else { len - 1 };
+    while nodes.len() > 1 && i < end(nodes.len()) {
+        let j = (i + 1) % nodes.len();
+        if nodes[i].location == nodes[j].location {
+            let remove = if nodes[i].control || (j == 0 && !nodes[j].control) { i } else { j };
+            nodes.remove(remove);
+            if remove < i {
+                i -= 1;
+            }
+        } else {
+            i += 1;
+        }
+    }
+
+    let first = match nodes.iter().position(|n| !n.control) {
+        Some(first) => first,
+        None => return Vec::new()
+    };
+
+    // Open lines start at their first on-curve point, rather than wrapping
+    // round to it
+    let count = if closed { nodes.len() } else { nodes.len() - first };
+    let mut segments: Vec<Segment> = Vec::with_capacity(nodes.len());
+    for k in 0..count {
+        let node = nodes[(first + k) % nodes.len()];
+        if node.control {
+            if let Some(last) = segments.last_mut() {
+                last.control = Some(node.location);
+            }
+        } else {
+            segments.push(Segment {
+                start: node.location,
+                control: None
+            });
+        }
+    }
+
+    segments
+}
+
+pub fn signed_area(points: &[Vec2]) -> f64 {
+    let mut area = 0.0;
+    for (i, p) in points.iter().enumerate() {
+        let q = points[(i + 1) % points.len()];
+        area += p.0 * q.1 - q.0 * p.1;
+    }
+
+    area / 2.0
+}
+
+pub fn winding(polygon: &[Vec2], p: Vec2) -> i32 {
+    let mut winding = 0;
+    for (i, &a) in polygon.iter().enumerate() {
+        let b = polygon[(i + 1) % polygon.len()];
+
+        if a.1 <= p.1 && b.1 > p.1 && vec2_cross(a, b, p) > 0.0 {
+            winding += 1;
+        } else if a.1 > p.1 && b.1 <= p.1 && vec2_cross(a, b, p) < 0.0 {
+            winding -= 1;
+        }
+    }
+
+    winding
+}
+
+// Works out which side of an outline is filled, by testing points either
+// side of its longest chord against all of the contours. This lets holes and
+// clockwise paths curve the right way.
+fn is_filled_left(outline: &[Segment], chords: &[Vec<Vec2>], fill_rule: FillRule) -> bool {
+    let starts = outline.iter().map(|s| s.start).collect::<Vec<_>>();
+    let all = outline.iter()
+        .flat_map(|s| Some(s.start).into_iter().chain(s.control))
+        .collect::<Vec<_>>();
+    let orientation = signed_area(&all) > 0.0;
+
+    let longest = (0..starts.len())
+        .map(|i| (starts[i], starts[(i + 1) % starts.len()]))
+        .max_by(|&(a1, b1), &(a2, b2)| {
+            vec2_distance(a1, b1).partial_cmp(&vec2_distance(a2, b2)).unwrap()
+        });
+
+    let (a, b) = match longest {
+        Some((a, b)) if a != b => (a, b),
+        _ => return orientation
+    };
+
+    let mid = ((a.0 + b.0) / 2.0, (a.1 + b.1) / 2.0);
+    let e = 1e-6;
+    let left = (mid.0 - e * (b.1 - a.1), mid.1 + e * (b.0 - a.0));
+    let right = (mid.0 + e * (b.1 - a.1), mid.1 - e * (b.0 - a.0));
+
+    let inside = |p: Vec2| {
+        let w = chords.iter().map(|c| winding(c, p)).fold(0, |a, b| a + b);
+        fill_rule.is_inside(w)
+    };
+
+    match (inside(left), inside(right)) {
+        (true, false) => true,
+        (false, true) => false,
+        _ => orientation
+    }
+}
+
+// Emits a triangle for each curved segment and returns the polygon to fill
+// underneath. Curves bulging out of the filled side are filled outside the
+// chord, while curves bulging in keep their control point in the polygon and
+// fill the part of the notch on the control point's side.
+fn build_curve_triangles(outline: &[Segment], filled_left: bool, output: &mut Vec<Triangle>) -> Vec<Vec2> {
+    let mut polygon = Vec::with_capacity(outline.len() * 2);
+
+    for (i, segment) in outline.iter().enumerate() {
+        let p1 = segment.start;
+        let p3 = outline[(i + 1) % outline.len()].start;
+        polygon.push(p1);
+
+        let p2 = match segment.control {
+            Some(p2) => p2,
+            None => continue
+        };
+
+        let cross = vec2_cross(p1, p2, p3);
+        if cross == 0.0 || p1 == p3 {
+            continue;
+        }
+
+        let control_left = cross < 0.0;
+        let sign = if control_left != filled_left {
+            -1.0
+        } else {
+            polygon.push(p2);
+            1.0
+        };
+
+        output.push([
+            vertex(p1, (0.0, 0.0, sign)),
+            vertex(p2, (0.5, 0.0, sign)),
+            vertex(p3, (1.0, 1.0, sign)),
+        ]);
+    }
+
+    polygon
+}
+
+fn add_edges(polygon: &[Vec2], edges: &mut Vec<Edge>) {
+    for (i, &p) in polygon.iter().enumerate() {
+        let q = polygon[(i + 1) % polygon.len()];
+
+        if p.1 < q.1 {
+            edges.push(Edge { x0: p.0, y0: p.1, x1: q.0, y1: q.1, winding: 1 });
+        } else if p.1 > q.1 {
+            edges.push(Edge { x0: q.0, y0: q.1, x1: p.0, y1: p.1, winding: -1 });
+        }
+    }
+}
+
+impl Edge {
+    fn x_at(&self, y: f64) -> f64 {
+        if y <= self.y0 {
+            self.x0
+        } else if y >= self.y1 {
+            self.x1
+        } else {
+            self.x0 + (y - self.y0) * (self.x1 - self.x0) / (self.y1 - self.y0)
+        }
+    }
+
+    fn crossing(&self, other: &Edge) -> Option<f64> {
+        let (dx1, dy1) = (self.x1 - self.x0, self.y1 - self.y0);
+        let (dx2, dy2) = (other.x1 - other.x0, other.y1 - other.y0);
+        let denom = dx1 * dy2 - dy1 * dx2;
+        if denom == 0.0 {
+            return None;
+        }
+
+        let (ex, ey) = (other.x0 - self.x0, other.y0 - self.y0);
+        let t = (ex * dy2 - ey * dx2) / denom;
+        let u = (ex * dy1 - ey * dx1) / denom;
+
+        if t > 0.0 && t < 1.0 && u > 0.0 && u < 1.0 {
+            Some(self.y0 + t * dy1)
+        } else {
+            None
+        }
+    }
+}
+
+impl FillRule {
+    pub fn is_inside(self, winding: i32) -> bool {
+        match self {
+            FillRule::NonZero => winding != 0,
+            FillRule::EvenOdd => winding % 2 != 0
+        }
+    }
+}
+
+// Splits the plane into horizontal slabs at every vertex and crossing, so no
+// edges cross within a slab, then fills the spans that are inside according
+// to the fill rule with a trapezoid each. Edges are swept from the bottom, so
+// only those overlapping vertically are compared.
+fn build_inner_triangles(edges: &[Edge], fill_rule: FillRule, output: &mut Vec<Triangle>) {
+    let mut order = (0..edges.len()).collect::<Vec<_>>();
+    order.sort_by(|&a, &b| edges[a].y0.partial_cmp(&edges[b].y0).unwrap());
+
+    let mut ys = Vec::with_capacity(edges.len() * 2);
+    for (k, &i) in order.iter().enumerate() {
+        let edge = &edges[i];
+        ys.push(edge.y0);
+        ys.push(edge.y1);
+
+        for &j in order[k + 1..].iter().take_while(|&&j| edges[j].y0 < edge.y1) {
+            let crossing = if i < j { edge.crossing(&edges[j]) } else { edges[j].crossing(edge) };
+            if let Some(y) = crossing {
+                ys.push(y);
+            }
+        }
+    }
+
+    ys.sort_by(|a, b| a.partial_cmp(b).unwrap());
+    ys.dedup();
+
+    // Every edge either spans a slab or misses it, so the edges spanning one
+    // are those that start at or below it and end above its bottom
+    let mut next = 0;
+    let mut spanning = Vec::new();
+    let mut active = Vec::new();
+
+    for w in ys.windows(2) {
+        let (y0, y1) = (w[0], w[1]);
+        let mid = (y0 + y1) / 2.0;
+
+        while next < order.len() && edges[order[next]].y0 <= y0 {
+            spanning.push(order[next]);
+            next += 1;
+        }
+        spanning.retain(|&i| edges[i].y1 > y0);
+
+        active.clear();
+        active.extend(spanning.iter()
+            .map(|&i| (edges[i].x_at(mid), i, edges[i].x_at(y0), edges[i].x_at(y1), edges[i].winding)));
+        active.sort_by(|a, b| (a.0, a.1).partial_cmp(&(b.0, b.1)).unwrap());
+
+        let mut winding = 0;
+        let mut left = (0.0, 0.0);
+
+        for &(_, _, x0, x1, w) in &active {
+            let was_inside = fill_rule.is_inside(winding);
+            winding += w;
+            let inside = fill_rule.is_inside(winding);
+
+            if !was_inside && inside {
+                left = (x0, x1);
+            } else if was_inside && !inside {
+                let (l0, l1) = left;
+
+                if x0 != l0 {
+                    output.push([
+                        vertex((l0, y0), INNER),
+                        vertex((x0, y0), INNER),
+                        vertex((x1, y1), INNER),
+                    ]);
+                }
+                if x1 != l1 {
+                    output.push([
+                        vertex((l0, y0), INNER),
+                        vertex((x1, y1), INNER),
+                        vertex((l1, y1), INNER),
+                    ]);
+                }
+            }
+        }
+    }
+}
+
+fn vec2_cross(a: Vec2, b: Vec2, c: Vec2) -> f64 {
+    (b.0 - a.0) * (c.1 - a.1) - (b.1 - a.1) * (c.0 - a.0)
+}
+
+fn vec2_distance(a: Vec2, b: Vec2) -> f64 {
+    ((b.0 - a.0) * (b.0 - a.0) + (b.1 - a.1) * (b.1 - a.1)).sqrt()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use super::{INNER, vec2_cross};
+    use super::super::{Model, Point, FillRule, NO_WEIGHTS, path};
+    use rand::{Rng, SeedableRng, XorShiftRng};
+
+    fn points(points: &[(f64, f64, f64)]) -> Vec<Point> {
+        points.iter()
+            .map(|&(x, y, b)| Point {
+                location: (x, y),
+                curve_bias: b,
+                weights: NO_WEIGHTS
+            })
+            .collect()
+    }
+
+    fn area(t: &Triangle) -> f64 {
+        vec2_cross(t[0].position, t[1].position, t[2].position).abs() / 2.0
+    }
+
+    fn inner_area(triangles: &[Triangle]) -> f64 {
+        triangles.iter()
+            .filter(|t| t.iter().all(|v| v.param == INNER))
+            .map(area)
+            .sum()
+    }
+
+    fn is_curve(t: &Triangle) -> bool {
+        t[0].param.0 == 0.0 && t[0].param.1 == 0.0 &&
+            t[1].param.0 == 0.5 && t[1].param.1 == 0.0 &&
+            t[2].param.0 == 1.0 && t[2].param.1 == 1.0 &&
+            t[0].param.2 == t[1].param.2 && t[1].param.2 == t[2].param.2
+    }
+
+    fn coverage(triangles: &[Triangle], p: Vec2) -> usize {
+        triangles.iter()
+            .filter(|t| {
+                let a = vec2_cross(t[0].position, t[1].position, p);
+                let b = vec2_cross(t[1].position, t[2].position, p);
+                let c = vec2_cross(t[2].position, t[0].position, p);
+                (a > 0.0 && b > 0.0 && c > 0.0) || (a < 0.0 && b < 0.0 && c < 0.0)
+            })
+            .count()
+    }
+
+    fn winding(points: &[Point], p: Vec2) -> i32 {
+        let mut winding = 0;
+        for (i, a) in points.iter().enumerate() {
+            let a = a.location;
+            let b = points[(i + 1) % points.len()].location;
+
+            if a.1 <= p.1 && b.1 > p.1 && vec2_cross(a, b, p) > 0.0 {
+                winding += 1;
+            } else if a.1 > p.1 && b.1 <= p.1 && vec2_cross(a, b, p) < 0.0 {
+                winding -= 1;
+            }
+        }
+
+        winding
+    }
+
+    fn near_edge(points: &[Point], p: Vec2) -> bool {
+        points.iter().enumerate().any(|(i, a)| {
+            let a = a.location;
+            let b = points[(i + 1) % points.len()].location;
+            let (dx, dy) = (b.0 - a.0, b.1 - a.1);
+            let length = (dx * dx + dy * dy).sqrt();
+            let t = ((p.0 - a.0) * dx + (p.1 - a.1) * dy) / (length * length);
+
+            length == 0.0 || (t > -0.01 && t < 1.01 &&
+                              (vec2_cross(a, b, p) / length).abs() < 1e-6)
+        })
+    }
+
+    #[test]
+    fn square() {
+        let triangles = path_triangles(&path(&[
+            (0.0, 0.0, 0.0), (1.0, 0.0, 0.0), (1.0, 1.0, 0.0), (0.0, 1.0, 0.0)
+        ]));
+
+        assert_eq!(triangles.len(), 2);
+        assert_eq!(inner_area(&triangles), 1.0);
+
+        let clockwise = path_triangles(&path(&[
+            (0.0, 0.0, 0.0), (0.0, 1.0, 0.0), (1.0, 1.0, 0.0), (1.0, 0.0, 0.0)
+        ]));
+
+        assert_eq!(inner_area(&clockwise), 1.0);
+    }
+
+    #[test]
+    fn concave() {
+        // An L shape with area 3, whose reflex vertex lies on the diagonal
+        let triangles = path_triangles(&path(&[
+            (0.0, 0.0, 0.0), (2.0, 0.0, 0.0), (2.0, 1.0, 0.0),
+            (1.0, 1.0, 0.0), (1.0, 2.0, 0.0), (0.0, 2.0, 0.0)
+        ]));
+
+        assert_eq!(inner_area(&triangles), 3.0);
+        assert_eq!(coverage(&triangles, (1.5, 1.5)), 0);
+        assert_eq!(coverage(&triangles, (0.3, 1.6)), 1);
+
+        let triangles = path_triangles(&path(&[
+            (0.0, 0.0, 0.0), (2.0, 1.0, 0.0), (4.0, 0.0, 0.0), (2.0, 4.0, 0.0)
+        ]));
+
+        assert_eq!(inner_area(&triangles), 6.0);
+        assert_eq!(coverage(&triangles, (2.0, 0.5)), 0);
+    }
+
+    #[test]
+    fn curved() {
+        // A square with one edge bulging out and one bulging in
+        let triangles = path_triangles(&path(&[
+            (0.0, 0.0, 0.0), (0.5, -0.5, 0.5), (1.0, 0.0, 0.0),
+            (1.0, 1.0, 0.0), (0.5, 0.7, 0.5), (0.0, 1.0, 0.0)
+        ]));
+
+        let curves = triangles.iter().filter(|t| is_curve(t)).collect::<Vec<_>>();
+        assert_eq!(curves.len(), 2);
+        assert_eq!(curves[0][1].position, (0.5, -0.5));
+        assert_eq!(curves[0][0].param.2, -1.0);
+        assert_eq!(curves[1][1].position, (0.5, 0.7));
+        assert_eq!(curves[1][0].param.2, 1.0);
+
+        // The outward control point is left out of the inner polygon, while
+        // the inward one is kept and its curve triangle fills the notch
+        assert!((inner_area(&triangles) - 0.85).abs() < 1e-9);
+
+        // The same outline drawn clockwise gives the same curves
+        let reversed = path_triangles(&path(&[
+            (0.0, 1.0, 0.0), (0.5, 0.7, 0.5), (1.0, 1.0, 0.0),
+            (1.0, 0.0, 0.0), (0.5, -0.5, 0.5), (0.0, 0.0, 0.0)
+        ]));
+
+        let signs = reversed.iter()
+            .filter(|t| is_curve(t))
+            .map(|t| (t[1].position, t[0].param.2))
+            .collect::<Vec<_>>();
+        assert_eq!(signs, vec![((0.5, 0.7), 1.0), ((0.5, -0.5), -1.0)]);
+        assert!((inner_area(&reversed) - 0.85).abs() < 1e-9);
+    }
+
+    #[test]
+    fn consecutive_curves() {
+        // Consecutive control points get an implied on-curve point between
+        // them, so a circle-like path of four control points has four curves
+        let triangles = path_triangles(&path(&[
+            (0.0, 0.0, 0.5), (1.0, 0.0, 0.5), (1.0, 1.0, 0.5), (0.0, 1.0, 0.5)
+        ]));
+
+        assert_eq!(triangles.iter().filter(|t| is_curve(t)).count(), 4);
+        assert_eq!(inner_area(&triangles), 0.5);
+    }
+
+    #[test]
+    fn open_lines() {
+        // A line ending where it started keeps its last segment, and leading
+        // controls don't wrap round to the end
+        let line = points(&[(0.0, 0.0, 0.0), (5.0, 5.0, 0.5), (10.0, 0.0, 0.0), (5.0, -5.0, 0.5), (0.0, 0.0, 0.0)]);
+        let segments = line_segments(&line);
+        assert_eq!(segments.len(), 3);
+        assert_eq!(segments[1].control, Some((5.0, -5.0)));
+        assert_eq!(segments[2].start, (0.0, 0.0));
+        assert_eq!(outline_segments(&line).len(), 2);
+
+        let line = points(&[(5.0, 5.0, 0.5), (0.0, 0.0, 0.0), (10.0, 0.0, 0.0)]);
+        let segments = line_segments(&line);
+        assert_eq!(segments.len(), 2);
+        assert_eq!(segments[0].start, (0.0, 0.0));
+        assert_eq!(segments[0].control, None);
+    }
+
+    #[test]
+    fn degenerate() {
+        assert_eq!(path_triangles(&path(&[])).len(), 0);
+        assert_eq!(path_triangles(&path(&[(0.0, 0.0, 0.0)])).len(), 0);
+        assert_eq!(path_triangles(&path(&[(0.0, 0.0, 0.5)])).len(), 0);
+        assert_eq!(path_triangles(&path(&[(0.0, 0.0, 0.0), (1.0, 0.0, 0.0)])).len(), 0);
+
+        let identical = path_triangles(&path(&[
+            (0.0, 0.0, 0.0), (0.0, 0.0, 0.0), (0.0, 0.0, 0.0), (0.0, 0.0, 0.0)
+        ]));
+        assert_eq!(identical.len(), 0);
+
+        let identical_curves = path_triangles(&path(&[
+            (1.0, 1.0, 0.5), (1.0, 1.0, 0.5), (1.0, 1.0, 0.5), (1.0, 1.0, 0.5)
+        ]));
+        assert_eq!(identical_curves.len(), 0);
+
+        let collinear = path_triangles(&path(&[
+            (0.0, 0.0, 0.0), (1.0, 0.0, 0.0), (2.0, 0.0, 0.0), (3.0, 0.0, 0.0)
+        ]));
+        assert_eq!(collinear.len(), 0);
+
+        let flat_curve = path_triangles(&path(&[
+            (0.0, 0.0, 0.0), (1.0, 0.0, 0.5), (2.0, 0.0, 0.0), (1.0, 1.0, 0.0)
+        ]));
+        assert_eq!(flat_curve.iter().filter(|t| is_curve(t)).count(), 0);
+        assert_eq!(inner_area(&flat_curve), 1.0);
+
+        let duplicates = path_triangles(&path(&[
+            (0.0, 0.0, 0.0), (0.0, 0.0, 0.0), (1.0, 0.0, 0.0), (1.0, 0.0, 0.5),
+            (1.0, 1.0, 0.0), (0.0, 1.0, 0.0), (0.0, 0.0, 0.0)
+        ]));
+        assert_eq!(inner_area(&duplicates), 1.0);
+
+        let not_finite = path_triangles(&path(&[
+            (0.0, 0.0, 0.0), (1.0, 0.0, 0.0), (::std::f64::NAN, 0.5, 0.0),
+            (1.0, 1.0, 0.0), (0.0, ::std::f64::INFINITY, 0.0), (0.0, 1.0, 0.0)
+        ]));
+        assert_eq!(inner_area(&not_finite), 1.0);
+    }
+
+    #[test]
+    fn self_intersecting() {
+        // A bow tie made of two triangles of area 1/4 each
+        let bow_tie = path_triangles(&path(&[
+            (0.0, 0.0, 0.0), (1.0, 1.0, 0.0), (1.0, 0.0, 0.0), (0.0, 1.0, 0.0)
+        ]));
+        assert!((inner_area(&bow_tie) - 0.5).abs() < 1e-9);
+        assert_eq!(coverage(&bow_tie, (0.2, 0.45)), 1);
+        assert_eq!(coverage(&bow_tie, (0.5, 0.25)), 0);
+
+        // A pentagram's centre is filled with non-zero but not with even-odd
+        let star = (0..5)
+            .map(|i| {
+                let a = i as f64 * 4.0 * ::std::f64::consts::PI / 5.0;
+                Point { location: (a.sin(), a.cos()), curve_bias: 0.0, weights: NO_WEIGHTS }
+            })
+            .collect::<Vec<_>>();
+
+        let non_zero = fill_triangles(&[&star[..]], FillRule::NonZero);
+        let even_odd = fill_triangles(&[&star[..]], FillRule::EvenOdd);
+
+        assert_eq!(coverage(&non_zero, (0.01, 0.02)), 1);
+        assert_eq!(coverage(&even_odd, (0.01, 0.02)), 0);
+        assert_eq!(coverage(&non_zero, (0.01, 0.85)), 1);
+        assert_eq!(coverage(&even_odd, (0.01, 0.85)), 1);
+        assert!(inner_area(&non_zero) > inner_area(&even_odd));
+    }
+
+    #[test]
+    fn holes() {
+        let outer = points(&[(0.0, 0.0, 0.0), (2.0, 0.0, 0.0), (2.0, 2.0, 0.0), (0.0, 2.0, 0.0)]);
+        let hole = points(&[(0.5, 0.5, 0.0), (0.5, 1.5, 0.0), (1.5, 1.5, 0.0), (1.5, 0.5, 0.0)]);
+        let same = points(&[(0.5, 0.5, 0.0), (1.5, 0.5, 0.0), (1.5, 1.5, 0.0), (0.5, 1.5, 0.0)]);
+
+        let opposite = fill_triangles(&[&outer[..], &hole[..]], FillRule::NonZero);
+        assert_eq!(inner_area(&opposite), 3.0);
+        assert_eq!(coverage(&opposite, (1.1, 1.2)), 0);
+        assert_eq!(coverage(&opposite, (0.2, 1.2)), 1);
+
+        let non_zero = fill_triangles(&[&outer[..], &same[..]], FillRule::NonZero);
+        assert_eq!(inner_area(&non_zero), 4.0);
+
+        let even_odd = fill_triangles(&[&outer[..], &same[..]], FillRule::EvenOdd);
+        assert_eq!(inner_area(&even_odd), 3.0);
+        assert_eq!(coverage(&even_odd, (1.1, 1.2)), 0);
+
+        let mut path = path(&[(0.0, 0.0, 0.0), (2.0, 0.0, 0.0), (2.0, 2.0, 0.0), (0.0, 2.0, 0.0)]);
+        path.contours.push(same.clone());
+        assert_eq!(inner_area(&path_triangles(&path)), 4.0);
+        path.fill_rule = FillRule::EvenOdd;
+        assert_eq!(inner_area(&path_triangles(&path)), 3.0);
+    }
+
+    #[test]
+    fn curved_holes() {
+        let outer = points(&[(0.0, 0.0, 0.0), (4.0, 0.0, 0.0), (4.0, 4.0, 0.0), (0.0, 4.0, 0.0)]);
+
+        // The bottom edge of the hole bulges down into the fill, and the top
+        // edge bulges up into the hole
+        let hole = points(&[
+            (1.0, 1.0, 0.0), (2.0, 0.5, 0.5), (3.0, 1.0, 0.0),
+            (3.0, 3.0, 0.0), (2.0, 2.5, 0.5), (1.0, 3.0, 0.0)
+        ]);
+
+        for &fill_rule in [FillRule::NonZero, FillRule::EvenOdd].iter() {
+            let mut reversed = hole.clone();
+            reversed.reverse();
+            let hole = if fill_rule == FillRule::NonZero { &reversed } else { &hole };
+
+            let triangles = fill_triangles(&[&outer[..], &hole[..]], fill_rule);
+            let signs = triangles.iter()
+                .filter(|t| is_curve(t))
+                .map(|t| (t[1].position, t[0].param.2))
+                .collect::<Vec<_>>();
+
+            assert_eq!(signs.len(), 2);
+            assert!(signs.contains(&((2.0, 0.5), 1.0)));
+            assert!(signs.contains(&((2.0, 2.5), -1.0)));
+
+            // The bottom control point notches the fill, while the top edge
+            // is filled up to its chord
+            assert!((inner_area(&triangles) - (16.0 - 4.0 - 0.5)).abs() < 1e-9);
+        }
+    }
+
+    #[test]
+    fn random_polygons() {
+        let mut rng = XorShiftRng::from_seed([1, 2, 3, 4]);
+
+        for _ in 0..200 {
+            // Points on a coarse grid, so duplicates, collinear runs and
+            // crossings are common
+            let n = rng.gen_range(0, 10);
+            let points = (0..n)
+                .map(|_| Point {
+                    location: (rng.gen_range(0, 5) as f64, rng.gen_range(0, 5) as f64),
+                    curve_bias: 0.0,
+                    weights: NO_WEIGHTS
+                })
+                .collect::<Vec<_>>();
+
+            for &fill_rule in [FillRule::NonZero, FillRule::EvenOdd].iter() {
+                let triangles = fill_triangles(&[&points[..]], fill_rule);
+
+                for i in 0..40 {
+                    for j in 0..40 {
+                        let p = (i as f64 * 0.1 + 0.0137, j as f64 * 0.1 + 0.0071);
+                        if near_edge(&points, p) {
+                            continue;
+                        }
+
+                        let inside = n > 0 && fill_rule.is_inside(winding(&points, p));
+                        assert_eq!(coverage(&triangles, p), if inside { 1 } else { 0 });
+                    }
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn random_curves() {
+        let mut rng = XorShiftRng::from_seed([4, 3, 2, 1]);
+
+        for _ in 0..500 {
+            let n = rng.gen_range(0, 10);
+            let points = (0..n)
+                .map(|_| Point {
+                    location: (rng.gen_range(0, 3) as f64, rng.gen_range(0, 3) as f64),
+                    curve_bias: if rng.gen() { rng.gen_range(0.0, 1.0) } else { 0.0 },
+                    weights: NO_WEIGHTS
+                })
+                .collect::<Vec<_>>();
+
+            let triangles = fill_triangles(&[&points[..]], FillRule::NonZero);
+            for t in &triangles {
+                for v in t.iter() {
+                    assert!(v.position.0 >= 0.0 && v.position.0 <= 2.0);
+                    assert!(v.position.1 >= 0.0 && v.position.1 <= 2.0);
+                }
+            }
+            assert!(inner_area(&triangles) <= 4.0);
+        }
+    }
+
+    #[test]
+    fn tagged_by_path() {
+        let model = Model {
+            paths: vec![
+                path(&[(0.0, 0.0, 0.0), (1.0, 0.0, 0.0), (0.0, 1.0, 0.0)]),
+                path(&[]),
+                path(&[(0.0, 0.0, 0.0), (2.0, 0.0, 0.0), (2.0, 2.0, 0.0), (0.0, 2.0, 0.0)]),
+            ],
+            .. Default::default()
+        };
+
+        let paths = model_triangles(&model);
+        assert_eq!(paths.iter().map(|p| p.path).collect::<Vec<_>>(), vec![0, 1, 2]);
+        assert_eq!(paths.iter().map(|p| inner_area(&p.triangles)).collect::<Vec<_>>(),
+                   vec![0.5, 0.0, 4.0]);
+    }
+}
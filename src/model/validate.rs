@@ -0,0 +1,369 @@
+use std::mem;
+use data::Vec2;
+use super::{Model, Path, Point};
+use super::simplify::flatten_outline;
+use super::stroke::has_fine_dashes;
+use super::tessellate::{signed_area, winding};
+
+// A problem with a path's points. The outline is 0 for the path's points and
+// i + 1 for contours[i], and the path is one of a symbol's if there's a
+// symbol.
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub struct Issue {
+    pub symbol: Option<usize>,
+    pub path: usize,
+    pub outline: usize,
+    pub point: Option<usize>,
+    pub problem: Problem
+}
+
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub enum Problem {
+    // Too few distinct points to draw: three for a fill, two for a stroke
+    TooFewPoints,
+    NonFinite,
+    // A curve bias outside [0, 1] or not a number
+    CurveBias,
+    // A point in the same place as the next, so the segment between is empty
+    ZeroLength,
+    // An outline winding the same way as the one it's a hole in, or an outer
+    // one winding clockwise
+    Orientation,
+    SelfIntersection,
+    // A dash pattern too short to draw, which is drawn solid
+    FineDashes
+}
+
+// Curves are flattened this finely to find their orientation and crossings
+const FLATNESS: f64 = 0.01;
+
+impl Model {
+    // Every problem with the paths, the model's and then the symbols'
+    pub fn validate(&self) -> Vec<Issue> {
+        let mut issues = Vec::new();
+
+        for (i, path) in self.paths.iter().enumerate() {
+            path_issues(path, None, i, &mut issues);
+        }
+
+        for (s, symbol) in self.symbols.iter().enumerate() {
+            for (i, path) in symbol.paths.iter().enumerate() {
+                path_issues(path, Some(s), i, &mut issues);
+            }
+        }
+
+        issues
+    }
+
+    // Fixes every problem but self intersections. Unusable points and
+    // outlines are dropped, then paths left empty, except clip paths, which
+    // still hide what they clip. Holes are reversed to wind against what
+    // they're in, so with the non-zero rule they're cut out.
+    pub fn repair(&mut self) {
+        let mut kept = Vec::with_capacity(self.paths.len());
+        let mut paths = Vec::with_capacity(self.paths.len());
+
+        for (i, mut path) in mem::replace(&mut self.paths, Vec::new()).into_iter().enumerate() {
+            if repair_path(&mut path) || self.is_clip_path(i) {
+                kept.push(Some(paths.len()));
+                paths.push(path);
+            } else {
+                kept.push(None);
+            }
+        }
+        self.paths = paths;
+
+        let remap = |indices: &[usize]| -> Vec<usize> {
+            indices.iter().filter_map(|&i| kept.get(i).and_then(|&k| k)).collect()
+        };
+        for layer in &mut self.layers {
+            layer.paths = remap(&layer.paths);
+        }
+        for clip in &mut self.clips {
+            if let Some(&Some(path)) = kept.get(clip.path) {
+                clip.path = path;
+            }
+            clip.paths = remap(&clip.paths);
+        }
+
+        for symbol in &mut self.symbols {
+            let paths = mem::replace(&mut symbol.paths, Vec::new());
+            symbol.paths = paths.into_iter().filter_map(|mut path| {
+                if repair_path(&mut path) { Some(path) } else { None }
+            }).collect();
+        }
+    }
+}
+
+fn path_issues(path: &Path, symbol: Option<usize>, index: usize, issues: &mut Vec<Issue>) {
+    let mut polygons = Vec::new();
+
+    for (j, points) in outlines(path).iter().enumerate() {
+        let issue = |point, problem| Issue { symbol: symbol, path: index, outline: j, point: point, problem: problem };
+
+        for (k, point) in points.iter().enumerate() {
+            if !is_finite(point.location) {
+                issues.push(issue(Some(k), Problem::NonFinite));
+            }
+            if !(point.curve_bias >= 0.0 && point.curve_bias <= 1.0) {
+                issues.push(issue(Some(k), Problem::CurveBias));
+            }
+        }
+
+        let usable = (0..points.len()).filter(|&k| is_finite(points[k].location)).collect::<Vec<_>>();
+        for (n, &k) in usable.iter().enumerate() {
+            let next = usable[(n + 1) % usable.len()];
+            if next != k && points[k].location == points[next].location {
+                issues.push(issue(Some(k), Problem::ZeroLength));
+            }
+        }
+
+        if repair_points(points).len() < minimum_points(path) {
+            issues.push(issue(None, Problem::TooFewPoints));
+        } else if fills(path) {
+            polygons.push((j, polygon(points)));
+        }
+    }
+
+    let all = polygons.iter().map(|&(_, ref polygon)| polygon).collect::<Vec<_>>();
+    for (n, &(j, ref polygon)) in polygons.iter().enumerate() {
+        let issue = |problem| Issue { symbol: symbol, path: index, outline: j, point: None, problem: problem };
+
+        if crosses_itself(polygon) {
+            issues.push(issue(Problem::SelfIntersection));
+        }
+
+        if is_misoriented(&all, n) {
+            issues.push(issue(Problem::Orientation));
+        }
+    }
+
+    if path.stroke.as_ref().map_or(false, has_fine_dashes) {
+        issues.push(Issue { symbol: symbol, path: index, outline: 0, point: None, problem: Problem::FineDashes });
+    }
+}
+
+// Whether anything of the path is left to draw
+fn repair_path(path: &mut Path) -> bool {
+    if let Some(ref mut stroke) = path.stroke {
+        if has_fine_dashes(stroke) {
+            stroke.dashes.clear();
+        }
+    }
+
+    let minimum = minimum_points(path);
+    let fills = fills(path);
+
+    let mut outlines = Some(mem::replace(&mut path.points, Vec::new())).into_iter()
+        .chain(path.contours.drain(..))
+        .map(|points| repair_points(&points))
+        .filter(|points| points.len() >= minimum)
+        .collect::<Vec<_>>();
+
+    if fills {
+        let polygons = outlines.iter().map(|points| polygon(points)).collect::<Vec<_>>();
+        let polygons = polygons.iter().collect::<Vec<_>>();
+
+        for (j, points) in outlines.iter_mut().enumerate() {
+            if is_misoriented(&polygons, j) {
+                *points = reversed(points);
+            }
+        }
+    }
+
+    if outlines.len() == 0 {
+        return false;
+    }
+
+    path.points = outlines.remove(0);
+    path.contours = outlines;
+    true
+}
+
+// Drops unusable and coincident points, keeping corners over controls as
+// tessellation does, and clamps curve biases
+fn repair_points(points: &[Point]) -> Vec<Point> {
+    let mut points = points.iter()
+        .filter(|p| is_finite(p.location))
+        .map(|p| Point { curve_bias: if p.curve_bias > 0.0 { p.curve_bias.min(1.0) } else { 0.0 }, .. *p })
+        .collect::<Vec<_>>();
+
+    let mut i = 0;
+    while points.len() > 1 && i < points.len() {
+        let j = (i + 1) % points.len();
+        if points[i].location == points[j].location {
+            let remove = if points[i].curve_bias > 0.0 { i } else { j };
+            points.remove(remove);
+            if remove < i {
+                i -= 1;
+            }
+        } else {
+            i += 1;
+        }
+    }
+
+    points
+}
+
+// Reverses an outline keeping its shape. The point implied between two
+// controls is placed by the first one's bias, which is then the other.
+fn reversed(points: &[Point]) -> Vec<Point> {
+    let n = points.len();
+
+    (0..n).rev()
+        .map(|i| {
+            let mut point = points[i];
+            let previous = points[(i + n - 1) % n];
+            if point.curve_bias > 0.0 && previous.curve_bias > 0.0 && previous.curve_bias < 1.0 {
+                point.curve_bias = 1.0 - previous.curve_bias;
+            }
+            point
+        })
+        .collect()
+}
+
+// Outlines should wind anticlockwise, or clockwise inside an odd number of
+// the path's others
+fn is_misoriented(polygons: &[&Vec<Vec2>], index: usize) -> bool {
+    let polygon = polygons[index];
+    let area = signed_area(polygon);
+    if area == 0.0 {
+        return false;
+    }
+
+    let depth = polygons.iter()
+        .enumerate()
+        .filter(|&(i, other)| i != index && winding(other, polygon[0]) != 0)
+        .count();
+
+    (area > 0.0) != (depth % 2 == 0)
+}
+
+// Whether any two edges that don't share an end cross
+fn crosses_itself(polygon: &[Vec2]) -> bool {
+    let n = polygon.len();
+    let side = |p: Vec2, q: Vec2, r: Vec2| (q.0 - p.0) * (r.1 - p.1) - (q.1 - p.1) * (r.0 - p.0);
+
+    (0..n).any(|i| {
+        let (a, b) = (polygon[i], polygon[(i + 1) % n]);
+        (i + 2..n).filter(|&j| (j + 1) % n != i).any(|j| {
+            let (c, d) = (polygon[j], polygon[(j + 1) % n]);
+            side(a, b, c) * side(a, b, d) < 0.0 && side(c, d, a) * side(c, d, b) < 0.0
+        })
+    })
+}
+
+fn outlines(path: &Path) -> Vec<&[Point]> {
+    Some(&path.points[..]).into_iter().chain(path.contours.iter().map(|c| &c[..])).collect()
+}
+
+fn polygon(points: &[Point]) -> Vec<Vec2> {
+    flatten_outline(points, FLATNESS).iter().map(|&(p, _)| p).collect()
+}
+
+fn minimum_points(path: &Path) -> usize {
+    if path.stroke.is_some() { 2 } else { 3 }
+}
+
+// Whether the path's area matters, rather than it only being a stroked line
+fn fills(path: &Path) -> bool {
+    path.filled || path.stroke.is_none()
+}
+
+fn is_finite(p: Vec2) -> bool {
+    p.0.is_finite() && p.1.is_finite()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::f64::NAN;
+    use model::{Model, Layer, Stroke, LineJoin, LineCap, path};
+    use model::tessellate::{model_triangles, signed_area, PathTriangles};
+
+    fn broken() -> Model {
+        let mut framed = path(&[(0.0, 0.0, 0.0), (4.0, 0.0, 0.0), (4.0, 4.0, 0.0), (0.0, 4.0, 0.0)]);
+        framed.contours.push(path(&[(1.0, 1.0, 0.0), (3.0, 1.0, 0.0), (3.0, 3.0, 0.0), (1.0, 3.0, 0.0)]).points);
+        framed.stroke = Some(Stroke {
+            colour: (0.0, 0.0, 0.0),
+            width: 1.0,
+            join: LineJoin::Miter,
+            cap: LineCap::Butt,
+            dashes: vec![1e-12],
+            closed: true
+        });
+
+        Model {
+            paths: vec![
+                path(&[(0.0, 0.0, 0.0), (NAN, 1.0, 0.0), (2.0, 0.0, 1e300), (2.0, 0.0, 0.0), (2.0, 2.0, 0.0)]),
+                path(&[(0.0, 0.0, 0.0), (1.0, 1.0, 0.0), (0.0, 0.0, 0.0)]),
+                path(&[(0.0, 0.0, 0.0), (2.0, 2.0, 0.0), (2.0, 0.0, 0.0), (0.0, 2.0, 0.0)]),
+                framed
+            ],
+            layers: vec![Layer { name: "all".to_string(), paths: vec![0, 1, 2, 3], visible: true, locked: false }],
+            .. Default::default()
+        }
+    }
+
+    #[test]
+    fn validate() {
+        let model = broken();
+        let issue = |path, outline, point, problem| {
+            Issue { symbol: None, path: path, outline: outline, point: point, problem: problem }
+        };
+
+        assert_eq!(model.validate(), vec![
+            issue(0, 0, Some(1), Problem::NonFinite),
+            issue(0, 0, Some(2), Problem::CurveBias),
+            issue(0, 0, Some(2), Problem::ZeroLength),
+            issue(1, 0, Some(2), Problem::ZeroLength),
+            issue(1, 0, None, Problem::TooFewPoints),
+            issue(2, 0, None, Problem::SelfIntersection),
+            issue(3, 1, None, Problem::Orientation),
+            issue(3, 0, None, Problem::FineDashes)
+        ]);
+
+        // None of it stops the model being drawn
+        assert_eq!(model_triangles(&model).len(), 4);
+    }
+
+    #[test]
+    fn repair() {
+        let mut model = broken();
+        model.repair();
+
+        assert_eq!(model.validate(), vec![
+            Issue { symbol: None, path: 1, outline: 0, point: None, problem: Problem::SelfIntersection }
+        ]);
+        assert_eq!(model.paths.len(), 3);
+        assert_eq!(model.layers[0].paths, vec![0, 1, 2]);
+
+        let locations = model.paths[0].points.iter().map(|p| p.location).collect::<Vec<_>>();
+        assert_eq!(locations, vec![(0.0, 0.0), (2.0, 0.0), (2.0, 2.0)]);
+        assert_eq!(model.paths[0].points[1].curve_bias, 0.0);
+
+        // The hole is cut out now that it winds the other way
+        assert!(model.paths[2].encloses((0.5, 0.5)));
+        assert!(!model.paths[2].encloses((2.0, 2.0)));
+        assert_eq!(model.paths[2].contours[0][0].location, (1.0, 3.0));
+    }
+
+    #[test]
+    fn reversed_curves() {
+        // Reversing keeps the implied points between controls in place
+        let mut model = Model {
+            paths: vec![path(&[(0.0, 0.0, 0.0), (4.0, 0.0, 0.25), (4.0, 4.0, 0.5), (0.0, 4.0, 0.0)])],
+            .. Default::default()
+        };
+        let before = model_triangles(&model);
+        model.paths[0].points = reversed(&model.paths[0].points);
+
+        let biases = model.paths[0].points.iter().map(|p| p.curve_bias).collect::<Vec<_>>();
+        assert_eq!(biases, vec![0.0, 0.75, 0.25, 0.0]);
+        let area = |triangles: &[PathTriangles]| triangles.iter()
+            .flat_map(|t| t.triangles.iter())
+            .map(|t| signed_area(&[t[0].position, t[1].position, t[2].position]).abs())
+            .sum::<f64>();
+        assert!((area(&before) - area(&model_triangles(&model))).abs() < 1e-9);
+    }
+}
@@ -1,8 +1,9 @@
 pub mod rendering;
+pub mod raster;
 
 use std::io::{Result, Error, ErrorKind};
 use super::data::{Value, Tag, Vec2, Vec3, Vec4, Box2, Reader, Writer};
-use super::model::Model;
+use super::model::{Model, Path};
 
 pub enum Element {
      Widget(Widget),
@@ -19,6 +20,8 @@ pub struct Widget {
     pub fill_colour: Vec4,
     pub border_colour: Vec3,
     pub border_width: i32,
+    // Whether children are only drawn and hit inside the widget's bounds
+    pub clip_children: bool,
 
     pub bindings: Vec<(EventType, Binding)>,
     pub children: Vec<Element>,
@@ -79,6 +82,8 @@ const GROUP: Tag = tag!(G R U P);
 const GRID: Tag = tag!(G R I D);
 const MODEL: Tag = tag!(M O D L);
 const TEXT: Tag = tag!(T E X T);
+const SHAPE: Tag = tag!(S H A P);
+const PATH: Tag = tag!(P A T H);
 
 const DOWN: Tag = tag!(D O W N);
 const UP: Tag = tag!(U P _ _);
@@ -116,6 +121,10 @@ impl Widget {
         }
         try!(writer.write_end());
 
+        if self.clip_children {
+            try!(writer.write_value(&Value::Bool(true)));
+        }
+
         try!(writer.write_end());
 
         try!(writer.write_start());
@@ -193,6 +202,14 @@ impl Widget {
             self.border_width = 0;
         }
 
+        // Clipping is left out unless it's on
+        if let Some(clip_children) = try!(reader.expect_bool_or_end()) {
+            self.clip_children = clip_children;
+        } else {
+            self.clip_children = false;
+            return Ok(());
+        }
+
         reader.skip_to_end()
     }
 
@@ -218,6 +235,16 @@ impl Widget {
 
     }
 
+    // The area that the widget drawn at a location clips its children to,
+    // within the area it's drawn in, snapped to whole pixels as it's drawn
+    fn clip_area(&self, location: Vec2, area: Box2) -> Box2 {
+        let min = (location.0.floor(), location.1.floor());
+        let max = (min.0 + self.size.0.floor(), min.1 + self.size.1.floor());
+
+        ((min.0.max((area.0).0), min.1.max((area.0).1)),
+         (max.0.min((area.1).0), max.1.min((area.1).1)))
+    }
+
     pub fn find_binding(&self, target_event_type: EventType) -> Option<Binding> {
         for &(event_type, binding) in &self.bindings {
             if event_type == target_event_type {
@@ -227,9 +254,62 @@ impl Widget {
 
         None
     }
+
+    // Finds the binding for a Down event at a point in the parent's space.
+    // The first child hit takes the event, passing it up to this widget if
+    // it has no binding of its own. Children outside the bounds can only be
+    // hit if they aren't clipped. Also gives whether anything was hit.
+    pub fn down_event(&self, pos: Vec2) -> (bool, Option<Binding>) {
+        let inside = self.is_in_bounds(pos);
+        if !inside && self.clip_children {
+            return (false, None);
+        }
+
+        let pos = (pos.0 - self.location.0, pos.1 - self.location.1);
+        let mut hit = inside;
+
+        for child in &self.children {
+            match down_event(child, pos) {
+                (true, Some(binding)) => return (true, Some(binding)),
+                (true, None) => {
+                    hit = true;
+                    break;
+                },
+                _ => ()
+            }
+        }
+
+        if hit {
+            (true, self.find_binding(EventType::Down))
+        } else {
+            (false, None)
+        }
+    }
+}
+
+fn down_event(element: &Element, pos: Vec2) -> (bool, Option<Binding>) {
+    match element {
+        &Element::Widget(ref widget) => widget.down_event(pos),
+        &Element::Group(ref group) => group.down_event(pos),
+        _ => (false, None)
+    }
 }
 
 impl Group {
+    // As for widgets, but groups have no bounds or bindings of their own
+    pub fn down_event(&self, pos: Vec2) -> (bool, Option<Binding>) {
+        let pos = (pos.0 - self.location.0, pos.1 - self.location.1);
+
+        for child in &self.children {
+            match down_event(child, pos) {
+                (true, binding) => return (true, binding),
+                _ => ()
+            }
+        }
+
+        (false, None)
+    }
+
     fn write(&self, writer: &mut Writer) -> Result<()> {
         try!(writer.write_start());
         try!(writer.write_value(&Value::Tag(GROUP)));
@@ -329,13 +409,50 @@ impl ModelElement {
             return Ok(());
         }
 
-        if !try!(reader.expect_start_or_end()) {
-            return Ok(());
+        // Either a whole model, or (PATH name path) groups replacing single
+        // paths by name
+        while try!(reader.expect_start_or_end()) {
+            match try!(reader.expect_tag()) {
+                SHAPE => self.model = try!(Model::read_tagged(reader)),
+                PATH => {
+                    let name = try!(reader.expect_string());
+                    let mut path = try!(Path::read(reader));
+                    try!(reader.skip_to_end());
+
+                    let i = match self.model.find_path(&name) {
+                        Some(i) => i,
+                        None => return Err(Error::new(ErrorKind::InvalidData, "Unknown path"))
+                    };
+                    if path.name.is_none() {
+                        path.name = Some(name.into_string());
+                    }
+                    self.model.paths[i] = path;
+                },
+                _ => return Err(Error::new(ErrorKind::InvalidData, "Unexpected data"))
+            }
         }
 
-        self.model = try!(Model::read_started(reader));
+        Ok(())
+    }
+}
 
-        reader.skip_to_end()
+impl ModelElement {
+    // Where the model's origin is drawn, relative to the parent, so that its
+    // anchor lands on the element's location
+    pub fn origin(&self) -> Vec2 {
+        let (x, y) = self.model.metadata.anchor.unwrap_or((0.0, 0.0));
+        (self.location.0 - x * self.scale, self.location.1 - y * self.scale)
+    }
+
+    // The path under a point in the parent's coordinates, as with
+    // Widget::is_in_bounds
+    pub fn hit_test(&self, (x, y): Vec2) -> Option<usize> {
+        if self.scale == 0.0 {
+            return None;
+        }
+
+        let origin = self.origin();
+        self.model.hit_test(((x - origin.0) / self.scale, (y - origin.1) / self.scale))
     }
 }
 
@@ -344,9 +461,7 @@ impl Default for ModelElement {
         ModelElement {
             location: (0.0, 0.0),
             scale: 0.0,
-            model: Model {
-                paths: Vec::new()
-            }
+            model: Model::default()
         }
     }
 }
@@ -515,8 +630,8 @@ pub fn update_children(children: &mut Vec<Element>, reader: &mut Reader) -> Resu
 #[cfg(test)]
 mod tests {
     use super::*;
-    use super::super::model::{Model, Path, Point};
-    use super::super::data::{BinaryWriter, BinaryReader, Reader};
+    use super::super::model::{Model, Path, Point, FillRule, Paint, Metadata, NO_WEIGHTS};
+    use super::super::data::{Value, BinaryWriter, BinaryReader, Reader, Writer};
     use std::io::{Cursor, copy};
 
     fn sample() -> Element {
@@ -526,6 +641,7 @@ mod tests {
             fill_colour: (0.0, 0.0, 0.0, 1.0),
             border_colour: (0.0, 0.0, 0.0),
             border_width: 0,
+            clip_children: false,
             bindings: Vec::new(),
             children: vec![
                 Element::Widget(Widget {
@@ -534,6 +650,7 @@ mod tests {
                     fill_colour: (0.1, 0.1, 0.1, 1.0),
                     border_colour: (0.0, 0.0, 0.0),
                     border_width: 0,
+                    clip_children: false,
                     bindings: Vec::new(),
                     children: vec![
                         Element::Grid(Grid {
@@ -548,15 +665,21 @@ mod tests {
                             model: Model {
                                 paths: vec![
                                     Path {
-                                        colour: (0.9, 0.3, 0.7),
+                                        name: None,
+                                        paint: Paint::solid((0.9, 0.3, 0.7)),
                                         points: vec![
-                                            Point {location: (0.0, 0.0), curve_bias: 0.0},
-                                            Point {location: (0.0, 0.0), curve_bias: 0.0},
-                                            Point {location: (0.0, 0.0), curve_bias: 0.0},
-                                            Point {location: (0.0, 0.0), curve_bias: 0.0},
-                                        ]
+                                            Point {location: (0.0, 0.0), curve_bias: 0.0, weights: NO_WEIGHTS},
+                                            Point {location: (0.0, 0.0), curve_bias: 0.0, weights: NO_WEIGHTS},
+                                            Point {location: (0.0, 0.0), curve_bias: 0.0, weights: NO_WEIGHTS},
+                                            Point {location: (0.0, 0.0), curve_bias: 0.0, weights: NO_WEIGHTS},
+                                        ],
+                                        contours: Vec::new(),
+                                        fill_rule: FillRule::NonZero,
+                                        filled: true,
+                                        stroke: None
                                     }
-                                ]
+                                ],
+                                .. Default::default()
                             }
                         }),
                         Element::Group(Group {
@@ -568,6 +691,7 @@ mod tests {
                                     fill_colour: (1.0, 1.0, 1.0, 1.0),
                                     border_colour: (0.0, 0.0, 0.0),
                                     border_width: 1,
+                                    clip_children: false,
                                     bindings: vec![
                                         (EventType::Down, 1004),
                                         (EventType::Up, 1014),
@@ -580,6 +704,7 @@ mod tests {
                                     fill_colour: (1.0, 1.0, 1.0, 1.0),
                                     border_colour: (0.0, 0.0, 0.0),
                                     border_width: 1,
+                                    clip_children: false,
                                     bindings: vec![
                                         (EventType::Down, 1005),
                                         (EventType::Up, 1005),
@@ -592,6 +717,7 @@ mod tests {
                                     fill_colour: (1.0, 1.0, 1.0, 1.0),
                                     border_colour: (0.0, 0.0, 0.0),
                                     border_width: 1,
+                                    clip_children: false,
                                     bindings: vec![
                                         (EventType::Down, 1006),
                                         (EventType::Up, 1006),
@@ -604,6 +730,7 @@ mod tests {
                                     fill_colour: (1.0, 1.0, 1.0, 1.0),
                                     border_colour: (0.0, 0.0, 0.0),
                                     border_width: 1,
+                                    clip_children: false,
                                     bindings: vec![
                                         (EventType::Down, 1007),
                                         (EventType::Up, 1007),
@@ -620,6 +747,7 @@ mod tests {
                     fill_colour: (0.1, 0.1, 0.1, 1.0),
                     border_colour: (0.0, 0.0, 0.0),
                     border_width: 0,
+                    clip_children: true,
                     bindings: Vec::new(),
                     children: vec![
                         Element::Widget(Widget {
@@ -628,6 +756,7 @@ mod tests {
                             fill_colour: (0.9, 0.0, 0.0, 1.0),
                             border_colour: (0.0, 0.0, 0.0),
                             border_width: 0,
+                            clip_children: false,
                             bindings: vec![(EventType::Down, 1001)],
                             children: vec![
                                 Element::Text(Text {
@@ -644,6 +773,7 @@ mod tests {
                             fill_colour: (0.0, 0.9, 0.0, 1.0),
                             border_colour: (0.0, 0.0, 0.0),
                             border_width: 0,
+                            clip_children: false,
                             bindings: vec![(EventType::Down, 1002)],
                             children: vec![
                                 Element::Text(Text {
@@ -660,6 +790,7 @@ mod tests {
                             fill_colour: (0.0, 0.0, 0.9, 1.0),
                             border_colour: (0.0, 0.0, 0.0),
                             border_width: 0,
+                            clip_children: false,
                             bindings: vec![(EventType::Down, 1003)],
                             children: vec![
                                 Element::Text(Text {
@@ -698,4 +829,108 @@ mod tests {
 
         assert_eq!(copy, orig);
     }
+    #[test]
+    fn down_event() {
+        let button = Widget {
+            location: (80.0, 10.0),
+            size: (40.0, 20.0),
+            bindings: vec![(EventType::Down, 2)],
+            .. Default::default()
+        };
+        let mut root = Widget {
+            location: (10.0, 10.0),
+            size: (100.0, 50.0),
+            bindings: vec![(EventType::Down, 1)],
+            children: vec![Element::Widget(button)],
+            .. Default::default()
+        };
+
+        assert_eq!(root.down_event((100.0, 30.0)), (true, Some(2)));
+        assert_eq!(root.down_event((50.0, 30.0)), (true, Some(1)));
+        assert_eq!(root.down_event((120.0, 30.0)), (true, Some(2)));
+
+        // The part of the button outside its parent isn't drawn, so it can't
+        // be pressed
+        root.clip_children = true;
+        assert_eq!(root.down_event((100.0, 30.0)), (true, Some(2)));
+        assert_eq!(root.down_event((120.0, 30.0)), (false, None));
+    }
+
+    fn square(name: &str, colour: (f64, f64, f64)) -> Path {
+        let points = [(0.0, 0.0), (1.0, 0.0), (1.0, 1.0), (0.0, 1.0)];
+        Path {
+            name: Some(name.to_string()),
+            paint: Paint::solid(colour),
+            points: points.iter()
+                .map(|&p| Point {location: p, curve_bias: 0.0, weights: NO_WEIGHTS})
+                .collect(),
+            contours: Vec::new(),
+            fill_rule: FillRule::NonZero,
+            filled: true,
+            stroke: None
+        }
+    }
+
+    #[test]
+    fn model_anchor() {
+        let mut element = ModelElement {
+            location: (10.0, 10.0),
+            scale: 2.0,
+            model: Model { paths: vec![square("face", (1.0, 1.0, 1.0))], .. Default::default() }
+        };
+        assert_eq!(element.hit_test((11.0, 11.0)), Some(0));
+        assert_eq!(element.hit_test((9.0, 9.0)), None);
+
+        // The model's top right corner is placed at the location
+        element.model.metadata = Metadata { anchor: Some((1.0, 1.0)), .. Default::default() };
+        assert_eq!(element.origin(), (8.0, 8.0));
+        assert_eq!(element.hit_test((11.0, 11.0)), None);
+        assert_eq!(element.hit_test((9.0, 9.0)), Some(0));
+    }
+
+    #[test]
+    fn update_named_path() {
+        let mut element = ModelElement {
+            location: (0.0, 0.0),
+            scale: 1.0,
+            model: Model {
+                paths: vec![square("face", (1.0, 1.0, 1.0)), square("left ear", (1.0, 1.0, 1.0))],
+                .. Default::default()
+            }
+        };
+
+        // Without a name of its own the new path keeps the old one
+        let mut ear = square("", (1.0, 0.0, 0.0));
+        ear.name = None;
+
+        let mut writer = BinaryWriter::new(Cursor::new(Vec::new()));
+        writer.write_start().unwrap();
+        writer.write_value(&Value::Tag(MODEL)).unwrap();
+        writer.write_value(&Value::Vec2((5.0, 5.0))).unwrap();
+        writer.write_value(&Value::Double(2.0)).unwrap();
+        writer.write_start().unwrap();
+        writer.write_value(&Value::Tag(PATH)).unwrap();
+        writer.write_value(&Value::String("left ear".to_string().into_boxed_str())).unwrap();
+        ear.write(&mut writer).unwrap();
+        writer.write_end().unwrap();
+        writer.write_end().unwrap();
+        let data = writer.into_inner().into_inner();
+
+        let mut reader = BinaryReader::new(Cursor::new(data.clone()));
+        reader.expect_start().unwrap();
+        reader.expect_tag().unwrap();
+        element.update(&mut reader).unwrap();
+
+        assert_eq!(element.location, (5.0, 5.0));
+        assert_eq!(element.scale, 2.0);
+        assert_eq!(element.model.paths[0].paint, Paint::solid((1.0, 1.0, 1.0)));
+        assert_eq!(element.model.paths[1].paint, Paint::solid((1.0, 0.0, 0.0)));
+        assert_eq!(element.model.find_path("left ear"), Some(1));
+
+        element.model.paths[1].name = Some("right ear".to_string());
+        let mut reader = BinaryReader::new(Cursor::new(data));
+        reader.expect_start().unwrap();
+        reader.expect_tag().unwrap();
+        assert!(element.update(&mut reader).is_err());
+    }
 }
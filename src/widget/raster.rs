@@ -0,0 +1,251 @@
+use data::{Vec2, Vec3, Box2};
+use image::Image;
+use model::{Font, Paint};
+use model::raster::draw_model_clipped;
+use super::{Element, Widget, Group, Grid, ModelElement, Text};
+
+// Draws widget trees into an image the way ElementRenderer does on the GPU,
+// so that they can be rendered without a display. Text is left out, as it is
+// by an ElementRenderer without a font.
+pub fn draw(image: &mut Image, element: &Element) {
+    let area = whole(image);
+    draw_element(image, element, (0.0, 0.0), area, None);
+}
+
+pub fn draw_root(image: &mut Image, widget: &Widget) {
+    let area = whole(image);
+    draw_widget(image, widget, (0.0, 0.0), area, None);
+}
+
+// As draw and draw_root, drawing text in the font
+pub fn draw_with_font(image: &mut Image, element: &Element, font: &Font) {
+    let area = whole(image);
+    draw_element(image, element, (0.0, 0.0), area, Some(font));
+}
+
+pub fn draw_root_with_font(image: &mut Image, widget: &Widget, font: &Font) {
+    let area = whole(image);
+    draw_widget(image, widget, (0.0, 0.0), area, Some(font));
+}
+
+fn whole(image: &Image) -> Box2 {
+    ((0.0, 0.0), (image.width as f64, image.height as f64))
+}
+
+// Each element is drawn only inside the area, as the scissor rectangle does
+// on the GPU
+fn draw_element(image: &mut Image, element: &Element, origin: Vec2, area: Box2, font: Option<&Font>) {
+    match element {
+        &Element::Widget(ref widget) => draw_widget(image, widget, origin, area, font),
+        &Element::Group(ref group) => draw_group(image, group, origin, area, font),
+        &Element::Grid(ref grid) => draw_grid(image, grid, origin, area),
+        &Element::Model(ref model) => draw_model_element(image, model, origin, area),
+        &Element::Text(ref text) => if let Some(font) = font {
+            draw_text(image, text, origin, area, font);
+        }
+    }
+}
+
+// Calls f with the local coordinates of the centre of each pixel in a quad
+// snapped to whole pixels, as the vertex shaders do, within the area
+fn fill_quad<F: FnMut(&mut Image, usize, usize, Vec2)>(image: &mut Image, location: Vec2, size: Vec2, area: Box2,
+                                                        mut f: F) {
+    let (location, size) = ((location.0.floor(), location.1.floor()), (size.0.floor(), size.1.floor()));
+    let clamp = |v: f64, min: f64, max: f64| v.max(min.floor()).min(max.floor()) as usize;
+    let ((min_x, min_y), (max_x, max_y)) = area;
+
+    for py in clamp(location.1, min_y, max_y)..clamp(location.1 + size.1, min_y, max_y) {
+        for px in clamp(location.0, min_x, max_x)..clamp(location.0 + size.0, min_x, max_x) {
+            let local = (px as f64 + 0.5 - location.0, py as f64 + 0.5 - location.1);
+            f(image, px, py, local);
+        }
+    }
+}
+
+fn draw_widget(image: &mut Image, widget: &Widget, origin: Vec2, area: Box2, font: Option<&Font>) {
+    let location = (origin.0 + widget.location.0, origin.1 + widget.location.1);
+    let size = (widget.size.0.floor(), widget.size.1.floor());
+    let border_width = (widget.border_width as f64).floor();
+
+    fill_quad(image, location, size, area, |image, x, y, local| {
+        // As in widget.frag, the border is where either coordinate is
+        // within the border width of the edge
+        let coords = (local.0 / size.0 - 0.5, local.1 / size.1 - 0.5);
+        let step = (0.5 - border_width / size.0, 0.5 - border_width / size.1);
+
+        if coords.0.abs() >= step.0 || coords.1.abs() >= step.1 {
+            image.blend(x, y, widget.border_colour, 1.0);
+        } else {
+            let (r, g, b, a) = widget.fill_colour;
+            image.blend(x, y, (r, g, b), a);
+        }
+    });
+
+    let child_area = if widget.clip_children { widget.clip_area(location, area) } else { area };
+    for child in &widget.children {
+        draw_element(image, child, location, child_area, font);
+    }
+}
+
+fn draw_group(image: &mut Image, group: &Group, origin: Vec2, area: Box2, font: Option<&Font>) {
+    let location = (origin.0 + group.location.0, origin.1 + group.location.1);
+
+    for child in &group.children {
+        draw_element(image, child, location, area, font);
+    }
+}
+
+fn draw_grid(image: &mut Image, grid: &Grid, origin: Vec2, area: Box2) {
+    let location = (origin.0 + (grid.bounds.0).0, origin.1 + (grid.bounds.0).1);
+    let size = ((grid.bounds.1).0 - (grid.bounds.0).0, (grid.bounds.1).1 - (grid.bounds.0).1);
+    let grid_size = (grid.size.0.floor(), grid.size.1.floor());
+    let offset = (grid.offset.0.floor(), grid.offset.1.floor());
+    let colour: Vec3 = grid.colour;
+
+    // grid.frag has no sensible result for cells smaller than a pixel
+    if !(grid_size.0 >= 1.0 && grid_size.1 >= 1.0) {
+        return;
+    }
+
+    fill_quad(image, location, size, area, |image, x, y, local| {
+        // A line one pixel wide at the start of each cell
+        let fract = |v: f64| v - v.floor();
+        let coords = ((local.0 - offset.0) / grid_size.0, (local.1 - offset.1) / grid_size.1);
+
+        if fract(coords.0) < 1.0 / grid_size.0 || fract(coords.1) < 1.0 / grid_size.1 {
+            image.blend(x, y, colour, 1.0);
+        }
+    });
+}
+
+fn draw_model_element(image: &mut Image, model: &ModelElement, origin: Vec2, area: Box2) {
+    let offset = model.origin();
+    let location = (origin.0 + offset.0, origin.1 + offset.1);
+    draw_model_clipped(image, location.0, location.1, model.scale, &model.model, area);
+}
+
+// The text's location is the start of its first baseline
+fn draw_text(image: &mut Image, text: &Text, origin: Vec2, area: Box2, font: &Font) {
+    let location = (origin.0 + text.location.0, origin.1 + text.location.1);
+
+    if let Ok(model) = font.layout(&text.value, text.size, Paint::solid(text.colour)) {
+        draw_model_clipped(image, location.0, location.1, 1.0, &model, area);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Cursor;
+    use data::{Reader, TextReader};
+    use image::{Image, check_golden};
+    use model::font::sample_font;
+    use widget::{Element, Widget, Grid, Text};
+
+    fn widget(location: Vec2, size: Vec2, border_width: i32, children: Vec<Element>) -> Widget {
+        Widget {
+            location: location,
+            size: size,
+            fill_colour: (1.0, 0.0, 0.0, 0.5),
+            border_colour: (0.0, 0.0, 1.0),
+            border_width: border_width,
+            clip_children: false,
+            bindings: Vec::new(),
+            children: children
+        }
+    }
+
+    #[test]
+    fn widgets() {
+        let grid = Element::Grid(Grid {
+            bounds: ((0.0, 0.0), (4.0, 4.0)),
+            size: (3.0, 2.0),
+            offset: (1.0, 0.0),
+            colour: (0.0, 1.0, 0.0)
+        });
+        let root = widget((1.5, 1.0), (6.9, 5.0), 1, vec![grid]);
+
+        let mut image = Image::new(10, 8, (0.0, 0.0, 0.0, 1.0));
+        draw_root(&mut image, &root);
+
+        // Snapped to (1, 1) and 6 x 5 pixels, with a one pixel border
+        let rows = (0..8).rev()
+            .map(|y| (0..10).map(|x| {
+                let colours = [((0.0, 0.0, 0.0, 1.0), '.'), ((0.0, 0.0, 1.0, 1.0), 'b'),
+                               ((0.5, 0.0, 0.0, 1.0), 'f'), ((0.0, 1.0, 0.0, 1.0), 'g')];
+                colours.iter().find(|c| c.0 == image.pixel(x, y)).map(|c| c.1).unwrap_or('?')
+            }).collect::<String>())
+            .collect::<Vec<_>>();
+
+        // The grid covers 4 x 4 pixels over the widget, with lines every 3
+        // pixels across starting 1 pixel in and every 2 pixels up
+        assert_eq!(rows, vec![
+            "..........",
+            "..........",
+            ".bbbbbb...",
+            ".bgfffb...",
+            ".ggggfb...",
+            ".bgfffb...",
+            ".ggggbb...",
+            "..........",
+        ]);
+    }
+
+    #[test]
+    fn clip_children() {
+        let child = Element::Widget(widget((2.0, 2.0), (6.0, 2.0), 0, Vec::new()));
+        let mut root = widget((1.0, 1.0), (4.0, 4.0), 1, vec![child]);
+
+        let mut image = Image::new(10, 8, (0.0, 0.0, 0.0, 1.0));
+        draw_root(&mut image, &root);
+        assert_eq!(image.pixel(8, 3), (0.5, 0.0, 0.0, 1.0));
+
+        // The child only shows over its parent
+        root.clip_children = true;
+        let mut image = Image::new(10, 8, (0.0, 0.0, 0.0, 1.0));
+        draw_root(&mut image, &root);
+        assert_eq!(image.pixel(3, 3), (0.75, 0.0, 0.0, 1.0));
+        assert_eq!(image.pixel(5, 3), (0.0, 0.0, 0.0, 1.0));
+        assert_eq!(image.pixel(8, 3), (0.0, 0.0, 0.0, 1.0));
+    }
+
+    #[test]
+    fn text() {
+        let text = Element::Text(Text {
+            location: (2.0, 3.0),
+            size: 10.0,
+            colour: (1.0, 0.5, 0.0),
+            value: "AV".to_string()
+        });
+
+        let mut image = Image::new(20, 16, (0.0, 0.0, 0.0, 1.0));
+        draw(&mut image, &text);
+        assert!(image.pixels.iter().all(|&p| p == (0.0, 0.0, 0.0, 1.0)));
+
+        // The glyphs are drawn as their model would be, from the baseline
+        let font = sample_font();
+        draw_with_font(&mut image, &text, &font);
+        let model = font.layout("AV", 10.0, Paint::solid((1.0, 0.5, 0.0))).unwrap();
+        let mut expected = Image::new(20, 16, (0.0, 0.0, 0.0, 1.0));
+        let area = whole(&expected);
+        draw_model_clipped(&mut expected, 2.0, 3.0, 1.0, &model, area);
+        assert!(image.pixels == expected.pixels);
+        assert_eq!(image.pixel(2, 5), (1.0, 0.5, 0.0, 1.0));
+        assert_eq!(image.pixel(4, 5), (0.0, 0.0, 0.0, 1.0));
+    }
+
+    #[test]
+    fn golden() {
+        let data = include_bytes!("../bin/scene.widget");
+        let mut reader = TextReader::new(Cursor::new(&data[..]));
+        reader.expect_start().unwrap();
+        reader.expect_tag().unwrap();
+
+        let mut root: Widget = Default::default();
+        root.update(&mut reader).unwrap();
+
+        let mut image = Image::new(800, 450, (0.02, 0.02, 0.02, 1.0));
+        draw_root(&mut image, &root);
+        check_golden(&image, "scene");
+    }
+}
@@ -1,9 +1,12 @@
-use glium::{VertexBuffer, Program, Surface, Blend};
+use std::cell::{Cell, RefCell};
+use std::collections::HashMap;
+use glium::{VertexBuffer, Program, Surface, Blend, Rect};
 use glium::backend::Facade;
 use glium::index::{NoIndices, PrimitiveType};
 use glium::draw_parameters::DrawParameters;
-use super::super::data::{Vec2};
-use super::super::model::rendering::{ModelRenderer, prepare_model};
+use super::super::data::{Vec2, Vec3, Box2};
+use super::super::model::{Font, Paint};
+use super::super::model::rendering::{ModelRenderer, ModelBuffers, prepare_model};
 use super::{Element, Widget, Group, Grid, ModelElement, Text};
 
 #[derive(Copy, Clone)]
@@ -13,6 +16,18 @@ struct Vertex {
 
 implement_vertex!(Vertex, position);
 
+// Text laid out and uploaded at one size and colour
+struct CachedText {
+    size: f64,
+    colour: Vec3,
+    model: ModelBuffers,
+    // The call to draw that last drew it
+    drawn: u64
+}
+
+// Text not drawn in this many calls to draw is dropped from the cache
+const TEXT_LIFETIME: u64 = 16;
+
 pub struct ElementRenderer<'a, F: 'a> {
     display: &'a F,
     params: DrawParameters<'a>,
@@ -21,6 +36,10 @@ pub struct ElementRenderer<'a, F: 'a> {
     widget_program: Program,
     grid_program: Program,
     model_renderer: ModelRenderer<'a>,
+    // Text is only drawn once there's a font
+    font: Option<Font>,
+    texts: RefCell<HashMap<String, Vec<CachedText>>>,
+    draws: Cell<u64>,
     width: f32,
     height: f32
 }
@@ -58,6 +77,9 @@ impl<'a, F: Facade> ElementRenderer<'a, F> {
                 None)
                 .unwrap(),
             model_renderer: ModelRenderer::new(display),
+            font: None,
+            texts: RefCell::new(HashMap::new()),
+            draws: Cell::new(0),
             width: 1.0,
             height: 1.0
         }
@@ -69,25 +91,69 @@ impl<'a, F: Facade> ElementRenderer<'a, F> {
         self.model_renderer.set_size(width, height);
     }
 
+    pub fn set_font(&mut self, font: Font) {
+        self.font = Some(font);
+        self.texts.borrow_mut().clear();
+    }
+
     pub fn draw<S: Surface>(&self, surface: &mut S, element: &Element) {
-        self.draw_element(surface, element, (0.0, 0.0));
+        self.draw_element(surface, element, (0.0, 0.0), None);
+        self.end_draw();
     }
 
     pub fn draw_root<S: Surface>(&self, surface: &mut S, widget: &Widget) {
-        self.draw_widget(surface, widget, (0.0, 0.0));
+        self.draw_widget(surface, widget, (0.0, 0.0), None);
+        self.end_draw();
     }
 
-    fn draw_element<S: Surface>(&self, surface: &mut S, element: &Element, origin: Vec2) {
+    // Drops the text that hasn't been drawn lately
+    fn end_draw(&self) {
+        let draws = self.draws.get();
+        let mut texts = self.texts.borrow_mut();
+
+        let mut unused = Vec::new();
+        for (value, cached) in texts.iter_mut() {
+            cached.retain(|text| draws - text.drawn < TEXT_LIFETIME);
+            if cached.is_empty() {
+                unused.push(value.clone());
+            }
+        }
+        for value in unused {
+            texts.remove(&value);
+        }
+
+        self.draws.set(draws + 1);
+    }
+
+    // The scissor rectangle in pixels for an area in points, whose y-axis
+    // also starts at the bottom
+    fn scissor<S: Surface>(&self, surface: &S, area: Option<Box2>) -> Option<Rect> {
+        let (width, height) = surface.get_dimensions();
+        let scale = (width as f64 / self.width as f64, height as f64 / self.height as f64);
+
+        area.map(|((x0, y0), (x1, y1))| {
+            let (left, bottom) = ((x0 * scale.0).max(0.0), (y0 * scale.1).max(0.0));
+            Rect {
+                left: left as u32,
+                bottom: bottom as u32,
+                width: (x1 * scale.0 - left).max(0.0) as u32,
+                height: (y1 * scale.1 - bottom).max(0.0) as u32
+            }
+        })
+    }
+
+    // Clipped widgets limit their descendants to the area, if there is one
+    fn draw_element<S: Surface>(&self, surface: &mut S, element: &Element, origin: Vec2, area: Option<Box2>) {
         match element {
-            &Element::Widget(ref widget) => self.draw_widget(surface, widget, origin),
-            &Element::Group(ref group) => self.draw_group(surface, group, origin),
-            &Element::Grid(ref grid) => self.draw_grid(surface, grid, origin),
-            &Element::Model(ref model) => self.draw_model(surface, model, origin),
-            &Element::Text(ref text) => self.draw_text(surface, text, origin),
+            &Element::Widget(ref widget) => self.draw_widget(surface, widget, origin, area),
+            &Element::Group(ref group) => self.draw_group(surface, group, origin, area),
+            &Element::Grid(ref grid) => self.draw_grid(surface, grid, origin, area),
+            &Element::Model(ref model) => self.draw_model(surface, model, origin, area),
+            &Element::Text(ref text) => self.draw_text(surface, text, origin, area),
         }
     }
 
-    fn draw_widget<S: Surface>(&self, surface: &mut S, widget: &Widget, origin: Vec2) {
+    fn draw_widget<S: Surface>(&self, surface: &mut S, widget: &Widget, origin: Vec2, area: Option<Box2>) {
         let location = (origin.0 + widget.location.0, origin.1 + widget.location.1);
 
         let uniforms = uniform! {
@@ -106,28 +172,35 @@ impl<'a, F: Facade> ElementRenderer<'a, F> {
             border_width: widget.border_width as f32
         };
 
+        let params = DrawParameters { scissor: self.scissor(surface, area), .. self.params.clone() };
         surface.draw(
             &self.vertices,
             self.indices,
             &self.widget_program,
             &uniforms,
-            &self.params)
+            &params)
             .unwrap();
 
+        let child_area = if widget.clip_children {
+            let outer = area.unwrap_or(((0.0, 0.0), (self.width as f64, self.height as f64)));
+            Some(widget.clip_area(location, outer))
+        } else {
+            area
+        };
         for ref child in &widget.children {
-            self.draw_element(surface, child, location);
+            self.draw_element(surface, child, location, child_area);
         }
     }
 
-    fn draw_group<S: Surface>(&self, surface: &mut S, group: &Group, origin: Vec2) {
+    fn draw_group<S: Surface>(&self, surface: &mut S, group: &Group, origin: Vec2, area: Option<Box2>) {
         let location = (origin.0 + group.location.0, origin.1 + group.location.1);
 
         for ref child in &group.children {
-            self.draw_element(surface, child, location);
+            self.draw_element(surface, child, location, area);
         }
     }
 
-    fn draw_grid<S: Surface>(&self, surface: &mut S, grid: &Grid, origin: Vec2) {
+    fn draw_grid<S: Surface>(&self, surface: &mut S, grid: &Grid, origin: Vec2, area: Option<Box2>) {
         let location = (origin.0 + (grid.bounds.0).0, origin.1 + (grid.bounds.0).1);
         let size = ((grid.bounds.1).0 - (grid.bounds.0).0, (grid.bounds.1).1 - (grid.bounds.0).1);
 
@@ -136,34 +209,80 @@ impl<'a, F: Facade> ElementRenderer<'a, F> {
             location: [location.0 as f32, location.1 as f32],
             size: [size.0 as f32, size.1 as f32],
             grid_size: [grid.size.0 as f32, grid.size.1 as f32],
-            grid_offset: [grid.offset.0 as f32, grid.size.1 as f32],
+            grid_offset: [grid.offset.0 as f32, grid.offset.1 as f32],
             grid_colour: [
                 grid.colour.0 as f32,
                 grid.colour.1 as f32,
                 grid.colour.2 as f32],
         };
 
+        let params = DrawParameters { scissor: self.scissor(surface, area), .. self.params.clone() };
         surface.draw(
             &self.vertices,
             self.indices,
             &self.grid_program,
             &uniforms,
-            &self.params)
+            &params)
             .unwrap();
     }
 
-    fn draw_model<S: Surface>(&self, surface: &mut S, model: &ModelElement, origin: Vec2) {
-        let location = (origin.0 + model.location.0, origin.1 + model.location.1);
+    fn draw_model<S: Surface>(&self, surface: &mut S, model: &ModelElement, origin: Vec2, area: Option<Box2>) {
+        let offset = model.origin();
+        let location = (origin.0 + offset.0, origin.1 + offset.1);
 
         let prepared = prepare_model(self.display, &model.model);
-        self.model_renderer.draw(
+        let scissor = self.scissor(surface, area);
+        self.model_renderer.draw_clipped(
             surface,
             location.0 as f32,
             location.1 as f32,
             model.scale as f32,
-            &prepared);
+            &prepared,
+            scissor);
     }
 
-    fn draw_text<S: Surface>(&self, _: &mut S, _: &Text, _: Vec2) {
+    // The text's location is the start of its first baseline
+    fn draw_text<S: Surface>(&self, surface: &mut S, text: &Text, origin: Vec2, area: Option<Box2>) {
+        let location = (origin.0 + text.location.0, origin.1 + text.location.1);
+
+        let font = match self.font {
+            Some(ref font) => font,
+            None => return
+        };
+
+        // Text is only laid out again when it changes
+        let mut texts = self.texts.borrow_mut();
+        if !texts.contains_key(&text.value) {
+            texts.insert(text.value.clone(), Vec::new());
+        }
+        let cached = texts.get_mut(&text.value).unwrap();
+
+        let i = match cached.iter().position(|c| c.size == text.size && c.colour == text.colour) {
+            Some(i) => i,
+            None => {
+                let model = match font.layout(&text.value, text.size, Paint::solid(text.colour)) {
+                    Ok(model) => model,
+                    Err(_) => return
+                };
+                cached.push(CachedText {
+                    size: text.size,
+                    colour: text.colour,
+                    model: prepare_model(self.display, &model),
+                    drawn: 0
+                });
+                cached.len() - 1
+            }
+        };
+        cached[i].drawn = self.draws.get();
+
+        let prepared = &cached[i].model;
+        let scissor = self.scissor(surface, area);
+        self.model_renderer.draw_clipped(
+            surface,
+            location.0 as f32,
+            location.1 as f32,
+            1.0,
+            prepared,
+            scissor);
     }
 }